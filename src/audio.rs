@@ -55,9 +55,95 @@ pub fn to_amplitude(input: Vec<Complex<f32>>, amplitude_scalar: f32) -> Vec<f32>
 
 #[derive(Clone)]
 pub struct AudioFrame {
+    // Estimated tempo, or 0 if there's no steady beat.
     pub bpm: f32,
-    pub low_power: f32,
-    pub mid_power: f32,
-    pub high_power: f32,
+    pub is_beat: bool,
     pub hundred_hz_buckets: [f32; 200],
+    // Whether the microphone has been quiet long enough to show the idle visualizer.
+    pub is_silent: bool,
+}
+
+impl AudioFrame {
+    // Sum the amplitudes of every hundred hz bucket that starts inside the band.
+    pub fn band_power(&self, band: FrequencyBand) -> f32 {
+        let mut power = 0.0;
+        for i in 0..self.hundred_hz_buckets.len() {
+            let bucket_hz = i as f32 * 100.0;
+            if bucket_hz >= band.low_hz && bucket_hz < band.high_hz {
+                power += self.hundred_hz_buckets[i];
+            }
+        }
+
+        power
+    }
+}
+
+// A range of frequencies, [low_hz, high_hz), feeding one of a visualizer's named inputs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrequencyBand {
+    pub low_hz: f32,
+    pub high_hz: f32,
+}
+
+impl FrequencyBand {
+    pub const fn new(low_hz: f32, high_hz: f32) -> FrequencyBand {
+        FrequencyBand { low_hz, high_hz }
+    }
+
+    // Parse a band written as "<low_hz>-<high_hz>", e.g. "400-2000".
+    pub fn parse(value: &str) -> Result<FrequencyBand, String> {
+        let parts: Vec<&str> = value.splitn(2, '-').collect();
+        if parts.len() != 2 {
+            return Err(format!("expected <low_hz>-<high_hz>, got '{}'", value));
+        }
+
+        let low_hz = parts[0].trim().parse::<f32>()
+            .map_err(|_| format!("invalid low frequency '{}'", parts[0]))?;
+        let high_hz = parts[1].trim().parse::<f32>()
+            .map_err(|_| format!("invalid high frequency '{}'", parts[1]))?;
+        if low_hz < 0.0 || high_hz <= low_hz {
+            return Err(format!("band '{}' must satisfy 0 <= low < high", value));
+        }
+
+        Ok(FrequencyBand::new(low_hz, high_hz))
+    }
+
+    // Split the band into `count` equally sized sub-bands, lowest first.
+    pub fn split(&self, count: usize) -> Vec<FrequencyBand> {
+        let width = (self.high_hz - self.low_hz) / count as f32;
+        (0..count)
+            .map(|i| FrequencyBand::new(self.low_hz + i as f32 * width,
+                                        self.low_hz + (i + 1) as f32 * width))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bands() {
+        assert_eq!(FrequencyBand::parse("400-2000"), Ok(FrequencyBand::new(400.0, 2000.0)));
+        assert_eq!(FrequencyBand::parse(" 0 - 60.5 "), Ok(FrequencyBand::new(0.0, 60.5)));
+    }
+
+    #[test]
+    fn rejects_invalid_bands() {
+        assert!(FrequencyBand::parse("400").is_err());
+        assert!(FrequencyBand::parse("low-2000").is_err());
+        assert!(FrequencyBand::parse("400-high").is_err());
+        assert!(FrequencyBand::parse("2000-400").is_err());
+        assert!(FrequencyBand::parse("400-400").is_err());
+        // The minus sign is taken as the separator.
+        assert!(FrequencyBand::parse("-10-20").is_err());
+    }
+
+    #[test]
+    fn splits_bands_evenly() {
+        let bands = FrequencyBand::new(0.0, 300.0).split(3);
+        assert_eq!(bands, vec![FrequencyBand::new(0.0, 100.0),
+                               FrequencyBand::new(100.0, 200.0),
+                               FrequencyBand::new(200.0, 300.0)]);
+    }
 }
@@ -0,0 +1,194 @@
+mod toml;
+mod watcher;
+
+use self::toml::{Entry, Table, Value};
+use screen;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+pub use self::watcher::watch;
+
+// The options each [[screen]] sets in the --screen_ lists, and what screens not setting one get.
+const SCREEN_LISTS: [(&str, &str); 9] = [
+    ("gamma", "0"),
+    ("brightness", "1"),
+    ("rotation", "0"),
+    ("flip", "none"),
+    ("strip", "0"),
+    ("region", "none"),
+    ("latency", "0"),
+    ("fps", "0"),
+    ("power_budget", "0"),
+];
+// Options that may be repeated; arrays of them are given once for each value.
+const REPEATED: [&str; 4] = ["band", "layer", "parameter", "screen_option"];
+
+// The config file to read: --config's, or config.toml in the user's config directory,
+// ~/.config/rpi-music-visualizer, if there's one there.
+pub fn path(args: &[String]) -> Option<PathBuf> {
+    for (i, arg) in args.iter().enumerate().skip(1) {
+        if arg == "--config" {
+            return args.get(i + 1).map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+
+    let path = directory()?.join("config.toml");
+    if path.is_file() { Some(path) } else { None }
+}
+
+// The user's config directory for the visualizer, ~/.config/rpi-music-visualizer, which may not
+// exist yet.
+pub fn directory() -> Option<PathBuf> {
+    let directory = match env::var_os("XDG_CONFIG_HOME") {
+        Some(ref x) if !x.is_empty() => PathBuf::from(x),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(directory.join("rpi-music-visualizer"))
+}
+
+// The command line options a config file stands for, parsed before the command line's own so those
+// override them. Its keys are the options' names without the dashes, at the top or grouped in any
+// tables, like [audio] or [visualizer], and true for options taking no value. Each [[screen]] is
+// one of --screen's, by its `name`, its gamma, brightness, rotation, flip, strip, region,
+// latency, fps and power_budget going in the --screen_ lists and anything else set with
+// --screen_option. [palettes] names lists of colors, for `palette` to pick one by, and
+// [parameters.<visualizer>] sets its parameters with --parameter.
+pub fn load(path: &PathBuf) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read it: {}", e))?;
+    let entries = toml::parse(&text)?;
+
+    let palettes: Vec<&Entry> = entries.iter().filter(|x| x.table == Table::Named("palettes".to_string())).collect();
+    let mut args = Vec::new();
+    let mut screens: Vec<(String, Vec<&Entry>)> = Vec::new();
+    for entry in entries.iter() {
+        match entry.table {
+            Table::Named(ref x) if x == "palettes" => {
+                if !is_palette(&entry.value) {
+                    return Err(format!("line {}: palette {} should be a list of colors like [\"#000000\", \"#ff0000\"]",
+                                       entry.line, entry.key));
+                }
+            },
+            Table::Named(ref x) if x.starts_with("parameters.") => {
+                let parameter = format!("{}.{}", &x["parameters.".len()..], entry.key);
+                match entry.value {
+                    Value::Integer(..) | Value::Float(..) => args.push(format!("--parameter={}={}", parameter, entry.value)),
+                    _ => return Err(format!("line {}: parameter {} should be a number", entry.line, parameter)),
+                }
+            },
+            Table::Array(ref x, index) if x == "screen" => {
+                while screens.len() <= index {
+                    screens.push((String::new(), Vec::new()));
+                }
+                if entry.key == "name" {
+                    screens[index].0 = entry.value.to_string();
+                } else {
+                    screens[index].1.push(entry);
+                }
+            },
+            Table::Array(ref x, _) => return Err(format!("line {}: unknown array of tables [[{}]], expected [[screen]]",
+                                                         entry.line, x)),
+            _ => {
+                if entry.key == "screen" && entries.iter().any(|x| x.table == Table::Array("screen".to_string(), 0)) {
+                    return Err(format!("line {}: screen is set as well as the [[screen]] tables", entry.line));
+                }
+                let value = if entry.key == "palette" {
+                    resolve_palette(&entry.value, &palettes)
+                } else {
+                    entry.value.clone()
+                };
+                push_option(&mut args, &entry.key, &value, entry.line)?;
+            },
+        }
+    }
+    if screens.is_empty() {
+        return Ok(args);
+    }
+
+    if let Some(i) = screens.iter().position(|x| x.0.is_empty()) {
+        return Err(format!("screen {} of the [[screen]] tables needs a name, like name = \"ws2812\"", i + 1));
+    }
+    let names: Vec<&str> = screens.iter().map(|x| x.0.as_ref()).collect();
+    args.push(format!("--screen={}", names.join(",")));
+    for &(list, default) in SCREEN_LISTS.iter() {
+        let values: Vec<Option<&Entry>> = screens.iter()
+            .map(|x| x.1.iter().cloned().find(|entry| entry.key == list))
+            .collect();
+        if values.iter().all(|x| x.is_none()) {
+            continue;
+        }
+        if let Some(entry) = values.iter().filter_map(|x| *x).find(|x| is_array(&x.value)) {
+            return Err(format!("line {}: a screen's {} is one value", entry.line, list));
+        }
+        let values: Vec<String> = values.iter()
+            .map(|x| x.map_or(default.to_string(), |entry| entry.value.to_string()))
+            .collect();
+        args.push(format!("--screen_{}={}", list, values.join(",")));
+    }
+    for (i, (_, entries)) in screens.iter().enumerate() {
+        for entry in entries.iter().filter(|x| !SCREEN_LISTS.iter().any(|&(list, _)| list == x.key)) {
+            args.push(format!("--screen_option={}:{}={}", i + 1, entry.key, entry.value));
+        }
+    }
+    Ok(args)
+}
+
+// One option with its value as arguments, like --fps=60, or --fake for true.
+fn push_option(args: &mut Vec<String>, key: &str, value: &Value, line: usize) -> Result<(), String> {
+    if key == "config" {
+        return Err(format!("line {}: config files can't read other config files", line));
+    }
+    match *value {
+        Value::Boolean(true) => args.push(format!("--{}", key)),
+        // The default, for options taking no value.
+        Value::Boolean(false) => (),
+        Value::Array(ref values) if REPEATED.contains(&key) => {
+            for value in values.iter() {
+                args.push(format!("--{}={}", key, value));
+            }
+        },
+        _ => args.push(format!("--{}={}", key, value)),
+    }
+    Ok(())
+}
+
+fn is_array(value: &Value) -> bool {
+    matches!(*value, Value::Array(..))
+}
+
+// Whether a palette in [palettes] is a list of colors, or one string of them.
+fn is_palette(value: &Value) -> bool {
+    match *value {
+        Value::Array(ref colors) => colors.iter().all(|x| matches!(*x, Value::String(..))),
+        Value::String(..) => true,
+        _ => false,
+    }
+}
+
+// The colors of the palette in [palettes] `value` names, or of the built in one, or `value` when
+// it's colors itself.
+fn resolve_palette(value: &Value, palettes: &[&Entry]) -> Value {
+    let name = match *value {
+        Value::String(ref x) => x,
+        _ => return value.clone(),
+    };
+    if let Some(entry) = palettes.iter().find(|x| x.key == *name) {
+        return entry.value.clone();
+    }
+    match screen::PALETTES.iter().find(|x| x.0 == name) {
+        Some(&(_, colors)) => Value::String(colors.to_string()),
+        None => value.clone(),
+    }
+}
+
+// The palettes a config file names in [palettes], with their colors.
+pub fn palettes(path: &PathBuf) -> Result<Vec<(String, String)>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read it: {}", e))?;
+    Ok(toml::parse(&text)?.into_iter()
+        .filter(|x| x.table == Table::Named("palettes".to_string()))
+        .map(|x| (x.key, x.value.to_string()))
+        .collect())
+}
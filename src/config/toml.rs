@@ -0,0 +1,432 @@
+use std::fmt;
+
+// The part of TOML config files need: tables, arrays of tables, and keys set to strings, numbers,
+// booleans or arrays of them. Dotted keys, inline tables, dates and multi-line strings aren't
+// read, and tables in tables, like [parameters.smiley], are named with their dots.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl fmt::Display for Value {
+    // How the value is written on the command line, arrays separated by commas.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::String(ref x) => write!(f, "{}", x),
+            Value::Integer(x) => write!(f, "{}", x),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Boolean(x) => write!(f, "{}", x),
+            Value::Array(ref values) => {
+                let values: Vec<String> = values.iter().map(|x| x.to_string()).collect();
+                write!(f, "{}", values.join(","))
+            },
+        }
+    }
+}
+
+// Which table a key is in.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Table {
+    Root,
+    // Like [audio] or [parameters.smiley].
+    Named(String),
+    // The nth of the [[name]] tables, from 0.
+    Array(String, usize),
+}
+
+#[derive(Debug)]
+pub struct Entry {
+    pub table: Table,
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+// Every key in `text` in the order they're set, with the line each is on. Errors say the line
+// they're on too.
+pub fn parse(text: &str) -> Result<Vec<Entry>, String> {
+    let mut parser = Parser { chars: text.chars().collect(), position: 0, line: 1 };
+    parser.parse().map_err(|e| format!("line {}: {}", parser.line, e))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn parse(&mut self) -> Result<Vec<Entry>, String> {
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut table = Table::Root;
+        let mut tables_seen = Vec::new();
+        let mut array_lengths: Vec<(String, usize)> = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            let c = match self.peek() {
+                Some(x) => x,
+                None => return Ok(entries),
+            };
+
+            if c == '[' {
+                self.next();
+                let is_array = self.peek() == Some('[');
+                if is_array {
+                    self.next();
+                }
+                self.skip_spaces();
+                let mut name = self.key()?;
+                self.skip_spaces();
+                while self.peek() == Some('.') {
+                    self.next();
+                    self.skip_spaces();
+                    name = format!("{}.{}", name, self.key()?);
+                    self.skip_spaces();
+                }
+                self.expect(']')?;
+                if is_array {
+                    self.expect(']')?;
+                    let index = match array_lengths.iter_mut().find(|x| x.0 == name) {
+                        Some(x) => {
+                            x.1 += 1;
+                            x.1 - 1
+                        },
+                        None => {
+                            array_lengths.push((name.clone(), 1));
+                            0
+                        },
+                    };
+                    table = Table::Array(name, index);
+                } else {
+                    if tables_seen.contains(&name) {
+                        return Err(format!("[{}] is defined twice", name));
+                    }
+                    tables_seen.push(name.clone());
+                    table = Table::Named(name);
+                }
+                if array_lengths.iter().any(|x| tables_seen.contains(&x.0)) {
+                    return Err("a table can't also be an array of tables".to_string());
+                }
+            } else {
+                let line = self.line;
+                let key = self.key()?;
+                self.skip_spaces();
+                if self.peek() == Some('.') {
+                    return Err(format!("dotted keys like {}.x aren't supported, use a [{}] table", key, key));
+                }
+                self.expect('=')?;
+                self.skip_spaces();
+                let value = self.value()?;
+                if entries.iter().any(|x| x.table == table && x.key == key) {
+                    return Err(format!("{} is set twice", key));
+                }
+                entries.push(Entry { table: table.clone(), key, value, line });
+            }
+            self.end_of_line()?;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        if c.is_some() {
+            self.position += 1;
+        }
+        c
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.next();
+                Ok(())
+            },
+            Some('\n') | None => Err(format!("expected '{}' before the end of the line", expected)),
+            Some(c) => Err(format!("expected '{}', found '{}'", expected, c)),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek() == Some(' ') || self.peek() == Some('\t') {
+            self.next();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while self.peek().is_some_and(|x| x != '\n') {
+                self.next();
+            }
+        }
+    }
+
+    // Spaces, comments and line breaks, as between the lines of a file or the values of an array.
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') | Some('\r') => {
+                    self.next();
+                },
+                _ => return,
+            }
+        }
+    }
+
+    // Nothing but a comment may follow a table or a key's value on its line.
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        self.skip_comment();
+        if self.peek() == Some('\r') {
+            self.next();
+        }
+        match self.next() {
+            Some('\n') | None => Ok(()),
+            Some(c) => Err(format!("expected the end of the line, found '{}'", c)),
+        }
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some('"') | Some('\'') => return self.string(),
+            _ => (),
+        }
+
+        let mut key = String::new();
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                break;
+            }
+            key.push(c);
+            self.next();
+        }
+        if key.is_empty() {
+            return Err(match self.peek() {
+                Some(c) if c != '\n' => format!("expected a key, found '{}'", c),
+                _ => "expected a key".to_string(),
+            });
+        }
+        Ok(key)
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') | Some('\'') => self.string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => Err("inline tables aren't supported, use a table of their own".to_string()),
+            Some('\n') | Some('\r') | Some('#') | None => Err("expected a value".to_string()),
+            _ => {
+                let mut word = String::new();
+                while let Some(c) = self.peek() {
+                    if c.is_whitespace() || c == ',' || c == ']' || c == '#' {
+                        break;
+                    }
+                    word.push(c);
+                    self.next();
+                }
+                parse_word(&word)
+            },
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        let line = self.line;
+        self.expect('[')?;
+        let mut values = Vec::new();
+        loop {
+            self.skip_blank_lines();
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(values));
+            }
+            if self.peek().is_none() {
+                return Err(format!("expected ']' to end the array from line {}", line));
+            }
+            values.push(self.value()?);
+            self.skip_blank_lines();
+            match self.peek() {
+                Some(',') => {
+                    self.next();
+                },
+                Some(']') => (),
+                Some(c) => return Err(format!("expected ',' or ']' in the array, found '{}'", c)),
+                None => return Err(format!("expected ']' to end the array from line {}", line)),
+            }
+        }
+    }
+
+    // A basic string in double quotes, with escapes, or a literal one in single quotes, without.
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.next().unwrap_or('"');
+        if self.peek() == Some(quote) && self.chars.get(self.position + 1) == Some(&quote) {
+            return Err("multi-line strings aren't supported".to_string());
+        }
+
+        let mut value = String::new();
+        loop {
+            let c = match self.peek() {
+                Some('\n') | None => return Err("expected the string's closing quote before the end of the line"
+                                                 .to_string()),
+                Some(c) => c,
+            };
+            self.next();
+            if c == quote {
+                return Ok(value);
+            }
+            if c != '\\' || quote == '\'' {
+                value.push(c);
+                continue;
+            }
+
+            value.push(match self.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('"') => '"',
+                Some('\\') => '\\',
+                Some(c) if c == 'u' || c == 'U' => {
+                    let digits: String = (0..if c == 'u' { 4 } else { 8 }).filter_map(|_| self.next()).collect();
+                    u32::from_str_radix(&digits, 16).ok()
+                        .and_then(::std::char::from_u32)
+                        .ok_or_else(|| format!("invalid escape \\{}{}", c, digits))?
+                },
+                Some(c) => return Err(format!("invalid escape \\{}", c)),
+                None => return Err("expected the string's closing quote".to_string()),
+            });
+        }
+    }
+}
+
+// A boolean or number.
+fn parse_word(word: &str) -> Result<Value, String> {
+    match word {
+        "true" => return Ok(Value::Boolean(true)),
+        "false" => return Ok(Value::Boolean(false)),
+        _ => (),
+    }
+
+    let number = word.replace('_', "");
+    let is_float = number.contains('.') || ((number.contains('e') || number.contains('E')) && !number.starts_with("0x"));
+    if is_float {
+        if let Ok(x) = number.parse::<f64>() {
+            return Ok(Value::Float(x));
+        }
+    } else if let Some(hex) = number.strip_prefix("0x") {
+        if let Ok(x) = i64::from_str_radix(hex, 16) {
+            return Ok(Value::Integer(x));
+        }
+    } else if let Ok(x) = number.parse::<i64>() {
+        return Ok(Value::Integer(x));
+    }
+    Err(format!("expected a value, found '{}' (are quotes missing around a string?)", word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(text: &str) -> Vec<(Table, String, Value, usize)> {
+        parse(text).unwrap().into_iter().map(|x| (x.table, x.key, x.value, x.line)).collect()
+    }
+
+    fn named(name: &str) -> Table {
+        Table::Named(name.to_string())
+    }
+
+    #[test]
+    fn reads_keys_in_tables() {
+        let text = "visualizer = \"smiley\"  # a comment\n\
+                    \n\
+                    [audio]\n\
+                    gain = 1.5\n\
+                    fake = true\n\
+                    [ parameters . smiley ]\n\
+                    speed = -2\n";
+        assert_eq!(values(text), vec![
+            (Table::Root, "visualizer".to_string(), Value::String("smiley".to_string()), 1),
+            (named("audio"), "gain".to_string(), Value::Float(1.5), 4),
+            (named("audio"), "fake".to_string(), Value::Boolean(true), 5),
+            (named("parameters.smiley"), "speed".to_string(), Value::Integer(-2), 7),
+        ]);
+    }
+
+    #[test]
+    fn reads_numbers() {
+        let text = "a = 1_000\nb = 0xff\nc = 1e3\nd = 2.5e-1\n";
+        let numbers: Vec<Value> = values(text).into_iter().map(|x| x.2).collect();
+        assert_eq!(numbers, vec![Value::Integer(1000), Value::Integer(255), Value::Float(1000.0), Value::Float(0.25)]);
+        assert!(parse("a = smiley\n").unwrap_err().contains("quotes missing"));
+    }
+
+    #[test]
+    fn reads_quotes_and_escapes() {
+        let text = "basic = \"a \\\"b\\\" \\\\ \\t\\u00e9\\U0001F600\"\n\
+                    literal = 'C:\\path\\n'\n\
+                    \"quoted key\" = 1\n";
+        let entries = values(text);
+        assert_eq!(entries[0].2, Value::String("a \"b\" \\ \t\u{e9}\u{1F600}".to_string()));
+        assert_eq!(entries[1].2, Value::String("C:\\path\\n".to_string()));
+        assert_eq!(entries[2].1, "quoted key");
+        // Literal strings end at the next quote, leaving the rest of the line.
+        assert!(parse("a = 'it''s'\n").is_err());
+        assert!(parse("a = \"\\q\"\n").unwrap_err().contains("invalid escape \\q"));
+        assert!(parse("a = \"\\u12\"\n").is_err());
+        assert!(parse("a = \"open\nb = 1\n").unwrap_err().contains("closing quote"));
+        assert!(parse("a = \"\"\"multi\nline\"\"\"\n").unwrap_err().contains("multi-line strings"));
+    }
+
+    #[test]
+    fn reads_multi_line_arrays() {
+        let text = "colors = [\n\
+                    \"#000000\",  # black\n\
+                    \n\
+                    \"#ff0000\",\n\
+                    ]\n\
+                    sizes = [[1, 2], []]\n\
+                    after = 1\n";
+        let entries = values(text);
+        assert_eq!(entries[0].2, Value::Array(vec![Value::String("#000000".to_string()),
+                                                   Value::String("#ff0000".to_string())]));
+        assert_eq!(entries[0].2.to_string(), "#000000,#ff0000");
+        assert_eq!(entries[1].2, Value::Array(vec![Value::Array(vec![Value::Integer(1), Value::Integer(2)]),
+                                                   Value::Array(Vec::new())]));
+        assert_eq!(entries[2].3, 7);
+        assert_eq!(parse("a = [1,\n2\n").err(), Some("line 3: expected ']' to end the array from line 1".to_string()));
+        assert!(parse("a = [1 2]\n").unwrap_err().contains("expected ',' or ']'"));
+    }
+
+    #[test]
+    fn counts_arrays_of_tables() {
+        let text = "[[layer]]\nvisualizer = \"smiley\"\n[[layer]]\nvisualizer = \"equalizer\"\n[audio]\n";
+        let tables: Vec<Table> = values(text).into_iter().map(|x| x.0).collect();
+        assert_eq!(tables, vec![Table::Array("layer".to_string(), 0), Table::Array("layer".to_string(), 1)]);
+    }
+
+    #[test]
+    fn rejects_repeated_tables_and_keys() {
+        assert_eq!(parse("[audio]\n[screen]\n[audio]\n").err(), Some("line 3: [audio] is defined twice".to_string()));
+        assert_eq!(parse("[audio]\ngain = 1\ngain = 2\n").err(), Some("line 3: gain is set twice".to_string()));
+        assert!(parse("[[layer]]\n[layer]\n").unwrap_err().contains("also be an array of tables"));
+        // The same key in different tables, or in each of an array's tables, is fine.
+        assert!(parse("gain = 1\n[audio]\ngain = 2\n[[layer]]\nx = 1\n[[layer]]\nx = 2\n").is_ok());
+    }
+
+    #[test]
+    fn rejects_what_it_does_not_support() {
+        assert!(parse("audio.gain = 1\n").unwrap_err().contains("dotted keys"));
+        assert!(parse("audio = { gain = 1 }\n").unwrap_err().contains("inline tables"));
+        assert_eq!(parse("a = 1 b = 2\n").err(), Some("line 1: expected the end of the line, found 'b'".to_string()));
+        assert!(parse("a =\n").unwrap_err().contains("expected a value"));
+    }
+}
@@ -0,0 +1,166 @@
+use config;
+use control;
+use screen;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_PLAYLIST_SECONDS: u64 = 30;
+
+// Watches the config file at `path`, read at startup into `args`, and sends the changes to its
+// brightness, palette, playlist and parameters as commands whenever it's saved, so the show goes
+// on. Changes to other options would mean setting up the screens or audio again, so they're only
+// reported, to apply on the next start. A file that doesn't load or has invalid values is left
+// unapplied until it's saved again.
+pub fn watch(path: PathBuf, args: Vec<String>, commands_tx: mpsc::Sender<control::Command>) {
+    thread::spawn(move || {
+        let mut modified = modified_time(&path);
+        let mut current = options(&args);
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let now = modified_time(&path);
+            if now == modified {
+                continue;
+            }
+            modified = now;
+
+            let new_options = match config::load(&path) {
+                Ok(x) => options(&x),
+                Err(e) => {
+                    println!("Not reloading the config file {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let commands = match changes(&current, &new_options, &palettes(&path)) {
+                Ok(x) => x,
+                Err(e) => {
+                    println!("Not reloading the config file {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            current = new_options;
+            for command in commands {
+                if commands_tx.send(command).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+}
+
+fn modified_time(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|x| x.modified()).ok()
+}
+
+// Each option in `args` with its values, in the order they're first given. Options taking no
+// value are "true".
+fn options(args: &[String]) -> Vec<(String, Vec<String>)> {
+    let mut options: Vec<(String, Vec<String>)> = Vec::new();
+    for arg in args.iter() {
+        let arg = arg.trim_start_matches('-');
+        let mut parts = arg.splitn(2, '=');
+        let key = parts.next().unwrap_or("").to_string();
+        let value = parts.next().unwrap_or("true").to_string();
+        match options.iter_mut().find(|x| x.0 == key) {
+            Some(x) => x.1.push(value),
+            None => options.push((key, vec![value])),
+        }
+    }
+    options
+}
+
+// The built in palettes and the config file's, which replace them, by name with their colors.
+fn palettes(path: &PathBuf) -> Vec<(String, String)> {
+    let mut palettes: Vec<(String, String)> = screen::PALETTES.iter()
+        .map(|&(name, colors)| (name.to_string(), colors.to_string()))
+        .collect();
+    for (name, colors) in config::palettes(path).unwrap_or_default() {
+        palettes.retain(|x| x.0 != name);
+        palettes.push((name, colors));
+    }
+    palettes
+}
+
+// The commands making the changes from `old` to `new` that can be made while running, or why
+// they can't be made. The palette is named by the one in `palettes` with its colors, as the
+// config file gives only the colors.
+fn changes(old: &[(String, Vec<String>)],
+           new: &[(String, Vec<String>)],
+           palettes: &[(String, String)]) -> Result<Vec<control::Command>, String> {
+    let values = |options: &[(String, Vec<String>)], key: &str| options.iter()
+        .find(|x| x.0 == key)
+        .map_or(Vec::new(), |x| x.1.clone());
+    let mut keys: Vec<&str> = new.iter().map(|x| x.0.as_ref()).collect();
+    keys.extend(old.iter().map(|x| x.0.as_ref()).filter(|x| !new.iter().any(|y| y.0 == *x)));
+
+    let mut commands = Vec::new();
+    let mut applied = Vec::new();
+    let mut not_applied = Vec::new();
+    let mut playlist_changed = false;
+    for key in keys {
+        let (old_values, new_values) = (values(old, key), values(new, key));
+        if old_values == new_values {
+            continue;
+        }
+        let value = new_values.last().cloned().unwrap_or_default();
+        match key {
+            "brightness" => {
+                let brightness = if value.is_empty() { 1.0 } else {
+                    value.parse::<f32>().map_err(|e| format!("invalid brightness {}: {}", value, e))?
+                };
+                if !(0.0..=1.0).contains(&brightness) {
+                    return Err(format!("invalid brightness {}: expected 0 to 1", value));
+                }
+                commands.push(control::Command::SetParameter("brightness".to_string(), brightness));
+            },
+            "palette" => {
+                let palette = if value.is_empty() { None } else {
+                    Some(screen::Palette::parse(&value).map_err(|e| format!("invalid palette {}: {}", value, e))?)
+                };
+                let name = match palettes.iter().find(|x| x.1 == value) {
+                    Some(x) => x.0.clone(),
+                    None if value.is_empty() => "none".to_string(),
+                    None => "custom".to_string(),
+                };
+                commands.push(control::Command::SetPalette(name, palette));
+            },
+            "playlist" | "playlist_seconds" => playlist_changed = true,
+            // Parameters no longer set keep their values until the next start.
+            "parameter" => {
+                for parameter in new_values.iter().filter(|x| !old_values.contains(x)) {
+                    let (name, value) = control::parse_parameter(parameter)
+                        .map_err(|e| format!("invalid parameter {}: {}", parameter, e))?;
+                    commands.push(control::Command::SetParameter(name, value));
+                }
+            },
+            _ => {
+                not_applied.push(format!("--{}", key));
+                continue;
+            },
+        }
+        applied.push(format!("--{}", key));
+    }
+    if playlist_changed {
+        let playlist = values(new, "playlist").last().cloned().unwrap_or_default();
+        let playlist: Vec<String> = playlist.split(',')
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect();
+        let seconds = match values(new, "playlist_seconds").last() {
+            Some(x) => x.parse::<u64>().map_err(|e| format!("invalid playlist_seconds {}: {}", x, e))?,
+            None => DEFAULT_PLAYLIST_SECONDS,
+        };
+        commands.push(control::Command::SetPlaylist(playlist, Duration::from_secs(seconds)));
+    }
+
+    if !applied.is_empty() {
+        println!("Applying the config file's changes to {}", applied.join(", "));
+    }
+    if !not_applied.is_empty() {
+        println!("Restart to apply the config file's changes to {}", not_applied.join(", "));
+    }
+    Ok(commands)
+}
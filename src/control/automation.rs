@@ -0,0 +1,251 @@
+use audio;
+use std::fs;
+use std::path::PathBuf;
+use std::time;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AutomationSync {
+    // Follow the live tempo, so a recorded show stays on the beat when the music is faster or slower.
+    Beats,
+    // Replay with the recorded timing.
+    Time,
+}
+
+impl AutomationSync {
+    pub fn parse(value: &str) -> Result<AutomationSync, String> {
+        match value {
+            "beats" => Ok(AutomationSync::Beats),
+            "time" => Ok(AutomationSync::Time),
+
+            _ => Err(format!("unknown automation sync '{}', expected beats or time", value)),
+        }
+    }
+}
+
+// How far into a recording something happened, both in seconds and in beats.
+#[derive(Clone, Copy)]
+struct Position {
+    seconds: f32,
+    beats: f32,
+}
+
+impl Position {
+    fn get(&self, sync: AutomationSync) -> f32 {
+        match sync {
+            AutomationSync::Beats => self.beats,
+            AutomationSync::Time => self.seconds,
+        }
+    }
+}
+
+// Counts time and beats since it started. Between beats, the position within the beat is
+// estimated from the tempo.
+struct Clock {
+    started: time::Instant,
+    beats: u32,
+    last_beat: time::Instant,
+    beat_seconds: f32,
+}
+
+impl Clock {
+    fn new() -> Clock {
+        let now = time::Instant::now();
+        Clock {
+            started: now,
+            beats: 0,
+            last_beat: now,
+            beat_seconds: 0.0,
+        }
+    }
+
+    fn update(&mut self, audio_frame: &audio::AudioFrame) {
+        if audio_frame.is_beat {
+            self.beats += 1;
+            self.last_beat = time::Instant::now();
+        }
+        if audio_frame.bpm > 0.0 {
+            self.beat_seconds = 60.0 / audio_frame.bpm;
+        }
+    }
+
+    fn position(&self) -> Position {
+        let beat_fraction = match self.beat_seconds {
+            x if x > 0.0 => f32::min(0.999, duration_seconds(self.last_beat.elapsed()) / x),
+            _ => 0.0,
+        };
+
+        Position {
+            seconds: duration_seconds(self.started.elapsed()),
+            beats: self.beats as f32 + beat_fraction,
+        }
+    }
+}
+
+struct Event {
+    position: Position,
+    name: String,
+    value: f32,
+}
+
+struct Recording {
+    clock: Clock,
+    events: Vec<Event>,
+}
+
+struct Playback {
+    clock: Clock,
+    events: Vec<Event>,
+    length: Position,
+    next_event: usize,
+}
+
+// Records live parameter changes with their timing, and replays them as a looping automation
+// track, for pre-choreographed shows.
+pub struct Automation {
+    path: Option<PathBuf>,
+    sync: AutomationSync,
+    recording: Option<Recording>,
+    playback: Option<Playback>,
+}
+
+impl Automation {
+    pub fn new(path: Option<PathBuf>, sync: AutomationSync) -> Automation {
+        Automation {
+            path,
+            sync,
+            recording: None,
+            playback: None,
+        }
+    }
+
+    pub fn toggle_recording(&mut self) {
+        let path = match self.path {
+            Some(ref x) => x.clone(),
+            None => {
+                println!("Not recording automation, no automation file was given.");
+                return;
+            }
+        };
+
+        match self.recording.take() {
+            Some(recording) => {
+                let length = recording.clock.position();
+                match fs::write(&path, serialize(&recording.events, length)) {
+                    Ok(_) => println!("Saved {} automation events to {}.", recording.events.len(), path.display()),
+                    Err(e) => println!("Could not save automation to {}: {}", path.display(), e),
+                }
+            }
+            None => {
+                // Replayed changes would otherwise be recorded over themselves.
+                self.playback = None;
+                self.recording = Some(Recording {
+                    clock: Clock::new(),
+                    events: Vec::new(),
+                });
+                println!("Recording automation.");
+            }
+        }
+    }
+
+    pub fn play(&mut self) -> Result<(), String> {
+        let path = match self.path {
+            Some(ref x) => x.clone(),
+            None => return Err("no automation file was given".to_string()),
+        };
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let (events, length) = deserialize(&contents)
+            .map_err(|e| format!("could not parse {}: {}", path.display(), e))?;
+
+        self.playback = Some(Playback {
+            clock: Clock::new(),
+            events,
+            length,
+            next_event: 0,
+        });
+
+        Ok(())
+    }
+
+    // Record a live parameter change, if recording.
+    pub fn record(&mut self, name: &str, value: f32) {
+        if let Some(ref mut recording) = self.recording {
+            let position = recording.clock.position();
+            recording.events.push(Event {
+                position,
+                name: name.to_string(),
+                value,
+            });
+        }
+    }
+
+    // Advance by an audio frame, returning the parameter changes that are now due.
+    pub fn update(&mut self, audio_frame: &audio::AudioFrame) -> Vec<(String, f32)> {
+        if let Some(ref mut recording) = self.recording {
+            recording.clock.update(audio_frame);
+        }
+
+        let sync = self.sync;
+        let mut due = Vec::new();
+        if let Some(ref mut playback) = self.playback {
+            playback.clock.update(audio_frame);
+
+            let position = playback.clock.position().get(sync);
+            while playback.next_event < playback.events.len() &&
+                playback.events[playback.next_event].position.get(sync) <= position {
+                let event = &playback.events[playback.next_event];
+                due.push((event.name.clone(), event.value));
+                playback.next_event += 1;
+            }
+
+            let length = playback.length.get(sync);
+            if length > 0.0 && position >= length {
+                playback.clock = Clock::new();
+                playback.next_event = 0;
+            }
+        }
+
+        due
+    }
+}
+
+// One event per line as "<seconds> <beats> <parameter> <value>", after a line with the length.
+fn serialize(events: &[Event], length: Position) -> String {
+    let mut contents = format!("length {} {}\n", length.seconds, length.beats);
+    for event in events.iter() {
+        contents.push_str(&format!("{} {} {} {}\n",
+                                   event.position.seconds, event.position.beats, event.name, event.value));
+    }
+
+    contents
+}
+
+fn deserialize(contents: &str) -> Result<(Vec<Event>, Position), String> {
+    let mut length = Position { seconds: 0.0, beats: 0.0 };
+    let mut events = Vec::new();
+
+    for (line_index, line) in contents.lines().enumerate() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let parse = |i: usize| parts[i].parse::<f32>()
+            .map_err(|_| format!("line {}: invalid number '{}'", line_index + 1, parts[i]));
+
+        match parts.len() {
+            0 => continue,
+            3 if parts[0] == "length" => length = Position { seconds: parse(1)?, beats: parse(2)? },
+            4 => events.push(Event {
+                position: Position { seconds: parse(0)?, beats: parse(1)? },
+                name: parts[2].to_string(),
+                value: parse(3)?,
+            }),
+
+            _ => return Err(format!("line {}: expected <seconds> <beats> <parameter> <value>", line_index + 1)),
+        }
+    }
+
+    Ok((events, length))
+}
+
+fn duration_seconds(duration: time::Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_nanos() as f32 / 1_000_000_000.0
+}
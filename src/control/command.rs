@@ -0,0 +1,70 @@
+use screen;
+use std::time::Duration;
+
+// What buttons can do, as --gpio_buttons and the IR remote's keys name it.
+pub const ACTIONS: [&str; 7] = ["next", "previous", "playlist", "blackout", "brighter", "dimmer", "beat"];
+const BRIGHTNESS_STEP: f32 = 0.1;
+
+// Runtime changes to a running visualizer, from the keyboard or other controllers.
+#[derive(Clone, Debug)]
+pub enum Command {
+    // Set a parameter, either "brightness" or "<visualizer>.<parameter>", e.g. "smiley.speed".
+    SetParameter(String, f32),
+    // Add to a parameter, by name like SetParameter's.
+    NudgeParameter(String, f32),
+    // A beat tapped by hand, on top of those heard. Taps in a row set the tempo too.
+    TapBeat,
+    ToggleOverlay,
+    // Start recording parameter changes, or stop and save the recording.
+    ToggleAutomationRecording,
+    // Save the next rendered frame as a PNG in the working directory.
+    Screenshot,
+    // Start recording an MP4 of the output, or stop and finish the file.
+    ToggleVideoRecording,
+    // Start recording an MP4 unless one is being recorded, or stop and finish it.
+    StartVideoRecording,
+    StopVideoRecording,
+    // Record a short GIF of the output at its own resolution, e.g. the LED matrix's.
+    RecordGif,
+    // Show one visualizer, or go back to the playlist if it's empty.
+    SelectVisualizer(String),
+    // Show the visualizer this many after the one shown, or before if it's negative.
+    CycleVisualizer(i32),
+    // Black the output out, or bring it back to the brightness before.
+    ToggleBlackout,
+    // Rotate through these visualizers, each for the time given, as --playlist does.
+    SetPlaylist(Vec<String>, Duration),
+    // Recolor the screens taking pixels with a palette, or stop recoloring them, with the name
+    // it's shown under, which is "none" for none.
+    SetPalette(String, Option<screen::Palette>),
+}
+
+// The command for one of the ACTIONS: next and previous cycle through the visualizers, playlist
+// goes back to the playlist, blackout toggles one, brighter and dimmer step the brightness, and
+// beat taps one.
+pub fn action_command(action: &str) -> Option<Command> {
+    match action {
+        "next" => Some(Command::CycleVisualizer(1)),
+        "previous" => Some(Command::CycleVisualizer(-1)),
+        "playlist" => Some(Command::SelectVisualizer(String::new())),
+        "blackout" => Some(Command::ToggleBlackout),
+        "brighter" => Some(Command::NudgeParameter("brightness".to_string(), BRIGHTNESS_STEP)),
+        "dimmer" => Some(Command::NudgeParameter("brightness".to_string(), -BRIGHTNESS_STEP)),
+        "beat" => Some(Command::TapBeat),
+        _ => None,
+    }
+}
+
+// Whether holding a button down repeats the action.
+pub fn is_repeating_action(action: &str) -> bool {
+    action == "brighter" || action == "dimmer"
+}
+
+// A parameter and its value from "<name>=<value>", like "smiley.speed=2", as --parameter takes.
+pub fn parse_parameter(value: &str) -> Result<(String, f32), String> {
+    let mut parts = value.splitn(2, '=');
+    let name = parts.next().unwrap_or("").trim();
+    let value = parts.next().ok_or_else(|| "expected <visualizer>.<parameter>=<value>".to_string())?;
+    let value = value.trim().parse::<f32>().map_err(|e| format!("{}: {}", value, e))?;
+    Ok((name.to_string(), value))
+}
@@ -0,0 +1,218 @@
+use control::{self, Command};
+use rppal::gpio::{Gpio, Level, Mode, PullUpDown};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Often enough not to miss an encoder's steps when it's spun.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+// How long a pin has to stay at a level for a press or release to count, as buttons bounce.
+const DEBOUNCE_TIME: Duration = Duration::from_millis(30);
+// Held buttons that repeat do so this often, once they've been held a while.
+const REPEAT_DELAY: Duration = Duration::from_millis(500);
+const REPEAT_INTERVAL: Duration = Duration::from_millis(150);
+const ENCODER_BRIGHTNESS_STEP: f32 = 0.05;
+// The BCM numbers of the pins on the header.
+const MAX_PIN: u8 = 27;
+
+// What the button of an encoder does, switching what turning it does.
+const SWITCH_ACTION: &str = "switch";
+// The steps an encoder's levels take, by the levels before and after as 2 bits each, 1 for a step
+// one way and -1 the other. Steps where both pins change, which bouncing or missing one makes,
+// are 0, as are those changing neither.
+const ENCODER_STEPS: [i32; 16] = [0, 1, -1, 0, -1, 0, 0, 1, 1, 0, 0, -1, 0, -1, 1, 0];
+
+struct Button {
+    pin: u8,
+    action: String,
+    is_pressed: bool,
+    // When the pin went to a level other than the button's, and when the held button last
+    // repeated, or REPEAT_INTERVAL before it starts to.
+    changed: Option<Instant>,
+    repeated: Instant,
+}
+
+impl Button {
+    // Whether the button's command is due with the pin being low or not.
+    fn update(&mut self, is_low: bool) -> bool {
+        let mut is_due = false;
+        if is_low == self.is_pressed {
+            self.changed = None;
+        } else {
+            let changed = *self.changed.get_or_insert_with(Instant::now);
+            if changed.elapsed() >= DEBOUNCE_TIME {
+                self.is_pressed = is_low;
+                self.changed = None;
+                self.repeated = Instant::now() + (REPEAT_DELAY - REPEAT_INTERVAL);
+                is_due = is_low;
+            }
+        }
+        if self.is_pressed && control::is_repeating_action(&self.action) && self.repeated.elapsed() >= REPEAT_INTERVAL {
+            self.repeated = Instant::now();
+            is_due = true;
+        }
+        is_due
+    }
+}
+
+// A quadrature rotary encoder, whose two pins both rest high, pulled up, at each detent, going
+// low in turn between them.
+struct Encoder {
+    pins: (u8, u8),
+    // The pins' levels as 2 bits, and the steps since the last detent.
+    levels: u8,
+    steps: i32,
+    // Whether turning it scrolls through the visualizers rather than scrubbing the brightness.
+    is_scrolling: bool,
+}
+
+impl Encoder {
+    // The command for a detent once the encoder reaches one after turning.
+    fn update(&mut self, levels: u8) -> Option<Command> {
+        if levels == self.levels {
+            return None;
+        }
+        self.steps += ENCODER_STEPS[(self.levels << 2 | levels) as usize];
+        self.levels = levels;
+        if levels != 0b11 {
+            return None;
+        }
+
+        // Turning back, or bouncing, before reaching the next detent leaves too few steps.
+        let direction = if self.steps >= 2 { 1 } else if self.steps <= -2 { -1 } else { 0 };
+        self.steps = 0;
+        match direction {
+            0 => None,
+            x if self.is_scrolling => Some(Command::CycleVisualizer(x)),
+            x => Some(Command::NudgeParameter("brightness".to_string(), x as f32 * ENCODER_BRIGHTNESS_STEP)),
+        }
+    }
+}
+
+// Sends commands for the buttons on the GPIO pins `buttons` lists as "<pin>:<action>,...", by
+// their BCM numbers, like "17:next,27:blackout", and for turning the rotary encoder on the pins
+// `encoder` lists as "<a>,<b>[,<button>]". Buttons connect their pin to ground when pressed, the
+// pins being pulled up. The actions are next and previous, to cycle through the visualizers,
+// playlist, blackout, brighter and dimmer, which repeat while held, and beat, to tap one. The
+// encoder scrubs the brightness, or scrolls through the visualizers once its button's pressed,
+// until it's pressed again.
+pub fn listen(buttons: &str, encoder: &str, commands_tx: mpsc::Sender<Command>) -> Result<(), String> {
+    let mut parsed = Vec::new();
+    for button in buttons.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+        let mut parts = button.splitn(2, ':');
+        let pin = parse_pin(parts.next().unwrap_or(""), &parsed)?;
+        let action = parts.next().unwrap_or("");
+        if !control::ACTIONS.contains(&action) {
+            return Err(format!("unknown action '{}' for pin {}, expected one of: {}",
+                               action, pin, control::ACTIONS.join(", ")));
+        }
+        parsed.push(new_button(pin, action));
+    }
+    let mut parsed_encoder = if encoder.is_empty() {
+        None
+    } else {
+        let pins: Vec<&str> = encoder.split(',').map(|x| x.trim()).collect();
+        if pins.len() != 2 && pins.len() != 3 {
+            return Err(format!("expected the encoder's pins as <a>,<b>[,<button>], got '{}'", encoder));
+        }
+        let a = parse_pin(pins[0], &parsed)?;
+        let b = parse_pin(pins[1], &parsed)?;
+        if a == b {
+            return Err(format!("pin {} is given twice", a));
+        }
+        if let Some(pin) = pins.get(2) {
+            let pin = parse_pin(pin, &parsed)?;
+            if pin == a || pin == b {
+                return Err(format!("pin {} is given twice", pin));
+            }
+            parsed.push(new_button(pin, SWITCH_ACTION));
+        }
+        Some(Encoder { pins: (a, b), levels: 0b11, steps: 0, is_scrolling: false })
+    };
+
+    // The GPIO has to stay on the thread polling it, so it's opened there.
+    let (opened_tx, opened_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut gpio = match Gpio::new() {
+            Ok(x) => x,
+            Err(e) => {
+                let _ = opened_tx.send(Err(format!("could not open the GPIO: {}", e)));
+                return;
+            },
+        };
+        let mut pins: Vec<u8> = parsed.iter().map(|x| x.pin).collect();
+        if let Some(ref encoder) = parsed_encoder {
+            pins.extend_from_slice(&[encoder.pins.0, encoder.pins.1]);
+        }
+        for &pin in pins.iter() {
+            gpio.set_mode(pin, Mode::Input);
+            gpio.set_pullupdown(pin, PullUpDown::PullUp);
+        }
+        let _ = opened_tx.send(Ok(()));
+        poll(&gpio, &mut parsed, parsed_encoder.as_mut(), &commands_tx);
+    });
+    opened_rx.recv().unwrap_or_else(|_| Err("the GPIO thread stopped".to_string()))?;
+    if !buttons.is_empty() {
+        println!("Listening for buttons on GPIO pins {}", buttons);
+    }
+    if !encoder.is_empty() {
+        println!("Listening for the rotary encoder on GPIO pins {}", encoder);
+    }
+    Ok(())
+}
+
+fn parse_pin(pin: &str, buttons: &[Button]) -> Result<u8, String> {
+    let pin = match pin.parse::<u8>() {
+        Ok(x) if x <= MAX_PIN => x,
+        _ => return Err(format!("expected a pin from 0 to {}, got '{}'", MAX_PIN, pin)),
+    };
+    if buttons.iter().any(|x| x.pin == pin) {
+        return Err(format!("pin {} is given twice", pin));
+    }
+    Ok(pin)
+}
+
+fn new_button(pin: u8, action: &str) -> Button {
+    Button {
+        pin,
+        action: action.to_string(),
+        is_pressed: false,
+        changed: None,
+        repeated: Instant::now(),
+    }
+}
+
+// Send the buttons' and the encoder's commands as they're pressed and turned, until the render
+// loop's gone. This runs on its own, the commands queueing up while the render loop's busy.
+fn poll(gpio: &Gpio, buttons: &mut [Button], mut encoder: Option<&mut Encoder>, commands_tx: &mpsc::Sender<Command>) {
+    let is_low = |pin| gpio.read(pin).map(|x| x == Level::Low).ok();
+    loop {
+        let mut commands = Vec::new();
+        for button in buttons.iter_mut() {
+            match is_low(button.pin) {
+                Some(x) if button.update(x) => (),
+                _ => continue,
+            }
+            match encoder {
+                Some(ref mut encoder) if button.action == SWITCH_ACTION => {
+                    encoder.is_scrolling = !encoder.is_scrolling;
+                    let target = if encoder.is_scrolling { "visualizers" } else { "brightness" };
+                    println!("The rotary encoder now changes the {}", target);
+                },
+                _ => commands.extend(control::action_command(&button.action)),
+            }
+        }
+        if let Some(ref mut encoder) = encoder {
+            if let (Some(a), Some(b)) = (is_low(encoder.pins.0), is_low(encoder.pins.1)) {
+                commands.extend(encoder.update(((!a as u8) << 1) | (!b as u8)));
+            }
+        }
+
+        for command in commands {
+            if commands_tx.send(command).is_err() {
+                return;
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,213 @@
+use control::{self, Command};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::mem;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use visualizer;
+
+const REOPEN_INTERVAL: Duration = Duration::from_secs(2);
+// An input device's event type for keys, and its values for a key going down and repeating.
+const EV_KEY: u16 = 1;
+const KEY_PRESSED: i32 = 1;
+const KEY_REPEATED: i32 = 2;
+
+// Linux's names for the keys remotes have, by their codes, as LIRC names them too.
+const KEY_NAMES: [(u16, &str); 52] = [
+    (1, "KEY_ESC"), (2, "KEY_1"), (3, "KEY_2"), (4, "KEY_3"), (5, "KEY_4"), (6, "KEY_5"), (7, "KEY_6"),
+    (8, "KEY_7"), (9, "KEY_8"), (10, "KEY_9"), (11, "KEY_0"), (28, "KEY_ENTER"), (57, "KEY_SPACE"),
+    (102, "KEY_HOME"), (103, "KEY_UP"), (105, "KEY_LEFT"), (106, "KEY_RIGHT"), (108, "KEY_DOWN"),
+    (113, "KEY_MUTE"), (114, "KEY_VOLUMEDOWN"), (115, "KEY_VOLUMEUP"), (116, "KEY_POWER"), (119, "KEY_PAUSE"),
+    (128, "KEY_STOP"), (139, "KEY_MENU"), (158, "KEY_BACK"), (164, "KEY_PLAYPAUSE"), (168, "KEY_REWIND"),
+    (174, "KEY_EXIT"), (207, "KEY_PLAY"), (208, "KEY_FASTFORWARD"), (0x160, "KEY_OK"), (0x161, "KEY_SELECT"),
+    (0x166, "KEY_INFO"), (0x18e, "KEY_RED"), (0x18f, "KEY_GREEN"), (0x190, "KEY_YELLOW"), (0x191, "KEY_BLUE"),
+    (0x192, "KEY_CHANNELUP"), (0x193, "KEY_CHANNELDOWN"), (0x197, "KEY_NEXT"), (0x19c, "KEY_PREVIOUS"),
+    (0x200, "KEY_NUMERIC_0"), (0x201, "KEY_NUMERIC_1"), (0x202, "KEY_NUMERIC_2"), (0x203, "KEY_NUMERIC_3"),
+    (0x204, "KEY_NUMERIC_4"), (0x205, "KEY_NUMERIC_5"), (0x206, "KEY_NUMERIC_6"), (0x207, "KEY_NUMERIC_7"),
+    (0x208, "KEY_NUMERIC_8"), (0x209, "KEY_NUMERIC_9"),
+];
+
+// The keys of most remotes, for when there's no keys file.
+const DEFAULT_KEYS: &str = "
+KEY_POWER blackout
+KEY_MUTE blackout
+KEY_UP brighter
+KEY_VOLUMEUP brighter
+KEY_DOWN dimmer
+KEY_VOLUMEDOWN dimmer
+KEY_RIGHT next
+KEY_CHANNELUP next
+KEY_NEXT next
+KEY_LEFT previous
+KEY_CHANNELDOWN previous
+KEY_PREVIOUS previous
+KEY_OK playlist
+KEY_ENTER playlist
+KEY_0 playlist
+KEY_NUMERIC_0 playlist
+KEY_1 equalizer
+KEY_NUMERIC_1 equalizer
+KEY_2 power_circles
+KEY_NUMERIC_2 power_circles
+KEY_3 smiley
+KEY_NUMERIC_3 smiley
+KEY_4 bisymmetry
+KEY_NUMERIC_4 bisymmetry
+KEY_5 trisymmetry
+KEY_NUMERIC_5 trisymmetry
+KEY_6 quadsymmetry
+KEY_NUMERIC_6 quadsymmetry
+KEY_7 pentasymmetry
+KEY_NUMERIC_7 pentasymmetry
+";
+
+// Takes the keys of an IR remote from `device`, either LIRC's socket, like /var/run/lirc/lircd,
+// or the input device the kernel's gpio-ir driver makes, like /dev/input/event0, and sends the
+// commands they're mapped to in the `keys` file, a line for each key like "KEY_UP brighter". The
+// keys are named as LIRC and Linux name them, and map to one of the button actions or a
+// visualizer's name to show it. Without the file, a remote's usual keys are mapped, the arrows
+// for the brightness and visualizers and the digits for each visualizer. Keys held down only
+// repeat brighter and dimmer.
+pub fn listen(device: &str, keys: PathBuf, commands_tx: mpsc::Sender<Command>) -> Result<(), String> {
+    let mappings = if keys.is_file() {
+        let text = fs::read_to_string(&keys).map_err(|e| format!("could not read {}: {}", keys.display(), e))?;
+        parse_keys(&text).map_err(|e| format!("invalid IR keys {}: {}", keys.display(), e))?
+    } else {
+        parse_keys(DEFAULT_KEYS)?
+    };
+
+    let device = device.to_string();
+    thread::spawn(move || {
+        let mut reported = false;
+        loop {
+            match Remote::open(&device) {
+                Ok(remote) => {
+                    println!("Reading the IR remote from {}", device);
+                    reported = false;
+                    match remote.read(&mappings, &commands_tx) {
+                        Ok(()) => return,
+                        Err(e) => println!("Lost the IR remote at {}, reopening: {}", device, e),
+                    }
+                },
+                Err(e) => {
+                    if !reported {
+                        println!("Could not open the IR remote at {}, retrying: {}", device, e);
+                        reported = true;
+                    }
+                },
+            }
+            thread::sleep(REOPEN_INTERVAL);
+        }
+    });
+    Ok(())
+}
+
+enum Remote {
+    Lirc(BufReader<UnixStream>),
+    Input(File),
+}
+
+impl Remote {
+    fn open(device: &str) -> Result<Remote, String> {
+        let metadata = fs::metadata(device).map_err(|e| e.to_string())?;
+        if metadata.file_type().is_socket() {
+            let stream = UnixStream::connect(device).map_err(|e| e.to_string())?;
+            return Ok(Remote::Lirc(BufReader::new(stream)));
+        }
+        File::open(device).map(Remote::Input).map_err(|e| e.to_string())
+    }
+
+    // Send the commands for the keys pressed until the remote's lost, or the render loop's gone,
+    // which is Ok.
+    fn read(mut self, mappings: &[(String, String)], commands_tx: &mpsc::Sender<Command>) -> Result<(), String> {
+        loop {
+            let (key, is_repeat) = self.next_key()?;
+            let action = match mappings.iter().find(|x| x.0 == key) {
+                Some(x) => &x.1,
+                None => {
+                    if !is_repeat {
+                        println!("The IR remote's {} key isn't mapped", key);
+                    }
+                    continue;
+                },
+            };
+            if is_repeat && !control::is_repeating_action(action) {
+                continue;
+            }
+            let command = control::action_command(action).unwrap_or_else(|| Command::SelectVisualizer(action.clone()));
+            if commands_tx.send(command).is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    // The next key pressed by name, and whether it's held down and repeating.
+    fn next_key(&mut self) -> Result<(String, bool), String> {
+        match *self {
+            // Lines of the code, the times it's repeated in hex, the key and the remote's name.
+            Remote::Lirc(ref mut reader) => loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+                    return Err("LIRC closed the connection".to_string());
+                }
+                let words: Vec<&str> = line.split_whitespace().collect();
+                if words.len() < 3 {
+                    continue;
+                }
+                let repeat = u32::from_str_radix(words[1], 16).unwrap_or(0);
+                return Ok((words[2].to_string(), repeat > 0));
+            },
+            // Linux's input events: the time, in two longs, then the type, the code and the value.
+            Remote::Input(ref mut file) => loop {
+                let time_size = 2 * mem::size_of::<usize>();
+                let mut event = vec![0; time_size + 8];
+                file.read_exact(&mut event).map_err(|e| match e.kind() {
+                    io::ErrorKind::UnexpectedEof => "the device closed".to_string(),
+                    _ => e.to_string(),
+                })?;
+                let kind = u16::from_ne_bytes([event[time_size], event[time_size + 1]]);
+                let code = u16::from_ne_bytes([event[time_size + 2], event[time_size + 3]]);
+                let mut value = [0; 4];
+                value.copy_from_slice(&event[time_size + 4..]);
+                let value = i32::from_ne_bytes(value);
+                if kind == EV_KEY && (value == KEY_PRESSED || value == KEY_REPEATED) {
+                    return Ok((key_name(code), value == KEY_REPEATED));
+                }
+            },
+        }
+    }
+}
+
+// Linux's name for a key code, or KEY_ and its code in hex, like KEY_0x1a0, for those of few
+// remotes.
+fn key_name(code: u16) -> String {
+    match KEY_NAMES.iter().find(|x| x.0 == code) {
+        Some(&(_, name)) => name.to_string(),
+        None => format!("KEY_{:#x}", code),
+    }
+}
+
+// The keys and their actions from lines like "KEY_UP brighter", comments starting with '#'.
+fn parse_keys(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut mappings: Vec<(String, String)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let words: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+        match words.len() {
+            0 => continue,
+            2 => (),
+            _ => return Err(format!("line {}: expected a key and its action, like KEY_UP brighter", i + 1)),
+        }
+        let (key, action) = (words[0], words[1]);
+        if !control::ACTIONS.contains(&action) && !visualizer::VISUALIZER_NAMES.contains(&action) {
+            return Err(format!("line {}: unknown action '{}', expected a visualizer or one of: {}",
+                               i + 1, action, control::ACTIONS.join(", ")));
+        }
+        mappings.retain(|x| x.0 != key);
+        mappings.push((key.to_string(), action.to_string()));
+    }
+    Ok(mappings)
+}
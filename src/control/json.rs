@@ -0,0 +1,188 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+// `value` as a JSON string.
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// The values taken in the fields of JSON messages.
+pub enum Json {
+    String(String),
+    Number(f64),
+}
+
+// The fields of a JSON object without nested objects or arrays, like {"value": 0.5}, the only kind
+// of message the controllers take.
+pub fn parse_object(text: &str) -> Result<Vec<(String, Json)>, String> {
+    let mut chars = text.trim().chars().peekable();
+    if chars.next() != Some('{') {
+        return Err("expected a JSON object".to_string());
+    }
+
+    let mut fields = Vec::new();
+    skip_spaces(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+    } else {
+        loop {
+            skip_spaces(&mut chars);
+            let key = parse_string(&mut chars)?;
+            skip_spaces(&mut chars);
+            if chars.next() != Some(':') {
+                return Err(format!("expected ':' after \"{}\"", key));
+            }
+            skip_spaces(&mut chars);
+            let value = match chars.peek() {
+                Some(&'"') => Json::String(parse_string(&mut chars)?),
+                _ => {
+                    let mut word = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() || c == ',' || c == '}' {
+                            break;
+                        }
+                        word.push(c);
+                        chars.next();
+                    }
+                    Json::Number(word.parse::<f64>()
+                        .map_err(|_| format!("expected a string or number for \"{}\"", key))?)
+                },
+            };
+            fields.push((key, value));
+            skip_spaces(&mut chars);
+            match chars.next() {
+                Some(',') => (),
+                Some('}') => break,
+                _ => return Err("expected ',' or '}' in the object".to_string()),
+            }
+        }
+    }
+    if chars.next().is_some() {
+        return Err("expected the end of the body after the object".to_string());
+    }
+    Ok(fields)
+}
+
+fn skip_spaces(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|x| x.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    if chars.next() != Some('"') {
+        return Err("expected a string in double quotes".to_string());
+    }
+    let mut value = String::new();
+    loop {
+        let c = chars.next().ok_or_else(|| "expected the string's closing quote".to_string())?;
+        match c {
+            '"' => return Ok(value),
+            '\\' => value.push(match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('b') => '\u{8}',
+                Some('f') => '\u{c}',
+                Some('u') => {
+                    let unit = parse_code_unit(chars)?;
+                    // Characters past the first 65536 are escaped as a pair of UTF-16 surrogates.
+                    let code = if (0xd800..0xdc00).contains(&unit) {
+                        let low = match (chars.next(), chars.next()) {
+                            (Some('\\'), Some('u')) => parse_code_unit(chars)?,
+                            _ => return Err(format!("expected a low surrogate after \\u{:04x}", unit)),
+                        };
+                        if !(0xdc00..0xe000).contains(&low) {
+                            return Err(format!("invalid low surrogate \\u{:04x}", low));
+                        }
+                        0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00)
+                    } else {
+                        unit
+                    };
+                    ::std::char::from_u32(code).ok_or_else(|| format!("invalid escape \\u{:04x}", code))?
+                },
+                Some(c) => c,
+                None => return Err("expected the string's closing quote".to_string()),
+            }),
+            c => value.push(c),
+        }
+    }
+}
+
+// The four hex digits after \u.
+fn parse_code_unit(chars: &mut Peekable<Chars>) -> Result<u32, String> {
+    let digits: String = (0..4).filter_map(|_| chars.next()).collect();
+    match u32::from_str_radix(&digits, 16) {
+        Ok(x) if digits.len() == 4 => Ok(x),
+        _ => Err(format!("invalid escape \\u{}", digits)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(text: &str) -> Result<String, String> {
+        match parse_object(&format!("{{\"name\": {}}}", text))?.pop() {
+            Some((_, Json::String(x))) => Ok(x),
+            _ => Err("expected a string".to_string()),
+        }
+    }
+
+    #[test]
+    fn reads_fields() {
+        let fields = parse_object(" { \"name\" : \"fire\", \"value\":-0.5e1 } ").unwrap();
+        assert_eq!(fields.len(), 2);
+        match (&fields[0], &fields[1]) {
+            ((name, Json::String(x)), (value, Json::Number(y))) => {
+                assert_eq!((name.as_ref(), x.as_ref(), value.as_ref(), *y), ("name", "fire", "value", -5.0));
+            },
+            _ => panic!("expected a string and a number"),
+        }
+        assert!(parse_object("{}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_escapes() {
+        assert_eq!(string("\"a\\\"b\\\\c\\/d\\n\\t\\r\\b\\f\"").unwrap(), "a\"b\\c/d\n\t\r\u{8}\u{c}");
+        assert_eq!(string("\"\\u00e9\\u20AC\"").unwrap(), "\u{e9}\u{20ac}");
+        assert_eq!(string("\"\\ud83d\\ude00\"").unwrap(), "\u{1f600}");
+        assert_eq!(string(&quote("tab\there \u{1}")).unwrap(), "tab\there \u{1}");
+    }
+
+    #[test]
+    fn rejects_bad_escapes() {
+        assert!(string("\"\\ud83d\"").is_err());
+        assert!(string("\"\\ud83d\\u0041\"").is_err());
+        assert!(string("\"\\ude00\"").is_err());
+        assert!(string("\"\\u12\"").is_err());
+        assert!(string("\"\\uzzzz\"").is_err());
+    }
+
+    #[test]
+    fn rejects_nesting_and_trailing_garbage() {
+        assert!(parse_object("{\"light\": {\"state\": \"ON\"}}").is_err());
+        assert!(parse_object("{\"effects\": [1, 2]}").is_err());
+        assert!(parse_object("{\"value\": 1} 2").is_err());
+        assert!(parse_object("{\"value\": 1}}").is_err());
+        assert!(parse_object("[1]").is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        for text in ["", "{", "{\"name", "{\"name\"", "{\"name\":", "{\"name\": \"fi", "{\"name\": \"fire\\",
+                     "{\"value\": 1", "{\"value\": 1,", "{\"name\": \"\\u00"].iter() {
+            assert!(parse_object(text).is_err(), "{} parsed", text);
+        }
+    }
+}
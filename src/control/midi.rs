@@ -0,0 +1,418 @@
+use control::Command;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Read};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use visualizer;
+
+const REOPEN_INTERVAL: Duration = Duration::from_secs(2);
+// The range parameters other than the brightness are mapped to unless their mapping says.
+const PARAMETER_RANGE: (f32, f32) = (0.0, 2.0);
+
+// A knob, fader or pad, by its channel, from 0, and its number.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Control {
+    Note(u8, u8),
+    ControlChange(u8, u8),
+}
+
+impl fmt::Display for Control {
+    // As in the mappings file, with channels from 1, like "cc 1 7".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Control::Note(channel, number) => write!(f, "note {} {}", channel + 1, number),
+            Control::ControlChange(channel, number) => write!(f, "cc {} {}", channel + 1, number),
+        }
+    }
+}
+
+// What a control drives.
+#[derive(Clone, Debug)]
+enum Target {
+    // Shown when the control's pressed, or the playlist if it's empty.
+    Visualizer(String),
+    // Tapped when the control's pressed.
+    Beat,
+    // Set from the low end of the range to the high end as the control goes from 0 to 127.
+    Parameter(String, f32, f32),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Target::Visualizer(ref x) if x.is_empty() => write!(f, "playlist"),
+            Target::Visualizer(ref x) => write!(f, "{}", x),
+            Target::Beat => write!(f, "beat"),
+            Target::Parameter(ref name, min, max) if (min, max) == default_range(name) => write!(f, "{}", name),
+            Target::Parameter(ref name, min, max) => write!(f, "{} {} {}", name, min, max),
+        }
+    }
+}
+
+fn default_range(parameter: &str) -> (f32, f32) {
+    if parameter == "brightness" { (0.0, 1.0) } else { PARAMETER_RANGE }
+}
+
+impl Target {
+    // A target from its words in the mappings file: "playlist", "beat", a visualizer's name, or
+    // "brightness" or a parameter like "smiley.speed", optionally with its range, like
+    // "smiley.speed 0 4".
+    fn parse(words: &[&str]) -> Result<Target, String> {
+        let name = match words.first() {
+            Some(x) => *x,
+            None => return Err("expected a target".to_string()),
+        };
+        let is_parameter = name == "brightness" || name.contains('.');
+        if !is_parameter && words.len() > 1 {
+            return Err(format!("only parameters take a range, not {}", name));
+        }
+        if name == "playlist" {
+            return Ok(Target::Visualizer(String::new()));
+        }
+        if name == "beat" {
+            return Ok(Target::Beat);
+        }
+        if !is_parameter {
+            if !visualizer::VISUALIZER_NAMES.contains(&name) {
+                return Err(format!("unknown visualizer {}", name));
+            }
+            return Ok(Target::Visualizer(name.to_string()));
+        }
+
+        let visualizer_name = name.split('.').next().unwrap_or("");
+        if name != "brightness" && !visualizer::VISUALIZER_NAMES.contains(&visualizer_name) {
+            return Err(format!("unknown visualizer {}", visualizer_name));
+        }
+        let (min, max) = match words.len() {
+            1 => default_range(name),
+            3 => {
+                let parse = |x: &str| x.parse::<f32>().map_err(|e| format!("{}: {}", x, e));
+                (parse(words[1])?, parse(words[2])?)
+            },
+            _ => return Err(format!("expected a range like {} 0 2", name)),
+        };
+        Ok(Target::Parameter(name.to_string(), min, max))
+    }
+
+    // The command for the control the target's mapped to reaching `value`, from 0 to 127, or None
+    // for buttons being let go of.
+    fn command(&self, value: u8) -> Option<Command> {
+        match *self {
+            Target::Parameter(ref name, min, max) => {
+                Some(Command::SetParameter(name.clone(), min + (max - min) * value as f32 / 127.0))
+            },
+            _ if value == 0 => None,
+            Target::Visualizer(ref name) => Some(Command::SelectVisualizer(name.clone())),
+            Target::Beat => Some(Command::TapBeat),
+        }
+    }
+}
+
+struct Mapping {
+    control: Control,
+    target: Target,
+}
+
+// Reads notes and control changes from the raw MIDI `device`, like /dev/snd/midiC1D0, and sends
+// the commands they're mapped to in the `mappings` file, a line for each control like
+// "cc 1 7 brightness", "note 10 36 smiley" or "cc 1 21 smiley.speed 0 4". A device that's
+// unplugged is reopened once it's back. While `is_learning`, targets typed in are mapped to the
+// next control moved and the file saved again, without its comments.
+pub fn listen(device: &str,
+              mappings: PathBuf,
+              is_learning: bool,
+              commands_tx: mpsc::Sender<Command>) -> Result<(), String> {
+    let loaded = if mappings.is_file() {
+        load(&mappings).map_err(|e| format!("invalid MIDI mappings {}: {}", mappings.display(), e))?
+    } else {
+        Vec::new()
+    };
+    if loaded.is_empty() && !is_learning {
+        println!("No MIDI mappings in {}, run with --midi_learn to make some", mappings.display());
+    }
+    let midi = Arc::new(Midi {
+        mappings_path: mappings,
+        mappings: Mutex::new(loaded),
+        learning: Mutex::new(None),
+    });
+
+    if is_learning {
+        let midi = midi.clone();
+        thread::spawn(move || midi.learn());
+    }
+    let device = device.to_string();
+    thread::spawn(move || {
+        let mut reported = false;
+        loop {
+            match File::open(&device) {
+                Ok(file) => {
+                    println!("Reading MIDI from {}", device);
+                    reported = false;
+                    match midi.read(file, &commands_tx) {
+                        Ok(()) => return,
+                        Err(e) => println!("Lost the MIDI device {}, reopening: {}", device, e),
+                    }
+                },
+                Err(e) => {
+                    if !reported {
+                        println!("Could not open the MIDI device {}, retrying: {}", device, e);
+                        reported = true;
+                    }
+                },
+            }
+            thread::sleep(REOPEN_INTERVAL);
+        }
+    });
+    Ok(())
+}
+
+struct Midi {
+    mappings_path: PathBuf,
+    mappings: Mutex<Vec<Mapping>>,
+    // The target for the next control moved, while learning.
+    learning: Mutex<Option<Target>>,
+}
+
+impl Midi {
+    // Send the commands for the controls moved until the device is lost, or the render loop's
+    // gone, which is Ok.
+    fn read(&self, mut file: File, commands_tx: &mpsc::Sender<Command>) -> Result<(), String> {
+        let mut parser = Parser { status: 0, data: Vec::with_capacity(2) };
+        let mut buffer = [0; 256];
+        loop {
+            let length = file.read(&mut buffer).map_err(|e| e.to_string())?;
+            if length == 0 {
+                return Err("the device closed".to_string());
+            }
+            for &byte in buffer[..length].iter() {
+                let (control, value) = match parser.push(byte) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                if let Some(target) = self.learning.lock().unwrap().take() {
+                    self.map(control, target);
+                    continue;
+                }
+                let command = self.mappings.lock().unwrap().iter()
+                    .find(|x| x.control == control)
+                    .and_then(|x| x.target.command(value));
+                if let Some(command) = command {
+                    if commands_tx.send(command).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    // Take targets typed in, one a line, each waiting for a control to be moved.
+    fn learn(&self) {
+        println!("MIDI learn: type a target, then move the control for it. Targets are brightness, playlist, \
+                  beat, a visualizer, or a parameter like smiley.speed, optionally with its range, like \
+                  smiley.speed 0 4.");
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(x) => x,
+                Err(_) => return,
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            match Target::parse(&words) {
+                Ok(target) => {
+                    println!("Move the MIDI control for {}", target);
+                    *self.learning.lock().unwrap() = Some(target);
+                },
+                Err(e) => println!("Not a MIDI target: {}", e),
+            }
+        }
+    }
+
+    fn map(&self, control: Control, target: Target) {
+        println!("Mapped {} to {}", control, target);
+        let mut mappings = self.mappings.lock().unwrap();
+        mappings.retain(|x| x.control != control);
+        mappings.push(Mapping { control, target });
+        if let Err(e) = save(&self.mappings_path, &mappings) {
+            println!("Could not save the MIDI mappings to {}: {}", self.mappings_path.display(), e);
+        }
+    }
+}
+
+// Channel messages from the bytes of a MIDI stream, with running status, skipping system
+// messages.
+struct Parser {
+    // The last channel message's status byte, or 0 in a system message.
+    status: u8,
+    data: Vec<u8>,
+}
+
+impl Parser {
+    // The control and its value once `byte` finishes a note on or a control change.
+    fn push(&mut self, byte: u8) -> Option<(Control, u8)> {
+        // Real-time messages, like the clock, can come anywhere, even in other messages.
+        if byte >= 0xf8 {
+            return None;
+        }
+        if byte >= 0x80 {
+            self.status = if byte < 0xf0 { byte } else { 0 };
+            self.data.clear();
+            return None;
+        }
+        if self.status == 0 {
+            return None;
+        }
+
+        self.data.push(byte);
+        // Program changes and channel pressure have one data byte, the others two.
+        let length = match self.status & 0xf0 {
+            0xc0 | 0xd0 => 1,
+            _ => 2,
+        };
+        if self.data.len() < length {
+            return None;
+        }
+        let channel = self.status & 0x0f;
+        let (number, value) = (self.data[0], self.data.get(1).cloned().unwrap_or(0));
+        self.data.clear();
+        match self.status & 0xf0 {
+            // Notes on at 0 are notes off, which are left out, the pads' presses being all that
+            // matters.
+            0x90 if value > 0 => Some((Control::Note(channel, number), value)),
+            0xb0 => Some((Control::ControlChange(channel, number), value)),
+            _ => None,
+        }
+    }
+}
+
+fn load(path: &PathBuf) -> Result<Vec<Mapping>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read it: {}", e))?;
+    let mut mappings = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        let error = |e: String| format!("line {}: {}", i + 1, e);
+        if words.len() < 4 {
+            return Err(error("expected a control and its target, like cc 1 7 brightness".to_string()));
+        }
+        let parse = |x: &str, min: u8, max: u8| match x.parse::<u8>() {
+            Ok(x) if x >= min && x <= max => Ok(x),
+            _ => Err(error(format!("expected a number from {} to {}, got {}", min, max, x))),
+        };
+        let channel = parse(words[1], 1, 16)? - 1;
+        let number = parse(words[2], 0, 127)?;
+        let control = match words[0] {
+            "note" => Control::Note(channel, number),
+            "cc" => Control::ControlChange(channel, number),
+            x => return Err(error(format!("expected note or cc, got {}", x))),
+        };
+        let target = Target::parse(&words[3..]).map_err(error)?;
+        mappings.retain(|x: &Mapping| x.control != control);
+        mappings.push(Mapping { control, target });
+    }
+    Ok(mappings)
+}
+
+fn save(path: &PathBuf, mappings: &[Mapping]) -> Result<(), String> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+    }
+    let lines: Vec<String> = mappings.iter().map(|x| format!("{} {}\n", x.control, x.target)).collect();
+    fs::write(path, lines.concat()).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_all(bytes: &[u8]) -> Vec<(Control, u8)> {
+        let mut parser = Parser { status: 0, data: Vec::with_capacity(2) };
+        bytes.iter().filter_map(|&x| parser.push(x)).collect()
+    }
+
+    #[test]
+    fn parses_running_status() {
+        assert_eq!(push_all(&[0xb0, 7, 100, 7, 101, 0x99, 36, 127, 38, 64]),
+                   vec![(Control::ControlChange(0, 7), 100), (Control::ControlChange(0, 7), 101),
+                        (Control::Note(9, 36), 127), (Control::Note(9, 38), 64)]);
+    }
+
+    #[test]
+    fn skips_other_messages() {
+        // Notes off, and on at 0.
+        assert!(push_all(&[0x80, 36, 64, 0x90, 36, 0]).is_empty());
+        // A program change has one data byte, so the next two are running status for it.
+        assert!(push_all(&[0xc0, 5, 6]).is_empty());
+        assert_eq!(push_all(&[0xd0, 5, 0xb1, 1, 2]), vec![(Control::ControlChange(1, 1), 2)]);
+        // System messages drop the running status.
+        assert_eq!(push_all(&[0xb0, 7, 100, 0xf0, 0x7e, 7, 100, 0xf7, 7, 100]),
+                   vec![(Control::ControlChange(0, 7), 100)]);
+    }
+
+    #[test]
+    fn ignores_real_time_messages() {
+        assert_eq!(push_all(&[0xb0, 0xf8, 7, 0xfe, 100]), vec![(Control::ControlChange(0, 7), 100)]);
+    }
+
+    #[test]
+    fn parses_targets() {
+        assert_eq!(Target::parse(&["playlist"]).unwrap().to_string(), "playlist");
+        assert_eq!(Target::parse(&["beat"]).unwrap().to_string(), "beat");
+        assert_eq!(Target::parse(&["smiley"]).unwrap().to_string(), "smiley");
+        assert_eq!(Target::parse(&["brightness"]).unwrap().to_string(), "brightness");
+        assert_eq!(Target::parse(&["smiley.speed", "0", "2"]).unwrap().to_string(), "smiley.speed");
+        assert_eq!(Target::parse(&["smiley.speed", "0", "4"]).unwrap().to_string(), "smiley.speed 0 4");
+        assert!(Target::parse(&[]).is_err());
+        assert!(Target::parse(&["nothing"]).is_err());
+        assert!(Target::parse(&["nothing.speed"]).is_err());
+        assert!(Target::parse(&["smiley", "0", "4"]).is_err());
+        assert!(Target::parse(&["smiley.speed", "0"]).is_err());
+        assert!(Target::parse(&["smiley.speed", "0", "fast"]).is_err());
+    }
+
+    #[test]
+    fn maps_values_to_commands() {
+        match Target::parse(&["smiley.speed", "1", "3"]).unwrap().command(127) {
+            Some(Command::SetParameter(ref name, value)) => assert_eq!((name.as_ref(), value), ("smiley.speed", 3.0)),
+            _ => panic!("expected a parameter"),
+        }
+        match Target::parse(&["brightness"]).unwrap().command(0) {
+            Some(Command::SetParameter(_, value)) => assert_eq!(value, 0.0),
+            _ => panic!("expected a parameter"),
+        }
+        match Target::parse(&["playlist"]).unwrap().command(1) {
+            Some(Command::SelectVisualizer(ref name)) => assert!(name.is_empty()),
+            _ => panic!("expected the playlist"),
+        }
+        assert!(Target::parse(&["smiley"]).unwrap().command(0).is_none());
+        assert!(Target::Beat.command(0).is_none());
+    }
+
+    #[test]
+    fn loads_and_saves_mappings() {
+        let path = std::env::temp_dir().join(format!("midi-mappings-{}.txt", std::process::id()));
+        fs::write(&path, "# Knobs\ncc 1 7 brightness\nnote 10 36 smiley # a pad\n\ncc 1 7 smiley.speed 0 4\n").unwrap();
+        let mappings = load(&path).unwrap();
+        save(&path, &mappings).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "note 10 36 smiley\ncc 1 7 smiley.speed 0 4\n");
+
+        for (text, error) in [("cc 1 7", "line 1: expected a control"), ("cc 0 7 beat", "line 1: expected a number from 1 to 16"),
+                              ("cc 1 128 beat", "line 1: expected a number from 0 to 127"),
+                              ("\npad 1 7 beat", "line 2: expected note or cc"),
+                              ("cc 1 7 nothing", "line 1: unknown visualizer")].iter() {
+            fs::write(&path, text).unwrap();
+            let e = load(&path).err().unwrap_or_default();
+            assert!(e.starts_with(error), "{}: {}", text, e);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,20 @@
+mod automation;
+mod command;
+#[cfg(feature="gpio")]
+mod gpio;
+mod ir;
+mod json;
+mod midi;
+mod mqtt;
+mod osc;
+mod web;
+
+pub use self::automation::{Automation, AutomationSync};
+pub use self::command::{action_command, is_repeating_action, parse_parameter, Command, ACTIONS};
+#[cfg(feature="gpio")]
+pub use self::gpio::listen as listen_gpio;
+pub use self::ir::listen as listen_ir;
+pub use self::midi::listen as listen_midi;
+pub use self::mqtt::connect as connect_mqtt;
+pub use self::osc::listen as listen_osc;
+pub use self::web::{serve as serve_web, SharedStatus};
@@ -0,0 +1,255 @@
+use control::json::{parse_object, quote, Json};
+use control::{Command, SharedStatus};
+use mqtt;
+use screen;
+use std::io::Write;
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use visualizer;
+
+const DISCOVERY_PREFIX: &str = "homeassistant";
+const CLIENT_ID: &str = "rpi-music-visualizer-control";
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+// How often the state's checked for changes to publish.
+const STATE_INTERVAL: Duration = Duration::from_millis(500);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+// The light's effect for the playlist, with one for each visualizer.
+const PLAYLIST_EFFECT: &str = "playlist";
+
+// Joins Home Assistant through the MQTT broker at `address`, "[user:password@]broker[:port]", as a
+// device called `name` with a light, switching the visualizer on and off and dimming it, whose
+// effects are the visualizers, and a select of the `palettes`. They're announced with MQTT
+// discovery and their topics are under `topic`. A lost broker is reconnected to in the background.
+pub fn connect(address: &str,
+               topic: &str,
+               name: &str,
+               status: SharedStatus,
+               palettes: Vec<(String, screen::Palette)>,
+               commands_tx: mpsc::Sender<Command>) {
+    let address = address.trim_start_matches("mqtt://").to_string();
+    // Credentials aren't printed.
+    let broker = address.rsplit('@').next().unwrap_or("").to_string();
+    let topic = topic.trim_end_matches('/');
+    let mut device = Device {
+        topic: topic.to_string(),
+        id: topic.chars().map(|x| if x.is_ascii_alphanumeric() { x } else { '_' }).collect(),
+        name: name.to_string(),
+        status,
+        palettes,
+        commands_tx,
+        on_brightness: 1.0,
+    };
+    let availability = format!("{}/availability", device.topic);
+
+    thread::spawn(move || {
+        let mut reported = false;
+        loop {
+            let will = mqtt::Will { topic: &availability, payload: b"offline" };
+            match mqtt::connect(&address, CLIENT_ID, KEEP_ALIVE, Some(will)) {
+                Ok(stream) => {
+                    println!("Connected to the MQTT broker at {}", broker);
+                    reported = false;
+                    match device.run(stream) {
+                        Ok(()) => return,
+                        Err(e) => println!("Lost the MQTT broker at {}, reconnecting: {}", broker, e),
+                    }
+                },
+                Err(e) => {
+                    if !reported {
+                        println!("Could not connect to the MQTT broker, retrying: {}", e);
+                        reported = true;
+                    }
+                },
+            }
+            thread::sleep(RECONNECT_INTERVAL);
+        }
+    });
+}
+
+struct Device {
+    topic: String,
+    // The topic, fit for Home Assistant's ids.
+    id: String,
+    name: String,
+    status: SharedStatus,
+    palettes: Vec<(String, screen::Palette)>,
+    commands_tx: mpsc::Sender<Command>,
+    // The brightness to go back to when the light's switched on again.
+    on_brightness: f32,
+}
+
+impl Device {
+    // Announce the device, then publish its state as it changes and take the commands sent to it,
+    // until the broker's lost, or the render loop's gone, which is Ok.
+    fn run(&mut self, mut stream: TcpStream) -> Result<(), String> {
+        let mut effects = vec![quote(PLAYLIST_EFFECT)];
+        effects.extend(visualizer::VISUALIZER_NAMES.iter().map(|x| quote(x)));
+        let mut palettes = vec![quote("none")];
+        palettes.extend(self.palettes.iter().map(|x| quote(&x.0)));
+        let availability = quote(&format!("{}/availability", self.topic));
+        let light = format!("{{\"name\":null,\"unique_id\":{},\"schema\":\"json\",\
+                             \"command_topic\":{},\"state_topic\":{},\"availability_topic\":{},\
+                             \"brightness\":true,\"brightness_scale\":255,\"effect\":true,\"effect_list\":[{}],\
+                             \"device\":{{\"identifiers\":[{}],\"name\":{},\"model\":\"rpi-music-visualizer\"}}}}",
+                            quote(&self.id), quote(&format!("{}/set", self.topic)),
+                            quote(&format!("{}/state", self.topic)), availability, effects.join(","),
+                            quote(&self.id), quote(&self.name));
+        let select = format!("{{\"name\":\"Palette\",\"unique_id\":{},\"command_topic\":{},\"state_topic\":{},\
+                              \"availability_topic\":{},\"options\":[{}],\"device\":{{\"identifiers\":[{}]}}}}",
+                             quote(&format!("{}_palette", self.id)), quote(&format!("{}/palette/set", self.topic)),
+                             quote(&format!("{}/palette", self.topic)), availability, palettes.join(","),
+                             quote(&self.id));
+
+        let mut packets = vec![
+            mqtt::publish_packet(&format!("{}/light/{}/config", DISCOVERY_PREFIX, self.id), light.as_bytes(), true),
+            mqtt::publish_packet(&format!("{}/select/{}/palette/config", DISCOVERY_PREFIX, self.id),
+                                 select.as_bytes(), true),
+            mqtt::publish_packet(&format!("{}/availability", self.topic), b"online", true),
+        ];
+        let set_topics = [format!("{}/set", self.topic), format!("{}/palette/set", self.topic)];
+        packets.push(mqtt::subscribe_packet(1, &[&set_topics[0], &set_topics[1]]));
+        for packet in packets {
+            stream.write_all(&packet).map_err(|e| e.to_string())?;
+        }
+
+        let mut reader = stream.try_clone().map_err(|e| e.to_string())?;
+        let (messages_tx, messages_rx) = mpsc::channel();
+        thread::spawn(move || loop {
+            match mqtt::read_packet(&mut reader) {
+                // Acknowledgements of the subscription and pings are all else the broker sends.
+                Ok((kind, body)) => {
+                    if let Some(message) = mqtt::published(kind, &body) {
+                        if messages_tx.send(Ok(message)).is_err() {
+                            return;
+                        }
+                    }
+                },
+                Err(e) => {
+                    let _ = messages_tx.send(Err(e));
+                    return;
+                },
+            }
+        });
+
+        let result = self.serve(&mut stream, messages_rx);
+        // So the reader stops too.
+        let _ = stream.shutdown(Shutdown::Both);
+        result
+    }
+
+    fn serve(&mut self,
+             stream: &mut TcpStream,
+             messages_rx: mpsc::Receiver<Result<(String, Vec<u8>), String>>) -> Result<(), String> {
+        let mut published: Vec<(String, String)> = Vec::new();
+        let mut last_sent = Instant::now();
+        loop {
+            match messages_rx.recv_timeout(STATE_INTERVAL) {
+                Ok(Ok((topic, payload))) => {
+                    let payload = String::from_utf8_lossy(&payload).to_string();
+                    match self.commands(&topic, &payload) {
+                        Ok(commands) => {
+                            for command in commands {
+                                if self.commands_tx.send(command).is_err() {
+                                    return Ok(());
+                                }
+                            }
+                        },
+                        Err(e) => println!("Ignoring MQTT message {} on {}: {}", payload, topic, e),
+                    }
+                },
+                Ok(Err(e)) => return Err(e),
+                Err(mpsc::RecvTimeoutError::Timeout) => (),
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err("the connection closed".to_string()),
+            }
+
+            for (topic, payload) in self.state() {
+                if published.contains(&(topic.clone(), payload.clone())) {
+                    continue;
+                }
+                stream.write_all(&mqtt::publish_packet(&topic, payload.as_bytes(), true)).map_err(|e| e.to_string())?;
+                published.retain(|x| x.0 != topic);
+                published.push((topic, payload));
+                last_sent = Instant::now();
+            }
+            if last_sent.elapsed() > KEEP_ALIVE / 2 {
+                stream.write_all(&mqtt::PING_PACKET).map_err(|e| e.to_string())?;
+                last_sent = Instant::now();
+            }
+        }
+    }
+
+    // The light's state in Home Assistant's JSON schema and the palette, with their topics, once a
+    // frame's rendered.
+    fn state(&mut self) -> Vec<(String, String)> {
+        let status = self.status.lock().unwrap().clone();
+        let (visualizer, rotating) = status.visualizer();
+        if visualizer.is_empty() {
+            return Vec::new();
+        }
+
+        let brightness = status.brightness();
+        let light = if brightness == 0.0 {
+            "{\"state\":\"OFF\"}".to_string()
+        } else {
+            self.on_brightness = brightness;
+            format!("{{\"state\":\"ON\",\"brightness\":{},\"effect\":{}}}",
+                    u32::max(1, (brightness * 255.0).round() as u32),
+                    quote(if rotating { PLAYLIST_EFFECT } else { visualizer }))
+        };
+        vec![
+            (format!("{}/state", self.topic), light),
+            (format!("{}/palette", self.topic), status.palette().to_string()),
+        ]
+    }
+
+    // The commands a message on `topic` stands for.
+    fn commands(&self, topic: &str, payload: &str) -> Result<Vec<Command>, String> {
+        if topic == format!("{}/palette/set", self.topic) {
+            let name = payload.trim();
+            let palette = match self.palettes.iter().find(|x| x.0 == name) {
+                Some(x) => Some(x.1.clone()),
+                None if name == "none" => None,
+                None => return Err(format!("unknown palette {}", name)),
+            };
+            return Ok(vec![Command::SetPalette(name.to_string(), palette)]);
+        }
+        if topic != format!("{}/set", self.topic) {
+            return Err("not a topic of ours".to_string());
+        }
+
+        let fields = parse_object(payload)?;
+        let field = |key: &str| fields.iter().find(|x| x.0 == key).map(|x| &x.1);
+        match field("state") {
+            Some(Json::String(x)) if x == "OFF" => {
+                return Ok(vec![Command::SetParameter("brightness".to_string(), 0.0)]);
+            },
+            Some(Json::String(x)) if x == "ON" => (),
+            None => (),
+            Some(_) => return Err("expected a state of ON or OFF".to_string()),
+        }
+
+        let mut commands = Vec::new();
+        match field("brightness") {
+            Some(&Json::Number(x)) => {
+                let brightness = (x as f32 / 255.0).clamp(0.0, 1.0);
+                commands.push(Command::SetParameter("brightness".to_string(), brightness));
+            },
+            Some(_) => return Err("expected a brightness from 0 to 255".to_string()),
+            None if self.status.lock().unwrap().brightness() == 0.0 => {
+                commands.push(Command::SetParameter("brightness".to_string(), self.on_brightness));
+            },
+            None => (),
+        }
+        match field("effect") {
+            Some(Json::String(x)) if x == PLAYLIST_EFFECT => commands.push(Command::SelectVisualizer(String::new())),
+            Some(Json::String(x)) if visualizer::VISUALIZER_NAMES.contains(&x.as_ref()) => {
+                commands.push(Command::SelectVisualizer(x.clone()));
+            },
+            Some(_) => return Err("unknown effect".to_string()),
+            None => (),
+        }
+        Ok(commands)
+    }
+}
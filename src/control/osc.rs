@@ -0,0 +1,319 @@
+use control::Command;
+use screen;
+use std::net::UdpSocket;
+use std::sync::mpsc;
+use std::thread;
+use visualizer;
+
+const MAX_PACKET: usize = 65536;
+
+// An OSC message's arguments, those of other types being skipped.
+#[derive(Debug)]
+enum Argument {
+    Number(f32),
+    String(String),
+}
+
+// Listens for OSC messages over UDP at `address`, from TouchOSC, VJ software or the like, and
+// sends them on as commands as soon as they arrive, bundles' times being ignored:
+// - /visualizer "<name>", or /visualizer/<name>, to show one, and /playlist to go back to the
+//   playlist.
+// - /parameter/<visualizer>/<parameter> <value> to set one, like /parameter/smiley/speed 2, and
+//   /nudge/<visualizer>/<parameter> <step> to add to it. /brightness <value> and
+//   /nudge/brightness <step> are the brightness's.
+// - /palette "<name>" to pick one of the `palettes`, or none.
+// - /beat to tap a beat.
+// Messages with a 0, which buttons send when they're let go of, are ignored but for setting
+// parameters.
+pub fn listen(address: &str,
+              palettes: Vec<(String, screen::Palette)>,
+              commands_tx: mpsc::Sender<Command>) -> Result<(), String> {
+    let socket = UdpSocket::bind(address).map_err(|e| format!("could not listen on {}: {}", address, e))?;
+    println!("Listening for OSC on {}", address);
+
+    thread::spawn(move || {
+        let mut buffer = vec![0; MAX_PACKET];
+        loop {
+            let length = match socket.recv_from(&mut buffer) {
+                Ok((x, _)) => x,
+                Err(_) => continue,
+            };
+            let mut messages = Vec::new();
+            if let Err(e) = parse_packet(&buffer[..length], &mut messages) {
+                println!("Ignoring an OSC packet: {}", e);
+                continue;
+            }
+            for (address, arguments) in messages {
+                let command = match command(&address, &arguments, &palettes) {
+                    Ok(Some(x)) => x,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        println!("Ignoring OSC message {} {:?}: {}", address, arguments, e);
+                        continue;
+                    },
+                };
+                if commands_tx.send(command).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+// The command a message stands for, or None for buttons being let go of.
+fn command(address: &str,
+           arguments: &[Argument],
+           palettes: &[(String, screen::Palette)]) -> Result<Option<Command>, String> {
+    let number = arguments.iter()
+        .filter_map(|x| match *x {
+            Argument::Number(x) => Some(x),
+            _ => None,
+        })
+        .next();
+    let parameter = |path: &str| if path == "brightness" { path.to_string() } else { path.replace('/', ".") };
+    if let Some(name) = address.strip_prefix("/parameter/") {
+        let value = number.ok_or_else(|| "expected the value".to_string())?;
+        return Ok(Some(Command::SetParameter(parameter(name), value)));
+    }
+    if address == "/brightness" {
+        let value = number.ok_or_else(|| "expected the value".to_string())?;
+        return Ok(Some(Command::SetParameter("brightness".to_string(), value)));
+    }
+    if number == Some(0.0) {
+        return Ok(None);
+    }
+
+    if let Some(name) = address.strip_prefix("/nudge/") {
+        let step = number.ok_or_else(|| "expected the step".to_string())?;
+        return Ok(Some(Command::NudgeParameter(parameter(name), step)));
+    }
+    if let Some(name) = address.strip_prefix("/visualizer/") {
+        return visualizer_command(name).map(Some);
+    }
+    match address {
+        "/visualizer" => visualizer_command(arguments_string(arguments).ok_or_else(|| "expected the name".to_string())?)
+            .map(Some),
+        "/playlist" => Ok(Some(Command::SelectVisualizer(String::new()))),
+        "/palette" => {
+            let name = arguments_string(arguments).ok_or_else(|| "expected the name".to_string())?;
+            match palettes.iter().find(|x| x.0 == name) {
+                Some(x) => Ok(Some(Command::SetPalette(name.to_string(), Some(x.1.clone())))),
+                None if name == "none" => Ok(Some(Command::SetPalette(name.to_string(), None))),
+                None => Err(format!("unknown palette {}", name)),
+            }
+        },
+        "/beat" => Ok(Some(Command::TapBeat)),
+        "/screenshot" => Ok(Some(Command::Screenshot)),
+        _ => Err("unknown address".to_string()),
+    }
+}
+
+fn visualizer_command(name: &str) -> Result<Command, String> {
+    if !visualizer::VISUALIZER_NAMES.contains(&name) {
+        return Err(format!("unknown visualizer {}", name));
+    }
+    Ok(Command::SelectVisualizer(name.to_string()))
+}
+
+fn arguments_string(arguments: &[Argument]) -> Option<&str> {
+    arguments.iter()
+        .filter_map(|x| match *x {
+            Argument::String(ref x) => Some(x.as_ref()),
+            _ => None,
+        })
+        .next()
+}
+
+// The messages in a packet, which may be a message or a bundle of them and other bundles.
+fn parse_packet(packet: &[u8], messages: &mut Vec<(String, Vec<Argument>)>) -> Result<(), String> {
+    if !packet.starts_with(b"#bundle\0") {
+        messages.push(parse_message(packet)?);
+        return Ok(());
+    }
+
+    // After the time tag, each element with its size first.
+    let mut position = 16;
+    while position < packet.len() {
+        let size = read_u32(packet, &mut position)? as usize;
+        let element = packet.get(position..).and_then(|x| x.get(..size)).ok_or_else(|| "truncated bundle".to_string())?;
+        parse_packet(element, messages)?;
+        position += size;
+    }
+    Ok(())
+}
+
+fn parse_message(packet: &[u8]) -> Result<(String, Vec<Argument>), String> {
+    let mut position = 0;
+    let address = read_string(packet, &mut position)?;
+    if !address.starts_with('/') {
+        return Err(format!("expected an address, got '{}'", address));
+    }
+    // Old senders leave out the type tags along with the arguments.
+    if position >= packet.len() {
+        return Ok((address, Vec::new()));
+    }
+
+    let type_tags = read_string(packet, &mut position)?;
+    if !type_tags.starts_with(',') {
+        return Err(format!("expected type tags, got '{}'", type_tags));
+    }
+    let mut arguments = Vec::new();
+    for tag in type_tags.chars().skip(1) {
+        match tag {
+            'i' => arguments.push(Argument::Number(read_u32(packet, &mut position)? as i32 as f32)),
+            'f' => arguments.push(Argument::Number(f32::from_bits(read_u32(packet, &mut position)?))),
+            'h' | 'd' => {
+                let bits = (read_u32(packet, &mut position)? as u64) << 32 | read_u32(packet, &mut position)? as u64;
+                let value = if tag == 'h' { bits as i64 as f32 } else { f64::from_bits(bits) as f32 };
+                arguments.push(Argument::Number(value));
+            },
+            's' | 'S' => arguments.push(Argument::String(read_string(packet, &mut position)?)),
+            'T' => arguments.push(Argument::Number(1.0)),
+            'F' => arguments.push(Argument::Number(0.0)),
+            'b' => {
+                let size = read_u32(packet, &mut position)? as usize;
+                position = position.saturating_add(size.saturating_add(3) / 4 * 4);
+            },
+            // Colors, MIDI messages and characters.
+            'r' | 'm' | 'c' => position = position.saturating_add(4),
+            't' => position = position.saturating_add(8),
+            'N' | 'I' | '[' | ']' => (),
+            _ => return Err(format!("unknown type tag '{}'", tag)),
+        }
+    }
+    if position > packet.len() {
+        return Err("truncated message".to_string());
+    }
+    Ok((address, arguments))
+}
+
+fn read_u32(packet: &[u8], position: &mut usize) -> Result<u32, String> {
+    let bytes = packet.get(*position..).and_then(|x| x.get(..4)).ok_or_else(|| "truncated message".to_string())?;
+    *position += 4;
+    Ok((bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32)
+}
+
+// A string ending in a zero, padded to a multiple of 4 bytes.
+fn read_string(packet: &[u8], position: &mut usize) -> Result<String, String> {
+    let rest = packet.get(*position..).unwrap_or(&[]);
+    let length = rest.iter().position(|&x| x == 0).ok_or_else(|| "unterminated string".to_string())?;
+    let string = String::from_utf8_lossy(&rest[..length]).to_string();
+    *position += (length + 4) / 4 * 4;
+    Ok(string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A string as OSC pads it.
+    fn padded(string: &str) -> Vec<u8> {
+        let mut bytes = string.as_bytes().to_vec();
+        bytes.resize((string.len() + 4) / 4 * 4, 0);
+        bytes
+    }
+
+    fn message(address: &str, type_tags: &str, arguments: &[u8]) -> Vec<u8> {
+        let mut message = padded(address);
+        message.extend_from_slice(&padded(type_tags));
+        message.extend_from_slice(arguments);
+        message
+    }
+
+    fn parse(packet: &[u8]) -> Result<Vec<(String, Vec<Argument>)>, String> {
+        let mut messages = Vec::new();
+        parse_packet(packet, &mut messages).map(|_| messages)
+    }
+
+    #[test]
+    fn reads_arguments() {
+        let mut arguments = vec![0xff, 0xff, 0xff, 0xfe];
+        arguments.extend_from_slice(&0.5f32.to_bits().to_be_bytes());
+        arguments.extend_from_slice(&padded("fire"));
+        arguments.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 3]);
+        let messages = parse(&message("/palette", ",ifsTh", &arguments)).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0, "/palette");
+        assert_eq!(format!("{:?}", messages[0].1),
+                   "[Number(-2.0), Number(0.5), String(\"fire\"), Number(1.0), Number(3.0)]");
+    }
+
+    #[test]
+    fn reads_messages_without_type_tags() {
+        let messages = parse(&padded("/beat")).unwrap();
+        assert_eq!(messages[0].0, "/beat");
+        assert!(messages[0].1.is_empty());
+    }
+
+    #[test]
+    fn reads_nested_bundles() {
+        let first = message("/beat", ",", &[]);
+        let second = message("/playlist", ",", &[]);
+        let mut inner = b"#bundle\0".to_vec();
+        inner.extend_from_slice(&[0; 8]);
+        inner.extend_from_slice(&(second.len() as u32).to_be_bytes());
+        inner.extend_from_slice(&second);
+        let mut bundle = b"#bundle\0".to_vec();
+        bundle.extend_from_slice(&[0; 8]);
+        for element in [first, inner].iter() {
+            bundle.extend_from_slice(&(element.len() as u32).to_be_bytes());
+            bundle.extend_from_slice(element);
+        }
+        let addresses: Vec<String> = parse(&bundle).unwrap().into_iter().map(|x| x.0).collect();
+        assert_eq!(addresses, vec!["/beat", "/playlist"]);
+    }
+
+    #[test]
+    fn rejects_truncated_packets() {
+        assert_eq!(parse(&message("/brightness", ",f", &[0, 0])).err(), Some("truncated message".to_string()));
+        assert_eq!(parse(&message("/palette", ",s", b"fire")).err(), Some("unterminated string".to_string()));
+        assert_eq!(parse(b"/beat").err(), Some("unterminated string".to_string()));
+        assert!(parse(&padded("beat")).unwrap_err().contains("expected an address"));
+        // A blob claiming to be as big as can be leaves the position at its end.
+        assert_eq!(parse(&message("/beat", ",bi", &[0xff, 0xff, 0xff, 0xff])).err(),
+                   Some("truncated message".to_string()));
+        assert_eq!(parse(&message("/beat", ",brs", &[0xff, 0xff, 0xff, 0xff])).err(),
+                   Some("unterminated string".to_string()));
+        let mut bundle = b"#bundle\0".to_vec();
+        bundle.extend_from_slice(&[0; 8]);
+        bundle.extend_from_slice(&[0, 0, 0, 64, 0]);
+        assert_eq!(parse(&bundle).err(), Some("truncated bundle".to_string()));
+    }
+
+    #[test]
+    fn maps_messages_to_commands() {
+        let palettes = vec![("fire".to_string(), screen::Palette::parse("#000000,#ff0000").unwrap())];
+        let command = |address: &str, arguments: Vec<Argument>| command(address, &arguments, &palettes);
+
+        match command("/parameter/smiley/speed", vec![Argument::Number(0.0)]) {
+            Ok(Some(Command::SetParameter(ref name, value))) => assert_eq!((name.as_ref(), value), ("smiley.speed", 0.0)),
+            _ => panic!("expected a parameter"),
+        }
+        match command("/nudge/brightness", vec![Argument::Number(-0.1)]) {
+            Ok(Some(Command::NudgeParameter(ref name, step))) => assert_eq!((name.as_ref(), step), ("brightness", -0.1)),
+            _ => panic!("expected a nudge"),
+        }
+        match command("/visualizer/smiley", Vec::new()) {
+            Ok(Some(Command::SelectVisualizer(ref name))) => assert_eq!(name, "smiley"),
+            _ => panic!("expected a visualizer"),
+        }
+        match command("/palette", vec![Argument::String("fire".to_string())]) {
+            Ok(Some(Command::SetPalette(ref name, Some(_)))) => assert_eq!(name, "fire"),
+            _ => panic!("expected a palette"),
+        }
+        match command("/palette", vec![Argument::String("none".to_string())]) {
+            Ok(Some(Command::SetPalette(ref name, None))) => assert_eq!(name, "none"),
+            _ => panic!("expected no palette"),
+        }
+        match command("/screenshot", Vec::new()) {
+            Ok(Some(Command::Screenshot)) => (),
+            _ => panic!("expected a screenshot"),
+        }
+        // Buttons let go of.
+        assert!(command("/beat", vec![Argument::Number(0.0)]).unwrap().is_none());
+        assert!(command("/visualizer/nothing", Vec::new()).is_err());
+        assert!(command("/palette", vec![Argument::String("sea".to_string())]).is_err());
+        assert!(command("/unknown", Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,538 @@
+use audio;
+use control::json::{parse_object, quote, Json};
+use control::Command;
+use screen;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use visualizer;
+
+const PAGE: &str = include_str!("web.html");
+const TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REQUEST: usize = 8192;
+// How often WebSocket clients are sent the status.
+const TELEMETRY_INTERVAL: Duration = Duration::from_millis(40);
+const LEVEL_BANDS: [audio::FrequencyBand; 3] = [
+    audio::FrequencyBand::new(0.0, 1000.0),
+    audio::FrequencyBand::new(1000.0, 4000.0),
+    audio::FrequencyBand::new(4000.0, 20000.0),
+];
+
+// What the API reports, kept up to date by the render loop.
+#[derive(Clone, Default)]
+pub struct Status {
+    visualizer: String,
+    rotating: bool,
+    // The palette last picked by name, or "none".
+    palette: String,
+    // Every live parameter with its value, brightness first.
+    parameters: Vec<(String, f32)>,
+    is_recording: bool,
+    frames: u64,
+    // Low, mid and high band powers of the last audio frame.
+    levels: [f32; 3],
+    bpm: f32,
+    // Beats heard so far, for the WebSocket to tell clients of each one, however short.
+    beats: u64,
+    is_silent: bool,
+}
+
+pub type SharedStatus = Arc<Mutex<Status>>;
+
+impl Status {
+    pub fn frame_rendered(&mut self, visualizer: &mut visualizer::Visualizer, is_recording: bool) {
+        self.frames += 1;
+        let (name, rotating) = visualizer.selected();
+        if self.visualizer != name {
+            self.visualizer = name.to_string();
+        }
+        self.rotating = rotating;
+        self.parameters = visualizer.parameters();
+        self.is_recording = is_recording;
+    }
+
+    pub fn audio_updated(&mut self, audio_frame: &audio::AudioFrame) {
+        for (level, &band) in self.levels.iter_mut().zip(LEVEL_BANDS.iter()) {
+            *level = audio_frame.band_power(band);
+        }
+        self.bpm = audio_frame.bpm;
+        if audio_frame.is_beat {
+            self.beats += 1;
+        }
+        self.is_silent = audio_frame.is_silent;
+    }
+
+    // Set as the render loop applies SetPalette, and to --palette's at startup.
+    pub fn palette_picked(&mut self, name: &str) {
+        self.palette = name.to_string();
+    }
+
+    // The visualizer showing, and whether it's the playlist's.
+    pub fn visualizer(&self) -> (&str, bool) {
+        (&self.visualizer, self.rotating)
+    }
+
+    pub fn brightness(&self) -> f32 {
+        self.parameters.iter().find(|x| x.0 == "brightness").map_or(1.0, |x| x.1)
+    }
+
+    pub fn palette(&self) -> &str {
+        &self.palette
+    }
+}
+
+// Serves a REST API over HTTP at `address` for scripts to control the show with, and a control
+// panel on top of it for picking the visualizer, brightness and palette from a phone. Requests
+// and answers are JSON, and changes are sent on as commands. `palettes` are the names and colors
+// it offers, besides none. The endpoints are listed in the README. WebSocket clients of
+// /api/socket are pushed the status as it changes instead, and send their changes as messages.
+pub fn serve(address: &str,
+             status: SharedStatus,
+             palettes: Vec<(String, screen::Palette)>,
+             commands_tx: mpsc::Sender<Command>) -> Result<(), String> {
+    let listener = TcpListener::bind(address).map_err(|e| format!("could not listen on {}: {}", address, e))?;
+    println!("Serving the control panel on http://{}/ and the API on http://{}/api/", address, address);
+
+    let panel = Arc::new(Panel {
+        status,
+        palettes,
+        last_frames: Mutex::new((0, Instant::now(), 0.0)),
+        commands_tx: Mutex::new(commands_tx),
+    });
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            let panel = panel.clone();
+            thread::spawn(move || {
+                // The client went away mid-request.
+                let _ = Panel::answer(&panel, stream);
+            });
+        }
+    });
+    Ok(())
+}
+
+struct Panel {
+    status: SharedStatus,
+    palettes: Vec<(String, screen::Palette)>,
+    // Frames rendered when the status was last asked for, and when, for the frame rate since.
+    last_frames: Mutex<(u64, Instant, f32)>,
+    commands_tx: Mutex<mpsc::Sender<Command>>,
+}
+
+// Why a request wasn't done, with the HTTP status to answer it with.
+struct Failure {
+    code: &'static str,
+    message: String,
+}
+
+fn failure(code: &'static str, message: String) -> Failure {
+    Failure { code, message }
+}
+
+impl Panel {
+    fn answer(panel: &Arc<Panel>, mut stream: TcpStream) -> Result<(), String> {
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        let header_length = loop {
+            if let Some(i) = request.windows(4).position(|x| x == b"\r\n\r\n") {
+                break i + 4;
+            }
+            if request.len() > MAX_REQUEST {
+                return Err("request too long".to_string());
+            }
+            let length = stream.read(&mut buffer).map_err(|e| e.to_string())?;
+            if length == 0 {
+                return Ok(());
+            }
+            request.extend_from_slice(&buffer[..length]);
+        };
+
+        let header = String::from_utf8_lossy(&request[..header_length]).to_string();
+        let body_length = header.lines()
+            .filter_map(|x| {
+                let mut parts = x.splitn(2, ':');
+                match (parts.next(), parts.next()) {
+                    (Some(name), Some(value)) if name.trim().eq_ignore_ascii_case("content-length") =>
+                        value.trim().parse::<usize>().ok(),
+                    _ => None,
+                }
+            })
+            .next()
+            .unwrap_or(0);
+        if body_length > MAX_REQUEST {
+            return Err("request too long".to_string());
+        }
+        while request.len() < header_length + body_length {
+            let length = stream.read(&mut buffer).map_err(|e| e.to_string())?;
+            if length == 0 {
+                return Ok(());
+            }
+            request.extend_from_slice(&buffer[..length]);
+        }
+        let body = String::from_utf8_lossy(&request[header_length..header_length + body_length]);
+
+        let mut words = header.lines().next().unwrap_or("").split_whitespace();
+        let method = words.next().unwrap_or("");
+        let target = words.next().unwrap_or("");
+        let path = target.split('?').next().unwrap_or("");
+        let websocket_key = header.lines()
+            .filter_map(|x| {
+                let mut parts = x.splitn(2, ':');
+                match (parts.next(), parts.next()) {
+                    (Some(name), Some(value)) if name.trim().eq_ignore_ascii_case("sec-websocket-key") =>
+                        Some(value.trim().to_string()),
+                    _ => None,
+                }
+            })
+            .next();
+        if let (Some(key), "GET", "/api/socket") = (websocket_key, method, path) {
+            return Panel::socket(panel, stream, &key);
+        }
+
+        let (code, content_type, body) = if method == "GET" && path == "/" {
+            ("200 OK", "text/html; charset=utf-8", PAGE.to_string())
+        } else {
+            match panel.api(method, path, &body) {
+                Ok(Some(json)) => ("200 OK", "application/json", json),
+                Ok(None) => ("204 No Content", "application/json", String::new()),
+                Err(e) => (e.code, "application/json", format!("{{\"error\":{}}}", quote(&e.message))),
+            }
+        };
+        let response = format!("HTTP/1.0 {}\r\n\
+                                Content-Type: {}\r\n\
+                                Content-Length: {}\r\n\
+                                Cache-Control: no-store\r\n\
+                                Connection: close\r\n\r\n{}", code, content_type, body.len(), body);
+        stream.write_all(response.as_bytes()).map_err(|e| e.to_string())
+    }
+
+    // Send the status to a WebSocket client every TELEMETRY_INTERVAL, with whether there's been a
+    // beat since the last, and apply the control messages it sends, answering mistakes with
+    // {"error": "..."} messages.
+    fn socket(panel: &Arc<Panel>, mut stream: TcpStream, key: &str) -> Result<(), String> {
+        screen::accept_websocket(&mut stream, key)?;
+        // Clients may be quiet for as long as they like.
+        stream.set_read_timeout(None).map_err(|e| e.to_string())?;
+        let mut reader = stream.try_clone().map_err(|e| e.to_string())?;
+
+        let (replies_tx, replies_rx) = mpsc::channel::<Vec<u8>>();
+        let reading_panel = panel.clone();
+        thread::spawn(move || {
+            while let Ok((opcode, payload)) = screen::read_websocket_frame(&mut reader, MAX_REQUEST) {
+                let reply = match opcode {
+                    screen::OPCODE_TEXT => match reading_panel.control(&String::from_utf8_lossy(&payload)) {
+                        Ok(()) => continue,
+                        Err(e) => format!("{{\"error\":{}}}", quote(&e.message)),
+                    },
+                    screen::OPCODE_PING => {
+                        let _ = replies_tx.send(screen::websocket_frame(screen::OPCODE_PONG, &payload));
+                        continue;
+                    },
+                    // The writer stops once this is sent.
+                    screen::OPCODE_CLOSE => {
+                        let _ = replies_tx.send(screen::websocket_frame(screen::OPCODE_CLOSE, &payload));
+                        return;
+                    },
+                    _ => continue,
+                };
+                if replies_tx.send(screen::websocket_frame(screen::OPCODE_TEXT, reply.as_bytes())).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let result = panel.push_telemetry(&mut stream, replies_rx);
+        // So the reader stops too.
+        let _ = stream.shutdown(Shutdown::Both);
+        result
+    }
+
+    // Write the status to `stream` every TELEMETRY_INTERVAL, and the replies to what it sent as
+    // they come, until there are none to come.
+    fn push_telemetry(&self, stream: &mut TcpStream, replies_rx: mpsc::Receiver<Vec<u8>>) -> Result<(), String> {
+        let mut beats = self.status.lock().unwrap().beats;
+        let mut next_telemetry = Instant::now();
+        loop {
+            let wait = next_telemetry.saturating_duration_since(Instant::now());
+            match replies_rx.recv_timeout(wait) {
+                Ok(reply) => {
+                    stream.write_all(&reply).map_err(|e| e.to_string())?;
+                    continue;
+                },
+                Err(mpsc::RecvTimeoutError::Timeout) => (),
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            let status = self.status.lock().unwrap().clone();
+            let telemetry = format!("{{{},\"beat\":{}}}", self.status_fields(&status), status.beats != beats);
+            beats = status.beats;
+            stream.write_all(&screen::websocket_frame(screen::OPCODE_TEXT, telemetry.as_bytes()))
+                .map_err(|e| e.to_string())?;
+            next_telemetry = Instant::now() + TELEMETRY_INTERVAL;
+        }
+    }
+
+    // Answer a request to the API at `path`, with the JSON asked for, or nothing for changes.
+    fn api(&self, method: &str, path: &str, body: &str) -> Result<Option<String>, Failure> {
+        let parameter = path.strip_prefix("/api/parameters/");
+        let route = match parameter {
+            Some(_) => "/api/parameters/<name>",
+            None => path,
+        };
+
+        let command = match (method, route) {
+            ("GET", "/api/status") => return Ok(Some(self.status_json())),
+            ("GET", "/api/visualizers") => {
+                let status = self.status.lock().unwrap().clone();
+                let names: Vec<String> = visualizer::VISUALIZER_NAMES.iter().map(|x| quote(x)).collect();
+                return Ok(Some(format!("{{\"visualizers\":[{}],\"visualizer\":{},\"rotating\":{}}}",
+                                       names.join(","), quote(&status.visualizer), status.rotating)));
+            },
+            ("PUT", "/api/visualizer") => self.visualizer_command(string_field(body, "name")?)?,
+            // Back to the playlist.
+            ("DELETE", "/api/visualizer") => Command::SelectVisualizer(String::new()),
+            ("GET", "/api/parameters") => {
+                let status = self.status.lock().unwrap().clone();
+                let parameters: Vec<String> = status.parameters.iter()
+                    .map(|&(ref name, value)| format!("{}:{}", quote(name), value))
+                    .collect();
+                return Ok(Some(format!("{{{}}}", parameters.join(","))));
+            },
+            ("GET", "/api/parameters/<name>") => {
+                let name = parameter.unwrap_or("");
+                let status = self.status.lock().unwrap().clone();
+                return match status.parameters.iter().find(|x| x.0 == name) {
+                    Some(&(_, value)) => Ok(Some(format!("{{\"value\":{}}}", value))),
+                    None => Err(failure("404 Not Found", format!("unknown parameter {}", name))),
+                };
+            },
+            ("PUT", "/api/parameters/<name>") => self.parameter_command(parameter.unwrap_or(""), body)?,
+            ("PUT", "/api/brightness") => self.parameter_command("brightness", body)?,
+            ("GET", "/api/palettes") => {
+                let names: Vec<String> = self.palettes.iter().map(|x| quote(&x.0)).collect();
+                return Ok(Some(format!("{{\"palettes\":[{}],\"palette\":{}}}",
+                                       names.join(","), quote(&self.status.lock().unwrap().palette))));
+            },
+            ("PUT", "/api/palette") => self.palette_command(string_field(body, "name")?)?,
+            ("POST", "/api/recording") => Command::StartVideoRecording,
+            ("DELETE", "/api/recording") => Command::StopVideoRecording,
+            ("POST", "/api/screenshot") => Command::Screenshot,
+            (_, "/api/status") | (_, "/api/visualizers") | (_, "/api/visualizer") | (_, "/api/parameters") |
+            (_, "/api/parameters/<name>") | (_, "/api/brightness") | (_, "/api/palettes") | (_, "/api/palette") |
+            (_, "/api/recording") | (_, "/api/screenshot") => return Err(failure("405 Method Not Allowed",
+                                                        format!("{} isn't allowed on {}", method, path))),
+            (_, "/api/socket") => return Err(failure("400 Bad Request", "expected a WebSocket upgrade".to_string())),
+            _ => return Err(failure("404 Not Found", format!("nothing at {}", path))),
+        };
+        self.send(command)?;
+        Ok(None)
+    }
+
+    // Answer a control message from a WebSocket, like {"action": "brightness", "value": 0.5}.
+    fn control(&self, message: &str) -> Result<(), Failure> {
+        let command = match string_field(message, "action")?.as_ref() {
+            "visualizer" => match string_field(message, "name")?.as_ref() {
+                "" => Command::SelectVisualizer(String::new()),
+                name => self.visualizer_command(name.to_string())?,
+            },
+            "parameter" => self.parameter_command(&string_field(message, "name")?, message)?,
+            "brightness" => self.parameter_command("brightness", message)?,
+            "palette" => self.palette_command(string_field(message, "name")?)?,
+            "start_recording" => Command::StartVideoRecording,
+            "stop_recording" => Command::StopVideoRecording,
+            "screenshot" => Command::Screenshot,
+            action => return Err(invalid_body(format!("unknown action {}", action))),
+        };
+        self.send(command)
+    }
+
+    fn send(&self, command: Command) -> Result<(), Failure> {
+        self.commands_tx.lock().unwrap().send(command)
+            .map_err(|e| failure("503 Service Unavailable", e.to_string()))
+    }
+
+    fn visualizer_command(&self, name: String) -> Result<Command, Failure> {
+        if !visualizer::VISUALIZER_NAMES.contains(&name.as_ref()) {
+            return Err(failure("404 Not Found", format!("unknown visualizer {}", name)));
+        }
+        Ok(Command::SelectVisualizer(name))
+    }
+
+    // Set the parameter `name` to the number `body` has as its value.
+    fn parameter_command(&self, name: &str, body: &str) -> Result<Command, Failure> {
+        if !self.status.lock().unwrap().parameters.iter().any(|x| x.0 == name) {
+            return Err(failure("404 Not Found", format!("unknown parameter {}", name)));
+        }
+        let value = match parse_object(body).map_err(invalid_body)?.into_iter().find(|x| x.0 == "value") {
+            Some((_, Json::Number(x))) => x as f32,
+            _ => return Err(invalid_body("expected a number, like {\"value\": 0.5}".to_string())),
+        };
+        if name == "brightness" && !(0.0..=1.0).contains(&value) {
+            return Err(invalid_body(format!("invalid brightness {}, expected 0 to 1", value)));
+        }
+        Ok(Command::SetParameter(name.to_string(), value))
+    }
+
+    fn palette_command(&self, name: String) -> Result<Command, Failure> {
+        let palette = match self.palettes.iter().find(|x| x.0 == name) {
+            Some(x) => Some(x.1.clone()),
+            None if name == "none" => None,
+            None => return Err(failure("404 Not Found", format!("unknown palette {}", name))),
+        };
+        Ok(Command::SetPalette(name, palette))
+    }
+
+    fn status_json(&self) -> String {
+        let status = self.status.lock().unwrap().clone();
+        format!("{{{}}}", self.status_fields(&status))
+    }
+
+    // The fields of the status as JSON, without the braces around them.
+    fn status_fields(&self, status: &Status) -> String {
+        let fps = {
+            let mut last = self.last_frames.lock().unwrap();
+            let elapsed = last.1.elapsed().as_secs_f32();
+            // Asked again too soon to tell, so the last rate holds.
+            if elapsed > 0.5 {
+                let rate = (status.frames - u64::min(last.0, status.frames)) as f32 / elapsed;
+                *last = (status.frames, Instant::now(), rate);
+            }
+            last.2
+        };
+
+        format!("\"visualizer\":{},\"rotating\":{},\"brightness\":{},\"palette\":{},\"recording\":{},\
+                 \"fps\":{:.1},\"levels\":[{},{},{}],\"bpm\":{},\"silent\":{}",
+                quote(&status.visualizer), status.rotating, status.brightness(),
+                quote(&status.palette), status.is_recording, fps,
+                status.levels[0], status.levels[1], status.levels[2], status.bpm, status.is_silent)
+    }
+}
+
+fn invalid_body(message: String) -> Failure {
+    failure("400 Bad Request", message)
+}
+
+// The string `key` is set to in the JSON object `body`.
+fn string_field(body: &str, key: &str) -> Result<String, Failure> {
+    match parse_object(body).map_err(invalid_body)?.into_iter().find(|x| x.0 == key) {
+        Some((_, Json::String(x))) => Ok(x),
+        _ => Err(invalid_body(format!("expected a string, like {{\"{}\": \"...\"}}", key))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn panel() -> (Panel, mpsc::Receiver<Command>) {
+        let (commands_tx, commands_rx) = mpsc::channel();
+        let status = Status {
+            visualizer: "smiley".to_string(),
+            palette: "none".to_string(),
+            parameters: vec![("brightness".to_string(), 0.5), ("smiley.speed".to_string(), 1.0)],
+            ..Status::default()
+        };
+        let panel = Panel {
+            status: Arc::new(Mutex::new(status)),
+            palettes: vec![("fire".to_string(), screen::Palette::parse("#000000,#ff0000").unwrap())],
+            last_frames: Mutex::new((0, Instant::now(), 0.0)),
+            commands_tx: Mutex::new(commands_tx),
+        };
+        (panel, commands_rx)
+    }
+
+    #[test]
+    fn takes_screenshots() {
+        let (panel, commands_rx) = panel();
+        assert!(matches!(panel.api("POST", "/api/screenshot", ""), Ok(None)));
+        assert!(panel.control("{\"action\": \"screenshot\"}").is_ok());
+        for _ in 0..2 {
+            match commands_rx.try_recv() {
+                Ok(Command::Screenshot) => (),
+                _ => panic!("expected a screenshot"),
+            }
+        }
+        assert_eq!(panel.api("GET", "/api/screenshot", "").err().map(|x| x.code), Some("405 Method Not Allowed"));
+    }
+
+    // The status code `api` answers with, 200 for JSON and 204 for a change sent on.
+    fn code(panel: &Panel, method: &str, path: &str, body: &str) -> &'static str {
+        match panel.api(method, path, body) {
+            Ok(Some(_)) => "200 OK",
+            Ok(None) => "204 No Content",
+            Err(e) => e.code,
+        }
+    }
+
+    #[test]
+    fn answers_queries() {
+        let (panel, _commands_rx) = panel();
+        assert!(panel.api("GET", "/api/status", "").ok().flatten().unwrap().contains("\"visualizer\":\"smiley\""));
+        assert_eq!(panel.api("GET", "/api/parameters/smiley.speed", "").ok().flatten(), Some("{\"value\":1}".to_string()));
+        assert_eq!(panel.api("GET", "/api/parameters", "").ok().flatten(),
+                   Some("{\"brightness\":0.5,\"smiley.speed\":1}".to_string()));
+        assert_eq!(panel.api("GET", "/api/palettes", "").ok().flatten(),
+                   Some("{\"palettes\":[\"fire\"],\"palette\":\"none\"}".to_string()));
+        assert!(panel.api("GET", "/api/visualizers", "").ok().flatten().unwrap().contains("\"smiley\""));
+    }
+
+    #[test]
+    fn sends_changes_as_commands() {
+        let (panel, commands_rx) = panel();
+        assert_eq!(code(&panel, "PUT", "/api/visualizer", "{\"name\": \"equalizer\"}"), "204 No Content");
+        match commands_rx.try_recv() {
+            Ok(Command::SelectVisualizer(ref name)) => assert_eq!(name, "equalizer"),
+            _ => panic!("expected a visualizer"),
+        }
+        assert_eq!(code(&panel, "PUT", "/api/parameters/smiley.speed", "{\"value\": 2}"), "204 No Content");
+        match commands_rx.try_recv() {
+            Ok(Command::SetParameter(ref name, value)) => assert_eq!((name.as_ref(), value), ("smiley.speed", 2.0)),
+            _ => panic!("expected a parameter"),
+        }
+        assert_eq!(code(&panel, "PUT", "/api/palette", "{\"name\": \"fire\"}"), "204 No Content");
+        match commands_rx.try_recv() {
+            Ok(Command::SetPalette(ref name, Some(_))) => assert_eq!(name, "fire"),
+            _ => panic!("expected a palette"),
+        }
+        assert_eq!(code(&panel, "DELETE", "/api/visualizer", ""), "204 No Content");
+        match commands_rx.try_recv() {
+            Ok(Command::SelectVisualizer(ref name)) => assert_eq!(name, ""),
+            _ => panic!("expected the playlist"),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_routes_and_methods() {
+        let (panel, commands_rx) = panel();
+        assert_eq!(code(&panel, "GET", "/api/nothing", ""), "404 Not Found");
+        assert_eq!(code(&panel, "GET", "/nothing", ""), "404 Not Found");
+        assert_eq!(code(&panel, "GET", "/api/parameters/smiley.nothing", ""), "404 Not Found");
+        assert_eq!(code(&panel, "PUT", "/api/visualizer", "{\"name\": \"nothing\"}"), "404 Not Found");
+        assert_eq!(code(&panel, "PUT", "/api/palette", "{\"name\": \"sea\"}"), "404 Not Found");
+        assert_eq!(code(&panel, "DELETE", "/api/status", ""), "405 Method Not Allowed");
+        assert_eq!(code(&panel, "POST", "/api/parameters/smiley.speed", ""), "405 Method Not Allowed");
+        assert_eq!(code(&panel, "GET", "/api/socket", ""), "400 Bad Request");
+        assert!(commands_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_bodies() {
+        let (panel, commands_rx) = panel();
+        assert_eq!(code(&panel, "PUT", "/api/visualizer", "{\"name\": "), "400 Bad Request");
+        assert_eq!(code(&panel, "PUT", "/api/visualizer", "{\"name\": 1}"), "400 Bad Request");
+        assert_eq!(code(&panel, "PUT", "/api/brightness", "{\"value\": \"high\"}"), "400 Bad Request");
+        assert_eq!(code(&panel, "PUT", "/api/brightness", "{\"value\": 2}"), "400 Bad Request");
+        assert_eq!(code(&panel, "PUT", "/api/palette", "not json"), "400 Bad Request");
+        assert!(panel.control("{\"action\": \"nothing\"}").is_err());
+        assert!(commands_rx.try_recv().is_err());
+    }
+}
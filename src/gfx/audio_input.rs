@@ -0,0 +1,82 @@
+use audio;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+// Taps further apart than this start counting the tempo again, and it's left to the audio once
+// they're over.
+const TAP_TIMEOUT: Duration = Duration::from_secs(2);
+// The tempo is the average of the last taps' intervals.
+const MAX_TAPS: usize = 8;
+
+// The latest audio, which fixed updates keep using until a newer frame arrives, with any beats
+// tapped by hand.
+pub struct AudioInput {
+    latest: Option<audio::AudioFrame>,
+    is_tapped: bool,
+    taps: Vec<Instant>,
+}
+
+impl AudioInput {
+    pub fn new() -> AudioInput {
+        AudioInput {
+            latest: None,
+            is_tapped: false,
+            taps: Vec::new(),
+        }
+    }
+
+    // Make the next update a beat, and keep time with the taps before it.
+    pub fn tap_beat(&mut self) {
+        let now = Instant::now();
+        if self.taps.last().is_some_and(|x| now.duration_since(*x) > TAP_TIMEOUT) {
+            self.taps.clear();
+        }
+        if self.taps.len() == MAX_TAPS {
+            self.taps.remove(0);
+        }
+        self.taps.push(now);
+        self.is_tapped = true;
+    }
+
+    // Beats per minute of the taps, while they're in a row.
+    fn tapped_bpm(&self) -> Option<f32> {
+        match (self.taps.first(), self.taps.last()) {
+            (Some(first), Some(last)) if self.taps.len() > 1 && last.elapsed() <= TAP_TIMEOUT => {
+                let interval = last.duration_since(*first).as_secs_f32() / (self.taps.len() - 1) as f32;
+                Some(60.0 / interval)
+            },
+            _ => None,
+        }
+    }
+
+    // Take the audio frames that arrived since the last call, without waiting for any, and
+    // return how many there were.
+    pub fn receive(&mut self, audio_rx: &mpsc::Receiver<audio::AudioFrame>) -> u32 {
+        let mut received = 0;
+        while let Ok(mut audio_frame) = audio_rx.try_recv() {
+            received += 1;
+            // Skipped frames still count their beats.
+            if let Some(ref previous) = self.latest {
+                audio_frame.is_beat |= previous.is_beat;
+            }
+            self.latest = Some(audio_frame);
+        }
+
+        received
+    }
+
+    // The audio for the next update, once any has arrived.
+    pub fn next_update(&mut self) -> Option<audio::AudioFrame> {
+        let bpm = self.tapped_bpm();
+        let latest = self.latest.as_mut()?;
+        let mut audio_frame = latest.clone();
+        audio_frame.is_beat |= self.is_tapped;
+        if let Some(bpm) = bpm {
+            audio_frame.bpm = bpm;
+        }
+        // A beat is only seen by the first update after it.
+        latest.is_beat = false;
+        self.is_tapped = false;
+        Some(audio_frame)
+    }
+}
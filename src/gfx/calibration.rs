@@ -0,0 +1,101 @@
+use std::io::{self, BufRead};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use audio;
+use gfx::audio_input::AudioInput;
+use gfx::frame_timer::FrameTimer;
+use gfx::gfx::Options;
+use screen;
+
+// How much of a flash is left each update after a beat, so it's gone well before the next.
+const FLASH_FADE: f32 = 0.5;
+
+// Flashes the screens taking pixels white on each beat heard, instead of the visualizer, until
+// the process exits. Latencies typed on stdin, a line like --screen_latency's in --screen's
+// order or +10 or -10 to move every screen's, hold the flashes back until they land on the
+// beats heard from the speakers. The latencies to pass next time are printed on each change.
+pub fn run(mut screens: Vec<Box<dyn screen::Screen>>,
+           audio_rx: mpsc::Receiver<audio::AudioFrame>,
+           options: Options) {
+    let output_gamma = if screens.iter().all(|x| x.is_linear()) { 1.0 } else { options.gamma };
+    for screen in screens.iter_mut() {
+        screen.set_output_gamma(output_gamma);
+    }
+    if screens.iter().any(|x| !x.renders_pixels()) {
+        println!("Leaving out the screens that can only be drawn with GL");
+    }
+    let mut latencies = options.latency_calibration.clone().unwrap_or_default();
+    latencies.resize(screens.len(), 0);
+    println!("Flashing on each beat. Type the screens' latencies in milliseconds, like 0,120, or +10 or -10 \
+              to move them all, until each screen's flashes land on the beats you hear");
+
+    let (lines_tx, lines_rx) = mpsc::channel::<String>();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            match line {
+                Ok(x) => if lines_tx.send(x).is_err() {
+                    break;
+                },
+                Err(_) => break,
+            }
+        }
+    });
+
+    let (width, height) = (options.width as usize, options.height as usize);
+    let mut frame_timer = FrameTimer::new(options.fps);
+    let mut audio_input = AudioInput::new();
+    let mut flash = 0.0;
+    loop {
+        while let Ok(line) = lines_rx.try_recv() {
+            if let Err(e) = adjust(&mut latencies, &line) {
+                println!("{}", e);
+                continue;
+            }
+            for (screen, &latency) in screens.iter_mut().zip(latencies.iter()) {
+                screen.set_latency(Duration::from_millis(latency as u64));
+            }
+            let values: Vec<String> = latencies.iter().map(|x| x.to_string()).collect();
+            println!("--screen_latency {}", values.join(","));
+        }
+
+        audio_input.receive(&audio_rx);
+        for _ in 0..frame_timer.due_updates() {
+            match audio_input.next_update() {
+                Some(ref x) if x.is_beat => flash = 1.0,
+                Some(_) => flash *= FLASH_FADE,
+                None => break,
+            }
+        }
+
+        let pixels = vec![(flash * 255.0 + 0.5) as u8; 3 * width * height];
+        screen::show_on_each(&mut screens, |screen| if screen.renders_pixels() {
+            screen.render_from_pixels(&pixels, options.width, options.height);
+        });
+        frame_timer.wait_for_next_frame();
+    }
+}
+
+// Set the latencies from a `line` like --screen_latency's, leaving out the screens it stops
+// before, or move them all by a signed number of milliseconds.
+fn adjust(latencies: &mut [u32], line: &str) -> Result<(), String> {
+    let line = line.trim();
+    let most = screen::MAX_LATENCY.as_millis() as i64;
+    if line.starts_with('+') || line.starts_with('-') {
+        let step = line.parse::<i64>().map_err(|e| format!("Invalid latency change {}: {}", line, e))?;
+        for latency in latencies.iter_mut() {
+            *latency = i64::max(0, i64::min(most, *latency as i64 + step)) as u32;
+        }
+        return Ok(());
+    }
+
+    let values = line.split(',')
+        .map(|x| x.trim().parse::<u32>().map_err(|e| format!("Invalid latencies {}: {}", line, e)))
+        .collect::<Result<Vec<u32>, String>>()?;
+    for (latency, value) in latencies.iter_mut().zip(values) {
+        *latency = u32::min(most as u32, value);
+    }
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use gfx::egl::{self, Egl, EglContext, EglDisplay, EglSharedContext, EglSurface};
+use libloading::Library;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::sync::Arc;
+
+// Where the legacy Broadcom driver keeps its libraries, under both the names of newer firmware
+// and older ones.
+const BCM_HOST_NAMES: [&str; 1] = ["/opt/vc/lib/libbcm_host.so"];
+const EGL_NAMES: [&str; 2] = ["/opt/vc/lib/libbrcmEGL.so", "/opt/vc/lib/libEGL.so"];
+const GLES_NAMES: [&str; 2] = ["/opt/vc/lib/libbrcmGLESv2.so", "/opt/vc/lib/libGLESv2.so"];
+
+// The main LCD/HDMI output.
+const DISPLAY_NUMBER: u32 = 0;
+const LAYER: i32 = 0;
+
+#[repr(C)]
+struct VcRect {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+// EGL_DISPMANX_WINDOW_T, the native window EGL draws into.
+#[repr(C)]
+struct DispmanxWindow {
+    element: u32,
+    width: i32,
+    height: i32,
+}
+
+// An OpenGL ES 2 context on the Broadcom EGL driver of the Pi Zero/1/2, drawing into a
+// DispmanX element in the corner of the screen. glutin can't create a context with this
+// driver at all. The element is removed and the display closed when dropped, as they'd
+// otherwise stay on the screen after the process exits.
+pub struct DispmanxContext {
+    egl: Arc<Egl>,
+    display: EglDisplay,
+    surface: EglSurface,
+    context: EglContext,
+    dispman_display: u32,
+    // Boxed as EGL holds on to its address for as long as the surface lives.
+    window: Box<DispmanxWindow>,
+    bcm_host: Library,
+}
+
+impl DispmanxContext {
+    // Swaps wait for the display's vertical blank when `vsync` is set.
+    pub fn new(width: i32, height: i32, vsync: bool) -> Result<DispmanxContext, String> {
+        let bcm_host = egl::load_library(&BCM_HOST_NAMES)?;
+        let egl = Arc::new(Egl::load(&EGL_NAMES, &GLES_NAMES)?);
+
+        unsafe {
+            let bcm_host_init: unsafe extern "C" fn() = *egl::symbol(&bcm_host, b"bcm_host_init\0")?;
+            let display_open: unsafe extern "C" fn(u32) -> u32 =
+                *egl::symbol(&bcm_host, b"vc_dispmanx_display_open\0")?;
+            let update_start: unsafe extern "C" fn(i32) -> u32 =
+                *egl::symbol(&bcm_host, b"vc_dispmanx_update_start\0")?;
+            let element_add: unsafe extern "C" fn(
+                u32, u32, i32, *const VcRect, u32, *const VcRect, u32,
+                *mut c_void, *mut c_void, u32,
+            ) -> u32 = *egl::symbol(&bcm_host, b"vc_dispmanx_element_add\0")?;
+            let update_submit_sync: unsafe extern "C" fn(u32) -> c_int =
+                *egl::symbol(&bcm_host, b"vc_dispmanx_update_submit_sync\0")?;
+
+            bcm_host_init();
+
+            let dispman_display = display_open(DISPLAY_NUMBER);
+            if dispman_display == 0 {
+                return Err("could not open the DispmanX display".to_string());
+            }
+
+            // Cleans up after whichever step below fails.
+            let mut context = DispmanxContext {
+                egl,
+                display: ptr::null_mut(),
+                surface: ptr::null_mut(),
+                context: ptr::null_mut(),
+                dispman_display,
+                window: Box::new(DispmanxWindow { element: 0, width, height }),
+                bcm_host,
+            };
+
+            let update = update_start(0);
+            let destination = VcRect { x: 0, y: 0, width, height };
+            // The source rectangle is in 16.16 fixed point.
+            let source = VcRect { x: 0, y: 0, width: width << 16, height: height << 16 };
+            context.window.element = element_add(
+                update, dispman_display, LAYER, &destination, 0, &source, 0,
+                ptr::null_mut(), ptr::null_mut(), 0,
+            );
+            update_submit_sync(update);
+            if context.window.element == 0 {
+                return Err("could not add a DispmanX element".to_string());
+            }
+
+            context.display = context.egl.display(ptr::null_mut())?;
+            let (config, egl_context) = context.egl.create_context(context.display, egl::EGL_WINDOW_BIT, ptr::null_mut())?;
+            context.context = egl_context;
+            context.surface = context.egl.create_window_surface(
+                context.display, config, &mut *context.window as *mut DispmanxWindow as *mut c_void,
+            )?;
+            context.egl.make_current(context.display, context.surface, context.context)?;
+            if let Err(e) = context.egl.swap_interval(context.display, if vsync { 1 } else { 0 }) {
+                println!("Keeping the driver's vsync setting, {}", e);
+            }
+
+            Ok(context)
+        }
+    }
+
+    pub fn get_proc_address(&self, name: &str) -> *const c_void {
+        self.egl.get_proc_address(name)
+    }
+
+    // A context sharing this one's textures, for uploading from another thread.
+    pub fn shared_context(&self) -> Result<EglSharedContext, String> {
+        EglSharedContext::new(&self.egl, self.display, self.context)
+    }
+
+    // Show what was drawn into the default framebuffer.
+    pub fn swap_buffers(&self) {
+        self.egl.swap_buffers(self.display, self.surface);
+    }
+
+    // Remove the element, if it was added, then close the display it was on.
+    unsafe fn close_display(&self) -> Result<(), String> {
+        let update_start: unsafe extern "C" fn(i32) -> u32 =
+            *egl::symbol(&self.bcm_host, b"vc_dispmanx_update_start\0")?;
+        let element_remove: unsafe extern "C" fn(u32, u32) -> c_int =
+            *egl::symbol(&self.bcm_host, b"vc_dispmanx_element_remove\0")?;
+        let update_submit_sync: unsafe extern "C" fn(u32) -> c_int =
+            *egl::symbol(&self.bcm_host, b"vc_dispmanx_update_submit_sync\0")?;
+        let display_close: unsafe extern "C" fn(u32) -> c_int =
+            *egl::symbol(&self.bcm_host, b"vc_dispmanx_display_close\0")?;
+
+        if self.window.element != 0 {
+            let update = update_start(0);
+            element_remove(update, self.window.element);
+            update_submit_sync(update);
+        }
+        display_close(self.dispman_display);
+        Ok(())
+    }
+}
+
+impl Drop for DispmanxContext {
+    fn drop(&mut self) {
+        self.egl.destroy(self.display, self.surface, self.context);
+        if let Err(e) = unsafe { self.close_display() } {
+            println!("Could not close the DispmanX display, {}", e);
+        }
+    }
+}
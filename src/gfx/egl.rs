@@ -0,0 +1,282 @@
+use gfx::uploader::SharedContext;
+use libloading::{Library, Symbol};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
+use std::sync::Arc;
+
+pub const EGL_NONE: i32 = 0x3038;
+const EGL_RED_SIZE: i32 = 0x3024;
+const EGL_GREEN_SIZE: i32 = 0x3023;
+const EGL_BLUE_SIZE: i32 = 0x3022;
+const EGL_ALPHA_SIZE: i32 = 0x3021;
+const EGL_SURFACE_TYPE: i32 = 0x3033;
+const EGL_RENDERABLE_TYPE: i32 = 0x3040;
+const EGL_OPENGL_ES2_BIT: i32 = 0x0004;
+const EGL_CONTEXT_CLIENT_VERSION: i32 = 0x3098;
+const EGL_OPENGL_ES_API: u32 = 0x30A0;
+const EGL_TRUE: u32 = 1;
+#[allow(dead_code)]
+pub const EGL_WINDOW_BIT: i32 = 0x0004;
+
+pub type EglDisplay = *mut c_void;
+pub type EglConfig = *mut c_void;
+pub type EglContext = *mut c_void;
+pub type EglSurface = *mut c_void;
+
+// The EGL and GLES libraries, loaded at runtime so builds don't depend on where a particular
+// driver keeps them. Only the few entry points needed to get a context going are looked up.
+pub struct Egl {
+    get_display: unsafe extern "C" fn(*mut c_void) -> EglDisplay,
+    initialize: unsafe extern "C" fn(EglDisplay, *mut i32, *mut i32) -> u32,
+    terminate: unsafe extern "C" fn(EglDisplay) -> u32,
+    bind_api: unsafe extern "C" fn(u32) -> u32,
+    choose_config: unsafe extern "C" fn(EglDisplay, *const i32, *mut EglConfig, i32, *mut i32) -> u32,
+    create_context: unsafe extern "C" fn(EglDisplay, EglConfig, EglContext, *const i32) -> EglContext,
+    destroy_context: unsafe extern "C" fn(EglDisplay, EglContext) -> u32,
+    create_window_surface: unsafe extern "C" fn(EglDisplay, EglConfig, *mut c_void, *const i32) -> EglSurface,
+    destroy_surface: unsafe extern "C" fn(EglDisplay, EglSurface) -> u32,
+    make_current: unsafe extern "C" fn(EglDisplay, EglSurface, EglSurface, EglContext) -> u32,
+    swap_buffers: unsafe extern "C" fn(EglDisplay, EglSurface) -> u32,
+    swap_interval: unsafe extern "C" fn(EglDisplay, i32) -> u32,
+    get_proc_address: unsafe extern "C" fn(*const c_char) -> *const c_void,
+    get_error: unsafe extern "C" fn() -> i32,
+
+    gles: Library,
+    _egl: Library,
+}
+
+impl Egl {
+    // Load the first of each list of library names that exists.
+    pub fn load(egl_names: &[&str], gles_names: &[&str]) -> Result<Egl, String> {
+        let egl = load_library(egl_names)?;
+        let gles = load_library(gles_names)?;
+
+        unsafe {
+            Ok(Egl {
+                get_display: *symbol(&egl, b"eglGetDisplay\0")?,
+                initialize: *symbol(&egl, b"eglInitialize\0")?,
+                terminate: *symbol(&egl, b"eglTerminate\0")?,
+                bind_api: *symbol(&egl, b"eglBindAPI\0")?,
+                choose_config: *symbol(&egl, b"eglChooseConfig\0")?,
+                create_context: *symbol(&egl, b"eglCreateContext\0")?,
+                destroy_context: *symbol(&egl, b"eglDestroyContext\0")?,
+                create_window_surface: *symbol(&egl, b"eglCreateWindowSurface\0")?,
+                destroy_surface: *symbol(&egl, b"eglDestroySurface\0")?,
+                make_current: *symbol(&egl, b"eglMakeCurrent\0")?,
+                swap_buffers: *symbol(&egl, b"eglSwapBuffers\0")?,
+                swap_interval: *symbol(&egl, b"eglSwapInterval\0")?,
+                get_proc_address: *symbol(&egl, b"eglGetProcAddress\0")?,
+                get_error: *symbol(&egl, b"eglGetError\0")?,
+
+                gles,
+                _egl: egl,
+            })
+        }
+    }
+
+    // An initialized display for the driver's native display, null for its default one.
+    pub fn display(&self, native_display: *mut c_void) -> Result<EglDisplay, String> {
+        unsafe {
+            let display = (self.get_display)(native_display);
+            if display.is_null() {
+                return Err("could not get an EGL display".to_string());
+            }
+            if (self.initialize)(display, ptr::null_mut(), ptr::null_mut()) != EGL_TRUE {
+                return Err(self.error("could not initialize EGL"));
+            }
+
+            Ok(display)
+        }
+    }
+
+    // An OpenGL ES 2 context with an RGBA config, whose surfaces can be of `surface_type`
+    // (0 for none, e.g. when surfaceless). It shares textures and buffers with `share`, unless
+    // that's null.
+    pub fn create_context(&self,
+                          display: EglDisplay,
+                          surface_type: i32,
+                          share: EglContext) -> Result<(EglConfig, EglContext), String> {
+        unsafe {
+            if (self.bind_api)(EGL_OPENGL_ES_API) != EGL_TRUE {
+                return Err(self.error("could not bind OpenGL ES"));
+            }
+
+            let config_attributes = [
+                EGL_RED_SIZE, 8,
+                EGL_GREEN_SIZE, 8,
+                EGL_BLUE_SIZE, 8,
+                EGL_ALPHA_SIZE, 8,
+                EGL_SURFACE_TYPE, surface_type,
+                EGL_RENDERABLE_TYPE, EGL_OPENGL_ES2_BIT,
+                EGL_NONE,
+            ];
+            let mut config = ptr::null_mut();
+            let mut num_configs = 0;
+            if (self.choose_config)(display, config_attributes.as_ptr(),
+                                    &mut config, 1, &mut num_configs) != EGL_TRUE
+                || num_configs == 0 {
+                return Err(self.error("no EGL config supports OpenGL ES 2"));
+            }
+
+            let context_attributes = [EGL_CONTEXT_CLIENT_VERSION, 2, EGL_NONE];
+            let context = (self.create_context)(display, config, share, context_attributes.as_ptr());
+            if context.is_null() {
+                return Err(self.error("could not create an EGL context"));
+            }
+
+            Ok((config, context))
+        }
+    }
+
+    // Only DispmanX draws into a window surface.
+    #[allow(dead_code)]
+    pub fn create_window_surface(&self,
+                                 display: EglDisplay,
+                                 config: EglConfig,
+                                 native_window: *mut c_void) -> Result<EglSurface, String> {
+        unsafe {
+            let surface = (self.create_window_surface)(display, config, native_window, [EGL_NONE].as_ptr());
+            if surface.is_null() {
+                return Err(self.error("could not create an EGL window surface"));
+            }
+
+            Ok(surface)
+        }
+    }
+
+    // A null surface makes the context current without one, which needs
+    // EGL_KHR_surfaceless_context.
+    pub fn make_current(&self,
+                        display: EglDisplay,
+                        surface: EglSurface,
+                        context: EglContext) -> Result<(), String> {
+        unsafe {
+            if (self.make_current)(display, surface, surface, context) != EGL_TRUE {
+                return Err(self.error("could not make the EGL context current"));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn swap_buffers(&self, display: EglDisplay, surface: EglSurface) {
+        unsafe {
+            (self.swap_buffers)(display, surface);
+        }
+    }
+
+    // How many vertical blanks a swap waits for on the current surface: 1 to sync to them, or 0
+    // to swap straight away.
+    #[allow(dead_code)]
+    pub fn swap_interval(&self, display: EglDisplay, interval: i32) -> Result<(), String> {
+        unsafe {
+            if (self.swap_interval)(display, interval) != EGL_TRUE {
+                return Err(self.error("could not set the swap interval"));
+            }
+        }
+
+        Ok(())
+    }
+
+    // Releases the context and its surface, if any, along with the display. Null handles are
+    // skipped, so this also cleans up after a partial setup.
+    pub fn destroy(&self, display: EglDisplay, surface: EglSurface, context: EglContext) {
+        if display.is_null() {
+            return;
+        }
+
+        unsafe {
+            (self.make_current)(display, ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
+            if !surface.is_null() {
+                (self.destroy_surface)(display, surface);
+            }
+            if !context.is_null() {
+                (self.destroy_context)(display, context);
+            }
+            (self.terminate)(display);
+        }
+    }
+
+    // Core GLES functions come from the library itself, as older EGL implementations only
+    // return extensions from eglGetProcAddress.
+    pub fn get_proc_address(&self, name: &str) -> *const c_void {
+        let c_name = match CString::new(name) {
+            Ok(x) => x,
+            Err(_) => return ptr::null(),
+        };
+
+        unsafe {
+            let function = self.gles.get::<*const c_void>(c_name.as_bytes_with_nul());
+            match function {
+                Ok(x) => *x,
+                Err(_) => (self.get_proc_address)(c_name.as_ptr()),
+            }
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        let error = unsafe { (self.get_error)() };
+        format!("{} (0x{:X})", message, error)
+    }
+}
+
+// A second context sharing textures and buffers with the render context, made current without
+// a surface on a worker thread. Only the context is destroyed when dropped, as the display
+// belongs to the render context.
+pub struct EglSharedContext {
+    egl: Arc<Egl>,
+    display: EglDisplay,
+    context: EglContext,
+}
+
+// EGL handles can be used from any thread, as long as a context is only current on one.
+unsafe impl Send for EglSharedContext {}
+
+impl EglSharedContext {
+    pub fn new(egl: &Arc<Egl>, display: EglDisplay, share: EglContext) -> Result<EglSharedContext, String> {
+        let (_, context) = egl.create_context(display, 0, share)?;
+        Ok(EglSharedContext {
+            egl: egl.clone(),
+            display,
+            context,
+        })
+    }
+}
+
+impl SharedContext for EglSharedContext {
+    fn make_current(&self) -> Result<(), String> {
+        self.egl.make_current(self.display, ptr::null_mut(), self.context)
+    }
+
+    fn get_proc_address(&self, name: &str) -> *const c_void {
+        self.egl.get_proc_address(name)
+    }
+}
+
+impl Drop for EglSharedContext {
+    fn drop(&mut self) {
+        unsafe {
+            (self.egl.make_current)(self.display, ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
+            (self.egl.destroy_context)(self.display, self.context);
+        }
+    }
+}
+
+pub fn load_library(names: &[&str]) -> Result<Library, String> {
+    let mut errors = Vec::new();
+    for name in names {
+        match Library::new(name) {
+            Ok(x) => return Ok(x),
+            Err(e) => errors.push(format!("{}: {}", name, e)),
+        }
+    }
+
+    Err(format!("could not load {}", errors.join(", ")))
+}
+
+pub unsafe fn symbol<'a, T>(library: &'a Library, name: &[u8]) -> Result<Symbol<'a, T>, String> {
+    library.get(name)
+        .map_err(|e| format!("could not find {}: {}", String::from_utf8_lossy(&name[..name.len() - 1]), e))
+}
@@ -0,0 +1,41 @@
+// How the visualizer's output is fitted to a window of a different shape.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fit {
+    // Render the visualizer at the window's size, so it always matches.
+    Resize,
+    // Keep the visualizer's size and stretch it over the window.
+    Stretch,
+    // Keep its size and proportions, and fit it inside the window with black bars.
+    Letterbox,
+    // Keep its size and proportions, and fill the window, cutting off the overflow.
+    Crop,
+}
+
+impl Fit {
+    pub fn parse(value: &str) -> Result<Fit, String> {
+        match value {
+            "resize" => Ok(Fit::Resize),
+            "stretch" => Ok(Fit::Stretch),
+            "letterbox" => Ok(Fit::Letterbox),
+            "crop" => Ok(Fit::Crop),
+
+            _ => Err(format!("unknown fit '{}', expected one of: resize, stretch, letterbox, crop", value)),
+        }
+    }
+
+    // The viewport, as x, y, width and height, to draw an `output` sized image into a `screen`
+    // sized one. It's centered, and with Crop extends past the screen's edges.
+    pub fn viewport(&self, output: (i32, i32), screen: (i32, i32)) -> (i32, i32, i32, i32) {
+        let (output_width, output_height) = (output.0 as f32, output.1 as f32);
+        let (screen_width, screen_height) = (screen.0 as f32, screen.1 as f32);
+        let scale = match *self {
+            Fit::Resize => return (0, 0, output.0, output.1),
+            Fit::Stretch => return (0, 0, screen.0, screen.1),
+            Fit::Letterbox => f32::min(screen_width / output_width, screen_height / output_height),
+            Fit::Crop => f32::max(screen_width / output_width, screen_height / output_height),
+        };
+
+        let (width, height) = ((output_width * scale).round() as i32, (output_height * scale).round() as i32);
+        ((screen.0 - width) / 2, (screen.1 - height) / 2, width, height)
+    }
+}
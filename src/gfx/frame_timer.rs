@@ -0,0 +1,58 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Visualizers advance their animations by a fixed step per update, tuned at about the default
+// audio frame rate (24000 samples a second in windows of 1024).
+pub const UPDATES_PER_SECOND: u32 = 24;
+
+// How many updates to catch up on at most after a stall, rather than fast forwarding.
+const MAX_CATCH_UP_UPDATES: u32 = 5;
+
+// Paces the render loop: updates happen at a fixed rate however often audio frames arrive, and
+// frames are rendered at most `fps` times a second.
+pub struct FrameTimer {
+    update_interval: Duration,
+    frame_interval: Duration,
+    last_update: Instant,
+    next_frame: Instant,
+}
+
+impl FrameTimer {
+    pub fn new(fps: u32) -> FrameTimer {
+        let now = Instant::now();
+        FrameTimer {
+            update_interval: Duration::from_secs(1) / UPDATES_PER_SECOND,
+            frame_interval: Duration::from_secs(1) / u32::max(1, fps),
+            last_update: now,
+            next_frame: now,
+        }
+    }
+
+    // The number of fixed updates due since the last call.
+    pub fn due_updates(&mut self) -> u32 {
+        let mut updates = 0;
+        while self.last_update.elapsed() >= self.update_interval {
+            self.last_update += self.update_interval;
+            updates += 1;
+
+            if updates == MAX_CATCH_UP_UPDATES {
+                self.last_update = Instant::now();
+                break;
+            }
+        }
+
+        updates
+    }
+
+    // Sleep until it's time to render the next frame.
+    pub fn wait_for_next_frame(&mut self) {
+        let now = Instant::now();
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+            self.next_frame += self.frame_interval;
+        } else {
+            // Running behind, so don't try to make up for the frames that were missed.
+            self.next_frame = now + self.frame_interval;
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use gfx;
+use gfx::gl;
+use gfx::texture::Texture;
+
+// A framebuffer rendering into a texture it owns. Both are deleted when dropped.
+pub struct Framebuffer {
+    gl: gl::Gl,
+    id: u32,
+    texture: Texture,
+}
+
+impl Framebuffer {
+    pub fn new(gl: &gl::Gl, texture: Texture) -> Result<Framebuffer, String> {
+        unsafe {
+            let mut id = 0;
+            gl_result!(gl; gl.GenFramebuffers(1, &mut id))?;
+            // Deleted by dropping it if a call below fails.
+            let framebuffer = Framebuffer {
+                gl: gl.clone(),
+                id,
+                texture,
+            };
+            gl_result!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, id))?;
+            gl_result!(gl; gl.FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                framebuffer.texture.id(), 0,
+            ))?;
+
+            let status = gl_result!(gl; gl.CheckFramebufferStatus(gl::FRAMEBUFFER))?;
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                return Err(format!("framebuffer is incomplete (0x{:X})", status));
+            }
+
+            Ok(framebuffer)
+        }
+    }
+
+    // A framebuffer to blend colors into in linear light, before they're encoded for a screen.
+    // Half floats keep the darks 8 bits would band, where they can be rendered into: on desktop
+    // GL 3, GL ES 3.2, or GL ES 3 with an extension. Otherwise an sRGB texture stores them
+    // encoded, which GL ES 3 blends in linear light and decodes when sampled. GL ES 2 only has
+    // linear 8 bits.
+    pub fn new_linear(gl: &gl::Gl, width: i32, height: i32) -> Result<Framebuffer, String> {
+        let (es, major, minor) = gfx::gl_version(gl);
+        let half_floats = major >= 3 && (!es || minor >= 2
+            || gfx::has_extension(gl, "GL_EXT_color_buffer_half_float")
+            || gfx::has_extension(gl, "GL_EXT_color_buffer_float"));
+        let with_storage = |internal_format, data_type| {
+            let texture = Texture::with_storage(gl, width, height, internal_format, gl::RGBA, data_type, gl::LINEAR)?;
+            Framebuffer::new(gl, texture)
+        };
+        if half_floats {
+            match with_storage(gl::RGBA16F, gl::HALF_FLOAT) {
+                Ok(x) => return Ok(x),
+                Err(e) => println!("Blending in 8 bits, as the half float framebuffer failed: {}", e),
+            }
+        }
+        if es && major >= 3 {
+            match with_storage(gl::SRGB8_ALPHA8, gl::UNSIGNED_BYTE) {
+                Ok(x) => return Ok(x),
+                Err(e) => println!("Blending in linear 8 bits, as the sRGB framebuffer failed: {}", e),
+            }
+        }
+
+        Framebuffer::new(gl, Texture::new(gl, width, height, gl::RGB, gl::LINEAR)?)
+    }
+
+    // Direct drawing into this framebuffer's texture.
+    pub fn bind(&self) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, self.id));
+        }
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.DeleteFramebuffers(1, &self.id));
+        }
+    }
+}
@@ -1,10 +1,39 @@
+#[cfg(not(any(feature = "kms", feature = "dispmanx")))]
+use std::env;
+use std::ffi::CStr;
+use std::fmt;
+use std::fs;
 use std::os::raw::c_void;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
 use glutin;
 use glutin::GlContext;
 
+#[cfg(all(feature = "dispmanx", not(feature = "kms")))]
+use gfx::dispmanx;
+#[cfg(feature = "kms")]
+use gfx::kms;
+
 use audio;
+use gfx::audio_input::AudioInput;
+use gfx::calibration;
+use gfx::fit::Fit;
+use gfx::frame_timer::FrameTimer;
+use gfx::gpu_timer::{GpuTimer, Stage};
+use gfx::peer_source;
+use gfx::pixel_reader::PixelReader;
+use gfx::recorder;
+use gfx::recorder::Recorder;
+use gfx::resolution_scaler::ResolutionScaler;
+use gfx::screenshot;
+use gfx::software;
+use gfx::stats::FrameStats;
+use gfx::test_pattern::{self, TestPattern};
+use gfx::uploader::{SharedContext, Uploader};
+use control;
 use visualizer;
 use screen;
 
@@ -13,90 +42,532 @@ pub mod gl {
     include!(concat!(env!("OUT_DIR"), "/gl_bindings.rs"));
 }
 
+// Make a GL call and log any error it raises, with where it was called, when checking for errors.
+// For calls made every frame, where there's nothing to do about an error but carry on; setup
+// uses `gl_result!` to fail with it instead.
 macro_rules! gl_try {
     ($gl:expr; $call:expr) => {{
         let result = $call;
-        if cfg!(debug_assertion) {
-            let gl_err = $gl.GetError();
-            if gl_err != gl::NO_ERROR {
-                panic!("gl error: {} (0x{:X})", gl_err, gl_err);
-            }
+        if let Err(e) = $crate::gfx::check_gl_error(&$gl, stringify!($call), file!(), line!()) {
+            $crate::gfx::log_gl_error(&e);
         }
 
         result
     }}
 }
 
+// Make a GL call, returning any error it raises as a GlError, when checking for errors.
+macro_rules! gl_result {
+    ($gl:expr; $call:expr) => {{
+        let result = $call;
+        $crate::gfx::check_gl_error(&$gl, stringify!($call), file!(), line!()).map(|_| result)
+    }}
+}
+
+// Checking costs a round trip to the driver per call, so release builds only check when asked.
+static CHECK_GL_ERRORS: AtomicBool = AtomicBool::new(cfg!(debug_assertions));
+// Errors in the render loop repeat every frame, so only so many are logged.
+static LOGGED_GL_ERRORS: AtomicUsize = AtomicUsize::new(0);
+const MAX_LOGGED_GL_ERRORS: usize = 50;
+// Pixels a preview window next to screens taking pixels is scaled up to on its longer side,
+// as their output is often only a few LEDs across.
+const PREVIEW_SIZE: i32 = 512;
+
+// A GL call that raised an error, and where it was made.
+#[derive(Debug)]
+pub struct GlError {
+    pub call: &'static str,
+    pub file: &'static str,
+    pub line: u32,
+    pub code: gl::types::GLenum,
+}
+
+impl fmt::Display for GlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.code {
+            gl::INVALID_ENUM => "invalid enum",
+            gl::INVALID_VALUE => "invalid value",
+            gl::INVALID_OPERATION => "invalid operation",
+            gl::INVALID_FRAMEBUFFER_OPERATION => "invalid framebuffer operation",
+            gl::OUT_OF_MEMORY => "out of memory",
+            _ => "unknown error",
+        };
+        write!(f, "gl error: {} (0x{:X}) at {}:{}, from {}", name, self.code, self.file, self.line, self.call)
+    }
+}
+
+impl From<GlError> for String {
+    fn from(e: GlError) -> String {
+        e.to_string()
+    }
+}
+
+// Check GL calls for errors in release builds too, e.g. to find what's wrong on an installation.
+pub fn set_gl_error_checks(enabled: bool) {
+    CHECK_GL_ERRORS.store(enabled, Ordering::Relaxed);
+}
+
+// The error raised by the GL call just made, if checking for errors. Any older errors still
+// queued are cleared too.
+pub fn check_gl_error(gl: &gl::Gl, call: &'static str, file: &'static str, line: u32) -> Result<(), GlError> {
+    if !CHECK_GL_ERRORS.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    let mut first = gl::NO_ERROR;
+    loop {
+        let code = unsafe { gl.GetError() };
+        if code == gl::NO_ERROR {
+            break;
+        }
+        if first == gl::NO_ERROR {
+            first = code;
+        }
+    }
+
+    match first {
+        gl::NO_ERROR => Ok(()),
+        code => Err(GlError { call, file, line, code }),
+    }
+}
+
+pub fn log_gl_error(e: &GlError) {
+    let logged = LOGGED_GL_ERRORS.fetch_add(1, Ordering::Relaxed);
+    if logged < MAX_LOGGED_GL_ERRORS {
+        println!("{}", e);
+    } else if logged == MAX_LOGGED_GL_ERRORS {
+        println!("Not logging any more GL errors");
+    }
+}
+
+// Whether the context has the GL extension `name`.
+pub fn has_extension(gl: &gl::Gl, name: &str) -> bool {
+    unsafe {
+        let extensions = gl.GetString(gl::EXTENSIONS);
+        if extensions.is_null() {
+            return false;
+        }
+
+        CStr::from_ptr(extensions as *const _)
+            .to_string_lossy()
+            .split_whitespace()
+            .any(|x| x == name)
+    }
+}
+
+// Whether the context is GL ES and its major and minor version, 0 when it can't be told.
+// Contexts asked for ES 2 are often ES 3 anyway.
+pub fn gl_version(gl: &gl::Gl) -> (bool, u32, u32) {
+    let version = unsafe {
+        let version = gl.GetString(gl::VERSION);
+        if version.is_null() {
+            return (true, 0, 0);
+        }
+        CStr::from_ptr(version as *const _).to_string_lossy().into_owned()
+    };
+
+    // Like "OpenGL ES 3.1 Mesa 20.3.5", or "4.6.0 NVIDIA 470.86" for desktop GL.
+    let es = version.starts_with("OpenGL ES ");
+    let mut numbers = version.trim_start_matches("OpenGL ES ").split(['.', ' ']);
+    let mut number = || numbers.next().and_then(|x| x.parse::<u32>().ok()).unwrap_or(0);
+    let major = number();
+    (es, major, number())
+}
+
+// How the pipeline renders, from the command line.
+#[derive(Clone)]
+pub struct Options {
+    // Size of the visualizer output, in pixels.
+    pub width: i32,
+    pub height: i32,
+    // Most frames to render per second.
+    pub fps: u32,
+    // Periodically print frame rates and timings.
+    pub show_stats: bool,
+    // Include how long the GPU takes in the stats, where the driver can tell.
+    pub gpu_stats: bool,
+    // Start the preview window fullscreen on the primary monitor.
+    pub fullscreen: bool,
+    // Render visualizers this many times bigger in each direction, then scale them down.
+    pub supersample: i32,
+    // Record an MP4 of the output here from the start.
+    pub record_file: Option<PathBuf>,
+    // How long GIF recordings last.
+    pub gif_seconds: u32,
+    // Render every audio frame in turn into numbered PNGs here, rather than in real time.
+    pub dump_frames_dir: Option<PathBuf>,
+    // Lower the visualizers' resolution while rendering can't keep up with `fps`.
+    pub adaptive_resolution: bool,
+    // Draw on the CPU without trying GL first.
+    pub software: bool,
+    // The gamma visualizer colors are in, decoded to blend in linear light. 1 blends them as is.
+    pub gamma: f32,
+    // Bits per channel to dither the output to, or 0 not to.
+    pub dither_bits: u32,
+    // Read pixels for screens that take them in the frame they're rendered, rather than the
+    // next, for a frame less latency but a GPU stall.
+    pub sync_readback: bool,
+    // Check every GL call for errors and log them, as debug builds always do.
+    pub check_gl: bool,
+    // How the output is fitted to a window of another shape.
+    pub fit: Fit,
+    // Wait for the display's vertical blank before showing each frame, in a window or with
+    // DispmanX. Frames are still paced to at most `fps`.
+    pub vsync: bool,
+    // Show this instead of the visualizer, to check screens before there's audio.
+    pub test_pattern: Option<TestPattern>,
+    // Flash on each beat instead of the visualizer, starting from each screen's latency in
+    // milliseconds, for the latencies to be found from stdin.
+    pub latency_calibration: Option<Vec<u32>>,
+    // Show the frames a peer screen sends to this address instead of rendering.
+    pub peer_source: Option<String>,
+    // Kept up to date for the control panel, if it's served.
+    pub status: Option<control::SharedStatus>,
+}
+
 pub fn run(visualizer: visualizer::Visualizer,
-           screen: Box<dyn screen::Screen>,
+           screens: Vec<Box<dyn screen::Screen>>,
+           automation: control::Automation,
            audio_rx: mpsc::Receiver<audio::AudioFrame>,
-           size: i32) {
-    if screen.uses_window() {
-        render_with_window(visualizer, screen, audio_rx, size);
+           commands_rx: mpsc::Receiver<control::Command>,
+           options: Options) {
+    if options.check_gl {
+        set_gl_error_checks(true);
+    }
+    if let Some(pattern) = options.test_pattern {
+        show_test_pattern(pattern, screens, options);
+        return;
+    }
+    if let Some(address) = options.peer_source.clone() {
+        show_peer_frames(&address, screens, options);
+        return;
+    }
+    if options.latency_calibration.is_some() {
+        calibration::run(screens, audio_rx, options);
+        return;
+    }
+    if options.software {
+        render_without_gl(visualizer, screens, automation, audio_rx, commands_rx, options, "Not using GL".to_string());
+        return;
+    }
+
+    // glutin can't create a context on the legacy Broadcom driver, where drawing goes to a
+    // DispmanX element instead. KMS is used over it when both are enabled. Frame dumps don't
+    // need to be seen.
+    let windowed = screens.iter().any(|x| x.uses_window()) && options.dump_frames_dir.is_none();
+    if windowed && !cfg!(all(feature = "dispmanx", not(feature = "kms"))) {
+        render_with_window(visualizer, screens, automation, audio_rx, commands_rx, options);
     } else {
-        render_without_window(visualizer, screen, audio_rx, size);
+        render_without_window(visualizer, screens, automation, audio_rx, commands_rx, options);
     }
 }
 
 fn render_with_window(visualizer: visualizer::Visualizer,
-                      screen: Box<dyn screen::Screen>,
+                      screens: Vec<Box<dyn screen::Screen>>,
+                      automation: control::Automation,
                       audio_rx: mpsc::Receiver<audio::AudioFrame>,
-                      size: i32) {
+                      commands_rx: mpsc::Receiver<control::Command>,
+                      options: Options) {
     let mut events_loop = glutin::EventsLoop::new();
+    let monitor = events_loop.get_primary_monitor();
+    let mut fullscreen = options.fullscreen;
+    let preview_scale = if shares_output(&screens) {
+        i32::max(1, PREVIEW_SIZE / i32::max(options.width, options.height))
+    } else {
+        1
+    };
     let window = glutin::WindowBuilder::new()
         .with_title("Music Visualizer")
-        .with_dimensions(size as u32, size as u32);
-    let context = glutin::ContextBuilder::new().with_vsync(true);
-    let gl_window = glutin::GlWindow::new(window, context, &events_loop).unwrap();
-    let mut pipeline = GfxPipeline::new(load_gl_window_as_context(&gl_window), visualizer, screen, size);
+        .with_dimensions((options.width * preview_scale) as u32, (options.height * preview_scale) as u32)
+        .with_fullscreen(if fullscreen { Some(monitor.clone()) } else { None });
+    let context = glutin::ContextBuilder::new().with_vsync(options.vsync);
+    let gl_window = match glutin::GlWindow::new(window, context, &events_loop) {
+        Ok(x) => x,
+        Err(e) => {
+            let reason = format!("Could not create a window, {}", e);
+            render_without_gl(visualizer, screens, automation, audio_rx, commands_rx, options, reason);
+            return;
+        }
+    };
+    // glutin can only share a context with another window's.
+    let mut pipeline = match GfxPipeline::new(load_gl_window_as_context(&gl_window),
+                                              visualizer, screens, automation, commands_rx, options, None) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    if preview_scale > 1 {
+        if let Some((width, height)) = gl_window.get_inner_size() {
+            pipeline.resize_screen(width as i32, height as i32);
+        }
+    }
 
     let mut running = true;
     while running {
-        let audio_frame = match audio_rx.recv() {
-            Ok(x) => x,
-            Err(_) => continue,
-        };
+        pipeline.receive_audio(&audio_rx);
 
         events_loop.poll_events(|event| match event {
             glutin::Event::WindowEvent { event, .. } => match event {
                 glutin::WindowEvent::Closed => running = false,
-                glutin::WindowEvent::Resized(w, h) => gl_window.resize(w, h),
+                glutin::WindowEvent::Resized(w, h) => {
+                    gl_window.resize(w, h);
+                    pipeline.resize_screen(w as i32, h as i32);
+                },
+                glutin::WindowEvent::KeyboardInput { input, .. } => {
+                    if input.state != glutin::ElementState::Pressed {
+                        return;
+                    }
+
+                    // Fullscreen only concerns the window, so it isn't a pipeline command.
+                    let toggles_fullscreen = match input.virtual_keycode {
+                        Some(glutin::VirtualKeyCode::F11) => true,
+                        Some(glutin::VirtualKeyCode::Return) => input.modifiers.alt,
+                        _ => false,
+                    };
+                    if toggles_fullscreen {
+                        fullscreen = !fullscreen;
+                        gl_window.set_fullscreen(if fullscreen { Some(monitor.clone()) } else { None });
+                    } else if let Some(command) = key_command(&mut pipeline, input.virtual_keycode) {
+                        pipeline.apply(command);
+                    }
+                },
                 _ => (),
             },
             _ => (),
         });
 
-        pipeline.update(audio_frame);
+        pipeline.update();
+        pipeline.render();
         gl_window.swap_buffers().unwrap();
+        pipeline.wait_for_next_frame();
+    }
+}
+
+// Whether a window shows the output next to screens taking its pixels, like an LED matrix.
+fn shares_output(screens: &[Box<dyn screen::Screen>]) -> bool {
+    screens.iter().any(|x| x.uses_window()) && screens.iter().any(|x| x.renders_pixels())
+}
+
+// Keyboard controls for tweaking the show from the visualizer window.
+fn key_command(pipeline: &mut GfxPipeline, key: Option<glutin::VirtualKeyCode>) -> Option<control::Command> {
+    let adjust = |pipeline: &mut GfxPipeline, name: String, step: f32| {
+        let value = pipeline.visualizer.parameter(&name).map(|x| x + step);
+        value.map(|x| control::Command::SetParameter(name, x))
+    };
+
+    match key {
+        Some(glutin::VirtualKeyCode::T) => Some(control::Command::ToggleOverlay),
+        Some(glutin::VirtualKeyCode::R) => Some(control::Command::ToggleAutomationRecording),
+        Some(glutin::VirtualKeyCode::P) => Some(control::Command::Screenshot),
+        Some(glutin::VirtualKeyCode::V) => Some(control::Command::ToggleVideoRecording),
+        Some(glutin::VirtualKeyCode::G) => Some(control::Command::RecordGif),
+        Some(glutin::VirtualKeyCode::N) => Some(control::Command::CycleVisualizer(1)),
+        Some(glutin::VirtualKeyCode::B) => Some(control::Command::ToggleBlackout),
+        Some(glutin::VirtualKeyCode::Up) => adjust(pipeline, "brightness".to_string(), 0.1),
+        Some(glutin::VirtualKeyCode::Down) => adjust(pipeline, "brightness".to_string(), -0.1),
+        Some(glutin::VirtualKeyCode::Right) => {
+            let name = pipeline.visualizer.active_parameter_name("speed");
+            adjust(pipeline, name, 0.1)
+        },
+        Some(glutin::VirtualKeyCode::Left) => {
+            let name = pipeline.visualizer.active_parameter_name("speed");
+            adjust(pipeline, name, -0.1)
+        },
+
+        _ => None,
     }
 }
 
+// Without a window, a GL context can be made straight on the DRM device, so no windowing
+// system needs to be running.
+#[cfg(feature = "kms")]
 fn render_without_window(visualizer: visualizer::Visualizer,
-                         screen: Box<dyn screen::Screen>,
+                         screens: Vec<Box<dyn screen::Screen>>,
+                         automation: control::Automation,
                          audio_rx: mpsc::Receiver<audio::AudioFrame>,
-                         size: i32) {
+                         commands_rx: mpsc::Receiver<control::Command>,
+                         options: Options) {
+    let context = match kms::KmsContext::new() {
+        Ok(x) => x,
+        Err(e) => {
+            let reason = format!("Could not create a KMS context, {}", e);
+            render_without_gl(visualizer, screens, automation, audio_rx, commands_rx, options, reason);
+            return;
+        }
+    };
+
+    let gl = load_gl(|ptr| context.get_proc_address(ptr));
+    let shared_context = shared_or_none(context.shared_context());
+    let pipeline = GfxPipeline::new(gl, visualizer, screens, automation, commands_rx, options, shared_context);
+    render_headless(pipeline, audio_rx, || ());
+}
+
+#[cfg(all(feature = "dispmanx", not(feature = "kms")))]
+fn render_without_window(visualizer: visualizer::Visualizer,
+                         screens: Vec<Box<dyn screen::Screen>>,
+                         automation: control::Automation,
+                         audio_rx: mpsc::Receiver<audio::AudioFrame>,
+                         commands_rx: mpsc::Receiver<control::Command>,
+                         options: Options) {
+    // Screens render at twice the visualizer's size.
+    let context = match dispmanx::DispmanxContext::new(options.width * 2, options.height * 2, options.vsync) {
+        Ok(x) => x,
+        Err(e) => {
+            let reason = format!("Could not create a DispmanX context, {}", e);
+            render_without_gl(visualizer, screens, automation, audio_rx, commands_rx, options, reason);
+            return;
+        }
+    };
+
+    let gl = load_gl(|ptr| context.get_proc_address(ptr));
+    let shared_context = shared_or_none(context.shared_context());
+    let pipeline = GfxPipeline::new(gl, visualizer, screens, automation, commands_rx, options, shared_context);
+    render_headless(pipeline, audio_rx, || context.swap_buffers());
+}
+
+#[cfg(not(any(feature = "kms", feature = "dispmanx")))]
+fn render_without_window(visualizer: visualizer::Visualizer,
+                         screens: Vec<Box<dyn screen::Screen>>,
+                         automation: control::Automation,
+                         audio_rx: mpsc::Receiver<audio::AudioFrame>,
+                         commands_rx: mpsc::Receiver<control::Command>,
+                         options: Options) {
+    // Even a hidden window needs a display server, and glutin panics without one.
+    if env::var_os("DISPLAY").is_none() && env::var_os("WAYLAND_DISPLAY").is_none() {
+        let reason = "No display to create a GL context on".to_string();
+        render_without_gl(visualizer, screens, automation, audio_rx, commands_rx, options, reason);
+        return;
+    }
+
     let window = glutin::WindowBuilder::new()
         .with_title("Music Visualizer")
         .with_visibility(false);
     let context = glutin::ContextBuilder::new();
-    let gl_window = glutin::GlWindow::new(window, context, &glutin::EventsLoop::new()).unwrap();
+    let gl_window = match glutin::GlWindow::new(window, context, &glutin::EventsLoop::new()) {
+        Ok(x) => x,
+        Err(e) => {
+            let reason = format!("Could not create a GL context, {}", e);
+            render_without_gl(visualizer, screens, automation, audio_rx, commands_rx, options, reason);
+            return;
+        }
+    };
 
-    let mut pipeline = GfxPipeline::new(load_gl_window_as_context(&gl_window),
-                                        visualizer, screen, size);
+    let gl = load_gl_window_as_context(&gl_window);
+    let pipeline = GfxPipeline::new(gl, visualizer, screens, automation, commands_rx, options, None);
+    render_headless(pipeline, audio_rx, || ());
+}
 
-    loop {
-        let audio_frame = match audio_rx.recv() {
-            Ok(x) => x,
-            Err(_) => continue,
-        };
+// Falls back to drawing on the CPU, for the screens that can show it.
+fn render_without_gl(visualizer: visualizer::Visualizer,
+                     mut screens: Vec<Box<dyn screen::Screen>>,
+                     automation: control::Automation,
+                     audio_rx: mpsc::Receiver<audio::AudioFrame>,
+                     commands_rx: mpsc::Receiver<control::Command>,
+                     options: Options,
+                     reason: String) {
+    let screen_count = screens.len();
+    screens.retain(|x| x.renders_pixels());
+    if screens.is_empty() {
+        println!("{}, and the screen can only be drawn with GL", reason);
+        return;
+    }
+
+    println!("{}, rendering in software", reason);
+    if screens.len() < screen_count {
+        println!("Leaving out the screens that can only be drawn with GL");
+    }
+    software::run(visualizer, screens, automation, audio_rx, commands_rx, options);
+}
+
+fn show_test_pattern(pattern: TestPattern, mut screens: Vec<Box<dyn screen::Screen>>, options: Options) {
+    let screen_count = screens.len();
+    screens.retain(|x| x.renders_pixels());
+    if screens.is_empty() {
+        println!("Test patterns can't be shown on screens that are drawn with GL");
+        return;
+    }
+
+    println!("Showing a test pattern instead of the visualizer");
+    if screens.len() < screen_count {
+        println!("Leaving out the screens that can only be drawn with GL");
+    }
+    test_pattern::run(pattern, screens, options);
+}
+
+fn show_peer_frames(address: &str, mut screens: Vec<Box<dyn screen::Screen>>, options: Options) {
+    let screen_count = screens.len();
+    screens.retain(|x| x.renders_pixels());
+    if screens.is_empty() {
+        println!("A peer's frames can't be shown on screens that are drawn with GL");
+        return;
+    }
+    let frames_rx = match screen::listen_for_peer(address) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Could not take frames from a peer, {}", e);
+            return;
+        }
+    };
+
+    if screens.len() < screen_count {
+        println!("Leaving out the screens that can only be drawn with GL");
+    }
+    peer_source::run(frames_rx, screens, options);
+}
+
+// Textures are uploaded on the render thread instead when there's no shared context.
+#[cfg(any(feature = "kms", feature = "dispmanx"))]
+fn shared_or_none<C>(context: Result<C, String>) -> Option<Box<dyn SharedContext>>
+    where C: SharedContext + 'static {
+    match context {
+        Ok(x) => Some(Box::new(x)),
+        Err(e) => {
+            println!("Uploading textures on the render thread, could not share the context: {}", e);
+            None
+        },
+    }
+}
+
+// Renders with `pipeline`, if it could be set up, until the process exits, calling `present`
+// after each frame.
+fn render_headless<F>(pipeline: Result<GfxPipeline, String>,
+                      audio_rx: mpsc::Receiver<audio::AudioFrame>,
+                      mut present: F) where F: FnMut() {
+    let mut pipeline = match pipeline {
+        Ok(x) => x,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+
+    if pipeline.dumps_frames() {
+        while pipeline.dump_next_frame(&audio_rx) {
+            present();
+        }
+        return;
+    }
 
-        pipeline.update(audio_frame);
+    loop {
+        pipeline.receive_audio(&audio_rx);
+        pipeline.update();
+        pipeline.render();
+        present();
+        pipeline.wait_for_next_frame();
     }
 }
 
 pub fn load_gl_window_as_context(gl_window: &glutin::GlWindow) -> gl::Gl {
     unsafe { gl_window.make_current() }.unwrap();
-    let gl = gl::Gl::load_with(|ptr| gl_window.get_proc_address(ptr) as *const _);
+    load_gl(|ptr| gl_window.get_proc_address(ptr) as *const _)
+}
+
+// Load the GL functions from a context that is already current, and set the state every
+// render relies on.
+fn load_gl<F>(loader: F) -> gl::Gl where F: FnMut(&str) -> *const c_void {
+    let gl = gl::Gl::load_with(loader);
 
     unsafe {
         gl.Enable(gl::BLEND);
@@ -111,50 +582,440 @@ pub fn load_gl_window_as_context(gl_window: &glutin::GlWindow) -> gl::Gl {
 pub struct GfxPipeline {
     gl: gl::Gl,
     visualizer: visualizer::Visualizer,
-    screen: Box<dyn screen::Screen>,
-    size: i32,
+    screens: Vec<Box<dyn screen::Screen>>,
+    automation: control::Automation,
+    // Changes from outside the window, like an edited config file.
+    commands_rx: mpsc::Receiver<control::Command>,
+    status: Option<control::SharedStatus>,
+    width: i32,
+    height: i32,
+    fit: Fit,
+    // Size of what the screen draws into, twice the output's unless the window has been resized.
+    screen_size: (i32, i32),
+
+    frame_timer: FrameTimer,
+    stats: Option<FrameStats>,
+    gpu_timer: Option<GpuTimer>,
+    resolution_scaler: Option<ResolutionScaler>,
+    // Set by a screenshot command, and taken care of by the next render.
+    screenshot_requested: bool,
+    fps: u32,
+    gif_length: Duration,
+    // At most one recording of each format.
+    recorders: Vec<Recorder>,
+    dump_frames_dir: Option<PathBuf>,
+    dumped_frames: u32,
+    audio_input: AudioInput,
+    // Reads the output back for screens that take pixels rather than drawing with GL.
+    pixel_reader: Option<PixelReader>,
 }
 
 impl GfxPipeline {
     pub fn new(
         gl: gl::Gl,
         mut visualizer: visualizer::Visualizer,
-        mut screen: Box<dyn screen::Screen>,
-        size: i32,
-    ) -> GfxPipeline {
-        visualizer.setup(&gl, size);
-        screen.setup(&gl);
+        mut screens: Vec<Box<dyn screen::Screen>>,
+        automation: control::Automation,
+        commands_rx: mpsc::Receiver<control::Command>,
+        options: Options,
+        shared_context: Option<Box<dyn SharedContext>>,
+    ) -> Result<GfxPipeline, String> {
+        // The output is encoded one way for all the screens, and those taking pixels correct it
+        // for themselves.
+        let output_gamma = if screens.iter().all(|x| x.is_linear()) { 1.0 } else { options.gamma };
+        for screen in screens.iter_mut() {
+            screen.set_output_gamma(output_gamma);
+        }
+        visualizer.set_gamma(options.gamma, output_gamma);
+        visualizer.set_dithering(options.dither_bits);
+        visualizer.set_uploader(Uploader::new(shared_context));
+        if options.dump_frames_dir.is_some() {
+            // Dumps are rendered as fast as they can be, but play back at the frame rate.
+            visualizer.count_frames(options.fps);
+        }
+        visualizer.setup(&gl, options.width, options.height, options.supersample)?;
+        screen::setup_each(&mut screens, &gl)?;
+        // Frame dumps go to the screens in turn with no rush, so don't need to overlap reads.
+        let pixel_reader = if screens.iter().any(|x| x.renders_pixels()) {
+            Some(PixelReader::new(&gl, !options.sync_readback && options.dump_frames_dir.is_none()))
+        } else {
+            None
+        };
 
-        let pipeline = GfxPipeline {
+        let gpu_timer = if options.gpu_stats { GpuTimer::new(&gl) } else { None };
+        if options.gpu_stats && gpu_timer.is_none() {
+            println!("Not timing the GPU, the driver has no timer queries");
+        }
+
+        // Screens taking pixels were set up for the output's size, so a window next to them
+        // shows it letterboxed rather than resizing it with the window.
+        let fit = if options.fit == Fit::Resize && shares_output(&screens) {
+            println!("Letterboxing the window, the screens taking pixels need the output at {}x{}",
+                     options.width, options.height);
+            Fit::Letterbox
+        } else {
+            options.fit
+        };
+
+        let mut pipeline = GfxPipeline {
             gl,
             visualizer,
-            screen,
-            size,
+            screens,
+            automation,
+            commands_rx,
+            status: options.status.clone(),
+            width: options.width,
+            height: options.height,
+            fit,
+            screen_size: (options.width * 2, options.height * 2),
+
+            frame_timer: FrameTimer::new(options.fps),
+            stats: if options.show_stats || options.gpu_stats { Some(FrameStats::new()) } else { None },
+            gpu_timer,
+            // Frame dumps should only depend on the audio.
+            resolution_scaler: if options.adaptive_resolution && options.dump_frames_dir.is_none() {
+                Some(ResolutionScaler::new(options.fps))
+            } else {
+                None
+            },
+            screenshot_requested: false,
+            fps: options.fps,
+            gif_length: Duration::from_secs(options.gif_seconds as u64),
+            recorders: Vec::new(),
+            dump_frames_dir: options.dump_frames_dir,
+            dumped_frames: 0,
+            audio_input: AudioInput::new(),
+            pixel_reader,
         };
 
-        pipeline
+        if let Some(ref dir) = pipeline.dump_frames_dir {
+            fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+            println!("Saving frames to {}", dir.display());
+        }
+
+        if let Some(path) = options.record_file {
+            pipeline.start_recording(path, recorder::Format::Mp4, None);
+        }
+
+        Ok(pipeline)
+    }
+
+    // Take the audio frames that arrived since the last call, without waiting for any.
+    pub fn receive_audio(&mut self, audio_rx: &mpsc::Receiver<audio::AudioFrame>) {
+        let received = self.audio_input.receive(audio_rx);
+        if let Some(ref mut stats) = self.stats {
+            for _ in 0..received {
+                stats.count_audio_frame();
+            }
+        }
     }
 
-    pub fn update(&mut self, audio_frame: audio::AudioFrame) {
+    // Run the fixed updates due since the last call, on the latest audio.
+    pub fn update(&mut self) {
+        while let Ok(command) = self.commands_rx.try_recv() {
+            self.apply(command);
+        }
+        for _ in 0..self.frame_timer.due_updates() {
+            match self.audio_input.next_update() {
+                Some(audio_frame) => self.update_with(audio_frame),
+                None => return,
+            }
+        }
+    }
+
+    fn update_with(&mut self, audio_frame: audio::AudioFrame) {
+        for (name, value) in self.automation.update(&audio_frame) {
+            if let Err(e) = self.visualizer.set_parameter(&name, value) {
+                println!("Skipping automation event: {}", e);
+            }
+        }
+        screen::show_on_each(&mut self.screens, |screen| screen.update_audio(&audio_frame));
+        if let Some(ref status) = self.status {
+            status.lock().unwrap().audio_updated(&audio_frame);
+        }
         self.visualizer.update(audio_frame);
+    }
 
+    pub fn dumps_frames(&self) -> bool {
+        self.dump_frames_dir.is_some()
+    }
+
+    // Wait for the next audio frame, then update once on it and render and save a frame, so the
+    // frames only depend on the audio. Returns false once the audio source is done.
+    pub fn dump_next_frame(&mut self, audio_rx: &mpsc::Receiver<audio::AudioFrame>) -> bool {
+        match audio_rx.recv() {
+            Ok(audio_frame) => {
+                self.update_with(audio_frame);
+                self.render();
+                true
+            },
+            Err(_) => {
+                println!("Saved {} frames", self.dumped_frames);
+                false
+            },
+        }
+    }
+
+    pub fn render(&mut self) {
         unsafe {
             let gl = &self.gl;
             gl_try!(gl; gl.ClearColor(0.0, 0.0, 0.0, 1.0));
             gl_try!(gl; gl.Clear(gl::COLOR_BUFFER_BIT));
 
-            gl_try!(gl; gl.Viewport(0, 0, self.size, self.size));
+            let visualizer_start = Instant::now();
+            if let Some(ref timer) = self.gpu_timer {
+                timer.begin(Stage::Visualizer);
+            }
             let texture = self.visualizer.render_to_texture(gl);
-            gl_try!(gl; gl.Viewport(0, 0, self.size * 2, self.size * 2));
-            self.screen.render_from_texture(gl, texture, self.size);
+            if let Some(ref timer) = self.gpu_timer {
+                timer.end();
+            }
+            if self.resolution_scaler.is_some() {
+                // Include the GPU's time in the visualizer's.
+                gl_try!(gl; gl.Finish());
+            }
+            let visualizer_time = visualizer_start.elapsed();
+            // The visualizer's output is still bound for these.
+            if self.screenshot_requested {
+                self.save_screenshot();
+                self.screenshot_requested = false;
+            }
+            if !self.recorders.is_empty() {
+                self.record_frame();
+            }
+            if self.dump_frames_dir.is_some() {
+                self.dump_frame();
+            }
+
+            let gl = &self.gl;
+            let screen_start = Instant::now();
+            let (x, y, width, height) = self.fit.viewport((self.width * 2, self.height * 2), self.screen_size);
+            gl_try!(gl; gl.Viewport(x, y, width, height));
+            if let Some(ref timer) = self.gpu_timer {
+                timer.begin(Stage::Screen);
+            }
+            // Screens taking pixels are given the previous frame's while this one renders, unless
+            // reads are synchronous. They're read while the visualizer's output is still bound,
+            // before the others draw the texture.
+            let (output_width, output_height) = (self.width, self.height);
+            if let Some(ref mut reader) = self.pixel_reader {
+                let pixels = reader.read(output_width as usize, output_height as usize);
+                screen::show_on_each(&mut self.screens, |screen| if screen.renders_pixels() {
+                    screen.render_from_pixels(pixels, output_width, output_height);
+                });
+            }
+            if self.screens.iter().any(|x| !x.renders_pixels()) {
+                if self.fit == Fit::Letterbox {
+                    // Black bars where the output doesn't reach.
+                    gl_try!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, 0));
+                    gl_try!(gl; gl.Clear(gl::COLOR_BUFFER_BIT));
+                }
+                screen::show_on_each(&mut self.screens, |screen| if !screen.renders_pixels() {
+                    screen.render_from_texture(gl, texture, output_width, output_height);
+                });
+            }
+            if let Some(ref timer) = self.gpu_timer {
+                timer.end();
+            }
+
+            let gpu_times = self.gpu_timer.as_mut().and_then(|x| x.end_frame());
+            if let (Some(stats), Some(times)) = (self.stats.as_mut(), gpu_times) {
+                stats.count_gpu_frame(times[Stage::Visualizer as usize], times[Stage::Screen as usize]);
+            }
+            if let Some(ref mut stats) = self.stats {
+                stats.count_frame(visualizer_time, screen_start.elapsed());
+            }
+            if let Some(ref status) = self.status {
+                let is_recording = self.is_recording_video();
+                status.lock().unwrap().frame_rendered(&mut self.visualizer, is_recording);
+            }
+
+            let new_scale = self.resolution_scaler.as_mut().and_then(|x| x.count_frame(visualizer_time));
+            if let Some(scale) = new_scale {
+                match self.visualizer.set_render_scale(gl, scale) {
+                    Ok(_) => println!("Rendering visualizers at {:.0}% resolution", scale * 100.0),
+                    Err(e) => println!("Could not change the resolution: {}", e),
+                }
+            }
+        }
+    }
+
+    // The window was resized to `width` x `height` pixels. Only resizes the output when fitting
+    // by resizing.
+    pub fn resize_screen(&mut self, width: i32, height: i32) {
+        self.screen_size = (i32::max(1, width), i32::max(1, height));
+        if self.fit == Fit::Resize {
+            // Screens draw at twice the visualizer's size.
+            self.resize(width / 2, height / 2);
+        }
+    }
+
+    pub fn resize(&mut self, width: i32, height: i32) {
+        let (width, height) = (i32::max(1, width), i32::max(1, height));
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+
+        match self.visualizer.resize(&self.gl, width, height) {
+            Ok(_) => {
+                self.width = width;
+                self.height = height;
+            },
+            Err(e) => println!("Could not resize to {}x{}: {}", width, height, e),
         }
     }
 
-    #[allow(dead_code)]
+    pub fn wait_for_next_frame(&mut self) {
+        self.frame_timer.wait_for_next_frame();
+    }
+
+    pub fn apply(&mut self, command: control::Command) {
+        match command {
+            control::Command::SetParameter(name, value) => {
+                match self.visualizer.set_parameter(&name, value) {
+                    Ok(_) => {
+                        let value = self.visualizer.parameter(&name).unwrap_or(value);
+                        self.automation.record(&name, value);
+                    },
+                    Err(e) => println!("{}", e),
+                }
+            },
+            control::Command::NudgeParameter(name, step) => match self.visualizer.parameter(&name) {
+                Some(value) => self.apply(control::Command::SetParameter(name, value + step)),
+                None => println!("Unknown parameter '{}'.", name),
+            },
+            control::Command::TapBeat => self.audio_input.tap_beat(),
+            control::Command::ToggleOverlay => self.visualizer.toggle_overlay(),
+            control::Command::ToggleAutomationRecording => self.automation.toggle_recording(),
+            control::Command::Screenshot => self.screenshot_requested = true,
+            control::Command::ToggleVideoRecording => {
+                if self.is_recording_video() {
+                    self.apply(control::Command::StopVideoRecording);
+                } else {
+                    self.apply(control::Command::StartVideoRecording);
+                }
+            },
+            control::Command::StartVideoRecording => {
+                if !self.is_recording_video() {
+                    let format = recorder::Format::Mp4;
+                    let path = screenshot::timestamped_path("recording", format.extension());
+                    self.start_recording(path, format, None);
+                }
+            },
+            control::Command::StopVideoRecording => self.stop_recording(recorder::Format::Mp4),
+            control::Command::RecordGif => {
+                let format = recorder::Format::Gif;
+                let path = screenshot::timestamped_path("recording", format.extension());
+                let length = self.gif_length;
+                self.start_recording(path, format, Some(length));
+            },
+            control::Command::SelectVisualizer(name) => {
+                if let Err(e) = self.visualizer.select(&name) {
+                    println!("{}", e);
+                }
+            },
+            control::Command::CycleVisualizer(step) => self.visualizer.cycle(step),
+            control::Command::ToggleBlackout => {
+                self.visualizer.toggle_blackout();
+                let brightness = self.visualizer.parameter("brightness").unwrap_or(0.0);
+                self.automation.record("brightness", brightness);
+            },
+            control::Command::SetPlaylist(playlist, time_per_visualizer) => {
+                if let Err(e) = self.visualizer.set_playlist(playlist, time_per_visualizer) {
+                    println!("{}", e);
+                }
+            },
+            control::Command::SetPalette(name, palette) => {
+                screen::show_on_each(&mut self.screens, |screen| screen.set_palette(palette.as_ref()));
+                if let Some(ref status) = self.status {
+                    status.lock().unwrap().palette_picked(&name);
+                }
+            },
+        }
+    }
+
+    fn is_recording_video(&self) -> bool {
+        self.recorders.iter().any(|x| x.format() == recorder::Format::Mp4)
+    }
+
+    fn start_recording(&mut self, path: PathBuf, format: recorder::Format, length: Option<Duration>) {
+        if self.recorders.iter().any(|x| x.format() == format) {
+            println!("Already recording a {}", format.extension());
+            return;
+        }
+
+        match Recorder::start(path, format, length, self.width as usize, self.height as usize, self.fps) {
+            Ok(recorder) => {
+                println!("Recording to {}", recorder.path().display());
+                self.recorders.push(recorder);
+            },
+            Err(e) => println!("Could not start recording, {}", e),
+        }
+    }
+
+    fn stop_recording(&mut self, format: recorder::Format) {
+        let (stopped, recording) = self.recorders.drain(..).partition(|x| x.format() == format);
+        self.recorders = recording;
+        finish_recordings(stopped);
+    }
+
+    // Add the bound framebuffer to every recording, finishing those that are done or failed.
+    fn record_frame(&mut self) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let pixels = self.read_pixels(width, height);
+
+        let mut finished = Vec::new();
+        for mut recorder in self.recorders.drain(..).collect::<Vec<_>>() {
+            let result = if recorder.size() != (width, height) {
+                // A clip can't change size partway through.
+                Err("the output was resized".to_string())
+            } else {
+                recorder.add_frame(&pixels)
+            };
+
+            match result {
+                Ok(_) if recorder.is_done() => finished.push(recorder),
+                Ok(_) => self.recorders.push(recorder),
+                Err(e) => {
+                    println!("Stopping the recording to {}, {}", recorder.path().display(), e);
+                    finished.push(recorder);
+                },
+            }
+        }
+        finish_recordings(finished);
+    }
+
+    fn dump_frame(&mut self) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let pixels = self.read_pixels(width, height);
+
+        self.dumped_frames += 1;
+        let path = match self.dump_frames_dir {
+            Some(ref dir) => dir.join(format!("frame-{:06}.png", self.dumped_frames)),
+            None => return,
+        };
+        if let Err(e) = screenshot::save_png(&path, width, height, &pixels) {
+            println!("Could not save frame {}, {}", self.dumped_frames, e);
+        }
+    }
+
+    fn save_screenshot(&self) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let pixels = self.read_pixels(width, height);
+
+        let path = screenshot::timestamped_path("screenshot", "png");
+        match screenshot::save_png(&path, width, height, &pixels) {
+            Ok(_) => println!("Saved a screenshot to {}", path.display()),
+            Err(e) => println!("Could not save a screenshot, {}", e),
+        }
+    }
+
+    // Read the bound framebuffer as tightly packed RGB rows, bottom to top.
     fn read_pixels(&self, width: usize, height: usize) -> Vec<u8> {
         let mut pixels = vec![0 as u8; 3 * width * height];
 
         unsafe {
+            self.gl.PixelStorei(gl::PACK_ALIGNMENT, 1);
             self.gl.ReadPixels(
                 0, 0,
                 width as i32, height as i32,
@@ -165,3 +1026,12 @@ impl GfxPipeline {
         pixels
     }
 }
+
+fn finish_recordings(recorders: Vec<Recorder>) {
+    for recorder in recorders {
+        let path = recorder.path().clone();
+        // Dropping it waits for ffmpeg to finish the file.
+        drop(recorder);
+        println!("Saved a recording to {}", path.display());
+    }
+}
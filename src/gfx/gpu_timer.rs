@@ -0,0 +1,112 @@
+use gfx;
+use gfx::gl;
+use std::time::Duration;
+
+// Results come back a few frames after they're measured, so queries are kept for this many
+// frames before being read rather than stalling on them.
+const FRAMES_IN_FLIGHT: usize = 4;
+
+// The stages of a frame that are timed on the GPU.
+#[derive(Clone, Copy)]
+pub enum Stage {
+    Visualizer = 0,
+    Screen = 1,
+}
+
+const STAGES: usize = 2;
+
+// Measures how long the GPU spends on each stage of a frame with timer queries, which the
+// CPU-side timings can't show as drawing only queues work for the GPU. Deleted when dropped.
+pub struct GpuTimer {
+    gl: gl::Gl,
+    // One query per stage for each frame in flight.
+    queries: [[gl::types::GLuint; STAGES]; FRAMES_IN_FLIGHT],
+    // Whether each frame's queries have been issued and not read yet.
+    pending: [bool; FRAMES_IN_FLIGHT],
+    frame: usize,
+}
+
+impl GpuTimer {
+    // Returns None when the driver has no timer queries.
+    pub fn new(gl: &gl::Gl) -> Option<GpuTimer> {
+        if !gl.GenQueriesEXT.is_loaded() || !gfx::has_extension(gl, "GL_EXT_disjoint_timer_query") {
+            return None;
+        }
+
+        let mut queries = [[0; STAGES]; FRAMES_IN_FLIGHT];
+        unsafe {
+            for frame_queries in queries.iter_mut() {
+                gl_try!(gl; gl.GenQueriesEXT(STAGES as i32, frame_queries.as_mut_ptr()));
+            }
+        }
+
+        Some(GpuTimer {
+            gl: gl.clone(),
+            queries,
+            pending: [false; FRAMES_IN_FLIGHT],
+            frame: 0,
+        })
+    }
+
+    pub fn begin(&self, stage: Stage) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.BeginQueryEXT(gl::TIME_ELAPSED_EXT, self.queries[self.frame][stage as usize]));
+        }
+    }
+
+    pub fn end(&self) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.EndQueryEXT(gl::TIME_ELAPSED_EXT));
+        }
+    }
+
+    // Finish timing this frame, returning the stage times of the oldest frame in flight if
+    // they're ready. Times spoiled by the GPU being reset or throttled are dropped.
+    pub fn end_frame(&mut self) -> Option<[Duration; STAGES]> {
+        self.pending[self.frame] = true;
+        self.frame = (self.frame + 1) % FRAMES_IN_FLIGHT;
+
+        // The next frame reuses the oldest queries, so they have to be read now.
+        if !self.pending[self.frame] {
+            return None;
+        }
+        self.pending[self.frame] = false;
+
+        let gl = &self.gl;
+        let mut times = [Duration::from_secs(0); STAGES];
+        unsafe {
+            for (stage, &query) in self.queries[self.frame].iter().enumerate() {
+                let mut available = 0;
+                gl_try!(gl; gl.GetQueryObjectuivEXT(query, gl::QUERY_RESULT_AVAILABLE_EXT, &mut available));
+                if available == 0 {
+                    return None;
+                }
+
+                let mut nanoseconds = 0;
+                gl_try!(gl; gl.GetQueryObjectui64vEXT(query, gl::QUERY_RESULT_EXT, &mut nanoseconds));
+                times[stage] = Duration::from_nanos(nanoseconds);
+            }
+
+            let mut disjoint = 0;
+            gl_try!(gl; gl.GetIntegerv(gl::GPU_DISJOINT_EXT, &mut disjoint));
+            if disjoint != 0 {
+                return None;
+            }
+        }
+
+        Some(times)
+    }
+}
+
+impl Drop for GpuTimer {
+    fn drop(&mut self) {
+        let gl = &self.gl;
+        unsafe {
+            for frame_queries in self.queries.iter() {
+                gl_try!(gl; gl.DeleteQueriesEXT(STAGES as i32, frame_queries.as_ptr()));
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use gfx::egl::{self, Egl, EglContext, EglDisplay, EglSharedContext};
+use libloading::Library;
+use std::fs::{File, OpenOptions};
+use std::os::raw::{c_int, c_void};
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::sync::Arc;
+
+// DRM devices to try, render nodes first since they don't need to be the display master.
+const DEVICE_PATHS: [&str; 3] = ["/dev/dri/renderD128", "/dev/dri/card0", "/dev/dri/card1"];
+
+// An OpenGL ES 2 context made straight on a DRM device through GBM and EGL, for rendering
+// without X or any other windowing system. It has no surface, so everything is drawn into
+// framebuffer objects.
+pub struct KmsContext {
+    egl: Arc<Egl>,
+    display: EglDisplay,
+    context: EglContext,
+    gbm: *mut c_void,
+    gbm_device_destroy: unsafe extern "C" fn(*mut c_void),
+    // Kept open for as long as the GBM device uses it.
+    _device: File,
+    _libgbm: Library,
+}
+
+impl KmsContext {
+    pub fn new() -> Result<KmsContext, String> {
+        let libgbm = egl::load_library(&["libgbm.so.1"])?;
+        let egl = Arc::new(Egl::load(&["libEGL.so.1"], &["libGLESv2.so.2"])?);
+
+        unsafe {
+            let gbm_create_device: unsafe extern "C" fn(c_int) -> *mut c_void =
+                *egl::symbol(&libgbm, b"gbm_create_device\0")?;
+            let gbm_device_destroy = *egl::symbol(&libgbm, b"gbm_device_destroy\0")?;
+
+            let device = open_device()?;
+            let gbm = gbm_create_device(device.as_raw_fd());
+            if gbm.is_null() {
+                return Err("could not create a GBM device".to_string());
+            }
+
+            // Cleans up after whichever step below fails.
+            let mut context = KmsContext {
+                egl,
+                display: ptr::null_mut(),
+                context: ptr::null_mut(),
+                gbm,
+                gbm_device_destroy,
+                _device: device,
+                _libgbm: libgbm,
+            };
+
+            // Mesa takes a GBM device as the native display.
+            context.display = context.egl.display(gbm)?;
+            context.context = context.egl.create_context(context.display, 0, ptr::null_mut())?.1;
+            context.egl.make_current(context.display, ptr::null_mut(), context.context)?;
+
+            Ok(context)
+        }
+    }
+
+    pub fn get_proc_address(&self, name: &str) -> *const c_void {
+        self.egl.get_proc_address(name)
+    }
+
+    // A context sharing this one's textures, for uploading from another thread.
+    pub fn shared_context(&self) -> Result<EglSharedContext, String> {
+        EglSharedContext::new(&self.egl, self.display, self.context)
+    }
+}
+
+impl Drop for KmsContext {
+    fn drop(&mut self) {
+        self.egl.destroy(self.display, ptr::null_mut(), self.context);
+        unsafe {
+            (self.gbm_device_destroy)(self.gbm);
+        }
+    }
+}
+
+fn open_device() -> Result<File, String> {
+    for path in DEVICE_PATHS.iter() {
+        if let Ok(file) = OpenOptions::new().read(true).write(true).open(path) {
+            return Ok(file);
+        }
+    }
+
+    Err(format!("could not open a DRM device, tried {}", DEVICE_PATHS.join(", ")))
+}
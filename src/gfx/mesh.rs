@@ -0,0 +1,94 @@
+use gfx::gl;
+use gfx::GlError;
+use std::cell::Cell;
+use std::ffi::CString;
+use std::mem;
+
+// A vertex buffer and the vertex array describing its layout, created once in setup and reused
+// every frame. Both are deleted when the mesh is dropped.
+pub struct Mesh {
+    gl: gl::Gl,
+    vb: u32,
+    vao: u32,
+    floats_per_vertex: usize,
+    vertex_count: Cell<usize>,
+}
+
+impl Mesh {
+    // `attributes` are the program's vertex attributes with their number of floats, in the order
+    // they're interleaved in the vertex data, e.g. `&[("position", 2), ("color", 3)]`. Fails
+    // with the first GL call that raises an error.
+    pub fn new(gl: &gl::Gl, program_id: u32, attributes: &[(&str, usize)]) -> Result<Mesh, GlError> {
+        let floats_per_vertex = attributes.iter().map(|&(_, size)| size).sum::<usize>();
+        let stride = (floats_per_vertex * mem::size_of::<f32>()) as gl::types::GLsizei;
+
+        unsafe {
+            // Deleted by dropping it if a call below fails.
+            let mut mesh = Mesh {
+                gl: gl.clone(),
+                vb: 0,
+                vao: 0,
+                floats_per_vertex,
+                vertex_count: Cell::new(0),
+            };
+            gl_result!(gl; gl.GenBuffers(1, &mut mesh.vb))?;
+            gl_result!(gl; gl.BindBuffer(gl::ARRAY_BUFFER, mesh.vb))?;
+
+            gl_result!(gl; gl.GenVertexArrays(1, &mut mesh.vao))?;
+            gl_result!(gl; gl.BindVertexArray(mesh.vao))?;
+
+            let mut offset = 0;
+            for &(name, size) in attributes.iter() {
+                let name = CString::new(name).unwrap();
+                let attrib = gl_result!(gl; gl.GetAttribLocation(program_id, name.as_ptr()))?;
+                // Attributes the shader doesn't use are optimized out.
+                if attrib >= 0 {
+                    gl_result!(gl; gl.VertexAttribPointer(
+                        attrib as gl::types::GLuint, size as i32, gl::FLOAT, 0,
+                        stride,
+                        (offset * mem::size_of::<f32>()) as *const () as *const _,
+                    ))?;
+                    gl_result!(gl; gl.EnableVertexAttribArray(attrib as gl::types::GLuint))?;
+                }
+                offset += size;
+            }
+
+            Ok(mesh)
+        }
+    }
+
+    // Replace the vertex data, keeping the buffer. Cheap enough to call every frame.
+    pub fn upload(&self, vertex_data: &[f32]) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.BindBuffer(gl::ARRAY_BUFFER, self.vb));
+            gl_try!(gl; gl.BufferData(
+                gl::ARRAY_BUFFER,
+                mem::size_of_val(vertex_data) as gl::types::GLsizeiptr,
+                vertex_data.as_ptr() as *const _,
+                gl::DYNAMIC_DRAW,
+            ));
+        }
+
+        self.vertex_count.set(vertex_data.len() / self.floats_per_vertex);
+    }
+
+    // Draw the uploaded vertices as triangles, with whatever program is in use.
+    pub fn draw(&self) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.BindVertexArray(self.vao));
+            gl_try!(gl; gl.DrawArrays(gl::TRIANGLES, 0, self.vertex_count.get() as i32));
+        }
+    }
+}
+
+impl Drop for Mesh {
+    fn drop(&mut self) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.DeleteVertexArrays(1, &self.vao));
+            gl_try!(gl; gl.DeleteBuffers(1, &self.vb));
+        }
+    }
+}
@@ -1,4 +1,40 @@
 #[macro_use]
 mod gfx;
+mod audio_input;
+mod calibration;
+#[cfg(all(feature = "dispmanx", not(feature = "kms")))]
+mod dispmanx;
+#[cfg(any(feature = "kms", feature = "dispmanx"))]
+mod egl;
+mod fit;
+mod framebuffer;
+mod frame_timer;
+mod gpu_timer;
+#[cfg(feature = "kms")]
+mod kms;
+mod mesh;
+mod peer_source;
+mod ping_pong;
+mod pixel_reader;
+mod program;
+mod recorder;
+mod resolution_scaler;
+mod screenshot;
+mod software;
+mod stats;
+mod test_pattern;
+mod texture;
+mod uniforms;
+mod uploader;
 
 pub use self::gfx::*;
+pub use self::fit::Fit;
+pub use self::framebuffer::Framebuffer;
+pub use self::mesh::Mesh;
+pub use self::ping_pong::PingPong;
+pub use self::pixel_reader::PixelReader;
+pub use self::program::Program;
+pub use self::screenshot::save_png;
+pub use self::test_pattern::TestPattern;
+pub use self::texture::Texture;
+pub use self::uploader::{Image, Uploader};
@@ -0,0 +1,18 @@
+use std::sync::mpsc;
+
+use gfx::gfx::Options;
+use screen;
+
+// Shows the frames a peer screen of another instance sends on the screens taking pixels, instead
+// of the visualizer, until the process exits. They're shown as they arrive, at their own size,
+// and encoded with --gamma like the visualizer's output, so both instances need the same.
+pub fn run(frames_rx: mpsc::Receiver<(Vec<u8>, i32, i32)>, mut screens: Vec<Box<dyn screen::Screen>>, options: Options) {
+    let output_gamma = if screens.iter().all(|x| x.is_linear()) { 1.0 } else { options.gamma };
+    for screen in screens.iter_mut() {
+        screen.set_output_gamma(output_gamma);
+    }
+
+    for (pixels, width, height) in frames_rx {
+        screen::show_on_each(&mut screens, |screen| screen.render_from_pixels(&pixels, width, height));
+    }
+}
@@ -0,0 +1,41 @@
+use gfx::framebuffer::Framebuffer;
+use gfx::gl;
+use gfx::texture::Texture;
+
+// Two framebuffers taking turns, so each frame can be drawn from the one before it: draw into
+// the bound one while sampling `previous`, then `swap`.
+pub struct PingPong {
+    targets: [Framebuffer; 2],
+    // Index of the target holding the last finished frame.
+    front: usize,
+}
+
+impl PingPong {
+    pub fn new(gl: &gl::Gl,
+               width: i32,
+               height: i32,
+               format: gl::types::GLenum) -> Result<PingPong, String> {
+        let first = Framebuffer::new(gl, Texture::new(gl, width, height, format, gl::LINEAR)?)?;
+        let second = Framebuffer::new(gl, Texture::new(gl, width, height, format, gl::LINEAR)?)?;
+
+        Ok(PingPong {
+            targets: [first, second],
+            front: 0,
+        })
+    }
+
+    // Direct drawing into the next frame.
+    pub fn bind(&self) {
+        self.targets[1 - self.front].bind();
+    }
+
+    // The last finished frame, to draw the next one from.
+    pub fn previous(&self) -> &Texture {
+        self.targets[self.front].texture()
+    }
+
+    // Finish the frame drawn since `bind`, making it the one returned by `previous`.
+    pub fn swap(&mut self) {
+        self.front = 1 - self.front;
+    }
+}
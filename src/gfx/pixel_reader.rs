@@ -0,0 +1,126 @@
+use gfx;
+use gfx::gl;
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+
+// Reads the bound framebuffer back to the CPU for screens that send pixels on, like LED drivers.
+// When asynchronous and the context has pixel buffer objects (GL ES 3), the read goes into a
+// buffer and is only collected a frame later, so the GPU isn't stalled waiting for it and the
+// copy overlaps the next frame's rendering. Otherwise it reads synchronously. Buffers are deleted
+// when dropped.
+pub struct PixelReader {
+    gl: gl::Gl,
+    // Read into in turn, the other holding the previous frame's read.
+    buffers: Option<[gl::types::GLuint; 2]>,
+    current: usize,
+    // Size of the reads in the buffers, and whether the other buffer has one waiting.
+    size: (usize, usize),
+    pending: bool,
+    pixels: Vec<u8>,
+}
+
+impl PixelReader {
+    pub fn new(gl: &gl::Gl, asynchronous: bool) -> PixelReader {
+        let mut buffers = None;
+        if asynchronous && supports_pixel_buffers(gl) {
+            let mut ids = [0; 2];
+            unsafe {
+                gl_try!(gl; gl.GenBuffers(2, ids.as_mut_ptr()));
+            }
+            buffers = Some(ids);
+        }
+
+        PixelReader {
+            gl: gl.clone(),
+            buffers,
+            current: 0,
+            size: (0, 0),
+            pending: false,
+            pixels: Vec::new(),
+        }
+    }
+
+    // Read the bound framebuffer as tightly packed RGB rows, bottom to top. When asynchronous
+    // these are the pixels read on the previous call, or black on the first call at a size.
+    pub fn read(&mut self, width: usize, height: usize) -> &[u8] {
+        let gl = &self.gl;
+        let length = 3 * width * height;
+
+        let buffers = match self.buffers {
+            Some(x) => x,
+            None => {
+                self.pixels.resize(length, 0);
+                unsafe {
+                    gl_try!(gl; gl.PixelStorei(gl::PACK_ALIGNMENT, 1));
+                    gl_try!(gl; gl.ReadPixels(
+                        0, 0,
+                        width as i32, height as i32,
+                        gl::RGB, gl::UNSIGNED_BYTE,
+                        self.pixels.as_mut_ptr() as *mut c_void));
+                }
+                return &self.pixels;
+            }
+        };
+
+        unsafe {
+            if self.size != (width, height) {
+                for &buffer in buffers.iter() {
+                    gl_try!(gl; gl.BindBuffer(gl::PIXEL_PACK_BUFFER, buffer));
+                    gl_try!(gl; gl.BufferData(gl::PIXEL_PACK_BUFFER, length as isize, ptr::null(), gl::STREAM_READ));
+                }
+                self.size = (width, height);
+                self.pending = false;
+                self.pixels = vec![0; length];
+            }
+
+            // Start this frame's read, which returns without waiting for it.
+            gl_try!(gl; gl.BindBuffer(gl::PIXEL_PACK_BUFFER, buffers[self.current]));
+            gl_try!(gl; gl.PixelStorei(gl::PACK_ALIGNMENT, 1));
+            gl_try!(gl; gl.ReadPixels(
+                0, 0,
+                width as i32, height as i32,
+                gl::RGB, gl::UNSIGNED_BYTE,
+                ptr::null_mut()));
+
+            // Then collect the previous frame's, which has had a frame to finish.
+            let previous = 1 - self.current;
+            if self.pending {
+                gl_try!(gl; gl.BindBuffer(gl::PIXEL_PACK_BUFFER, buffers[previous]));
+                let mapped = gl_try!(gl; gl.MapBufferRange(gl::PIXEL_PACK_BUFFER, 0, length as isize, gl::MAP_READ_BIT));
+                if !mapped.is_null() {
+                    self.pixels.copy_from_slice(slice::from_raw_parts(mapped as *const u8, length));
+                    gl_try!(gl; gl.UnmapBuffer(gl::PIXEL_PACK_BUFFER));
+                }
+            }
+
+            // Other reads into client memory would otherwise go into the buffer.
+            gl_try!(gl; gl.BindBuffer(gl::PIXEL_PACK_BUFFER, 0));
+            self.pending = true;
+            self.current = previous;
+        }
+
+        &self.pixels
+    }
+}
+
+impl Drop for PixelReader {
+    fn drop(&mut self) {
+        let gl = &self.gl;
+        if let Some(ref buffers) = self.buffers {
+            unsafe {
+                gl_try!(gl; gl.DeleteBuffers(2, buffers.as_ptr()));
+            }
+        }
+    }
+}
+
+// Mapping pixel buffers needs GL ES 3 or desktop GL 3.
+fn supports_pixel_buffers(gl: &gl::Gl) -> bool {
+    if !gl.MapBufferRange.is_loaded() || !gl.UnmapBuffer.is_loaded() {
+        return false;
+    }
+
+    let (_, major, _) = gfx::gl_version(gl);
+    major >= 3
+}
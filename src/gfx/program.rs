@@ -0,0 +1,160 @@
+use gfx::gl;
+use gfx::uniforms::Uniforms;
+use std::ptr;
+
+// A linked shader program along with its uniform locations. Deleted when dropped.
+pub struct Program {
+    gl: gl::Gl,
+    id: u32,
+    uniforms: Uniforms,
+}
+
+impl Program {
+    // Compile and link the shaders, which are null terminated GLSL source. Errors carry the
+    // info log from the driver.
+    pub fn new(gl: &gl::Gl, vs_src: &[u8], fs_src: &[u8]) -> Result<Program, String> {
+        let vs = compile_shader(gl, gl::VERTEX_SHADER, vs_src)
+            .map_err(|e| format!("vertex shader failed to compile: {}", e))?;
+        let fs = match compile_shader(gl, gl::FRAGMENT_SHADER, fs_src) {
+            Ok(x) => x,
+            Err(e) => {
+                unsafe { gl_try!(gl; gl.DeleteShader(vs)); }
+                return Err(format!("fragment shader failed to compile: {}", e));
+            }
+        };
+
+        unsafe {
+            let id = match gl_result!(gl; gl.CreateProgram()) {
+                Ok(x) => x,
+                Err(e) => {
+                    gl_try!(gl; gl.DeleteShader(vs));
+                    gl_try!(gl; gl.DeleteShader(fs));
+                    return Err(e.into());
+                }
+            };
+            let linked = gl_result!(gl; gl.AttachShader(id, vs))
+                .and_then(|_| gl_result!(gl; gl.AttachShader(id, fs)))
+                .and_then(|_| gl_result!(gl; gl.LinkProgram(id)));
+
+            // The program keeps what it needs from the shaders once linked.
+            gl_try!(gl; gl.DetachShader(id, vs));
+            gl_try!(gl; gl.DetachShader(id, fs));
+            gl_try!(gl; gl.DeleteShader(vs));
+            gl_try!(gl; gl.DeleteShader(fs));
+            if let Err(e) = linked {
+                gl_try!(gl; gl.DeleteProgram(id));
+                return Err(e.into());
+            }
+
+            if program_parameter(gl, id, gl::LINK_STATUS) == gl::FALSE as i32 {
+                let mut max_length = program_parameter(gl, id, gl::INFO_LOG_LENGTH);
+
+                let mut info_log = vec![0; max_length as usize];
+                gl_try!(gl; gl.GetProgramInfoLog(id, max_length, &mut max_length, info_log.as_mut_ptr()));
+                gl_try!(gl; gl.DeleteProgram(id));
+
+                return Err(format!("program failed to link: {}", info_log_to_string(&info_log)));
+            }
+
+            Ok(Program {
+                gl: gl.clone(),
+                id,
+                uniforms: Uniforms::load(gl, id),
+            })
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    // Make this the program used for drawing, and for setting uniforms.
+    pub fn bind(&self) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.UseProgram(self.id));
+        }
+    }
+
+    // Uniforms the program doesn't use are ignored, as they may have been optimized out.
+    pub fn set_f32(&self, name: &str, value: f32) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.Uniform1f(self.uniforms.location(name), value));
+        }
+    }
+
+    pub fn set_vec2(&self, name: &str, x: f32, y: f32) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.Uniform2f(self.uniforms.location(name), x, y));
+        }
+    }
+
+    pub fn set_i32(&self, name: &str, value: i32) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.Uniform1i(self.uniforms.location(name), value));
+        }
+    }
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.DeleteProgram(self.id));
+        }
+    }
+}
+
+fn compile_shader(gl: &gl::Gl, kind: gl::types::GLenum, src: &[u8]) -> Result<u32, String> {
+    unsafe {
+        let shader = gl_result!(gl; gl.CreateShader(kind))?;
+        let compiled = gl_result!(gl; gl.ShaderSource(shader, 1, [src.as_ptr() as *const _].as_ptr(), ptr::null()))
+            .and_then(|_| gl_result!(gl; gl.CompileShader(shader)));
+        if let Err(e) = compiled {
+            gl_try!(gl; gl.DeleteShader(shader));
+            return Err(e.into());
+        }
+
+        if shader_parameter(gl, shader, gl::COMPILE_STATUS) == gl::FALSE as i32 {
+            let mut max_length = shader_parameter(gl, shader, gl::INFO_LOG_LENGTH);
+
+            let mut info_log = vec![0; max_length as usize];
+            gl_try!(gl; gl.GetShaderInfoLog(shader, max_length, &mut max_length, info_log.as_mut_ptr()));
+            gl_try!(gl; gl.DeleteShader(shader));
+
+            return Err(info_log_to_string(&info_log));
+        }
+
+        Ok(shader)
+    }
+}
+
+// A program's parameter, such as its LINK_STATUS. 0 if the query fails.
+pub fn program_parameter(gl: &gl::Gl, id: u32, parameter: gl::types::GLenum) -> i32 {
+    let mut value = 0;
+    unsafe {
+        gl_try!(gl; gl.GetProgramiv(id, parameter, &mut value));
+    }
+    value
+}
+
+// A shader's parameter, such as its COMPILE_STATUS. 0 if the query fails.
+fn shader_parameter(gl: &gl::Gl, id: u32, parameter: gl::types::GLenum) -> i32 {
+    let mut value = 0;
+    unsafe {
+        gl_try!(gl; gl.GetShaderiv(id, parameter, &mut value));
+    }
+    value
+}
+
+fn info_log_to_string(info_log: &[gl::types::GLchar]) -> String {
+    info_log.iter()
+        .take_while(|x| **x != 0)
+        .map(|x| *x as u8 as char)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
@@ -0,0 +1,132 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Mp4,
+    // A looping GIF with a palette picked from the whole clip, best kept short and small.
+    Gif,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Format::Mp4 => "mp4",
+            Format::Gif => "gif",
+        }
+    }
+
+    fn ffmpeg_args(&self) -> &'static [&'static str] {
+        match *self {
+            Format::Mp4 => &[
+                // GL reads rows bottom to top, and yuv420p needs even sizes.
+                "-vf", "vflip,pad=ceil(iw/2)*2:ceil(ih/2)*2",
+                "-c:v", "libx264", "-pix_fmt", "yuv420p",
+                // Keep the file playable if the visualizer is killed rather than closed.
+                "-movflags", "frag_keyframe+empty_moov"],
+            Format::Gif => &[
+                "-vf", "vflip,split[a][b];[a]palettegen[p];[b][p]paletteuse",
+                "-loop", "0"],
+        }
+    }
+}
+
+// Encodes rendered frames by piping them to ffmpeg, which has to be on the PATH. Frames are
+// repeated or dropped to keep the clip in real time when rendering falls behind or runs ahead
+// of `fps`.
+pub struct Recorder {
+    path: PathBuf,
+    format: Format,
+    // How long to record for, or until stopped.
+    length: Option<Duration>,
+    width: usize,
+    height: usize,
+    fps: u32,
+    ffmpeg: Child,
+    stdin: Option<ChildStdin>,
+    start: Instant,
+    frames_written: u64,
+}
+
+impl Recorder {
+    pub fn start(path: PathBuf,
+                 format: Format,
+                 length: Option<Duration>,
+                 width: usize,
+                 height: usize,
+                 fps: u32) -> Result<Recorder, String> {
+        let fps = u32::max(1, fps);
+        let mut ffmpeg = Command::new("ffmpeg")
+            .args(["-loglevel", "error", "-y",
+                    "-f", "rawvideo", "-pix_fmt", "rgb24"])
+            .args(["-s", &format!("{}x{}", width, height), "-r", &fps.to_string()])
+            .args(["-i", "-"])
+            .args(format.ffmpeg_args())
+            .arg(&path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("could not run ffmpeg: {}", e))?;
+        let stdin = ffmpeg.stdin.take();
+
+        Ok(Recorder {
+            path,
+            format,
+            length,
+            width,
+            height,
+            fps,
+            ffmpeg,
+            stdin,
+            start: Instant::now(),
+            frames_written: 0,
+        })
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    // Whether a recording with a length has reached it.
+    pub fn is_done(&self) -> bool {
+        self.length.is_some_and(|x| self.start.elapsed() >= x)
+    }
+
+    // Add a frame of tightly packed RGB rows, as many times as the clip needs to catch up.
+    pub fn add_frame(&mut self, pixels: &[u8]) -> Result<(), String> {
+        let elapsed = self.start.elapsed();
+        let due = (elapsed.as_secs() * self.fps as u64)
+            + (elapsed.subsec_nanos() as u64 * self.fps as u64 / 1_000_000_000)
+            + 1;
+
+        let stdin = match self.stdin {
+            Some(ref mut x) => x,
+            None => return Err("ffmpeg has no input".to_string()),
+        };
+        while self.frames_written < due {
+            stdin.write_all(pixels).map_err(|e| format!("ffmpeg stopped taking frames: {}", e))?;
+            self.frames_written += 1;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // Closing ffmpeg's input lets it finish the file.
+        self.stdin = None;
+        if let Err(e) = self.ffmpeg.wait() {
+            println!("Could not finish recording {}: {}", self.path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+// How many frames to average rendering time over before changing the scale.
+const WINDOW_FRAMES: u32 = 30;
+// Rendering time, as a fraction of the frame interval, above which to scale down and below which
+// to scale back up. Scaling up by a step costs about 1.8 times as much, so these are far enough
+// apart not to flip back and forth.
+const SCALE_DOWN_ABOVE: f32 = 0.8;
+const SCALE_UP_BELOW: f32 = 0.4;
+const STEP: f32 = 0.75;
+const MIN_SCALE: f32 = 0.25;
+
+// Picks the fraction of their full size visualizers render at, from how long rendering takes,
+// so heavy visualizers get blurrier rather than stutter on slow GPUs.
+pub struct ResolutionScaler {
+    frame_interval: Duration,
+    scale: f32,
+    frames: u32,
+    render_time: Duration,
+}
+
+impl ResolutionScaler {
+    pub fn new(fps: u32) -> ResolutionScaler {
+        ResolutionScaler {
+            frame_interval: Duration::from_secs(1) / u32::max(1, fps),
+            scale: 1.0,
+            frames: 0,
+            render_time: Duration::from_secs(0),
+        }
+    }
+
+    // Count how long a frame took to render, returning a new scale when it should change.
+    pub fn count_frame(&mut self, render_time: Duration) -> Option<f32> {
+        self.frames += 1;
+        self.render_time += render_time;
+        if self.frames < WINDOW_FRAMES {
+            return None;
+        }
+
+        let load = duration_seconds(self.render_time) / self.frames as f32
+            / duration_seconds(self.frame_interval);
+        self.frames = 0;
+        self.render_time = Duration::from_secs(0);
+
+        let scale = if load > SCALE_DOWN_ABOVE {
+            f32::max(MIN_SCALE, self.scale * STEP)
+        } else if load < SCALE_UP_BELOW {
+            f32::min(1.0, self.scale / STEP)
+        } else {
+            self.scale
+        };
+
+        if scale == self.scale {
+            return None;
+        }
+        self.scale = scale;
+
+        Some(scale)
+    }
+}
+
+fn duration_seconds(duration: Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_nanos() as f32 / 1_000_000_000.0
+}
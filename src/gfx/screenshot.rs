@@ -0,0 +1,32 @@
+use png;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A file name in the working directory that sorts by when it was made, e.g. "screenshot" and
+// "png" give screenshot-1700000000-123.png.
+pub fn timestamped_path(prefix: &str, extension: &str) -> PathBuf {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    PathBuf::from(format!("{}-{}-{:03}.{}", prefix, now.as_secs(), now.subsec_millis(), extension))
+}
+
+// Write tightly packed RGB rows, bottom to top as GL reads them, to a PNG.
+pub fn save_png(path: &PathBuf, width: usize, height: usize, pixels: &[u8]) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("could not create {}: {}", path.display(), e))?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    // PNG rows go top to bottom.
+    let row_length = 3 * width;
+    let mut flipped = Vec::with_capacity(pixels.len());
+    for row in pixels.chunks(row_length).rev() {
+        flipped.extend_from_slice(row);
+    }
+
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(&flipped))
+        .map_err(|e| format!("could not write {}: {}", path.display(), e))
+}
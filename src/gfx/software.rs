@@ -0,0 +1,98 @@
+use std::sync::mpsc;
+
+use audio;
+use control;
+use gfx::audio_input::AudioInput;
+use gfx::frame_timer::FrameTimer;
+use gfx::gfx::Options;
+use screen;
+use visualizer;
+
+// Renders on the CPU until the process exits, for boards or containers where no GL context can
+// be made. Only screens that take pixels can show it, and only the simpler visualizers draw.
+pub fn run(mut visualizer: visualizer::Visualizer,
+           mut screens: Vec<Box<dyn screen::Screen>>,
+           mut automation: control::Automation,
+           audio_rx: mpsc::Receiver<audio::AudioFrame>,
+           commands_rx: mpsc::Receiver<control::Command>,
+           options: Options) {
+    let output_gamma = if screens.iter().all(|x| x.is_linear()) { 1.0 } else { options.gamma };
+    for screen in screens.iter_mut() {
+        screen.set_output_gamma(output_gamma);
+    }
+    visualizer.set_gamma(options.gamma, output_gamma);
+    visualizer.set_dithering(options.dither_bits);
+    if let Err(e) = visualizer.setup_software() {
+        println!("{}", e);
+        return;
+    }
+
+    let mut frame_timer = FrameTimer::new(options.fps);
+    let mut audio_input = AudioInput::new();
+    loop {
+        while let Ok(command) = commands_rx.try_recv() {
+            match command {
+                control::Command::SetParameter(name, value) => {
+                    if let Err(e) = visualizer.set_parameter(&name, value) {
+                        println!("{}", e);
+                    }
+                },
+                control::Command::NudgeParameter(name, step) => {
+                    let result = match visualizer.parameter(&name) {
+                        Some(value) => visualizer.set_parameter(&name, value + step),
+                        None => Err(format!("Unknown parameter '{}'.", name)),
+                    };
+                    if let Err(e) = result {
+                        println!("{}", e);
+                    }
+                },
+                control::Command::TapBeat => audio_input.tap_beat(),
+                control::Command::SelectVisualizer(name) => {
+                    if let Err(e) = visualizer.select(&name) {
+                        println!("{}", e);
+                    }
+                },
+                control::Command::CycleVisualizer(step) => visualizer.cycle(step),
+                control::Command::ToggleBlackout => visualizer.toggle_blackout(),
+                control::Command::SetPlaylist(playlist, time_per_visualizer) => {
+                    if let Err(e) = visualizer.set_playlist(playlist, time_per_visualizer) {
+                        println!("{}", e);
+                    }
+                },
+                control::Command::SetPalette(name, palette) => {
+                    screen::show_on_each(&mut screens, |screen| screen.set_palette(palette.as_ref()));
+                    if let Some(ref status) = options.status {
+                        status.lock().unwrap().palette_picked(&name);
+                    }
+                },
+                // The rest are for the window and recordings, which need GL.
+                _ => (),
+            }
+        }
+        audio_input.receive(&audio_rx);
+        for _ in 0..frame_timer.due_updates() {
+            let audio_frame = match audio_input.next_update() {
+                Some(x) => x,
+                None => break,
+            };
+
+            for (name, value) in automation.update(&audio_frame) {
+                if let Err(e) = visualizer.set_parameter(&name, value) {
+                    println!("Skipping automation event: {}", e);
+                }
+            }
+            screen::show_on_each(&mut screens, |screen| screen.update_audio(&audio_frame));
+            if let Some(ref status) = options.status {
+                status.lock().unwrap().audio_updated(&audio_frame);
+            }
+            visualizer.update(audio_frame);
+        }
+
+        let pixels = visualizer.render_software(options.width, options.height);
+        screen::show_on_each(&mut screens, |screen| screen.render_from_pixels(&pixels, options.width, options.height));
+        if let Some(ref status) = options.status {
+            status.lock().unwrap().frame_rendered(&mut visualizer, false);
+        }
+        frame_timer.wait_for_next_frame();
+    }
+}
@@ -0,0 +1,77 @@
+use std::time::{Duration, Instant};
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+// Frame rates and how long each stage of a frame takes, printed every REPORT_INTERVAL to help
+// pick a size and FPS the Pi can keep up with. Stage timings are measured on the CPU, so they
+// include waiting on the GPU only where a stage reads back from it, as LED screens do.
+pub struct FrameStats {
+    frames: u32,
+    audio_frames: u32,
+    visualizer_time: Duration,
+    screen_time: Duration,
+    // The same stages timed on the GPU, for the frames whose timings were read back.
+    gpu_frames: u32,
+    gpu_visualizer_time: Duration,
+    gpu_screen_time: Duration,
+    last_report: Instant,
+}
+
+impl FrameStats {
+    pub fn new() -> FrameStats {
+        FrameStats {
+            frames: 0,
+            audio_frames: 0,
+            visualizer_time: Duration::from_secs(0),
+            screen_time: Duration::from_secs(0),
+            gpu_frames: 0,
+            gpu_visualizer_time: Duration::from_secs(0),
+            gpu_screen_time: Duration::from_secs(0),
+            last_report: Instant::now(),
+        }
+    }
+
+    pub fn count_audio_frame(&mut self) {
+        self.audio_frames += 1;
+    }
+
+    // Count how long the GPU took on a frame's visualizer and screen.
+    pub fn count_gpu_frame(&mut self, visualizer_time: Duration, screen_time: Duration) {
+        self.gpu_frames += 1;
+        self.gpu_visualizer_time += visualizer_time;
+        self.gpu_screen_time += screen_time;
+    }
+
+    // Count a rendered frame, given how long the visualizer and the screen took on it.
+    pub fn count_frame(&mut self, visualizer_time: Duration, screen_time: Duration) {
+        self.frames += 1;
+        self.visualizer_time += visualizer_time;
+        self.screen_time += screen_time;
+
+        if self.last_report.elapsed() >= REPORT_INTERVAL {
+            self.report();
+        }
+    }
+
+    fn report(&mut self) {
+        let seconds = duration_seconds(self.last_report.elapsed());
+        let frames = u32::max(1, self.frames) as f32;
+        println!("{:.1} FPS, {:.1} audio frames/s, visualizer {:.2} ms, screen {:.2} ms per frame",
+                 self.frames as f32 / seconds,
+                 self.audio_frames as f32 / seconds,
+                 duration_seconds(self.visualizer_time) * 1000.0 / frames,
+                 duration_seconds(self.screen_time) * 1000.0 / frames);
+        if self.gpu_frames > 0 {
+            let gpu_frames = self.gpu_frames as f32;
+            println!("GPU: visualizer {:.2} ms, screen {:.2} ms per frame",
+                     duration_seconds(self.gpu_visualizer_time) * 1000.0 / gpu_frames,
+                     duration_seconds(self.gpu_screen_time) * 1000.0 / gpu_frames);
+        }
+
+        *self = FrameStats::new();
+    }
+}
+
+fn duration_seconds(duration: Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_nanos() as f32 / 1_000_000_000.0
+}
@@ -0,0 +1,103 @@
+use gfx::frame_timer::FrameTimer;
+use gfx::gfx::Options;
+use screen;
+
+// The colors of the bars, left to right, like broadcast color bars.
+const BARS: [(u8, u8, u8); 8] = [
+    (255, 255, 255),
+    (255, 255, 0),
+    (0, 255, 255),
+    (0, 255, 0),
+    (255, 0, 255),
+    (255, 0, 0),
+    (0, 0, 255),
+    (0, 0, 0),
+];
+
+// The colors the gradient fades to, in bands from the top.
+const GRADIENTS: [(u8, u8, u8); 4] = [
+    (255, 255, 255),
+    (255, 0, 0),
+    (0, 255, 0),
+    (0, 0, 255),
+];
+
+// A fixed picture shown instead of the visualizer, to check a screen's wiring, color order and
+// mapping without any audio.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TestPattern {
+    // Vertical bars of the primaries and their mixes, showing whether channels are swapped.
+    Bars,
+    // One pixel lit at a time, row by row from the top left, which stays red to show where
+    // the output starts.
+    Sweep,
+    // Bands fading from black on the left to white, red, green and blue on the right.
+    Gradient,
+    // Every pixel fully on, e.g. to check the power supply.
+    White,
+}
+
+impl TestPattern {
+    pub fn parse(value: &str) -> Result<TestPattern, String> {
+        match value {
+            "bars" => Ok(TestPattern::Bars),
+            "sweep" => Ok(TestPattern::Sweep),
+            "gradient" => Ok(TestPattern::Gradient),
+            "white" => Ok(TestPattern::White),
+
+            _ => Err(format!("unknown test pattern '{}', expected one of: bars, sweep, gradient, white", value)),
+        }
+    }
+
+    // Tightly packed RGB rows, bottom to top as GL would read them. `step` counts the updates
+    // so far, for the patterns that move.
+    pub fn draw(&self, width: usize, height: usize, step: usize) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity(3 * width * height);
+        for row in (0..height).rev() {
+            for column in 0..width {
+                let (r, g, b) = match *self {
+                    TestPattern::Bars => BARS[column * BARS.len() / width],
+                    TestPattern::Sweep => {
+                        let index = row * width + column;
+                        if index == step % (width * height) {
+                            (255, 255, 255)
+                        } else if index == 0 {
+                            (255, 0, 0)
+                        } else {
+                            (0, 0, 0)
+                        }
+                    },
+                    TestPattern::Gradient => {
+                        let (r, g, b) = GRADIENTS[row * GRADIENTS.len() / height];
+                        let level = if width > 1 { column as f32 / (width - 1) as f32 } else { 1.0 };
+                        let fade = |x: u8| (x as f32 * level + 0.5) as u8;
+                        (fade(r), fade(g), fade(b))
+                    },
+                    TestPattern::White => (255, 255, 255),
+                };
+                pixels.extend_from_slice(&[r, g, b]);
+            }
+        }
+
+        pixels
+    }
+}
+
+// Shows `pattern` on the screens taking pixels until the process exits. The sweep moves on a
+// pixel per update.
+pub fn run(pattern: TestPattern, mut screens: Vec<Box<dyn screen::Screen>>, options: Options) {
+    let output_gamma = if screens.iter().all(|x| x.is_linear()) { 1.0 } else { options.gamma };
+    for screen in screens.iter_mut() {
+        screen.set_output_gamma(output_gamma);
+    }
+
+    let (width, height) = (options.width as usize, options.height as usize);
+    let mut frame_timer = FrameTimer::new(options.fps);
+    let mut step = 0;
+    loop {
+        step += frame_timer.due_updates() as usize;
+        let pixels = pattern.draw(width, height, step);
+        screen::show_on_each(&mut screens, |screen| screen.render_from_pixels(&pixels, options.width, options.height));
+        frame_timer.wait_for_next_frame();
+    }
+}
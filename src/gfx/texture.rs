@@ -0,0 +1,136 @@
+use gfx::gl;
+use gfx::GlError;
+use std::mem;
+use std::ptr;
+
+// A 2D texture, of unsigned bytes unless made `with_storage`, clamped at the edges. Deleted when
+// dropped.
+pub struct Texture {
+    gl: gl::Gl,
+    id: u32,
+    width: i32,
+    height: i32,
+    format: gl::types::GLenum,
+}
+
+impl Texture {
+    // An empty texture, e.g. to render into. `format` is RGB or RGBA, `filter` LINEAR or NEAREST.
+    // Fails with the first GL call that raises an error, like running out of memory.
+    pub fn new(gl: &gl::Gl,
+               width: i32,
+               height: i32,
+               format: gl::types::GLenum,
+               filter: gl::types::GLenum) -> Result<Texture, GlError> {
+        Texture::with_storage(gl, width, height, format, format, gl::UNSIGNED_BYTE, filter)
+    }
+
+    // An empty texture stored as `internal_format`, e.g. RGBA16F with `format` RGBA and
+    // `data_type` HALF_FLOAT, to render into. Only textures from `new` can be uploaded to.
+    pub fn with_storage(gl: &gl::Gl,
+                        width: i32,
+                        height: i32,
+                        internal_format: gl::types::GLenum,
+                        format: gl::types::GLenum,
+                        data_type: gl::types::GLenum,
+                        filter: gl::types::GLenum) -> Result<Texture, GlError> {
+        unsafe {
+            let mut id = 0;
+            gl_result!(gl; gl.GenTextures(1, &mut id))?;
+            // Deleted by dropping it if a call below fails.
+            let texture = Texture {
+                gl: gl.clone(),
+                id,
+                width,
+                height,
+                format,
+            };
+            gl_result!(gl; gl.ActiveTexture(gl::TEXTURE0))?;
+            gl_result!(gl; gl.BindTexture(gl::TEXTURE_2D, id))?;
+
+            gl_result!(gl; gl.TexParameteri(
+                gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as i32
+            ))?;
+            gl_result!(gl; gl.TexParameteri(
+                gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as i32
+            ))?;
+            gl_result!(gl; gl.TexParameteri(
+                gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32
+            ))?;
+            gl_result!(gl; gl.TexParameteri(
+                gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32
+            ))?;
+
+            gl_result!(gl; gl.TexImage2D(
+                gl::TEXTURE_2D, 0, internal_format as i32,
+                width, height, 0, format, data_type,
+                ptr::null(),
+            ))?;
+
+            Ok(texture)
+        }
+    }
+
+    // Take ownership of a texture made in a context sharing `gl`'s, e.g. on an upload thread.
+    pub fn from_raw(gl: &gl::Gl, id: u32, width: i32, height: i32, format: gl::types::GLenum) -> Texture {
+        Texture {
+            gl: gl.clone(),
+            id,
+            width,
+            height,
+            format,
+        }
+    }
+
+    // Give up ownership of the texture without deleting it, to hand to a sharing context.
+    pub fn into_raw(self) -> u32 {
+        let id = self.id;
+        mem::forget(self);
+        id
+    }
+
+    // Replace the whole image with tightly packed rows, bottom to top.
+    pub fn upload(&self, pixels: &[u8]) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.ActiveTexture(gl::TEXTURE0));
+            gl_try!(gl; gl.BindTexture(gl::TEXTURE_2D, self.id));
+
+            gl_try!(gl; gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1));
+            gl_try!(gl; gl.TexImage2D(
+                gl::TEXTURE_2D, 0, self.format as i32,
+                self.width, self.height, 0, self.format, gl::UNSIGNED_BYTE,
+                pixels.as_ptr() as *const _,
+            ));
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    // Bind to a texture unit, for sampling from a shader.
+    pub fn bind(&self, unit: u32) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.ActiveTexture(gl::TEXTURE0 + unit));
+            gl_try!(gl; gl.BindTexture(gl::TEXTURE_2D, self.id));
+        }
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        let gl = &self.gl;
+        unsafe {
+            gl_try!(gl; gl.DeleteTextures(1, &self.id));
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use gfx::gl;
+use gfx::program;
+use std::collections::HashMap;
+use std::ffi::CString;
+
+// Locations of a linked program's uniforms, looked up once by name instead of every frame.
+pub struct Uniforms {
+    locations: HashMap<String, i32>,
+}
+
+impl Uniforms {
+    // Look up every uniform the program uses.
+    pub fn load(gl: &gl::Gl, program_id: u32) -> Uniforms {
+        let mut locations = HashMap::new();
+
+        let count = program::program_parameter(gl, program_id, gl::ACTIVE_UNIFORMS);
+        let max_length = program::program_parameter(gl, program_id, gl::ACTIVE_UNIFORM_MAX_LENGTH);
+
+        unsafe {
+            for index in 0..count as u32 {
+                let mut name = vec![0u8; max_length as usize];
+                let mut length = 0;
+                let mut size = 0;
+                let mut uniform_type = 0;
+                gl_try!(gl; gl.GetActiveUniform(
+                    program_id, index, max_length, &mut length, &mut size, &mut uniform_type,
+                    name.as_mut_ptr() as *mut _,
+                ));
+                name.truncate(length as usize);
+
+                // Arrays are reported as "name[0]", but are looked up by their plain name.
+                let name = String::from_utf8_lossy(&name).trim_end_matches("[0]").to_string();
+                let c_name = CString::new(name.clone()).unwrap();
+                let location = gl_try!(gl; gl.GetUniformLocation(program_id, c_name.as_ptr()));
+                locations.insert(name, location);
+            }
+        }
+
+        Uniforms { locations }
+    }
+
+    // The location of a uniform, or -1 if the program doesn't use it, which GL ignores when set.
+    pub fn location(&self, name: &str) -> i32 {
+        match self.locations.get(name) {
+            Some(x) => *x,
+            None => -1,
+        }
+    }
+}
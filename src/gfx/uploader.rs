@@ -0,0 +1,130 @@
+use gfx::gl;
+use gfx::texture::Texture;
+use std::os::raw::c_void;
+use std::sync::mpsc;
+use std::thread;
+
+// A GL context sharing textures with the render context, which can be made current on
+// another thread.
+pub trait SharedContext: Send {
+    fn make_current(&self) -> Result<(), String>;
+    fn get_proc_address(&self, name: &str) -> *const c_void;
+}
+
+// Pixels for a texture as tightly packed rows, bottom to top. `format` is RGB or RGBA.
+pub struct Image {
+    pub width: i32,
+    pub height: i32,
+    pub format: gl::types::GLenum,
+    pub pixels: Vec<u8>,
+}
+
+type Decode = Box<dyn FnOnce() -> Result<Image, String> + Send>;
+
+enum Upload {
+    // Made in the shared context, and finished.
+    Texture(u32, i32, i32, gl::types::GLenum),
+    // Still to upload, without a shared context.
+    Image(Image),
+}
+
+// Decodes and uploads large textures, like album art or sprite sheets, on a worker thread so
+// the render loop doesn't stall on them. With a shared context the upload happens there too,
+// otherwise only decoding does and `receive` uploads on the render thread. Textures are linearly
+// filtered. The worker is stopped when dropped.
+pub struct Uploader {
+    requests_tx: Option<mpsc::Sender<(u32, Decode)>>,
+    results_rx: mpsc::Receiver<(u32, Result<Upload, String>)>,
+    worker: Option<thread::JoinHandle<()>>,
+    next_id: u32,
+}
+
+impl Uploader {
+    pub fn new(shared_context: Option<Box<dyn SharedContext>>) -> Uploader {
+        let (requests_tx, requests_rx) = mpsc::channel::<(u32, Decode)>();
+        let (results_tx, results_rx) = mpsc::channel();
+
+        let worker = thread::spawn(move || {
+            let context = shared_context.and_then(|context| match context.make_current() {
+                Ok(_) => {
+                    let gl = gl::Gl::load_with(|name| context.get_proc_address(name));
+                    Some((gl, context))
+                },
+                Err(e) => {
+                    println!("Uploading textures on the render thread, {}", e);
+                    None
+                },
+            });
+
+            for (id, decode) in requests_rx {
+                let upload = decode().and_then(|image| match context {
+                    Some((ref gl, _)) => {
+                        let texture = Texture::new(gl, image.width, image.height, image.format, gl::LINEAR)?;
+                        texture.upload(&image.pixels);
+                        // The render context can only use it once it's finished.
+                        unsafe {
+                            gl_try!(gl; gl.Finish());
+                        }
+                        Ok(Upload::Texture(texture.into_raw(), image.width, image.height, image.format))
+                    },
+                    None => Ok(Upload::Image(image)),
+                });
+
+                if results_tx.send((id, upload)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Uploader {
+            requests_tx: Some(requests_tx),
+            results_rx,
+            worker: Some(worker),
+            next_id: 0,
+        }
+    }
+
+    // Run `decode` on the worker and upload what it returns, reported by `receive` under the
+    // returned id.
+    pub fn load<F>(&mut self, decode: F) -> u32 where F: FnOnce() -> Result<Image, String> + Send + 'static {
+        let id = self.next_id;
+        self.next_id += 1;
+        if let Some(ref requests_tx) = self.requests_tx {
+            // The worker only stops once this is dropped.
+            requests_tx.send((id, Box::new(decode))).unwrap();
+        }
+
+        id
+    }
+
+    // The textures finished since the last call, without waiting for any, under the ids `load`
+    // returned.
+    pub fn receive(&self, gl: &gl::Gl) -> Vec<(u32, Result<Texture, String>)> {
+        let mut textures = Vec::new();
+        while let Ok((id, upload)) = self.results_rx.try_recv() {
+            let texture = upload.and_then(|x| match x {
+                Upload::Texture(texture_id, width, height, format) =>
+                    Ok(Texture::from_raw(gl, texture_id, width, height, format)),
+                Upload::Image(image) => {
+                    let texture = Texture::new(gl, image.width, image.height, image.format, gl::LINEAR)?;
+                    texture.upload(&image.pixels);
+                    Ok(texture)
+                },
+            });
+            textures.push((id, texture));
+        }
+
+        textures
+    }
+}
+
+impl Drop for Uploader {
+    fn drop(&mut self) {
+        // Finishes the upload in progress, if any, so its context is released before the
+        // render context goes away.
+        self.requests_tx = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
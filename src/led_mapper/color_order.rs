@@ -0,0 +1,38 @@
+// The order an LED takes its red, green and blue in, like "grb" for most WS2812Bs, which varies
+// even between strips of the same chip.
+#[derive(Clone, Copy)]
+pub struct ColorOrder {
+    // Which of red, green and blue goes in each place.
+    channels: [usize; 3],
+}
+
+impl ColorOrder {
+    pub fn parse(value: &str) -> Result<ColorOrder, String> {
+        let mut channels = [0; 3];
+        let mut seen = [false; 3];
+        if value.chars().count() != 3 {
+            return Err(format!("unknown color order '{}', expected r, g and b in any order, like grb", value));
+        }
+        for (i, x) in value.chars().enumerate() {
+            let channel = match x.to_ascii_lowercase() {
+                'r' => 0,
+                'g' => 1,
+                'b' => 2,
+                _ => return Err(format!("unknown color order '{}', expected r, g and b in any order, like grb", value)),
+            };
+            if seen[channel] {
+                return Err(format!("color order '{}' has {} twice", value, x));
+            }
+            seen[channel] = true;
+            channels[i] = channel;
+        }
+
+        Ok(ColorOrder { channels })
+    }
+
+    // The color in the order the LED takes it.
+    pub fn channels(&self, r: u8, g: u8, b: u8) -> [u8; 3] {
+        let rgb = [r, g, b];
+        [rgb[self.channels[0]], rgb[self.channels[1]], rgb[self.channels[2]]]
+    }
+}
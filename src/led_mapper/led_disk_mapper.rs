@@ -1,8 +1,4 @@
 use std::f32;
-use std::os::raw::c_void;
-
-use gfx;
-use gfx::gl;
 
 // For use with DotStar RGB LED Disk - 240mm
 // https://adafruit.com/product/2477
@@ -20,77 +16,65 @@ static RINGS: [[u8; 2]; 10] = [
 ];
 
 pub const NUM_PIXELS: usize = 255;
+// Global brightness the disk's LEDs are driven at, from 0 to 1.
+pub const BRIGHTNESS: f32 = 0.08;
 // Distance units based on normalized point space of [-1, 1].
 pub const DISTANCE_BETWEEN_RINGS: f32 = 0.1;
 pub const PIXEL_RADIUS: f32 = 0.035;
 
-pub struct LedDiskMapper {
-    program_id: u32,
-}
+pub struct LedDiskMapper {}
 
 impl LedDiskMapper {
     pub fn new() -> LedDiskMapper {
-        LedDiskMapper { program_id: 0 }
+        LedDiskMapper {}
     }
 
-    // Convert from a texture image into a map of tightly packed RGB values mapping from
-    // pixel 0 to pixel 255. This mapping is generated by averaging colors near each pixel
-    // from the texture. This is done using 5 samples - center of pixel, and the four corners
-    // surrounded it (based on PIXEL_RADIUS).
-    pub fn map_from_texture(&self, gl: &gfx::gl::Gl, _texture: u32, size: i32) -> [(u8, u8, u8); NUM_PIXELS] {
-        unsafe {
-            gl_try!(gl; gl.UseProgram(self.program_id));
-
-            let width = size as usize;
-            let height = size as usize;
-            let mut pixels = vec![0 as u8; 3 * width * height];
-
-            gl_try!(gl; gl.ReadPixels(
-                0, 0,
-                width as i32, height as i32,
-                gl::RGB, gl::UNSIGNED_BYTE,
-                pixels.as_mut_ptr() as *mut c_void));
-
-            let get_index = |x: f32, y: f32| -> (usize, usize) {
-                (self.map_normalized_value(x, (width - 1) as f32) as usize,
-                 self.map_normalized_value(y, (height - 1) as f32) as usize)
-            };
-
-            let get_rgb = |x_index: usize, y_index: usize| -> (u8, u8, u8) {
-                let pixel_index = x_index * 3 + y_index * width * 3;
-                return (
-                    pixels[pixel_index],
-                    pixels[pixel_index + 1],
-                    pixels[pixel_index + 2],
-                );
-            };
-
-            let mut mapped_led_pixel_colors = [(0, 0, 0); NUM_PIXELS];
-            for pixel_index in 0..NUM_PIXELS {
-                let (x, y) = self.get_pixel_normalized_position(pixel_index as u8);
-
-                let normalized_sample_positions = [
-                    (x, y),
-                    (x - PIXEL_RADIUS, y - PIXEL_RADIUS),
-                    (x - PIXEL_RADIUS, y + PIXEL_RADIUS),
-                    (x + PIXEL_RADIUS, y - PIXEL_RADIUS),
-                    (x + PIXEL_RADIUS, y + PIXEL_RADIUS),
-                ];
-
-                let (mut r_avg, mut g_avg, mut b_avg) = (0, 0, 0);
-                for &(x, y) in normalized_sample_positions.iter() {
-                    let (x_index, y_index) = get_index(x, y);
-                    let (r, g, b) = get_rgb(x_index, y_index);
-                    r_avg += r / normalized_sample_positions.len() as u8;
-                    g_avg += g / normalized_sample_positions.len() as u8;
-                    b_avg += b / normalized_sample_positions.len() as u8;
-                }
-
-                mapped_led_pixel_colors[pixel_index] = (r_avg, g_avg, b_avg);
+    // Convert from tightly packed RGB rows, bottom to top, into the LED colors from pixel 0 to
+    // pixel 255. Each is generated by averaging colors near its pixel, using 5 samples - center
+    // of pixel, and the four corners surrounded it (based on PIXEL_RADIUS).
+    pub fn map_from_pixels(&self, pixels: &[u8], width: i32, height: i32) -> [(u8, u8, u8); NUM_PIXELS] {
+        let width = width as usize;
+        let height = height as usize;
+
+        let get_index = |x: f32, y: f32| -> (usize, usize) {
+            (self.map_normalized_value(x, (width - 1) as f32) as usize,
+             self.map_normalized_value(y, (height - 1) as f32) as usize)
+        };
+
+        let get_rgb = |x_index: usize, y_index: usize| -> (u8, u8, u8) {
+            let pixel_index = x_index * 3 + y_index * width * 3;
+            return (
+                pixels[pixel_index],
+                pixels[pixel_index + 1],
+                pixels[pixel_index + 2],
+            );
+        };
+
+        let mut mapped_led_pixel_colors = [(0, 0, 0); NUM_PIXELS];
+        for pixel_index in 0..NUM_PIXELS {
+            let (x, y) = self.get_pixel_normalized_position(pixel_index as u8);
+
+            let normalized_sample_positions = [
+                (x, y),
+                (x - PIXEL_RADIUS, y - PIXEL_RADIUS),
+                (x - PIXEL_RADIUS, y + PIXEL_RADIUS),
+                (x + PIXEL_RADIUS, y - PIXEL_RADIUS),
+                (x + PIXEL_RADIUS, y + PIXEL_RADIUS),
+            ];
+
+            let (mut r_avg, mut g_avg, mut b_avg) = (0, 0, 0);
+            for &(x, y) in normalized_sample_positions.iter() {
+                let (x_index, y_index) = get_index(x, y);
+                let (r, g, b) = get_rgb(x_index, y_index);
+                r_avg += r / normalized_sample_positions.len() as u8;
+                g_avg += g / normalized_sample_positions.len() as u8;
+                b_avg += b / normalized_sample_positions.len() as u8;
             }
 
-            mapped_led_pixel_colors
+            mapped_led_pixel_colors[pixel_index] = (r_avg, g_avg, b_avg);
         }
+
+        mapped_led_pixel_colors
     }
 
     // Given a pixel from 0..255, return it's (x, y) position as a tuple of two floats
@@ -0,0 +1,85 @@
+use std::ops::Range;
+
+// Orders the pixels of a frame as the LEDs of a strip or matrix, one per pixel. Matrix rows start
+// at the top left and snake back and forth, as most are wired, and a strip is a single row.
+pub fn map_from_pixels(pixels: &[u8], width: i32, height: i32) -> Vec<(u8, u8, u8)> {
+    let (width, height) = (width as usize, height as usize);
+    let mut pixel_colors = Vec::with_capacity(width * height);
+    for row in 0..height {
+        // Pixel rows are bottom to top.
+        let pixel_row = height - 1 - row;
+        for i in 0..width {
+            let column = if row % 2 == 0 { i } else { width - 1 - i };
+            let index = 3 * (pixel_row * width + column);
+            pixel_colors.push((pixels[index], pixels[index + 1], pixels[index + 2]));
+        }
+    }
+
+    pixel_colors
+}
+
+// Colors for a `columns` x `rows` grid of LEDs, in rows top to bottom, each the nearest pixel of
+// the frame scaled to the grid.
+pub fn sample_pixels(pixels: &[u8], width: i32, height: i32, columns: i32, rows: i32) -> Vec<(u8, u8, u8)> {
+    let mut pixel_colors = Vec::with_capacity((columns * rows) as usize);
+    for y in 0..rows {
+        // Pixel rows are bottom to top.
+        let pixel_row = height - 1 - y * height / rows;
+        for x in 0..columns {
+            let column = x * width / columns;
+            let index = 3 * (pixel_row * width + column) as usize;
+            pixel_colors.push((pixels[index], pixels[index + 1], pixels[index + 2]));
+        }
+    }
+
+    pixel_colors
+}
+
+// The average color of a frame's columns from `start` to `end`, top to bottom, or of the one at
+// `start` when there are none between, e.g. for an LED of a strip spanning them.
+pub fn average_columns(pixels: &[u8], width: usize, height: usize, start: usize, end: usize) -> (u8, u8, u8) {
+    let start = usize::min(start, width - 1);
+    average_region(pixels, width, start..usize::max(start + 1, end), 0..height)
+}
+
+// The average color of the pixels of a frame in `columns` and `rows`, the rows from the bottom
+// as the pixels are, e.g. for a light standing in that part of a room.
+pub fn average_region(pixels: &[u8], width: usize, columns: Range<usize>, rows: Range<usize>) -> (u8, u8, u8) {
+    let mut sums = [0u64; 3];
+    for row in rows.clone() {
+        for column in columns.clone() {
+            let index = 3 * (row * width + column);
+            for channel in 0..3 {
+                sums[channel] += pixels[index + channel] as u64;
+            }
+        }
+    }
+
+    let count = u64::max(1, (columns.len() * rows.len()) as u64);
+    ((sums[0] / count) as u8, (sums[1] / count) as u8, (sums[2] / count) as u8)
+}
+
+// Turn a `columns` x `rows` grid of LED colors, rows top to bottom, clockwise by `degrees`,
+// rounded down to a quarter turn, for matrices mounted on their side or upside down. Returns the
+// turned grid and its columns and rows.
+pub fn rotate(pixel_colors: &[(u8, u8, u8)], columns: i32, rows: i32, degrees: u32)
+              -> (Vec<(u8, u8, u8)>, i32, i32) {
+    let quarter_turns = degrees / 90 % 4;
+    let (turned_columns, turned_rows) = if quarter_turns.is_multiple_of(2) { (columns, rows) } else { (rows, columns) };
+
+    let mut turned = Vec::with_capacity(pixel_colors.len());
+    for y in 0..turned_rows {
+        for x in 0..turned_columns {
+            // Where the LED at (x, y) of the turned grid comes from.
+            let (column, row) = match quarter_turns {
+                1 => (y, rows - 1 - x),
+                2 => (columns - 1 - x, rows - 1 - y),
+                3 => (columns - 1 - y, x),
+                _ => (x, y),
+            };
+            turned.push(pixel_colors[(row * columns + column) as usize]);
+        }
+    }
+
+    (turned, turned_columns, turned_rows)
+}
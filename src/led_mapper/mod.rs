@@ -1,3 +1,20 @@
+// Only some screens take one.
+#[allow(dead_code)]
+pub mod color_order;
 pub mod led_disk_mapper;
+// Each matrix screen uses only some of it.
+#[allow(dead_code)]
+pub mod led_matrix_mapper;
+// Only screens behind features use it.
+#[allow(dead_code)]
+pub mod panel_layout;
+// Only some screens take one.
+#[allow(dead_code)]
+pub mod pixel_map;
+pub mod power_limiter;
 
+pub use led_mapper::color_order::ColorOrder;
 pub use led_mapper::led_disk_mapper::LedDiskMapper;
+pub use led_mapper::panel_layout::{PanelLayout, Wiring};
+pub use led_mapper::pixel_map::PixelMap;
+pub use led_mapper::power_limiter::PowerLimiter;
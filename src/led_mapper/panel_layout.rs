@@ -0,0 +1,118 @@
+use led_mapper::led_matrix_mapper;
+
+// How the LEDs of a row are wired, or the panels of a row of the grid are chained.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Wiring {
+    // Every row left to right.
+    Progressive,
+    // Back and forth, the second row right to left.
+    Serpentine,
+}
+
+impl Wiring {
+    pub fn parse(value: &str) -> Result<Wiring, String> {
+        match value {
+            "progressive" => Ok(Wiring::Progressive),
+            "serpentine" | "zigzag" => Ok(Wiring::Serpentine),
+
+            _ => Err(format!("unknown wiring '{}', expected one of: progressive, serpentine", value)),
+        }
+    }
+
+    // The column of the `i`th of `count` in `row`.
+    fn column(&self, i: i32, row: i32, count: i32) -> i32 {
+        match *self {
+            Wiring::Serpentine if row % 2 == 1 => count - 1 - i,
+            _ => i,
+        }
+    }
+}
+
+// A matrix made of a grid of panels chained one after the other, `panels_across` by
+// `panels_down`, each `panel_columns` x `panel_rows` LEDs where it sits in the grid, with 0 for
+// the output's size split between them. Panels are chained in rows from the top left by
+// `panel_order`, and each is wired from its own top left by `wiring`, turned clockwise by its
+// entry of `rotations` as mounted, the last entry repeating for the rest. One panel wired
+// serpentine is how most single matrices are.
+pub struct PanelLayout {
+    panel_columns: i32,
+    panel_rows: i32,
+    panels_across: i32,
+    panels_down: i32,
+    wiring: Wiring,
+    panel_order: Wiring,
+    rotations: Vec<u32>,
+}
+
+impl PanelLayout {
+    pub fn new(panel_columns: i32,
+               panel_rows: i32,
+               panels_across: i32,
+               panels_down: i32,
+               wiring: Wiring,
+               panel_order: Wiring,
+               rotations: Vec<u32>) -> Result<PanelLayout, String> {
+        if panels_across < 1 || panels_down < 1 {
+            return Err("there has to be at least one panel across and down".to_string());
+        }
+        if panel_columns < 0 || panel_rows < 0 {
+            return Err("panels can't have a negative size".to_string());
+        }
+        if let Some(x) = rotations.iter().find(|&&x| x % 90 != 0) {
+            return Err(format!("panels can only be turned by quarter turns, not {} degrees", x));
+        }
+
+        Ok(PanelLayout {
+            panel_columns,
+            panel_rows,
+            panels_across,
+            panels_down,
+            wiring,
+            panel_order,
+            rotations,
+        })
+    }
+
+    // Colors for every LED of a frame scaled to the matrix, in the order they're chained.
+    pub fn map(&self, pixels: &[u8], width: i32, height: i32) -> Vec<(u8, u8, u8)> {
+        let (panel_columns, panel_rows) = match (self.panel_columns, self.panel_rows) {
+            (0, _) | (_, 0) => (width / self.panels_across, height / self.panels_down),
+            x => x,
+        };
+        let (columns, rows) = (panel_columns * self.panels_across, panel_rows * self.panels_down);
+        if columns == 0 || rows == 0 {
+            return Vec::new();
+        }
+        let grid = led_matrix_mapper::sample_pixels(pixels, width, height, columns, rows);
+
+        let mut pixel_colors = Vec::with_capacity(grid.len());
+        for panel_y in 0..self.panels_down {
+            for i in 0..self.panels_across {
+                let panel_x = self.panel_order.column(i, panel_y, self.panels_across);
+                let panel = (panel_y * self.panels_across + panel_x) as usize;
+                let degrees = self.rotations.get(panel).or(self.rotations.last()).cloned().unwrap_or(0);
+                let quarter_turns = degrees / 90 % 4;
+                // The panel's own size, before it's turned into its place.
+                let (own_columns, own_rows) =
+                    if quarter_turns % 2 == 0 { (panel_columns, panel_rows) } else { (panel_rows, panel_columns) };
+
+                for own_y in 0..own_rows {
+                    for j in 0..own_columns {
+                        let own_x = self.wiring.column(j, own_y, own_columns);
+                        // Where that LED ends up in the grid once the panel is turned.
+                        let (x, y) = match quarter_turns {
+                            1 => (own_rows - 1 - own_y, own_x),
+                            2 => (own_columns - 1 - own_x, own_rows - 1 - own_y),
+                            3 => (own_y, own_columns - 1 - own_x),
+                            _ => (own_x, own_y),
+                        };
+                        let (x, y) = (panel_x * panel_columns + x, panel_y * panel_rows + y);
+                        pixel_colors.push(grid[(y * columns + x) as usize]);
+                    }
+                }
+            }
+        }
+
+        pixel_colors
+    }
+}
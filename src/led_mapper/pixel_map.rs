@@ -0,0 +1,267 @@
+use std::fs;
+use std::path::Path;
+
+// Where each LED of an irregular layout is, like a ring, a spiral or a costume, in the order
+// they're chained. Loaded from a CSV file of one "x,y" line per LED, or a JSON array of [x, y]
+// pairs or {"x": ..., "y": ...} objects, in any units with y growing down. The layout is scaled
+// to fit the frame, keeping its proportions, and each LED shows the pixel under it.
+pub struct PixelMap {
+    // From 0 to 1 across and down the frame.
+    points: Vec<(f32, f32)>,
+}
+
+impl PixelMap {
+    pub fn load(path: &str) -> Result<PixelMap, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+        let is_json = Path::new(path).extension().is_some_and(|x| x.eq_ignore_ascii_case("json"));
+        let points = if is_json { parse_json(&contents)? } else { parse_csv(&contents)? };
+        if points.is_empty() {
+            return Err(format!("{} has no LEDs", path));
+        }
+
+        Ok(PixelMap { points: normalize(points) })
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    // The color of each LED, in order, from a frame of tightly packed RGB rows, bottom to top.
+    pub fn sample(&self, pixels: &[u8], width: i32, height: i32) -> Vec<(u8, u8, u8)> {
+        self.points.iter()
+            .map(|&(x, y)| {
+                let column = i32::min(width - 1, (x * width as f32) as i32);
+                // Pixel rows are bottom to top.
+                let row = height - 1 - i32::min(height - 1, (y * height as f32) as i32);
+                let index = 3 * (row * width + column) as usize;
+                (pixels[index], pixels[index + 1], pixels[index + 2])
+            })
+            .collect()
+    }
+}
+
+// Lines of "x,y", with more columns, blank lines, # comments and a header line ignored. Both
+// have to be finite, as "nan" and "inf" parse too.
+fn parse_csv(contents: &str) -> Result<Vec<(f32, f32)>, String> {
+    let mut points = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.split(',').map(|x| x.trim().parse::<f32>());
+        match (fields.next(), fields.next()) {
+            (Some(Ok(x)), Some(Ok(y))) if x.is_finite() && y.is_finite() => points.push((x, y)),
+            _ if i == 0 => continue,
+            _ => return Err(format!("line {} isn't an x,y coordinate: {}", i + 1, line)),
+        }
+    }
+
+    Ok(points)
+}
+
+// Only as much JSON as an array of [x, y] pairs or {"x": ..., "y": ...} objects needs.
+fn parse_json(contents: &str) -> Result<Vec<(f32, f32)>, String> {
+    let mut tokens = JsonTokens { chars: contents.chars().peekable() };
+    let mut points = Vec::new();
+    tokens.expect('[')?;
+    if tokens.next_if(']') {
+        return Ok(points);
+    }
+    loop {
+        points.push(match tokens.next_char() {
+            Some('[') => {
+                let x = tokens.number()?;
+                tokens.expect(',')?;
+                let y = tokens.number()?;
+                // Anything after the coordinates, like a z, is skipped.
+                while !tokens.next_if(']') {
+                    tokens.expect(',')?;
+                    tokens.number()?;
+                }
+                (x, y)
+            }
+            Some('{') => {
+                let (mut x, mut y) = (None, None);
+                loop {
+                    let key = tokens.string()?;
+                    tokens.expect(':')?;
+                    match key.as_ref() {
+                        "x" => x = Some(tokens.number()?),
+                        "y" => y = Some(tokens.number()?),
+                        _ => tokens.scalar()?,
+                    }
+                    if tokens.next_if('}') {
+                        break;
+                    }
+                    tokens.expect(',')?;
+                }
+                match (x, y) {
+                    (Some(x), Some(y)) => (x, y),
+                    _ => return Err(format!("LED {} has no x and y", points.len())),
+                }
+            }
+            _ => return Err(format!("LED {} isn't an [x, y] pair or an object", points.len())),
+        });
+
+        if tokens.next_if(']') {
+            return Ok(points);
+        }
+        tokens.expect(',')?;
+    }
+}
+
+struct JsonTokens<'a> {
+    chars: ::std::iter::Peekable<::std::str::Chars<'a>>,
+}
+
+impl<'a> JsonTokens<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|x| x.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.next()
+    }
+
+    fn next_if(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&expected) {
+            self.chars.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next_char() {
+            Some(x) if x == expected => Ok(()),
+            Some(x) => Err(format!("expected '{}' but found '{}'", expected, x)),
+            None => Err(format!("expected '{}' but the file ended", expected)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    self.chars.next();
+                }
+                Some(x) => string.push(x),
+                None => return Err("a string runs to the end of the file".to_string()),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<f32, String> {
+        self.skip_whitespace();
+        let mut number = String::new();
+        while let Some(&x) = self.chars.peek() {
+            if !(x.is_ascii_digit() || x == '-' || x == '+' || x == '.' || x == 'e' || x == 'E') {
+                break;
+            }
+            number.push(x);
+            self.chars.next();
+        }
+        number.parse::<f32>().map_err(|_| format!("expected a number but found '{}'", number))
+    }
+
+    // Skip a string, number, true, false or null.
+    fn scalar(&mut self) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some(&'"') => self.string().map(|_| ()),
+            Some(x) if x.is_alphabetic() => {
+                while self.chars.peek().is_some_and(|x| x.is_alphabetic()) {
+                    self.chars.next();
+                }
+                Ok(())
+            }
+            _ => self.number().map(|_| ()),
+        }
+    }
+}
+
+// Scale the points' bounding box to fit from 0 to 1 both ways, keeping its proportions and
+// centering the shorter side.
+fn normalize(points: Vec<(f32, f32)>) -> Vec<(f32, f32)> {
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (points[0].0, points[0].1, points[0].0, points[0].1);
+    for &(x, y) in points.iter() {
+        min_x = f32::min(min_x, x);
+        max_x = f32::max(max_x, x);
+        min_y = f32::min(min_y, y);
+        max_y = f32::max(max_y, y);
+    }
+
+    // A single LED, or a line of them, is still spread over the frame's middle.
+    let size = f32::max(max_x - min_x, max_y - min_y);
+    let scale = if size > 0.0 { 1.0 / size } else { 0.0 };
+    let offset_x = 0.5 - (max_x - min_x) * scale / 2.0;
+    let offset_y = 0.5 - (max_y - min_y) * scale / 2.0;
+    points.iter()
+        .map(|&(x, y)| (offset_x + (x - min_x) * scale, offset_y + (y - min_y) * scale))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(csv: &str) -> PixelMap {
+        PixelMap { points: normalize(parse_csv(csv).unwrap()) }
+    }
+
+    #[test]
+    fn skips_blank_lines_comments_and_the_header() {
+        let points = parse_csv("x,y\n\n# the ring\n0,0\n  \n1, 2, 3\n").unwrap();
+        assert_eq!(points, vec![(0.0, 0.0), (1.0, 2.0)]);
+        assert!(parse_csv("\n\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_rows() {
+        assert!(parse_csv("0,0\n1\n").unwrap_err().contains("line 2"));
+        assert!(parse_csv("0,0\n1;2\n").is_err());
+        assert!(parse_csv("0,0\nx,1\n").is_err());
+        assert!(parse_csv("0,0\nnan,1\n").is_err());
+        assert!(parse_csv("0,0\n1,inf\n").is_err());
+        assert!(parse_json("[[0, 0], [1]]").is_err());
+        assert!(parse_json("[[0, 0], {\"x\": 1}]").is_err());
+        assert!(parse_json("[[0, 0]").is_err());
+    }
+
+    #[test]
+    fn samples_duplicate_leds_alike() {
+        let map = map("0,0\n1,1\n1,1\n");
+        assert_eq!(map.len(), 3);
+        // 2x2, bottom row first.
+        let pixels = [1, 1, 1, 2, 2, 2, 3, 3, 3, 4, 4, 4];
+        assert_eq!(map.sample(&pixels, 2, 2), vec![(3, 3, 3), (2, 2, 2), (2, 2, 2)]);
+    }
+
+    #[test]
+    fn fits_out_of_range_coordinates_in_the_frame() {
+        let map = map("-500,-500\n1500,1500\n500,40\n");
+        for &(x, y) in map.points.iter() {
+            assert!((0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y), "{},{} is outside the frame", x, y);
+        }
+        // The far corners land on the frame's edge pixels rather than past them.
+        let pixels: Vec<u8> = (0..4 * 4 * 3).map(|x| x as u8).collect();
+        let colors = map.sample(&pixels, 4, 4);
+        assert_eq!(colors[0], (pixels[36], pixels[37], pixels[38]));
+        assert_eq!(colors[1], (pixels[9], pixels[10], pixels[11]));
+    }
+
+    #[test]
+    fn centers_a_single_led() {
+        assert_eq!(map("7,7\n").points, vec![(0.5, 0.5)]);
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::{Duration, Instant};
+
+// Estimated current an APA102 or WS2812 LED draws per color channel at full PWM and global
+// brightness, and when dark, from their datasheets.
+const MILLIAMPS_PER_CHANNEL: f32 = 20.0;
+const IDLE_MILLIAMPS: f32 = 1.0;
+// Limiting is reported when it starts, and as over once the LEDs have stayed within the budget
+// this long, so a song's worth of flashes on beats is one report.
+const QUIET_TIME: Duration = Duration::from_secs(5);
+
+// Scales LED colors down when the current they'd draw is over a budget, so full-white flashes
+// on beats dim instead of browning out the power supply.
+pub struct PowerLimiter {
+    // What the LEDs are, like "the WS2812 LEDs", for the reports.
+    name: String,
+    budget_milliamps: f32,
+    // The LEDs' global brightness, from 0 to 1, which current scales with.
+    brightness: f32,
+    // While limiting, when it started, when it last limited a frame and the most the LEDs would
+    // have drawn.
+    limiting: Option<(Instant, Instant, f32)>,
+}
+
+impl PowerLimiter {
+    // A budget of 0 doesn't limit.
+    pub fn new(name: &str, budget_milliamps: u32, brightness: f32) -> PowerLimiter {
+        PowerLimiter {
+            name: name.to_string(),
+            budget_milliamps: budget_milliamps as f32,
+            brightness,
+            limiting: None,
+        }
+    }
+
+    // The current the LEDs would draw showing these colors.
+    pub fn estimate_milliamps(&self, pixel_colors: &[(u8, u8, u8)]) -> f32 {
+        let channels: u32 = pixel_colors.iter()
+            .map(|&(r, g, b)| r as u32 + g as u32 + b as u32)
+            .sum();
+
+        pixel_colors.len() as f32 * IDLE_MILLIAMPS
+            + channels as f32 / 255.0 * MILLIAMPS_PER_CHANNEL * self.brightness
+    }
+
+    // Scale the colors down to fit the budget if they're over it.
+    pub fn limit(&mut self, pixel_colors: &mut [(u8, u8, u8)]) {
+        if self.budget_milliamps <= 0.0 {
+            return;
+        }
+
+        let milliamps = self.estimate_milliamps(pixel_colors);
+        let idle = pixel_colors.len() as f32 * IDLE_MILLIAMPS;
+        let lit = milliamps - idle;
+        let available = f32::max(0.0, self.budget_milliamps - idle);
+        if lit <= available {
+            if let Some((start, last, most)) = self.limiting {
+                if last.elapsed() >= QUIET_TIME {
+                    println!("Stopped limiting {} after {} seconds, at most they'd have drawn {:.0} mA",
+                             self.name, (last - start).as_secs(), most);
+                    self.limiting = None;
+                }
+            }
+            return;
+        }
+
+        let now = Instant::now();
+        match self.limiting {
+            Some((_, ref mut last, ref mut most)) => {
+                *last = now;
+                *most = f32::max(*most, milliamps);
+            },
+            None => {
+                println!("Limiting {} to {} mA, they'd draw {:.0} mA", self.name, self.budget_milliamps, milliamps);
+                self.limiting = Some((now, now, milliamps));
+            },
+        }
+
+        let scale = available / lit;
+        let scale_channel = |x: u8| (x as f32 * scale) as u8;
+        for color in pixel_colors.iter_mut() {
+            *color = (scale_channel(color.0), scale_channel(color.1), scale_channel(color.2));
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use audio;
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+// Pass audio frames from `rx` on to `tx`, saving each to a file that `replay_audio_file` can
+// play back, so a visualizer can be run again on exactly the same input.
+pub fn record_audio_file(rx: mpsc::Receiver<audio::AudioFrame>, tx: mpsc::SyncSender<audio::AudioFrame>,
+                         path: PathBuf) {
+    let mut file = match File::create(&path) {
+        Ok(x) => BufWriter::new(x),
+        Err(e) => panic!("Could not record audio to {}: {}", path.display(), e),
+    };
+    println!("Recording audio frames to {}.", path.display());
+
+    let started = time::Instant::now();
+    for audio_frame in rx.iter() {
+        let seconds = duration_seconds(started.elapsed());
+        if let Err(e) = file.write_all(serialize(seconds, &audio_frame).as_bytes()).and_then(|_| file.flush()) {
+            println!("Stopped recording audio to {}: {}", path.display(), e);
+            break;
+        }
+
+        if tx.send(audio_frame).is_err() {
+            return;
+        }
+    }
+
+    // Keep the visualizer going if the file can't be written to anymore.
+    for audio_frame in rx.iter() {
+        if tx.send(audio_frame).is_err() {
+            return;
+        }
+    }
+}
+
+// Send the audio frames saved by `record_audio_file`. In real time they are sent with their
+// recorded timing, looping forever, otherwise each is sent once as soon as the last was taken.
+pub fn replay_audio_file(tx: mpsc::SyncSender<audio::AudioFrame>, path: PathBuf, real_time: bool) {
+    let contents = match fs::read_to_string(&path) {
+        Ok(x) => x,
+        Err(e) => panic!("Could not read audio frames from {}: {}", path.display(), e),
+    };
+    let frames = match deserialize(&contents) {
+        Ok(x) => x,
+        Err(e) => panic!("Could not parse audio frames in {}: {}", path.display(), e),
+    };
+    if frames.is_empty() {
+        panic!("No audio frames in {}.", path.display());
+    }
+    println!("Replaying {} audio frames from {}.", frames.len(), path.display());
+
+    loop {
+        let started = time::Instant::now();
+        for &(seconds, ref audio_frame) in frames.iter() {
+            if real_time {
+                let elapsed = duration_seconds(started.elapsed());
+                if seconds > elapsed {
+                    thread::sleep(time::Duration::from_millis(((seconds - elapsed) * 1000.0) as u64));
+                }
+            }
+
+            if tx.send(audio_frame.clone()).is_err() {
+                return;
+            }
+        }
+
+        if !real_time {
+            // Dropping the sender tells the visualizer the replay is over.
+            return;
+        }
+    }
+}
+
+// One frame per line as "<seconds> <bpm> <is_beat> <is_silent>", then the hundred hz buckets.
+fn serialize(seconds: f32, audio_frame: &audio::AudioFrame) -> String {
+    let mut line = format!("{} {} {} {}", seconds, audio_frame.bpm,
+                           audio_frame.is_beat as u8, audio_frame.is_silent as u8);
+    for bucket in audio_frame.hundred_hz_buckets.iter() {
+        line.push_str(&format!(" {}", bucket));
+    }
+    line.push('\n');
+
+    line
+}
+
+fn deserialize(contents: &str) -> Result<Vec<(f32, audio::AudioFrame)>, String> {
+    let mut frames = Vec::new();
+
+    for (line_index, line) in contents.lines().enumerate() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.is_empty() {
+            continue;
+        }
+
+        let mut hundred_hz_buckets = [0.0; 200];
+        if parts.len() != 4 + hundred_hz_buckets.len() {
+            return Err(format!("line {}: expected <seconds> <bpm> <is_beat> <is_silent> and {} buckets",
+                               line_index + 1, hundred_hz_buckets.len()));
+        }
+
+        let parse = |i: usize| parts[i].parse::<f32>()
+            .map_err(|_| format!("line {}: invalid number '{}'", line_index + 1, parts[i]));
+        for (i, bucket) in hundred_hz_buckets.iter_mut().enumerate() {
+            *bucket = parse(4 + i)?;
+        }
+
+        frames.push((parse(0)?, audio::AudioFrame {
+            bpm: parse(1)?,
+            is_beat: parse(2)? != 0.0,
+            hundred_hz_buckets,
+            is_silent: parse(3)? != 0.0,
+        }));
+    }
+
+    Ok(frames)
+}
+
+fn duration_seconds(duration: time::Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_nanos() as f32 / 1_000_000_000.0
+}
@@ -0,0 +1,80 @@
+use std::time;
+
+// Windows of energy history to compare against, roughly a second of audio.
+const HISTORY_LENGTH: usize = 24;
+// How far above the recent average the energy has to jump to count as a beat.
+const BEAT_THRESHOLD: f32 = 1.4;
+// Beats closer together than this are treated as the same beat, capping detection at 200 BPM.
+const MIN_BEAT_INTERVAL_SECONDS: f32 = 0.3;
+// Intervals longer than this are treated as a pause in the music rather than a slow tempo.
+const MAX_BEAT_INTERVAL_SECONDS: f32 = 2.0;
+const INTERVAL_COUNT: usize = 8;
+
+// Detects beats as sudden jumps in low frequency energy, and estimates the tempo from the
+// intervals between them.
+pub struct BeatDetector {
+    history: Vec<f32>,
+    last_beat: Option<time::Instant>,
+    intervals: Vec<f32>,
+}
+
+impl BeatDetector {
+    pub fn new() -> BeatDetector {
+        BeatDetector {
+            history: Vec::with_capacity(HISTORY_LENGTH),
+            last_beat: None,
+            intervals: Vec::with_capacity(INTERVAL_COUNT),
+        }
+    }
+
+    // Feed the low frequency energy of a window. Returns whether it's a beat, and the current
+    // tempo estimate in beats per minute, which is 0 until enough beats have been heard.
+    pub fn update(&mut self, energy: f32) -> (bool, f32) {
+        let average = match self.history.len() {
+            0 => energy,
+            _ => self.history.iter().sum::<f32>() / self.history.len() as f32,
+        };
+
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.remove(0);
+        }
+        self.history.push(energy);
+
+        let since_last_beat = self.last_beat.map(|x| duration_seconds(x.elapsed()));
+        let is_beat = self.history.len() == HISTORY_LENGTH &&
+            energy > average * BEAT_THRESHOLD &&
+            since_last_beat.is_none_or(|x| x >= MIN_BEAT_INTERVAL_SECONDS);
+
+        if is_beat {
+            if let Some(interval) = since_last_beat {
+                if interval <= MAX_BEAT_INTERVAL_SECONDS {
+                    if self.intervals.len() == INTERVAL_COUNT {
+                        self.intervals.remove(0);
+                    }
+                    self.intervals.push(interval);
+                } else {
+                    self.intervals.clear();
+                }
+            }
+            self.last_beat = Some(time::Instant::now());
+        }
+
+        (is_beat, self.bpm())
+    }
+
+    // Uses the median interval, so a missed or doubled beat doesn't throw the estimate off.
+    fn bpm(&self) -> f32 {
+        if self.intervals.len() < 2 {
+            return 0.0;
+        }
+
+        let mut intervals = self.intervals.clone();
+        intervals.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        60.0 / intervals[intervals.len() / 2]
+    }
+}
+
+fn duration_seconds(duration: time::Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_nanos() as f32 / 1_000_000_000.0
+}
@@ -1,14 +1,26 @@
 use audio;
-use sphinxad_sys::{ad_open_sps, ad_read, ad_start_rec};
+use listen::{BeatDetector, SilenceDetector};
+use sphinxad_sys::{ad_open_dev, ad_open_sps, ad_read, ad_start_rec};
 use rand::prelude::*;
+use std::ffi::CString;
 use std::sync::mpsc;
 use std::thread;
 use std::time;
 
-pub fn visualize_microphone(tx: mpsc::SyncSender<audio::AudioFrame>,
-    samples_per_second: u32, window_sample_size: usize, amplitude_scalar: f32) {
+// Listens to the ALSA `device`, like "plughw:1,0", or the default one when it's empty.
+pub fn visualize_microphone(tx: mpsc::SyncSender<audio::AudioFrame>, device: String,
+    samples_per_second: u32, window_sample_size: usize, amplitude_scalar: f32,
+    mut silence_detector: SilenceDetector) {
     println!("Connecting to microphone.");
-    let ad = unsafe { ad_open_sps(samples_per_second) };
+    let ad = if device.is_empty() {
+        unsafe { ad_open_sps(samples_per_second) }
+    } else {
+        let name = CString::new(device.clone()).expect("Audio device names can't contain NUL.");
+        unsafe { ad_open_dev(name.as_ptr(), samples_per_second) }
+    };
+    if ad.is_null() {
+        panic!("Could not open the microphone {}.", if device.is_empty() { "default" } else { &device });
+    }
     let rec_successful = unsafe { ad_start_rec(ad) } == 0;
     if !rec_successful {
         panic!("Could not start recording microphone.");
@@ -21,6 +33,7 @@ pub fn visualize_microphone(tx: mpsc::SyncSender<audio::AudioFrame>,
     let mut buffer = vec![0; samples_per_second as usize];
     let raw_buffer = buffer.as_mut_ptr();
 
+    let mut beat_detector = BeatDetector::new();
     let mut average_amplitudes = vec![0.0; 0];  // Store the average amplitudes over the last 800 samples.
     let mut amplitude_scalar = amplitude_scalar;
     loop {
@@ -38,7 +51,9 @@ pub fn visualize_microphone(tx: mpsc::SyncSender<audio::AudioFrame>,
             continue;
         }
 
-        let average_amplitude = visualize_samples(&window[0..window_sample_size].to_vec(), duration_seconds, amplitude_scalar, &tx);
+        let is_silent = silence_detector.update(&window[0..window_sample_size]);
+        let average_amplitude = visualize_samples(&window[0..window_sample_size].to_vec(), duration_seconds,
+                                                  amplitude_scalar, is_silent, &mut beat_detector, &tx);
         average_amplitudes.push(average_amplitude);
         if average_amplitudes.len() > 400 {
             average_amplitudes.drain(0..1);
@@ -52,13 +67,13 @@ pub fn visualize_microphone(tx: mpsc::SyncSender<audio::AudioFrame>,
 
 pub fn visualize_fake(tx: mpsc::SyncSender<audio::AudioFrame>) {
 	let mut rng = thread_rng();
+	let mut frame_count: u32 = 0;
 
 	loop {
-		let bpm = 0.0;
-
-		let low_power = rng.gen();
-		let mid_power = rng.gen();
-		let high_power = rng.gen();
+		// A steady 120 BPM, as frames are sent every 50ms.
+		let bpm = 120.0;
+		let is_beat = frame_count.is_multiple_of(10);
+		frame_count = frame_count.wrapping_add(1);
 
 		let mut hundred_hz_buckets = [0.0; 200];
 		for i in 0..hundred_hz_buckets.len() {
@@ -67,12 +82,9 @@ pub fn visualize_fake(tx: mpsc::SyncSender<audio::AudioFrame>) {
 
 		let audio_frame = audio::AudioFrame {
 			bpm,
-
-			low_power,
-			mid_power,
-			high_power,
-
+			is_beat,
 			hundred_hz_buckets,
+			is_silent: false,
 		};
 
 		tx.send(audio_frame).unwrap();
@@ -82,7 +94,7 @@ pub fn visualize_fake(tx: mpsc::SyncSender<audio::AudioFrame>) {
 }
 
 fn visualize_samples(samples: &Vec<f32>, duration_seconds: f32, amplitude_scalar: f32,
-    tx: &mpsc::SyncSender<audio::AudioFrame>) -> f32 {
+    is_silent: bool, beat_detector: &mut BeatDetector, tx: &mpsc::SyncSender<audio::AudioFrame>) -> f32 {
     let samples_per_second = (samples.len() as f32 / duration_seconds).ceil();
     let frequency_bins = audio::frequency_bins(
         samples_per_second as u32,
@@ -91,44 +103,20 @@ fn visualize_samples(samples: &Vec<f32>, duration_seconds: f32, amplitude_scalar
     let fft_output = audio::compute_fft(samples.to_vec());
     let amplitudes = audio::to_amplitude(fft_output, amplitude_scalar);
 
-    let low_threshold_hz = 1000.0;
-    let mid_threshold_hz = 4000.0;
-    let high_threshold_hz = 20000.0;
-
-    let mut low_power = 0.0;
-    let mut mid_power = 0.0;
-    let mut high_power = 0.0;
-
-    for i in 0..frequency_bins.len() {
-        if frequency_bins[i] <= low_threshold_hz {
-            low_power += amplitudes[i];
-        } else if frequency_bins[i] <= mid_threshold_hz {
-            mid_power += amplitudes[i];
-        } else if frequency_bins[i] <= high_threshold_hz {
-            high_power += amplitudes[i];
-        }
-    }
-
-    low_power = f32::min(1.0, low_power);
-    mid_power = f32::min(1.0, mid_power);
-    high_power = f32::min(1.0, high_power);
-
     let mut hundred_hz_buckets = [0.0; 200];
     for i in 0..frequency_bins.len() {
         let hundred_hz_bucket_index = (frequency_bins[i] / 100.0).floor() as usize;
         hundred_hz_buckets[hundred_hz_bucket_index] += amplitudes[i];
     }
 
-    let bpm = 0.0;
+    // Kicks and bass lines sit below 200hz.
+    let (is_beat, bpm) = beat_detector.update(hundred_hz_buckets[0] + hundred_hz_buckets[1]);
 
     let audio_frame = audio::AudioFrame {
         bpm,
-
-        low_power,
-        mid_power,
-        high_power,
-
+        is_beat,
         hundred_hz_buckets,
+        is_silent,
     };
 
     tx.send(audio_frame).unwrap();
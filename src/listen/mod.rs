@@ -1,3 +1,9 @@
+mod audio_file;
+mod beat_detector;
 mod listener;
+mod silence_detector;
 
+pub use self::audio_file::*;
+pub use self::beat_detector::BeatDetector;
 pub use self::listener::*;
+pub use self::silence_detector::SilenceDetector;
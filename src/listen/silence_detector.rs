@@ -0,0 +1,38 @@
+use std::time;
+
+// Tracks how long the microphone has been quiet. This looks at the raw signal level, since
+// the automatic amplitude scaling in the listener boosts silence up to the same loudness as music.
+pub struct SilenceDetector {
+    threshold: f32,
+    timeout: time::Duration,
+    quiet_since: Option<time::Instant>,
+}
+
+impl SilenceDetector {
+    // A threshold of 0 disables detection, as no window can be quieter than that.
+    pub fn new(threshold: f32, timeout: time::Duration) -> SilenceDetector {
+        SilenceDetector {
+            threshold,
+            timeout,
+            quiet_since: None,
+        }
+    }
+
+    // Feed a window of samples in [-1, 1]. Returns whether every window for at least the
+    // timeout has had a root mean square level below the threshold.
+    pub fn update(&mut self, samples: &[f32]) -> bool {
+        let rms = (samples.iter().map(|x| x * x).sum::<f32>() / samples.len() as f32).sqrt();
+        if rms >= self.threshold {
+            self.quiet_since = None;
+            return false;
+        }
+
+        match self.quiet_since {
+            Some(quiet_since) => quiet_since.elapsed() >= self.timeout,
+            None => {
+                self.quiet_since = Some(time::Instant::now());
+                false
+            }
+        }
+    }
+}
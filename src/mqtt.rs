@@ -0,0 +1,220 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+const PORT: u16 = 1883;
+const TIMEOUT: Duration = Duration::from_secs(2);
+pub const PING_PACKET: [u8; 2] = [0xc0, 0x00];
+const PUBLISH: u8 = 0x30;
+
+// What the broker publishes, retained, for a client whose connection is lost.
+pub struct Will<'a> {
+    pub topic: &'a str,
+    pub payload: &'a [u8],
+}
+
+// Connect to the MQTT broker at "[user:password@]broker[:port]" as `client_id`, with a clean
+// session. With a `keep_alive`, the client has to send something that often or be dropped.
+pub fn connect(address: &str, client_id: &str, keep_alive: Duration, will: Option<Will>) -> Result<TcpStream, String> {
+    let (credentials, broker) = match address.rfind('@') {
+        Some(i) => (Some(&address[..i]), &address[i + 1..]),
+        None => (None, address),
+    };
+    let broker = if broker.contains(':') { broker.to_string() } else { format!("{}:{}", broker, PORT) };
+
+    let mut stream = TcpStream::connect(&broker).map_err(|e| format!("could not connect to {}: {}", broker, e))?;
+    let _ = stream.set_write_timeout(Some(TIMEOUT));
+    let _ = stream.set_read_timeout(Some(TIMEOUT));
+    let _ = stream.set_nodelay(true);
+    let (username, password) = match credentials {
+        Some(x) => {
+            let mut parts = x.splitn(2, ':');
+            (parts.next(), parts.next())
+        },
+        None => (None, None),
+    };
+    stream.write_all(&connect_packet(client_id, username, password, keep_alive, will)).map_err(|e| e.to_string())?;
+
+    let mut connack = [0u8; 4];
+    stream.read_exact(&mut connack).map_err(|e| format!("{} didn't answer: {}", broker, e))?;
+    match connack {
+        [0x20, 0x02, _, 0] => {},
+        [0x20, 0x02, _, 4] | [0x20, 0x02, _, 5] => return Err(format!("{} refused the username or password", broker)),
+        [0x20, 0x02, _, code] => return Err(format!("{} refused the connection, code {}", broker, code)),
+        _ => return Err(format!("{} isn't an MQTT broker", broker)),
+    }
+    let _ = stream.set_read_timeout(None);
+
+    Ok(stream)
+}
+
+// MQTT 3.1.1's CONNECT, with a clean session.
+fn connect_packet(client_id: &str,
+                  username: Option<&str>,
+                  password: Option<&str>,
+                  keep_alive: Duration,
+                  will: Option<Will>) -> Vec<u8> {
+    let mut flags = 0x02;
+    let mut body = Vec::new();
+    body.extend_from_slice(&[0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04]);
+    let mut payload = Vec::new();
+    push_string(&mut payload, client_id.as_bytes());
+    if let Some(will) = will {
+        // Retained, at QoS 0.
+        flags |= 0x24;
+        push_string(&mut payload, will.topic.as_bytes());
+        push_string(&mut payload, will.payload);
+    }
+    if let Some(username) = username {
+        flags |= 0x80;
+        push_string(&mut payload, username.as_bytes());
+    }
+    if let Some(password) = password {
+        flags |= 0x40;
+        push_string(&mut payload, password.as_bytes());
+    }
+    body.push(flags);
+    body.extend_from_slice(&(u64::min(keep_alive.as_secs(), 0xffff) as u16).to_be_bytes());
+    body.extend_from_slice(&payload);
+    packet(0x10, &body)
+}
+
+// MQTT's PUBLISH at QoS 0, kept by the broker for later subscribers with `retain`.
+pub fn publish_packet(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = Vec::with_capacity(2 + topic.len() + payload.len());
+    push_string(&mut body, topic.as_bytes());
+    body.extend_from_slice(payload);
+    packet(if retain { PUBLISH | 0x01 } else { PUBLISH }, &body)
+}
+
+// MQTT's SUBSCRIBE to `topics` at QoS 0.
+pub fn subscribe_packet(id: u16, topics: &[&str]) -> Vec<u8> {
+    let mut body = id.to_be_bytes().to_vec();
+    for topic in topics {
+        push_string(&mut body, topic.as_bytes());
+        body.push(0);
+    }
+    packet(0x82, &body)
+}
+
+// The first byte, with the packet's kind and flags, and the rest after the length of the next
+// packet the broker sends.
+pub fn read_packet(stream: &mut dyn Read) -> Result<(u8, Vec<u8>), String> {
+    let read_error = |e: io::Error| match e.kind() {
+        io::ErrorKind::UnexpectedEof => "the broker closed the connection".to_string(),
+        _ => e.to_string(),
+    };
+    let mut byte = [0];
+    stream.read_exact(&mut byte).map_err(read_error)?;
+    let kind = byte[0];
+    let mut length = 0;
+    for i in 0..4 {
+        stream.read_exact(&mut byte).map_err(read_error)?;
+        length |= (byte[0] as usize & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).map_err(read_error)?;
+    Ok((kind, body))
+}
+
+// The topic and payload of a packet from `read_packet`, if it's a PUBLISH.
+pub fn published(kind: u8, body: &[u8]) -> Option<(String, Vec<u8>)> {
+    if kind & 0xf0 != PUBLISH || body.len() < 2 {
+        return None;
+    }
+    let topic_end = 2 + ((body[0] as usize) << 8 | body[1] as usize);
+    // Messages at QoS 1 or 2 have an id after the topic.
+    let payload_start = if kind & 0x06 == 0 { topic_end } else { topic_end + 2 };
+    if body.len() < payload_start {
+        return None;
+    }
+    let topic = String::from_utf8_lossy(&body[2..topic_end]).to_string();
+    Some((topic, body[payload_start..].to_vec()))
+}
+
+// A packet of `kind` with its remaining length, 7 bits to a byte, lowest first.
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(5 + body.len());
+    packet.push(kind);
+    let mut length = body.len();
+    loop {
+        let byte = (length % 128) as u8;
+        length /= 128;
+        if length == 0 {
+            packet.push(byte);
+            break;
+        }
+        packet.push(byte | 0x80);
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn push_string(data: &mut Vec<u8>, value: &[u8]) {
+    data.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    data.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lays_out_connect() {
+        let will = Will { topic: "t", payload: b"x" };
+        let packet = connect_packet("id", Some("u"), Some("p"), Duration::from_secs(60), Some(will));
+        assert_eq!(packet, vec![
+            0x10, 26,
+            0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04,
+            // Username, password, retained will and clean session.
+            0xe6, 0x00, 60,
+            0x00, 0x02, b'i', b'd',
+            0x00, 0x01, b't', 0x00, 0x01, b'x',
+            0x00, 0x01, b'u',
+            0x00, 0x01, b'p',
+        ]);
+        assert_eq!(connect_packet("id", None, None, Duration::from_secs(100000), None)[9..12], [0x02, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn lays_out_subscribe_and_publish() {
+        assert_eq!(subscribe_packet(1, &["a", "bc"]), vec![
+            0x82, 11, 0x00, 0x01, 0x00, 0x01, b'a', 0, 0x00, 0x02, b'b', b'c', 0,
+        ]);
+        assert_eq!(publish_packet("a/b", b"on", true), vec![0x31, 7, 0x00, 0x03, b'a', b'/', b'b', b'o', b'n']);
+        assert_eq!(publish_packet("a", b"", false), vec![0x30, 3, 0x00, 0x01, b'a']);
+    }
+
+    #[test]
+    fn encodes_remaining_lengths() {
+        for &(length, ref encoded) in [
+            (0, vec![0x00]),
+            (127, vec![0x7f]),
+            (128, vec![0x80, 0x01]),
+            (16383, vec![0xff, 0x7f]),
+            (16384, vec![0x80, 0x80, 0x01]),
+        ].iter() {
+            let packet = packet(PUBLISH, &vec![0; length]);
+            assert_eq!(&packet[1..1 + encoded.len()], &encoded[..], "length {}", length);
+            assert_eq!(packet.len(), 1 + encoded.len() + length);
+
+            let (kind, body) = read_packet(&mut &packet[..]).unwrap();
+            assert_eq!((kind, body.len()), (PUBLISH, length));
+        }
+    }
+
+    #[test]
+    fn reads_published_messages() {
+        let packet = publish_packet("a/b", b"on", false);
+        let (kind, body) = read_packet(&mut &packet[..]).unwrap();
+        assert_eq!(published(kind, &body), Some(("a/b".to_string(), b"on".to_vec())));
+        // At QoS 1, with an id before the payload.
+        assert_eq!(published(0x32, &[0x00, 0x01, b'a', 0x00, 0x07, b'x']), Some(("a".to_string(), b"x".to_vec())));
+        assert_eq!(published(0x90, &[0x00, 0x01, 0x00]), None);
+        assert_eq!(published(PUBLISH, &[0x00, 0x05, b'a']), None);
+        assert!(read_packet(&mut &[0x30, 0x05, 0x00][..]).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use gfx;
+use led_mapper;
+use screen;
+use screen::reconnect::Reconnecting;
+use screen::spi;
+use std::io::Write;
+use std::sync::mpsc;
+use std::thread;
+
+// Highest level of the LEDs' 5-bit global brightness.
+const MAX_BRIGHTNESS: u8 = 31;
+
+// Drives an APA102 or SK9822 (DotStar) strip or matrix from the Pi's SPI pins, data on MOSI
+// (GPIO 10) and clock on SCLK (GPIO 11), one LED per output pixel like the ws2812 screen. Being
+// clocked, they take any speed the wiring allows, and refresh fast enough for persistence of
+// vision. Dimming them with the global brightness rather than the colors keeps all 8 bits of
+// each color, at the cost of a slower PWM that can flicker on camera.
+pub struct Apa102Screen {
+    panel_layout: led_mapper::PanelLayout,
+    pixel_map: Option<led_mapper::PixelMap>,
+    power_limiter: led_mapper::PowerLimiter,
+    pixels_tx: mpsc::SyncSender<Vec<(u8, u8, u8)>>,
+}
+
+impl Apa102Screen {
+    // `brightness` is the global brightness sent to every LED, from 1 to 31.
+    pub fn new(power_budget_milliamps: u32,
+               spi_device: &str,
+               spi_hz: u32,
+               brightness: u8,
+               panel_layout: led_mapper::PanelLayout,
+               pixel_map: Option<led_mapper::PixelMap>,
+               color_order: led_mapper::ColorOrder) -> Apa102Screen {
+        let (pixels_tx, pixels_rx) = mpsc::sync_channel::<Vec<(u8, u8, u8)>>(1);
+        let brightness = u8::min(u8::max(1, brightness), MAX_BRIGHTNESS);
+
+        let spi_device = spi_device.to_string();
+        thread::spawn(move || spi_pipeline(pixels_rx, spi_device, spi_hz, brightness, color_order));
+
+        Apa102Screen {
+            panel_layout,
+            pixel_map,
+            power_limiter: led_mapper::PowerLimiter::new("the APA102 LEDs",
+                                                         power_budget_milliamps,
+                                                         brightness as f32 / MAX_BRIGHTNESS as f32),
+            pixels_tx,
+        }
+    }
+}
+
+impl screen::Screen for Apa102Screen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn is_linear(&self) -> bool {
+        true
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let mut pixel_colors = match self.pixel_map {
+            Some(ref map) => map.sample(pixels, width, height),
+            None => self.panel_layout.map(pixels, width, height),
+        };
+        self.power_limiter.limit(&mut pixel_colors);
+
+        // Frames are dropped while the LEDs are still being sent the last one, or if sending
+        // them failed.
+        let _ = self.pixels_tx.try_send(pixel_colors);
+    }
+}
+
+fn spi_pipeline(pixels_rx: mpsc::Receiver<Vec<(u8, u8, u8)>>,
+                device: String,
+                spi_hz: u32,
+                brightness: u8,
+                color_order: led_mapper::ColorOrder) {
+    let mut spi = Reconnecting::new("the APA102 LEDs".to_string(), None, || spi::open(&device, spi_hz));
+
+    let mut frame = Vec::new();
+    for pixel_colors in pixels_rx {
+        encode_frame(&pixel_colors, brightness, color_order, &mut frame);
+        spi.send(|spi| spi.write_all(&frame).map_err(|e| format!("could not write to {}: {}", device, e)));
+    }
+}
+
+// The SPI bytes for the LEDs to show `pixel_colors`: a start frame of zeros, then each LED's
+// global brightness and colors in the order they take them.
+fn encode_frame(pixel_colors: &[(u8, u8, u8)], brightness: u8, color_order: led_mapper::ColorOrder, frame: &mut Vec<u8>) {
+    frame.clear();
+    frame.extend_from_slice(&[0; 4]);
+    for &(r, g, b) in pixel_colors.iter() {
+        frame.push(0b1110_0000 | brightness);
+        frame.extend_from_slice(&color_order.channels(r, g, b));
+    }
+
+    // Each LED delays the data by half a clock, so half a clock per LED more is needed to push
+    // it to the end of the strip. SK9822s also only latch the colors on the next frame without
+    // 4 more zeros first.
+    frame.extend_from_slice(&[0; 4]);
+    frame.resize(frame.len() + pixel_colors.len() / 16 + 1, 0);
+}
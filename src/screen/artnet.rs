@@ -0,0 +1,170 @@
+use gfx;
+use screen;
+use screen::dmx::UniverseLayout;
+use screen::reconnect::Reconnecting;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::thread;
+
+const PORT: u16 = 6454;
+const PROTOCOL_VERSION: u16 = 14;
+const OP_DMX: u16 = 0x5000;
+const OP_SYNC: u16 = 0x5200;
+
+// Sends the output to Art-Net nodes over UDP, for DMX lighting and LED controllers, laid out
+// over universes by `layout`. With more than one universe, an ArtSync follows each frame so
+// nodes that support it show all of them at once.
+pub struct ArtnetScreen {
+    layout: UniverseLayout,
+    packets_tx: mpsc::SyncSender<Vec<Vec<u8>>>,
+    sequence: u8,
+}
+
+impl ArtnetScreen {
+    // `address` is a host or broadcast address, with the standard port unless it has one.
+    pub fn new(address: &str, layout: UniverseLayout) -> Result<ArtnetScreen, String> {
+        let destination = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, PORT) };
+        let destination = destination.to_socket_addrs()
+            .map_err(|e| format!("invalid Art-Net address {}: {}", address, e))?
+            .next()
+            .ok_or_else(|| format!("could not resolve {}", address))?;
+        let socket = open_socket(destination)?;
+
+        let (packets_tx, packets_rx) = mpsc::sync_channel::<Vec<Vec<u8>>>(1);
+        let name = format!("the Art-Net nodes at {}", destination);
+        thread::spawn(move || {
+            let mut connection = Reconnecting::new(name, Some(socket), || open_socket(destination));
+            for packets in packets_rx {
+                connection.send(|socket| {
+                    for packet in packets.iter() {
+                        socket.send(packet).map_err(|e| e.to_string())?;
+                    }
+                    Ok(())
+                });
+            }
+        });
+
+        Ok(ArtnetScreen {
+            layout,
+            packets_tx,
+            sequence: 0,
+        })
+    }
+
+    // The ArtDmx packets for each universe, and an ArtSync when there's more than one.
+    fn packets(&mut self, universes: Vec<(u16, Vec<u8>)>) -> Vec<Vec<u8>> {
+        // 0 means the sequence isn't used, so it goes from 1 to 255.
+        self.sequence = self.sequence % 255 + 1;
+
+        let mut packets = Vec::new();
+        for (universe, mut channels) in universes {
+            // The length has to be even.
+            if channels.len() % 2 == 1 {
+                channels.push(0);
+            }
+
+            let mut packet = header(OP_DMX);
+            packet.extend_from_slice(&[self.sequence, 0]);
+            // The 15-bit port address, net and subnet over universe, low byte first.
+            packet.extend_from_slice(&[(universe & 0xff) as u8, (universe >> 8 & 0x7f) as u8]);
+            packet.extend_from_slice(&[(channels.len() >> 8) as u8, channels.len() as u8]);
+            packet.extend_from_slice(&channels);
+            packets.push(packet);
+        }
+
+        if packets.len() > 1 {
+            let mut sync = header(OP_SYNC);
+            sync.extend_from_slice(&[0, 0]);
+            packets.push(sync);
+        }
+
+        packets
+    }
+}
+
+impl screen::Screen for ArtnetScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn is_linear(&self) -> bool {
+        true
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let universes = self.layout.universes(pixels, width, height);
+        let packets = self.packets(universes);
+
+        // Frames are dropped while the last one is still being sent, or while reconnecting.
+        let _ = self.packets_tx.try_send(packets);
+    }
+}
+
+// The start of every Art-Net packet.
+fn header(op_code: u16) -> Vec<u8> {
+    let mut header = b"Art-Net\0".to_vec();
+    // The op code is little endian, and the version big endian.
+    header.extend_from_slice(&[op_code as u8, (op_code >> 8) as u8]);
+    header.extend_from_slice(&[(PROTOCOL_VERSION >> 8) as u8, PROTOCOL_VERSION as u8]);
+    header
+}
+
+// A UDP socket sending to `destination`, which may be a broadcast address.
+fn open_socket(destination: SocketAddr) -> Result<UdpSocket, String> {
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("could not open a UDP socket: {}", e))?;
+    socket.set_broadcast(true).map_err(|e| format!("could not allow broadcasts: {}", e))?;
+    socket.connect(destination).map_err(|e| format!("could not connect to {}: {}", destination, e))?;
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use led_mapper::ColorOrder;
+
+    fn screen() -> ArtnetScreen {
+        ArtnetScreen {
+            layout: UniverseLayout::new(0, 1, 170, false, None, ColorOrder::parse("rgb").unwrap()).unwrap(),
+            packets_tx: mpsc::sync_channel(1).0,
+            sequence: 0,
+        }
+    }
+
+    #[test]
+    fn builds_dmx_packets() {
+        let packets = screen().packets(vec![(0x1234, vec![1, 2, 3])]);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0], vec![
+            b'A', b'r', b't', b'-', b'N', b'e', b't', 0,
+            0x00, 0x50, 0, 14,
+            1, 0,
+            0x34, 0x12,
+            0, 4,
+            1, 2, 3, 0,
+        ]);
+    }
+
+    #[test]
+    fn follows_universes_with_a_sync() {
+        let packets = screen().packets(vec![(0, vec![0; 6]), (1, vec![0; 6])]);
+        assert_eq!(packets.len(), 3);
+        assert_eq!(packets[1][14..16], [1, 0]);
+        assert_eq!(packets[2], vec![b'A', b'r', b't', b'-', b'N', b'e', b't', 0, 0x00, 0x52, 0, 14, 0, 0]);
+    }
+
+    #[test]
+    fn skips_sequence_zero() {
+        let mut screen = screen();
+        screen.sequence = 254;
+        assert_eq!(screen.packets(vec![(0, vec![0; 2])])[0][12], 255);
+        assert_eq!(screen.packets(vec![(0, vec![0; 2])])[0][12], 1);
+    }
+}
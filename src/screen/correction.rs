@@ -0,0 +1,108 @@
+use audio;
+use gfx;
+use screen;
+use std::time::Duration;
+
+// Corrects what a screen taking pixels is sent for its own gamma and brightness, from however the
+// output is encoded for all of them, so a preview window and an LED strip can both look right.
+// `gamma` is the one the screen decodes its values with, or 0 for the usual: `default_gamma` for
+// monitors and 1 for LEDs driven by PWM. Raising it brightens the mids. Screens drawing with GL
+// are shown the output as it is.
+pub struct CorrectedScreen {
+    screen: Box<dyn screen::Screen>,
+    gamma: f32,
+    brightness: f32,
+    default_gamma: f32,
+    // What each level of the output becomes, unless it's left as it is.
+    levels: Option<[u8; 256]>,
+    pixels: Vec<u8>,
+}
+
+impl CorrectedScreen {
+    pub fn new(screen: Box<dyn screen::Screen>, gamma: f32, brightness: f32, default_gamma: f32) -> CorrectedScreen {
+        let gamma = if gamma > 0.0 {
+            gamma
+        } else if screen.is_linear() {
+            1.0
+        } else {
+            default_gamma
+        };
+
+        CorrectedScreen {
+            screen,
+            gamma,
+            brightness: f32::max(0.0, brightness),
+            default_gamma,
+            levels: None,
+            pixels: Vec::new(),
+        }
+    }
+
+    // Whether the correction asked for can't be made, for screens drawing with GL.
+    pub fn is_ignored(&self) -> bool {
+        let default_gamma = if self.screen.is_linear() { 1.0 } else { self.default_gamma };
+        !self.screen.renders_pixels()
+            && ((self.gamma - default_gamma).abs() > 0.001 || (self.brightness - 1.0).abs() > 0.001)
+    }
+}
+
+impl screen::Screen for CorrectedScreen {
+    fn setup(&mut self, gl: &gfx::gl::Gl) -> Result<(), String> {
+        self.screen.setup(gl)
+    }
+
+    fn render_from_texture(&mut self, gl: &gfx::gl::Gl, texture: u32, width: i32, height: i32) {
+        self.screen.render_from_texture(gl, texture, width, height);
+    }
+
+    fn uses_window(&self) -> bool {
+        self.screen.uses_window()
+    }
+
+    fn is_linear(&self) -> bool {
+        self.screen.is_linear()
+    }
+
+    fn renders_pixels(&self) -> bool {
+        self.screen.renders_pixels()
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let levels = match self.levels {
+            Some(ref x) => x,
+            None => return self.screen.render_from_pixels(pixels, width, height),
+        };
+
+        self.pixels.clear();
+        self.pixels.extend(pixels.iter().map(|&x| levels[x as usize]));
+        self.screen.render_from_pixels(&self.pixels, width, height);
+    }
+
+    fn update_audio(&mut self, audio_frame: &audio::AudioFrame) {
+        self.screen.update_audio(audio_frame);
+    }
+
+    fn set_output_gamma(&mut self, gamma: f32) {
+        let exponent = gamma / self.gamma;
+        let unchanged = (exponent - 1.0).abs() < 0.001 && (self.brightness - 1.0).abs() < 0.001;
+        if unchanged || !self.screen.renders_pixels() {
+            self.levels = None;
+            return;
+        }
+
+        let mut levels = [0; 256];
+        for (i, level) in levels.iter_mut().enumerate() {
+            let value = self.brightness * (i as f32 / 255.0).powf(exponent);
+            *level = (f32::min(1.0, value) * 255.0).round() as u8;
+        }
+        self.levels = Some(levels);
+    }
+
+    fn set_latency(&mut self, latency: Duration) {
+        self.screen.set_latency(latency);
+    }
+
+    fn set_palette(&mut self, palette: Option<&screen::Palette>) {
+        self.screen.set_palette(palette);
+    }
+}
@@ -0,0 +1,115 @@
+use audio;
+use gfx;
+use screen;
+use std::time::Duration;
+
+// A rectangle of the output in pixels, from the top left.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Region {
+    // Parse a region written like an X geometry, "<width>x<height>+<x>+<y>", e.g. "32x16+32+0",
+    // which has to fit in an output of `output_width` by `output_height`.
+    pub fn parse(value: &str, output_width: i32, output_height: i32) -> Result<Region, String> {
+        let expected = || format!("expected <width>x<height>+<x>+<y>, got '{}'", value);
+        let parts: Vec<&str> = value.split(['x', '+']).collect();
+        if parts.len() != 4 {
+            return Err(expected());
+        }
+        let numbers = parts.iter()
+            .map(|x| x.trim().parse::<i32>().map_err(|_| expected()))
+            .collect::<Result<Vec<i32>, String>>()?;
+        let region = Region { width: numbers[0], height: numbers[1], x: numbers[2], y: numbers[3] };
+
+        if region.width < 1 || region.height < 1 || region.x < 0 || region.y < 0 ||
+           region.x + region.width > output_width || region.y + region.height > output_height {
+            return Err(format!("region '{}' doesn't fit in the {}x{} output", value, output_width, output_height));
+        }
+        Ok(region)
+    }
+}
+
+// Shows a screen taking pixels only a region of the frames it's sent, so one big output can be
+// split across several displays arranged around a room, each with its own screen. Screens
+// drawing with GL are shown the whole output.
+pub struct CroppedScreen {
+    screen: Box<dyn screen::Screen>,
+    region: Region,
+    pixels: Vec<u8>,
+}
+
+impl CroppedScreen {
+    pub fn new(screen: Box<dyn screen::Screen>, region: Region) -> CroppedScreen {
+        CroppedScreen {
+            screen,
+            region,
+            pixels: Vec::new(),
+        }
+    }
+
+    // Whether the crop asked for can't be made, for screens drawing with GL.
+    pub fn is_ignored(&self) -> bool {
+        !self.screen.renders_pixels()
+    }
+}
+
+impl screen::Screen for CroppedScreen {
+    fn setup(&mut self, gl: &gfx::gl::Gl) -> Result<(), String> {
+        self.screen.setup(gl)
+    }
+
+    fn render_from_texture(&mut self, gl: &gfx::gl::Gl, texture: u32, width: i32, height: i32) {
+        self.screen.render_from_texture(gl, texture, width, height);
+    }
+
+    fn uses_window(&self) -> bool {
+        self.screen.uses_window()
+    }
+
+    fn is_linear(&self) -> bool {
+        self.screen.is_linear()
+    }
+
+    fn renders_pixels(&self) -> bool {
+        self.screen.renders_pixels()
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        // The region was checked against the output's size, but not every frame is that size.
+        let Region { x, y, .. } = self.region;
+        let region_width = i32::min(self.region.width, width - x);
+        let region_height = i32::min(self.region.height, height - y);
+        if region_width < 1 || region_height < 1 {
+            return;
+        }
+
+        self.pixels.clear();
+        // Rows are bottom to top, so the region's bottom row comes first.
+        for row in (y..y + region_height).rev() {
+            let start = 3 * ((height - 1 - row) * width + x) as usize;
+            self.pixels.extend_from_slice(&pixels[start..start + 3 * region_width as usize]);
+        }
+        self.screen.render_from_pixels(&self.pixels, region_width, region_height);
+    }
+
+    fn update_audio(&mut self, audio_frame: &audio::AudioFrame) {
+        self.screen.update_audio(audio_frame);
+    }
+
+    fn set_output_gamma(&mut self, gamma: f32) {
+        self.screen.set_output_gamma(gamma);
+    }
+
+    fn set_latency(&mut self, latency: Duration) {
+        self.screen.set_latency(latency);
+    }
+
+    fn set_palette(&mut self, palette: Option<&screen::Palette>) {
+        self.screen.set_palette(palette);
+    }
+}
@@ -0,0 +1,90 @@
+use audio;
+use gfx;
+use screen;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Frames are held back at most this long, so a typo doesn't queue up minutes of them.
+pub const MAX_LATENCY: Duration = Duration::from_secs(2);
+
+// Holds back the frames a screen taking pixels is sent by its latency, so it lines up with
+// the music heard when that comes out of a slower path, like Bluetooth speakers, or with other
+// screens behind slower networks. Frames are shown by the next render after they're due, so to
+// within a frame. Screens drawing with GL are shown the output as it is.
+pub struct DelayedScreen {
+    screen: Box<dyn screen::Screen>,
+    latency: Duration,
+    // Frames not yet due, oldest first, with when they were sent.
+    frames: VecDeque<(Instant, Vec<u8>, i32, i32)>,
+}
+
+impl DelayedScreen {
+    pub fn new(screen: Box<dyn screen::Screen>, latency: Duration) -> DelayedScreen {
+        DelayedScreen {
+            screen,
+            latency: Duration::min(latency, MAX_LATENCY),
+            frames: VecDeque::new(),
+        }
+    }
+
+    // Whether the latency asked for can't be added, for screens drawing with GL.
+    pub fn is_ignored(&self) -> bool {
+        !self.screen.renders_pixels() && self.latency > Duration::from_secs(0)
+    }
+}
+
+impl screen::Screen for DelayedScreen {
+    fn setup(&mut self, gl: &gfx::gl::Gl) -> Result<(), String> {
+        self.screen.setup(gl)
+    }
+
+    fn render_from_texture(&mut self, gl: &gfx::gl::Gl, texture: u32, width: i32, height: i32) {
+        self.screen.render_from_texture(gl, texture, width, height);
+    }
+
+    fn uses_window(&self) -> bool {
+        self.screen.uses_window()
+    }
+
+    fn is_linear(&self) -> bool {
+        self.screen.is_linear()
+    }
+
+    fn renders_pixels(&self) -> bool {
+        self.screen.renders_pixels()
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        if self.latency == Duration::from_secs(0) && self.frames.is_empty() {
+            return self.screen.render_from_pixels(pixels, width, height);
+        }
+
+        let now = Instant::now();
+        self.frames.push_back((now, pixels.to_vec(), width, height));
+
+        // Only the newest of the frames that are due is shown.
+        let mut due = None;
+        while self.frames.front().is_some_and(|x| x.0 + self.latency <= now) {
+            due = self.frames.pop_front();
+        }
+        if let Some((_, pixels, width, height)) = due {
+            self.screen.render_from_pixels(&pixels, width, height);
+        }
+    }
+
+    fn update_audio(&mut self, audio_frame: &audio::AudioFrame) {
+        self.screen.update_audio(audio_frame);
+    }
+
+    fn set_output_gamma(&mut self, gamma: f32) {
+        self.screen.set_output_gamma(gamma);
+    }
+
+    fn set_latency(&mut self, latency: Duration) {
+        self.latency = Duration::min(latency, MAX_LATENCY);
+    }
+
+    fn set_palette(&mut self, palette: Option<&screen::Palette>) {
+        self.screen.set_palette(palette);
+    }
+}
@@ -0,0 +1,77 @@
+use led_mapper;
+
+pub const CHANNELS_PER_UNIVERSE: usize = 512;
+
+// How a frame's pixels are laid out over DMX universes, for the network lighting screens. Each
+// pixel is an RGB fixture of 3 channels, in rows from the top left, snaking back and forth when
+// `serpentine`. They fill `leds_per_universe` LEDs of each universe in turn, from `universe`, the
+// first starting at `first_channel`, their channels in `color_order`. With a `pixel_map`, the
+// pixels are its LEDs instead.
+pub struct UniverseLayout {
+    universe: u16,
+    // 0-based.
+    first_channel: usize,
+    leds_per_universe: usize,
+    serpentine: bool,
+    pixel_map: Option<led_mapper::PixelMap>,
+    color_order: led_mapper::ColorOrder,
+}
+
+impl UniverseLayout {
+    // `first_channel` counts from 1, as DMX channels do.
+    pub fn new(universe: u16,
+               first_channel: usize,
+               leds_per_universe: usize,
+               serpentine: bool,
+               pixel_map: Option<led_mapper::PixelMap>,
+               color_order: led_mapper::ColorOrder) -> Result<UniverseLayout, String> {
+        if !(1..=CHANNELS_PER_UNIVERSE - 2).contains(&first_channel) {
+            return Err(format!("the first channel must be from 1 to {}", CHANNELS_PER_UNIVERSE - 2));
+        }
+        if leds_per_universe < 1 || 3 * leds_per_universe > CHANNELS_PER_UNIVERSE {
+            return Err(format!("a universe takes from 1 to {} LEDs", CHANNELS_PER_UNIVERSE / 3));
+        }
+
+        Ok(UniverseLayout {
+            universe,
+            first_channel: first_channel - 1,
+            leds_per_universe,
+            serpentine,
+            pixel_map,
+            color_order,
+        })
+    }
+
+    pub fn first_universe(&self) -> u16 {
+        self.universe
+    }
+
+    // The channels of each universe a frame's pixels fill, with the universe's number.
+    pub fn universes(&self, pixels: &[u8], width: i32, height: i32) -> Vec<(u16, Vec<u8>)> {
+        let pixel_colors = match self.pixel_map {
+            Some(ref map) => map.sample(pixels, width, height),
+            None if self.serpentine => led_mapper::led_matrix_mapper::map_from_pixels(pixels, width, height),
+            None => led_mapper::led_matrix_mapper::sample_pixels(pixels, width, height, width, height),
+        };
+
+        let mut universes = Vec::new();
+        let mut universe = self.universe;
+        let mut offset = self.first_channel;
+        let mut remaining = &pixel_colors[..];
+        while !remaining.is_empty() {
+            let count = usize::min(remaining.len(), usize::min(self.leds_per_universe,
+                                                               (CHANNELS_PER_UNIVERSE - offset) / 3));
+            let mut channels = vec![0; offset];
+            for &(r, g, b) in remaining[..count].iter() {
+                channels.extend_from_slice(&self.color_order.channels(r, g, b));
+            }
+            universes.push((universe, channels));
+
+            remaining = &remaining[count..];
+            universe = universe.wrapping_add(1);
+            offset = 0;
+        }
+
+        universes
+    }
+}
@@ -0,0 +1,216 @@
+use libc;
+use libloading::{Library, Symbol};
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::mem;
+use std::net::{SocketAddr, UdpSocket};
+use std::os::raw::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::slice;
+use std::time::Duration;
+
+// OpenSSL, loaded at runtime so builds don't need its headers, and so machines without it
+// only lose the screens that need it.
+const LIBRARY_NAMES: [&str; 3] = ["libssl.so.3", "libssl.so.1.1", "libssl.so"];
+const BIO_NOCLOSE: c_int = 0;
+const BIO_CTRL_DGRAM_SET_CONNECTED: c_int = 32;
+// How long the handshake waits for each reply before it gives up.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Ssl = *mut c_void;
+type SslContext = *mut c_void;
+type PskClientCallback = unsafe extern "C" fn(Ssl, *const c_char, *mut c_char, c_uint, *mut u8, c_uint) -> c_uint;
+
+thread_local! {
+    // What the PSK callback answers the server with, the identity and key, during a handshake on
+    // this thread.
+    static HANDSHAKE_PSK: RefCell<Option<(CString, Vec<u8>)>> = const { RefCell::new(None) };
+}
+
+// A DTLS 1.2 client connection authenticated by a pre-shared key, like the Hue Entertainment
+// API takes. Writes are whole datagrams. Shut down when dropped.
+pub struct DtlsConnection {
+    ssl: Ssl,
+    context: SslContext,
+    _socket: UdpSocket,
+    write: unsafe extern "C" fn(Ssl, *const c_void, c_int) -> c_int,
+    shutdown: unsafe extern "C" fn(Ssl) -> c_int,
+    free: unsafe extern "C" fn(Ssl),
+    free_context: unsafe extern "C" fn(SslContext),
+    _library: Library,
+}
+
+// OpenSSL only touches the connection from the thread using it.
+unsafe impl Send for DtlsConnection {}
+
+impl DtlsConnection {
+    // Connect and handshake with `destination` using only the `cipher`, e.g.
+    // PSK-AES128-GCM-SHA256, as `identity` with `key`.
+    pub fn connect(destination: SocketAddr, cipher: &str, identity: &str, key: &[u8]) -> Result<DtlsConnection, String> {
+        let library = load_library()?;
+        let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("could not open a UDP socket: {}", e))?;
+        socket.connect(destination).map_err(|e| format!("could not connect to {}: {}", destination, e))?;
+        // Bounds each wait for the server during the handshake, which retransmits meanwhile.
+        let _ = socket.set_read_timeout(Some(HANDSHAKE_TIMEOUT));
+
+        let cipher = CString::new(cipher).map_err(|_| "invalid cipher".to_string())?;
+        let identity = CString::new(identity).map_err(|_| "invalid PSK identity".to_string())?;
+
+        unsafe {
+            let client_method: unsafe extern "C" fn() -> *const c_void = *symbol(&library, b"DTLS_client_method\0")?;
+            let new_context: unsafe extern "C" fn(*const c_void) -> SslContext = *symbol(&library, b"SSL_CTX_new\0")?;
+            let free_context: unsafe extern "C" fn(SslContext) = *symbol(&library, b"SSL_CTX_free\0")?;
+            let set_cipher_list: unsafe extern "C" fn(SslContext, *const c_char) -> c_int =
+                *symbol(&library, b"SSL_CTX_set_cipher_list\0")?;
+            let new_ssl: unsafe extern "C" fn(SslContext) -> Ssl = *symbol(&library, b"SSL_new\0")?;
+            let free: unsafe extern "C" fn(Ssl) = *symbol(&library, b"SSL_free\0")?;
+            let set_psk_client_callback: unsafe extern "C" fn(Ssl, PskClientCallback) =
+                *symbol(&library, b"SSL_set_psk_client_callback\0")?;
+            let new_dgram: unsafe extern "C" fn(c_int, c_int) -> *mut c_void = *symbol(&library, b"BIO_new_dgram\0")?;
+            let bio_ctrl: unsafe extern "C" fn(*mut c_void, c_int, c_long, *mut c_void) -> c_long =
+                *symbol(&library, b"BIO_ctrl\0")?;
+            let set_bio: unsafe extern "C" fn(Ssl, *mut c_void, *mut c_void) = *symbol(&library, b"SSL_set_bio\0")?;
+            let connect: unsafe extern "C" fn(Ssl) -> c_int = *symbol(&library, b"SSL_connect\0")?;
+            let write: unsafe extern "C" fn(Ssl, *const c_void, c_int) -> c_int = *symbol(&library, b"SSL_write\0")?;
+            let shutdown: unsafe extern "C" fn(Ssl) -> c_int = *symbol(&library, b"SSL_shutdown\0")?;
+            let get_error: unsafe extern "C" fn() -> c_ulong = *symbol(&library, b"ERR_get_error\0")?;
+            let error_string: unsafe extern "C" fn(c_ulong, *mut c_char, usize) = *symbol(&library, b"ERR_error_string_n\0")?;
+            let last_error = || {
+                let mut buffer = [0 as c_char; 256];
+                match get_error() {
+                    0 => "no reply".to_string(),
+                    code => {
+                        error_string(code, buffer.as_mut_ptr(), buffer.len());
+                        let bytes = slice::from_raw_parts(buffer.as_ptr() as *const u8, buffer.len());
+                        String::from_utf8_lossy(bytes.split(|&x| x == 0).next().unwrap_or(&[])).into_owned()
+                    },
+                }
+            };
+
+            let context = new_context(client_method());
+            if context.is_null() {
+                return Err(format!("could not set up DTLS, {}", last_error()));
+            }
+            if set_cipher_list(context, cipher.as_ptr()) != 1 {
+                free_context(context);
+                return Err(format!("OpenSSL doesn't have the {} cipher", cipher.to_string_lossy()));
+            }
+
+            let ssl = new_ssl(context);
+            if ssl.is_null() {
+                free_context(context);
+                return Err(format!("could not set up DTLS, {}", last_error()));
+            }
+            let connection = DtlsConnection {
+                ssl,
+                context,
+                _socket: socket,
+                write,
+                shutdown,
+                free,
+                free_context,
+                _library: library,
+            };
+            set_psk_client_callback(ssl, psk_client_callback);
+
+            // The BIO takes the socket's file descriptor, but not its ownership.
+            let bio = new_dgram(connection._socket.as_raw_fd(), BIO_NOCLOSE);
+            if bio.is_null() {
+                return Err(format!("could not set up DTLS, {}", last_error()));
+            }
+            let mut address = socket_address(destination);
+            bio_ctrl(bio, BIO_CTRL_DGRAM_SET_CONNECTED, 0, &mut address as *mut _ as *mut c_void);
+            set_bio(ssl, bio, bio);
+
+            HANDSHAKE_PSK.with(|x| *x.borrow_mut() = Some((identity, key.to_vec())));
+            let connected = connect(ssl);
+            HANDSHAKE_PSK.with(|x| *x.borrow_mut() = None);
+            if connected != 1 {
+                return Err(format!("the DTLS handshake with {} failed, {}", destination, last_error()));
+            }
+
+            Ok(connection)
+        }
+    }
+
+    pub fn write(&mut self, datagram: &[u8]) -> Result<(), String> {
+        let written = unsafe { (self.write)(self.ssl, datagram.as_ptr() as *const c_void, datagram.len() as c_int) };
+        if written <= 0 {
+            return Err("could not write over DTLS".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DtlsConnection {
+    fn drop(&mut self) {
+        unsafe {
+            (self.shutdown)(self.ssl);
+            (self.free)(self.ssl);
+            (self.free_context)(self.context);
+        }
+    }
+}
+
+unsafe extern "C" fn psk_client_callback(_ssl: Ssl,
+                                         _hint: *const c_char,
+                                         identity: *mut c_char,
+                                         max_identity_length: c_uint,
+                                         key: *mut u8,
+                                         max_key_length: c_uint) -> c_uint {
+    HANDSHAKE_PSK.with(|psk| {
+        let psk = psk.borrow();
+        let (psk_identity, psk_key) = match *psk {
+            Some((ref identity, ref key)) => (identity, key),
+            None => return 0,
+        };
+        // The identity is NUL terminated and the key isn't.
+        let identity_bytes = psk_identity.as_bytes_with_nul();
+        if identity_bytes.len() > max_identity_length as usize || psk_key.len() > max_key_length as usize {
+            return 0;
+        }
+        ptr::copy_nonoverlapping(identity_bytes.as_ptr() as *const c_char, identity, identity_bytes.len());
+        ptr::copy_nonoverlapping(psk_key.as_ptr(), key, psk_key.len());
+        psk_key.len() as c_uint
+    })
+}
+
+// `address` as a sockaddr, for OpenSSL.
+fn socket_address(address: SocketAddr) -> libc::sockaddr_storage {
+    unsafe {
+        let mut storage: libc::sockaddr_storage = mem::zeroed();
+        match address {
+            SocketAddr::V4(ref v4) => {
+                let sockaddr = &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in);
+                sockaddr.sin_family = libc::AF_INET as libc::sa_family_t;
+                sockaddr.sin_port = v4.port().to_be();
+                sockaddr.sin_addr.s_addr = u32::from(*v4.ip()).to_be();
+            },
+            SocketAddr::V6(ref v6) => {
+                let sockaddr = &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6);
+                sockaddr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sockaddr.sin6_port = v6.port().to_be();
+                sockaddr.sin6_addr.s6_addr = v6.ip().octets();
+            },
+        }
+        storage
+    }
+}
+
+fn load_library() -> Result<Library, String> {
+    let mut errors = Vec::new();
+    for name in LIBRARY_NAMES.iter() {
+        match Library::new(name) {
+            Ok(x) => return Ok(x),
+            Err(e) => errors.push(format!("{}: {}", name, e)),
+        }
+    }
+
+    Err(format!("could not load {}", errors.join(", ")))
+}
+
+unsafe fn symbol<'a, T>(library: &'a Library, name: &[u8]) -> Result<Symbol<'a, T>, String> {
+    library.get(name)
+        .map_err(|e| format!("could not find {}: {}", String::from_utf8_lossy(&name[..name.len() - 1]), e))
+}
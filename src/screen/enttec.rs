@@ -0,0 +1,126 @@
+use gfx;
+use led_mapper;
+use screen;
+use screen::dmx;
+use screen::reconnect::Reconnecting;
+use screen::serial;
+use std::io::Write;
+use std::sync::mpsc;
+use std::thread;
+
+// Frames to an Enttec DMX USB Pro, or an adapter speaking its protocol, are a message labelled
+// with what to do, here send a DMX universe.
+const START_OF_MESSAGE: u8 = 0x7e;
+const END_OF_MESSAGE: u8 = 0xe7;
+const LABEL_SEND_DMX: u8 = 6;
+// Receivers need at least 24 channels.
+const MIN_CHANNELS: usize = 24;
+
+// Drives a few DMX fixtures, like stage wash lights, through an Enttec DMX USB Pro compatible
+// adapter. The frame is split into `fixtures` columns side by side, each averaged into one
+// fixture's color. Each fixture takes `channel_layout.len()` channels from `first_channel` on,
+// one per letter: r, g and b for its colors, w for white as the colors' common part, d for a
+// dimmer kept at full, and - for a channel left at 0.
+pub struct EnttecScreen {
+    fixtures: usize,
+    first_channel: usize,
+    channel_layout: Vec<char>,
+    messages_tx: mpsc::SyncSender<Vec<u8>>,
+}
+
+impl EnttecScreen {
+    // `first_channel` counts from 1, as DMX channels do.
+    pub fn new(device: &str,
+               fixtures: usize,
+               first_channel: usize,
+               channel_layout: &str) -> Result<EnttecScreen, String> {
+        let channel_layout: Vec<char> = channel_layout.chars().collect();
+        if let Some(x) = channel_layout.iter().find(|x| !"rgbwd-".contains(**x)) {
+            return Err(format!("unknown fixture channel {}, expected r, g, b, w, d or -", x));
+        }
+        if fixtures < 1 || first_channel < 1
+            || first_channel - 1 + fixtures * channel_layout.len() > dmx::CHANNELS_PER_UNIVERSE {
+            return Err(format!("{} fixtures from channel {} don't fit in a universe", fixtures, first_channel));
+        }
+
+        let adapter = serial::open(device, None)?;
+        let (messages_tx, messages_rx) = mpsc::sync_channel::<Vec<u8>>(1);
+        let device = device.to_string();
+        thread::spawn(move || {
+            // USB adapters come back under the same name when plugged in again.
+            let name = format!("the DMX adapter at {}", device);
+            let mut connection = Reconnecting::new(name, Some(adapter), || serial::open(&device, None));
+            for message in messages_rx {
+                connection.send(|adapter| adapter.write_all(&message).map_err(|e| e.to_string()));
+            }
+        });
+
+        Ok(EnttecScreen {
+            fixtures,
+            first_channel: first_channel - 1,
+            channel_layout,
+            messages_tx,
+        })
+    }
+
+    // The average color of each fixture's column of the frame.
+    fn fixture_colors(&self, pixels: &[u8], width: i32, height: i32) -> Vec<(u8, u8, u8)> {
+        let (width, height) = (width as usize, height as usize);
+        (0..self.fixtures).map(|fixture| {
+            led_mapper::led_matrix_mapper::average_columns(pixels, width, height,
+                                                           fixture * width / self.fixtures,
+                                                           (fixture + 1) * width / self.fixtures)
+        }).collect()
+    }
+
+    fn message(&self, fixture_colors: &[(u8, u8, u8)]) -> Vec<u8> {
+        let mut channels = vec![0; self.first_channel];
+        for &(r, g, b) in fixture_colors.iter() {
+            let white = u8::min(r, u8::min(g, b));
+            for channel in self.channel_layout.iter() {
+                channels.push(match *channel {
+                    'r' => r,
+                    'g' => g,
+                    'b' => b,
+                    'w' => white,
+                    'd' => 255,
+                    _ => 0,
+                });
+            }
+        }
+        channels.resize(usize::max(channels.len(), MIN_CHANNELS), 0);
+
+        // The length includes the null start code before the channels.
+        let length = channels.len() + 1;
+        let mut message = vec![START_OF_MESSAGE, LABEL_SEND_DMX, length as u8, (length >> 8) as u8, 0];
+        message.extend_from_slice(&channels);
+        message.push(END_OF_MESSAGE);
+        message
+    }
+}
+
+impl screen::Screen for EnttecScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn is_linear(&self) -> bool {
+        true
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let fixture_colors = self.fixture_colors(pixels, width, height);
+        let message = self.message(&fixture_colors);
+
+        // Frames are dropped while the last one is still being sent, or while reconnecting.
+        let _ = self.messages_tx.try_send(message);
+    }
+}
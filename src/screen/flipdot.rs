@@ -0,0 +1,154 @@
+use gfx;
+use screen;
+use screen::mono::{self, Conversion};
+use screen::reconnect::Reconnecting;
+use screen::serial;
+use std::fs::File;
+use std::io::{self, Write};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::thread;
+
+// Every AlfaZeta panel has 7 rows of dots.
+const ROWS: i32 = 7;
+const START: u8 = 0x80;
+const END: u8 = 0x8f;
+// Flips every panel to the dots it was last sent at once, with the broadcast address.
+const REFRESH: [u8; 3] = [START, 0x82, END];
+const BROADCAST: u8 = 0xff;
+
+// Drives AlfaZeta flip-dot panels, and other displays speaking their protocol, over RS-485: a
+// serial adapter at a device path like "/dev/ttyUSB0", or a serial to Ethernet bridge at a UDP
+// address like "192.168.1.60:5000". The panels, each `columns` dots wide and 7 high, are stacked
+// top to bottom in the order of `addresses`, and the output scaled to them and turned into dots
+// by `conversion`. They're all sent their dots first and then flipped together, and only when
+// the dots changed, as flipping is slow and loud; --flipdot_fps limits how often.
+pub struct FlipdotScreen {
+    columns: i32,
+    addresses: Vec<u8>,
+    conversion: Conversion,
+    threshold: u8,
+    // What was last handed to the thread, so frames flipping no dots aren't sent.
+    last_message: Vec<u8>,
+    messages_tx: mpsc::SyncSender<Vec<u8>>,
+}
+
+impl FlipdotScreen {
+    // `addresses` are the panels' addresses as set on their DIP switches, comma separated.
+    pub fn new(address: &str, addresses: &str, columns: i32, baud: u32, conversion: &str, threshold: u8)
+               -> Result<FlipdotScreen, String> {
+        if columns != 28 && columns != 56 && columns != 112 {
+            return Err(format!("panels {} dots wide aren't supported, expected 28, 56 or 112", columns));
+        }
+        let addresses = addresses.split(',')
+            .map(|x| x.trim().parse::<u8>().map_err(|e| format!("invalid panel address {}: {}", x.trim(), e)))
+            .collect::<Result<Vec<u8>, String>>()?;
+        if addresses.contains(&BROADCAST) {
+            return Err(format!("panel address {} is every panel's", BROADCAST));
+        }
+        let conversion = Conversion::parse(conversion)?;
+        if address.is_empty() {
+            return Err("the flipdot screen needs --flipdot_address".to_string());
+        }
+        let connection = Connection::open(address, baud)?;
+
+        let (messages_tx, messages_rx) = mpsc::sync_channel::<Vec<u8>>(1);
+        let address = address.to_string();
+        thread::spawn(move || {
+            let name = format!("the flip-dots at {}", address);
+            let mut connection = Reconnecting::new(name, Some(connection), || Connection::open(&address, baud));
+            for message in messages_rx {
+                connection.send(|connection| connection.send(&message).map_err(|e| e.to_string()));
+            }
+        });
+
+        Ok(FlipdotScreen {
+            columns,
+            addresses,
+            conversion,
+            threshold,
+            last_message: Vec::new(),
+            messages_tx,
+        })
+    }
+}
+
+impl screen::Screen for FlipdotScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let rows = ROWS * self.addresses.len() as i32;
+        let lit = mono::lit_pixels(pixels, width, height, self.columns, rows, self.conversion, self.threshold);
+
+        // Sent without flipping, by how many columns follow.
+        let command = match self.columns {
+            28 => 0x84,
+            56 => 0x86,
+            _ => 0x81,
+        };
+        let mut message = Vec::with_capacity(self.addresses.len() * (4 + self.columns as usize) + REFRESH.len());
+        for (panel, &address) in self.addresses.iter().enumerate() {
+            message.extend_from_slice(&[START, command, address]);
+            // Each byte is a column of the panel, the top dot in the lowest bit.
+            for x in 0..self.columns {
+                let mut column = 0;
+                for bit in 0..ROWS {
+                    if lit[((panel as i32 * ROWS + bit) * self.columns + x) as usize] {
+                        column |= 1 << bit;
+                    }
+                }
+                message.push(column);
+            }
+            message.push(END);
+        }
+        message.extend_from_slice(&REFRESH);
+        if message == self.last_message {
+            return;
+        }
+
+        // Frames are dropped while the last one is still being sent, or while reconnecting.
+        if self.messages_tx.try_send(message.clone()).is_ok() {
+            self.last_message = message;
+        }
+    }
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Serial(File),
+}
+
+impl Connection {
+    // Device paths open serial connections at `baud`, anything else is a UDP address.
+    fn open(address: &str, baud: u32) -> Result<Connection, String> {
+        if address.starts_with('/') {
+            return serial::open(address, Some(baud)).map(Connection::Serial);
+        }
+
+        let destination = address.to_socket_addrs()
+            .map_err(|e| format!("invalid flip-dot address {}: {}", address, e))?
+            .next()
+            .ok_or_else(|| format!("could not resolve {}", address))?;
+        let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("could not open a UDP socket: {}", e))?;
+        socket.connect(destination).map_err(|e| format!("could not connect to {}: {}", address, e))?;
+
+        Ok(Connection::Udp(socket))
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        match *self {
+            Connection::Udp(ref socket) => socket.send(message).map(|_| ()),
+            Connection::Serial(ref mut file) => file.write_all(message),
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use gfx;
+use libc;
+use screen;
+use std::fs::{File, OpenOptions};
+use std::mem;
+use std::os::raw::{c_char, c_ulong};
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::slice;
+
+// From linux/fb.h.
+const FBIOGET_VSCREENINFO: u32 = 0x4600;
+const FBIOGET_FSCREENINFO: u32 = 0x4602;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FbBitfield {
+    offset: u32,
+    length: u32,
+    msb_right: u32,
+}
+
+#[repr(C)]
+struct FbVarScreeninfo {
+    xres: u32,
+    yres: u32,
+    xres_virtual: u32,
+    yres_virtual: u32,
+    xoffset: u32,
+    yoffset: u32,
+    bits_per_pixel: u32,
+    grayscale: u32,
+    red: FbBitfield,
+    green: FbBitfield,
+    blue: FbBitfield,
+    transp: FbBitfield,
+    nonstd: u32,
+    activate: u32,
+    height: u32,
+    width: u32,
+    accel_flags: u32,
+    pixclock: u32,
+    left_margin: u32,
+    right_margin: u32,
+    upper_margin: u32,
+    lower_margin: u32,
+    hsync_len: u32,
+    vsync_len: u32,
+    sync: u32,
+    vmode: u32,
+    rotate: u32,
+    colorspace: u32,
+    reserved: [u32; 4],
+}
+
+#[repr(C)]
+struct FbFixScreeninfo {
+    id: [c_char; 16],
+    smem_start: c_ulong,
+    smem_len: u32,
+    type_: u32,
+    type_aux: u32,
+    visual: u32,
+    xpanstep: u16,
+    ypanstep: u16,
+    ywrapstep: u16,
+    line_length: u32,
+    mmio_start: c_ulong,
+    mmio_len: u32,
+    accel: u32,
+    capabilities: u16,
+    reserved: [u16; 2],
+}
+
+// Draws the output straight into a Linux framebuffer device, like the console's /dev/fb0 or a
+// small HDMI, DPI or SPI display's fbtft device, without X or a compositor. It's scaled up to fit
+// the display with black bars. The console's blinking cursor can be hidden with
+// `echo 0 > /sys/class/graphics/fbcon/cursor_blink`. The mapping is unmapped when dropped.
+pub struct FramebufferScreen {
+    mapping: *mut libc::c_void,
+    mapping_length: usize,
+    // Where the visible display starts in it, with rows `line_length` bytes apart.
+    offset: usize,
+    width: i32,
+    height: i32,
+    line_length: usize,
+    bytes_per_pixel: usize,
+    red: FbBitfield,
+    green: FbBitfield,
+    blue: FbBitfield,
+    // The output size the display was last drawn at, to clear the bars when it changes.
+    frame_size: (i32, i32),
+    _device: File,
+}
+
+impl FramebufferScreen {
+    pub fn new(path: &str) -> Result<FramebufferScreen, String> {
+        let device = OpenOptions::new().read(true).write(true).open(path)
+            .map_err(|e| format!("could not open {}: {}", path, e))?;
+
+        unsafe {
+            let mut variable: FbVarScreeninfo = mem::zeroed();
+            let mut fixed: FbFixScreeninfo = mem::zeroed();
+            if libc::ioctl(device.as_raw_fd(), FBIOGET_VSCREENINFO as _, &mut variable) < 0
+                || libc::ioctl(device.as_raw_fd(), FBIOGET_FSCREENINFO as _, &mut fixed) < 0 {
+                return Err(format!("{} isn't a framebuffer", path));
+            }
+            let bytes_per_pixel = variable.bits_per_pixel as usize / 8;
+            if !(2..=4).contains(&bytes_per_pixel) {
+                return Err(format!("{} bits per pixel aren't supported", variable.bits_per_pixel));
+            }
+
+            let mapping_length = fixed.smem_len as usize;
+            let mapping = libc::mmap(ptr::null_mut(), mapping_length, libc::PROT_READ | libc::PROT_WRITE,
+                                     libc::MAP_SHARED, device.as_raw_fd(), 0);
+            if mapping == libc::MAP_FAILED {
+                return Err(format!("could not map {}", path));
+            }
+            println!("Drawing on a {}x{} framebuffer at {} bits per pixel",
+                     variable.xres, variable.yres, variable.bits_per_pixel);
+
+            // Drawn into the visible part of the virtual screen.
+            let offset = variable.yoffset as usize * fixed.line_length as usize
+                + variable.xoffset as usize * bytes_per_pixel;
+            Ok(FramebufferScreen {
+                mapping,
+                mapping_length,
+                offset: usize::min(offset, mapping_length),
+                width: variable.xres as i32,
+                height: variable.yres as i32,
+                line_length: fixed.line_length as usize,
+                bytes_per_pixel,
+                red: variable.red,
+                green: variable.green,
+                blue: variable.blue,
+                frame_size: (0, 0),
+                _device: device,
+            })
+        }
+    }
+
+    // The display's encoding of a color.
+    fn encode(&self, r: u8, g: u8, b: u8) -> u32 {
+        let channel = |x: u8, field: &FbBitfield| (x as u32 >> (8 - u32::min(8, field.length))) << field.offset;
+        channel(r, &self.red) | channel(g, &self.green) | channel(b, &self.blue)
+    }
+}
+
+impl screen::Screen for FramebufferScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let memory = unsafe {
+            slice::from_raw_parts_mut((self.mapping as *mut u8).add(self.offset), self.mapping_length - self.offset)
+        };
+        if self.frame_size != (width, height) {
+            for byte in memory.iter_mut() {
+                *byte = 0;
+            }
+            self.frame_size = (width, height);
+        }
+
+        let (x, y, scaled_width, scaled_height) =
+            gfx::Fit::Letterbox.viewport((width, height), (self.width, self.height));
+        for row in 0..scaled_height {
+            // Pixel rows are bottom to top.
+            let pixel_row = height - 1 - row * height / scaled_height;
+            let line = (y + row) as usize * self.line_length;
+            for column in 0..scaled_width {
+                let i = 3 * (pixel_row * width + column * width / scaled_width) as usize;
+                let color = self.encode(pixels[i], pixels[i + 1], pixels[i + 2]);
+
+                let start = line + (x + column) as usize * self.bytes_per_pixel;
+                if start + self.bytes_per_pixel > memory.len() {
+                    return;
+                }
+                // Pixels are little endian.
+                for byte in 0..self.bytes_per_pixel {
+                    memory[start + byte] = (color >> (8 * byte)) as u8;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for FramebufferScreen {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.mapping, self.mapping_length);
+        }
+    }
+}
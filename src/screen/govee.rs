@@ -0,0 +1,195 @@
+use gfx;
+use led_mapper;
+use screen;
+use screen::reconnect::Reconnecting;
+use screen::websocket;
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const SCAN_ADDRESS: (Ipv4Addr, u16) = (Ipv4Addr::new(239, 255, 255, 250), 4001);
+// Devices answer scans on this port, whoever asks.
+const SCAN_REPLY_PORT: u16 = 4002;
+const CONTROL_PORT: u16 = 4003;
+// How long devices are listened for when they're discovered.
+const DISCOVERY_TIME: Duration = Duration::from_secs(1);
+// The devices fall behind when they're sent colors much faster.
+const SEND_INTERVAL: Duration = Duration::from_millis(50);
+// The segment colors messages' first byte, and their kinds.
+const SEGMENTS_HEADER: u8 = 0xbb;
+const SEGMENTS_ENABLE: u8 = 0xb1;
+const SEGMENTS_COLORS: u8 = 0xb0;
+
+// Drives Govee lights with LAN control turned on in the Govee Home app, over their local UDP API,
+// without the cloud. The devices split the output's columns between them, left to right in the
+// order they're given, or of their addresses when they're discovered. Each shows its columns
+// averaged, or when `segments` is more than 0, RGBIC strips spread them over that many
+// segments, with the segment colors messages DreamView and OpenRGB use.
+pub struct GoveeScreen {
+    devices: Vec<SocketAddr>,
+    segments: usize,
+    last_sent: Option<Instant>,
+    messages_tx: mpsc::SyncSender<Vec<(SocketAddr, String)>>,
+}
+
+impl GoveeScreen {
+    // `devices` are addresses separated by commas, or empty to discover the devices on the
+    // network.
+    pub fn new(devices: &str, segments: usize) -> Result<GoveeScreen, String> {
+        let devices = if devices.trim().is_empty() {
+            discover()?
+        } else {
+            devices.split(',').map(|x| resolve(x.trim())).collect::<Result<Vec<SocketAddr>, String>>()?
+        };
+        if devices.is_empty() {
+            return Err("no Govee devices with LAN control found on the network".to_string());
+        }
+        println!("Sending to {} Govee devices", devices.len());
+        if segments > 255 {
+            return Err(format!("Govee strips take up to 255 segments, not {}", segments));
+        }
+
+        let destinations = devices.clone();
+        let open = move || {
+            let socket = open_socket()?;
+            // Turn them on, and to taking segment colors.
+            for &device in destinations.iter() {
+                send(&socket, device, &command("turn", "{\"value\":1}"))?;
+                if segments > 0 {
+                    send(&socket, device, &segments_command(&[SEGMENTS_ENABLE, 0x01]))?;
+                }
+            }
+            Ok(socket)
+        };
+        let socket = open()?;
+
+        let (messages_tx, messages_rx) = mpsc::sync_channel::<Vec<(SocketAddr, String)>>(1);
+        thread::spawn(move || {
+            let mut connection = Reconnecting::new("the Govee devices".to_string(), Some(socket), open);
+            for messages in messages_rx {
+                connection.send(|socket| {
+                    for &(device, ref message) in messages.iter() {
+                        send(socket, device, message)?;
+                    }
+                    Ok(())
+                });
+            }
+        });
+
+        Ok(GoveeScreen {
+            devices,
+            segments,
+            last_sent: None,
+            messages_tx,
+        })
+    }
+}
+
+impl screen::Screen for GoveeScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        if self.last_sent.is_some_and(|x| x.elapsed() < SEND_INTERVAL) {
+            return;
+        }
+        self.last_sent = Some(Instant::now());
+
+        let (width, height) = (width as usize, height as usize);
+        let messages = self.devices.iter().enumerate().map(|(i, &device)| {
+            let start = i * width / self.devices.len();
+            let end = (i + 1) * width / self.devices.len();
+            if self.segments == 0 {
+                let (r, g, b) = led_mapper::led_matrix_mapper::average_columns(pixels, width, height, start, end);
+                let data = format!("{{\"color\":{{\"r\":{},\"g\":{},\"b\":{}}},\"colorTemInKelvin\":0}}", r, g, b);
+                return (device, command("colorwc", &data));
+            }
+
+            // Without a gradient between the segments, then how many there are and their colors.
+            let mut data = vec![SEGMENTS_COLORS, 0x00, self.segments as u8];
+            for segment in 0..self.segments {
+                let segment_start = start + segment * (end - start) / self.segments;
+                let segment_end = start + (segment + 1) * (end - start) / self.segments;
+                let (r, g, b) = led_mapper::led_matrix_mapper::average_columns(pixels, width, height,
+                                                                               segment_start, segment_end);
+                data.extend_from_slice(&[r, g, b]);
+            }
+            (device, segments_command(&data))
+        }).collect();
+
+        // Frames are dropped while the last one is still being sent, or while reconnecting.
+        let _ = self.messages_tx.try_send(messages);
+    }
+}
+
+fn command(name: &str, data: &str) -> String {
+    format!("{{\"msg\":{{\"cmd\":\"{}\",\"data\":{}}}}}", name, data)
+}
+
+// A segment colors message of `data`, which goes in a razer command, in base64, after a header
+// and its length and before a checksum of all its bytes.
+fn segments_command(data: &[u8]) -> String {
+    let mut message = vec![SEGMENTS_HEADER, 0x00, (data.len() - 1) as u8];
+    message.extend_from_slice(data);
+    let checksum = message.iter().fold(0, |sum, &x| sum ^ x);
+    message.push(checksum);
+    command("razer", &format!("{{\"pt\":\"{}\"}}", websocket::base64(&message)))
+}
+
+fn send(socket: &UdpSocket, device: SocketAddr, message: &str) -> Result<(), String> {
+    socket.send_to(message.as_bytes(), device).map(|_| ()).map_err(|e| e.to_string())
+}
+
+// The addresses of the devices answering a scan, in order, from their replies'
+// {"msg":{"cmd":"scan","data":{"ip":"192.168.1.70",...}}}.
+fn discover() -> Result<Vec<SocketAddr>, String> {
+    let socket = UdpSocket::bind(("0.0.0.0", SCAN_REPLY_PORT))
+        .map_err(|e| format!("could not listen for Govee devices on port {}: {}", SCAN_REPLY_PORT, e))?;
+    socket.send_to(command("scan", "{\"account_topic\":\"reserve\"}").as_bytes(), SCAN_ADDRESS)
+        .map_err(|e| format!("could not look for Govee devices: {}", e))?;
+
+    let end = Instant::now() + DISCOVERY_TIME;
+    let mut devices = Vec::new();
+    let mut buffer = [0u8; 2048];
+    while let Some(left) = end.checked_duration_since(Instant::now()) {
+        let _ = socket.set_read_timeout(Some(left + Duration::from_millis(1)));
+        let (length, from) = match socket.recv_from(&mut buffer) {
+            Ok(x) => x,
+            Err(_) => break,
+        };
+        let reply = String::from_utf8_lossy(&buffer[..length]);
+        let ip = reply.find("\"ip\":\"")
+            .and_then(|i| reply[i + "\"ip\":\"".len()..].split('"').next())
+            .and_then(|x| x.parse().ok())
+            .unwrap_or_else(|| from.ip());
+        let device = SocketAddr::new(ip, CONTROL_PORT);
+        if !devices.contains(&device) {
+            devices.push(device);
+        }
+    }
+    devices.sort();
+    Ok(devices)
+}
+
+// `address`, with the control port unless it has one.
+fn resolve(address: &str) -> Result<SocketAddr, String> {
+    let with_port = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, CONTROL_PORT) };
+    with_port.to_socket_addrs()
+        .map_err(|e| format!("invalid Govee address {}: {}", address, e))?
+        .next()
+        .ok_or_else(|| format!("could not resolve {}", address))
+}
+
+fn open_socket() -> Result<UdpSocket, String> {
+    UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("could not open a UDP socket: {}", e))
+}
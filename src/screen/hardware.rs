@@ -7,11 +7,12 @@ use std::thread;
 
 pub struct HardwareScreen {
     mapper: led_mapper::LedDiskMapper,
+    power_limiter: led_mapper::PowerLimiter,
     pixels_tx: mpsc::SyncSender<[(u8, u8, u8); led_mapper::led_disk_mapper::NUM_PIXELS]>,
 }
 
 impl HardwareScreen {
-    pub fn new() -> HardwareScreen {
+    pub fn new(power_budget_milliamps: u32) -> HardwareScreen {
         let (pixels_tx, pixels_rx) = mpsc::sync_channel::<[(u8, u8, u8); led_mapper::led_disk_mapper::NUM_PIXELS]>(1);
 
         thread::spawn(move || {
@@ -20,28 +21,43 @@ impl HardwareScreen {
 
         HardwareScreen {
             mapper: led_mapper::LedDiskMapper::new(),
+            power_limiter: led_mapper::PowerLimiter::new("the Blinkt LEDs",
+                                                         power_budget_milliamps,
+                                                         led_mapper::led_disk_mapper::BRIGHTNESS),
             pixels_tx,
         }
     }
 }
 
 impl screen::Screen for HardwareScreen {
-    fn setup(&mut self, _gl: &gfx::gl::Gl) {}
-
-    fn render_from_texture(&mut self, gl: &gfx::gl::Gl, texture: u32, size: i32) {
-        let pixel_colors = self.mapper.map_from_texture(gl, texture, size);
-
-        self.pixels_tx.send(pixel_colors).unwrap();
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
     }
 
     fn uses_window(&self) -> bool {
         false
     }
+
+    fn is_linear(&self) -> bool {
+        true
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let mut pixel_colors = self.mapper.map_from_pixels(pixels, width, height);
+        self.power_limiter.limit(&mut pixel_colors);
+
+        // Frames are dropped while the last one is still being sent.
+        let _ = self.pixels_tx.try_send(pixel_colors);
+    }
 }
 
 fn blinkt_pipeline(pixels_rx: mpsc::Receiver<[(u8, u8, u8); led_mapper::led_disk_mapper::NUM_PIXELS]>) {
     let mut blinkt = Blinkt::with_spi(1_000_000, 255).unwrap();
-    blinkt.set_all_pixels_brightness(0.08);
+    blinkt.set_all_pixels_brightness(led_mapper::led_disk_mapper::BRIGHTNESS);
 
     loop {
         let pixel_colors = match pixels_rx.recv() {
@@ -0,0 +1,149 @@
+use gfx;
+use led_mapper;
+use libloading::{Library, Symbol};
+use screen;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::sync::mpsc;
+use std::thread;
+
+// hzeller's rpi-rgb-led-matrix, loaded at runtime so builds don't need it installed.
+const LIBRARY_NAMES: [&str; 2] = ["librgbmatrix.so.1", "librgbmatrix.so"];
+
+type Matrix = *mut c_void;
+type Canvas = *mut c_void;
+
+// Drives chains of HUB75 RGB LED panels through the rpi-rgb-led-matrix library, which refreshes
+// them from a realtime thread of its own. The output is scaled to the whole display, panels
+// wide by `hub75_chain` and high by `hub75_parallel`, so --width and --height are best set to
+// match it, e.g. 128 by 64 for two chained 64x64 panels. Needs root for the GPIO.
+pub struct Hub75Screen {
+    pixels_tx: mpsc::SyncSender<(Vec<u8>, i32, i32)>,
+}
+
+impl Hub75Screen {
+    pub fn new(options: &screen::Options) -> Hub75Screen {
+        let (pixels_tx, pixels_rx) = mpsc::sync_channel::<(Vec<u8>, i32, i32)>(1);
+
+        let mut flags = vec![
+            format!("--led-rows={}", options.hub75_rows),
+            format!("--led-cols={}", options.hub75_cols),
+            format!("--led-chain={}", options.hub75_chain),
+            format!("--led-parallel={}", options.hub75_parallel),
+            format!("--led-gpio-mapping={}", options.hub75_mapping),
+            // The library otherwise switches to the daemon user once it has the GPIO, and the
+            // renderer loses access to the GPU and the output files.
+            "--led-no-drop-privs".to_string(),
+        ];
+        flags.extend(options.hub75_flags.split_whitespace().map(|x| x.to_string()));
+
+        thread::spawn(move || {
+            if let Err(e) = matrix_pipeline(pixels_rx, &flags) {
+                println!("Not driving the HUB75 panels, {}", e);
+            }
+        });
+
+        Hub75Screen { pixels_tx }
+    }
+}
+
+impl screen::Screen for Hub75Screen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn is_linear(&self) -> bool {
+        true
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        // Frames are dropped while the panels are still waiting to show the last one, or if
+        // setting them up failed.
+        let _ = self.pixels_tx.try_send((pixels.to_vec(), width, height));
+    }
+}
+
+fn matrix_pipeline(pixels_rx: mpsc::Receiver<(Vec<u8>, i32, i32)>, flags: &[String]) -> Result<(), String> {
+    let library = load_library()?;
+
+    unsafe {
+        let create_from_options: unsafe extern "C" fn(*mut c_void, *mut c_int, *mut *mut *mut c_char) -> Matrix =
+            *symbol(&library, b"led_matrix_create_from_options\0")?;
+        let create_offscreen_canvas: unsafe extern "C" fn(Matrix) -> Canvas =
+            *symbol(&library, b"led_matrix_create_offscreen_canvas\0")?;
+        let swap_on_vsync: unsafe extern "C" fn(Matrix, Canvas) -> Canvas =
+            *symbol(&library, b"led_matrix_swap_on_vsync\0")?;
+        let get_size: unsafe extern "C" fn(Canvas, *mut c_int, *mut c_int) =
+            *symbol(&library, b"led_canvas_get_size\0")?;
+        let set_pixel: unsafe extern "C" fn(Canvas, c_int, c_int, u8, u8, u8) =
+            *symbol(&library, b"led_canvas_set_pixel\0")?;
+        let delete: unsafe extern "C" fn(Matrix) = *symbol(&library, b"led_matrix_delete\0")?;
+
+        // Configured with command line flags rather than its options struct, whose layout
+        // changes between versions.
+        let arguments = Some("rpi-music-visualizer").into_iter()
+            .chain(flags.iter().map(|x| x.as_ref()))
+            .map(|x| CString::new(x).map_err(|_| format!("invalid flag {}", x)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut argv: Vec<*mut c_char> = arguments.iter().map(|x| x.as_ptr() as *mut c_char).collect();
+        argv.push(ptr::null_mut());
+        let mut argc = arguments.len() as c_int;
+        let mut argv_pointer = argv.as_mut_ptr();
+
+        let matrix = create_from_options(ptr::null_mut(), &mut argc, &mut argv_pointer);
+        if matrix.is_null() {
+            return Err(format!("could not set them up with {}", flags.join(" ")));
+        }
+        // It takes the flags it knows out of the list.
+        for i in 1..argc as usize {
+            let flag = CStr::from_ptr(*argv_pointer.add(i));
+            println!("Ignoring HUB75 flag {}", flag.to_string_lossy());
+        }
+
+        let mut canvas = create_offscreen_canvas(matrix);
+        let (mut canvas_width, mut canvas_height) = (0, 0);
+        get_size(canvas, &mut canvas_width, &mut canvas_height);
+
+        for (pixels, width, height) in pixels_rx {
+            let pixel_colors = led_mapper::led_matrix_mapper::sample_pixels(
+                &pixels, width, height, canvas_width, canvas_height);
+            for (i, &(r, g, b)) in pixel_colors.iter().enumerate() {
+                let i = i as c_int;
+                set_pixel(canvas, i % canvas_width, i / canvas_width, r, g, b);
+            }
+
+            canvas = swap_on_vsync(matrix, canvas);
+        }
+
+        // Also turns the panels off.
+        delete(matrix);
+    }
+
+    Ok(())
+}
+
+fn load_library() -> Result<Library, String> {
+    let mut errors = Vec::new();
+    for name in LIBRARY_NAMES.iter() {
+        match Library::new(name) {
+            Ok(x) => return Ok(x),
+            Err(e) => errors.push(format!("{}: {}", name, e)),
+        }
+    }
+
+    Err(format!("could not load {}", errors.join(", ")))
+}
+
+unsafe fn symbol<'a, T>(library: &'a Library, name: &[u8]) -> Result<Symbol<'a, T>, String> {
+    library.get(name)
+        .map_err(|e| format!("could not find {}: {}", String::from_utf8_lossy(&name[..name.len() - 1]), e))
+}
@@ -0,0 +1,226 @@
+use gfx;
+use led_mapper;
+use screen;
+use screen::dtls::DtlsConnection;
+use screen::reconnect::Reconnecting;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const API_PORT: u16 = 80;
+const API_TIMEOUT: Duration = Duration::from_secs(2);
+const STREAM_PORT: u16 = 2100;
+// The only cipher the bridge takes.
+const CIPHER: &str = "PSK-AES128-GCM-SHA256";
+// Lights change about 25 times a second, however often they're sent colors. Colors are sent
+// again at this rate even when they haven't changed, or the bridge ends the stream.
+const SEND_INTERVAL: Duration = Duration::from_millis(40);
+// Each light shows the average of this fraction of the frame's width and height, around where
+// it stands.
+const ZONE_SIZE: f32 = 0.5;
+
+// A light of the entertainment area, with where it stands from left to right and from floor to
+// ceiling, both -1 to 1.
+struct Light {
+    id: u16,
+    x: f32,
+    z: f32,
+}
+
+// Streams the output to a Philips Hue entertainment area through the bridge's Entertainment API,
+// so the room's lights follow the visualizer. Each light of `group` shows the part of the frame
+// where it stands in the area, as set up in the Hue app, left to right and floor to ceiling.
+// `username` and `client_key` are an application's, registered on the bridge with
+// generateclientkey. The stream goes over DTLS with OpenSSL, loaded at runtime.
+pub struct HueScreen {
+    lights: Vec<Light>,
+    colors_tx: mpsc::SyncSender<Vec<(u8, u8, u8)>>,
+}
+
+impl HueScreen {
+    pub fn new(address: &str, username: &str, client_key: &str, group: u32) -> Result<HueScreen, String> {
+        let key = parse_hex(client_key).ok_or_else(|| format!("the Hue client key {} isn't hex", client_key))?;
+        let bridge = resolve(address)?;
+        let lights = group_lights(bridge, username, group)?;
+        if lights.is_empty() {
+            return Err(format!("the Hue entertainment area {} has no lights", group));
+        }
+        println!("Streaming to {} Hue lights", lights.len());
+
+        let username = username.to_string();
+        let open = move || {
+            set_streaming(bridge, &username, group)?;
+            DtlsConnection::connect(SocketAddr::new(bridge.ip(), STREAM_PORT), CIPHER, &username, &key)
+        };
+        let connection = open()?;
+
+        let ids: Vec<u16> = lights.iter().map(|x| x.id).collect();
+        let (colors_tx, colors_rx) = mpsc::sync_channel::<Vec<(u8, u8, u8)>>(1);
+        thread::spawn(move || {
+            let name = format!("the Hue bridge at {}", bridge.ip());
+            let mut connection = Reconnecting::new(name, Some(connection), open);
+            let mut colors = None;
+            let mut next_send = Instant::now();
+            loop {
+                let timeout = next_send.saturating_duration_since(Instant::now());
+                match colors_rx.recv_timeout(timeout) {
+                    Ok(x) => colors = Some(x),
+                    Err(mpsc::RecvTimeoutError::Timeout) => (),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+                let now = Instant::now();
+                if now < next_send {
+                    continue;
+                }
+
+                next_send = now + SEND_INTERVAL;
+                if let Some(ref colors) = colors {
+                    let message = stream_message(&ids, colors);
+                    connection.send(|connection| connection.write(&message));
+                }
+            }
+        });
+
+        Ok(HueScreen {
+            lights,
+            colors_tx,
+        })
+    }
+}
+
+impl screen::Screen for HueScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let colors = self.lights.iter()
+            .map(|light| zone_color(pixels, width as usize, height as usize, light))
+            .collect();
+
+        // Frames are dropped while the bridge is still to be sent the last one, or while
+        // reconnecting.
+        let _ = self.colors_tx.try_send(colors);
+    }
+}
+
+// The average color of the part of the frame, tightly packed RGB rows from the bottom, where
+// `light` stands.
+fn zone_color(pixels: &[u8], width: usize, height: usize, light: &Light) -> (u8, u8, u8) {
+    let span = |position: f32, size: usize| {
+        let center = (position + 1.0) / 2.0 * size as f32;
+        let half = ZONE_SIZE / 2.0 * size as f32;
+        let start = f32::max(0.0, center - half) as usize;
+        let end = usize::min(size, f32::max(0.0, center + half).ceil() as usize);
+        (usize::min(start, size - 1), usize::max(start + 1, end))
+    };
+    let (left, right) = span(light.x, width);
+    // Rows go up from the floor, like the light's height.
+    let (bottom, top) = span(light.z, height);
+
+    led_mapper::led_matrix_mapper::average_region(pixels, width, left..right, bottom..top)
+}
+
+// A HueStream version 1 message setting each light of `ids` to its color in RGB.
+fn stream_message(ids: &[u16], colors: &[(u8, u8, u8)]) -> Vec<u8> {
+    let mut message = b"HueStream".to_vec();
+    // Version 1.0, an unused sequence number, two reserved bytes, the RGB color space and
+    // another reserved byte.
+    message.extend_from_slice(&[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    for (&id, &(r, g, b)) in ids.iter().zip(colors.iter()) {
+        // A light, then its colors in 16 bits each, big endian.
+        message.extend_from_slice(&[0x00, (id >> 8) as u8, id as u8]);
+        for &channel in [r, g, b].iter() {
+            message.extend_from_slice(&[channel, channel]);
+        }
+    }
+    message
+}
+
+// The lights of the entertainment area `group`, and where they stand.
+fn group_lights(bridge: SocketAddr, username: &str, group: u32) -> Result<Vec<Light>, String> {
+    let response = api_request(bridge, "GET", &format!("/api/{}/groups/{}", username, group), "")?;
+    let locations = response.find("\"locations\"")
+        .map(|i| &response[i + "\"locations\"".len()..])
+        .ok_or_else(|| format!("the Hue group {} isn't an entertainment area", group))?;
+    let end = locations.find('}').ok_or("its light locations end early")?;
+
+    // Like {"1":[-0.5,0.5,0.0],"3":[0.5,0.5,0.0]}.
+    let mut lights = Vec::new();
+    for entry in locations[..end].split(']') {
+        let entry = entry.trim_start_matches(|x: char| x == ':' || x == '{' || x == ',' || x.is_whitespace());
+        if entry.is_empty() {
+            continue;
+        }
+        let (id, position) = match entry.find('[') {
+            Some(i) => (&entry[..i], &entry[i + 1..]),
+            None => return Err(format!("its light location {} has no position", entry)),
+        };
+        let id = id.trim().trim_end_matches(':').trim().trim_matches('"');
+        let coordinates: Vec<f32> = position.split(',').filter_map(|x| x.trim().parse().ok()).collect();
+        match (id.parse::<u16>(), coordinates.len()) {
+            (Ok(id), 3) => lights.push(Light { id, x: coordinates[0], z: coordinates[2] }),
+            _ => return Err(format!("its light location {} isn't a light and 3 coordinates", entry)),
+        }
+    }
+
+    Ok(lights)
+}
+
+// Ask the bridge to take a stream for the entertainment area `group`.
+fn set_streaming(bridge: SocketAddr, username: &str, group: u32) -> Result<(), String> {
+    api_request(bridge, "PUT", &format!("/api/{}/groups/{}", username, group), "{\"stream\":{\"active\":true}}")
+        .map(|_| ())
+}
+
+// The body of the bridge's reply to an HTTP request of its API, or the error in it.
+fn api_request(bridge: SocketAddr, method: &str, path: &str, body: &str) -> Result<String, String> {
+    let mut stream = TcpStream::connect_timeout(&bridge, API_TIMEOUT)
+        .map_err(|e| format!("could not reach the Hue bridge at {}: {}", bridge.ip(), e))?;
+    let _ = stream.set_read_timeout(Some(API_TIMEOUT));
+    let request = format!("{} {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                          method, path, bridge.ip(), body.len(), body);
+    stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(|e| e.to_string())?;
+
+    let body = match response.find("\r\n\r\n") {
+        Some(i) => response[i + 4..].to_string(),
+        None => return Err("the Hue bridge's reply has no body".to_string()),
+    };
+    // Errors come as [{"error":{"type":1,"address":"/","description":"unauthorized user"}}].
+    if body.contains("\"error\"") {
+        let description = body.find("\"description\":\"")
+            .map(|i| &body[i + "\"description\":\"".len()..])
+            .and_then(|x| x.split('"').next())
+            .unwrap_or("unknown error");
+        return Err(format!("the Hue bridge says {}", description));
+    }
+    Ok(body)
+}
+
+// The bridge's API address, with its port unless `address` has one.
+fn resolve(address: &str) -> Result<SocketAddr, String> {
+    let with_port = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, API_PORT) };
+    with_port.to_socket_addrs()
+        .map_err(|e| format!("invalid Hue bridge address {}: {}", address, e))?
+        .next()
+        .ok_or_else(|| format!("could not resolve {}", address))
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    if text.is_empty() || !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok()).collect()
+}
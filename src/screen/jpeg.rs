@@ -0,0 +1,531 @@
+use std::f32::consts::PI;
+
+// The example tables of the JPEG standard, which the quality scales. Row by row, then sent in
+// zigzag order.
+const LUMINANCE_QUANTIZATION: [u8; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61,
+    12, 12, 14, 19, 26, 58, 60, 55,
+    14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77,
+    24, 35, 55, 64, 81, 104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+const CHROMINANCE_QUANTIZATION: [u8; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+// The standard's Huffman tables, as how many codes there are of each length from 1 to 16 bits
+// and the symbols they stand for, shortest first.
+const LUMINANCE_DC_LENGTHS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const CHROMINANCE_DC_LENGTHS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_SYMBOLS: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const LUMINANCE_AC_LENGTHS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+const LUMINANCE_AC_SYMBOLS: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+const CHROMINANCE_AC_LENGTHS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+const CHROMINANCE_AC_SYMBOLS: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0,
+    0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26,
+    0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5,
+    0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3,
+    0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+    0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+// A Huffman table's (code, length in bits) for each symbol.
+struct HuffmanTable {
+    codes: [(u16, u8); 256],
+}
+
+impl HuffmanTable {
+    fn new(lengths: &[u8; 16], symbols: &[u8]) -> HuffmanTable {
+        let mut codes = [(0, 0); 256];
+        let mut symbols = symbols.iter();
+        let mut code = 0u16;
+        for (i, &count) in lengths.iter().enumerate() {
+            for _ in 0..count {
+                codes[*symbols.next().unwrap() as usize] = (code, i as u8 + 1);
+                code += 1;
+            }
+            code <<= 1;
+        }
+
+        HuffmanTable { codes }
+    }
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u32,
+    bit_count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u16, length: u8) {
+        self.bits = (self.bits << length) | (value as u32 & ((1 << length) - 1));
+        self.bit_count += length as u32;
+        while self.bit_count >= 8 {
+            self.bit_count -= 8;
+            let byte = (self.bits >> self.bit_count) as u8;
+            self.bytes.push(byte);
+            // So it isn't taken for a marker.
+            if byte == 0xff {
+                self.bytes.push(0);
+            }
+        }
+    }
+
+    // Pad the last byte with ones.
+    fn flush(&mut self) {
+        if self.bit_count > 0 {
+            let padding = 8 - self.bit_count as u8;
+            self.write(0xff, padding);
+        }
+    }
+}
+
+// Encodes tightly packed RGB rows, bottom to top, as a baseline JPEG of `quality` from 1 to 100,
+// without chroma subsampling.
+pub fn encode(pixels: &[u8], width: usize, height: usize, quality: u8) -> Vec<u8> {
+    let scale = match quality.clamp(1, 100) as u32 {
+        x if x < 50 => 5000 / x,
+        x => 200 - 2 * x,
+    };
+    let scaled = |table: &[u8; 64]| {
+        let mut scaled = [0u8; 64];
+        for (x, &y) in scaled.iter_mut().zip(table.iter()) {
+            *x = ((y as u32 * scale + 50) / 100).clamp(1, 255) as u8;
+        }
+        scaled
+    };
+    let quantization = [scaled(&LUMINANCE_QUANTIZATION), scaled(&CHROMINANCE_QUANTIZATION)];
+    let zigzag = zigzag_order();
+
+    let mut jpeg = vec![0xff, 0xd8];
+    for (id, table) in quantization.iter().enumerate() {
+        let mut data = vec![id as u8];
+        data.extend(zigzag.iter().map(|&i| table[i]));
+        segment(&mut jpeg, 0xdb, &data);
+    }
+
+    segment(&mut jpeg, 0xc0, &[
+        8, (height >> 8) as u8, height as u8, (width >> 8) as u8, width as u8, 3,
+        // Y, Cb and Cr, each at full resolution, with their quantization table.
+        1, 0x11, 0, 2, 0x11, 1, 3, 0x11, 1,
+    ]);
+
+    let huffman_tables = [
+        (0x00, &LUMINANCE_DC_LENGTHS, &DC_SYMBOLS[..]),
+        (0x10, &LUMINANCE_AC_LENGTHS, &LUMINANCE_AC_SYMBOLS[..]),
+        (0x01, &CHROMINANCE_DC_LENGTHS, &DC_SYMBOLS[..]),
+        (0x11, &CHROMINANCE_AC_LENGTHS, &CHROMINANCE_AC_SYMBOLS[..]),
+    ];
+    for &(class_and_id, lengths, symbols) in huffman_tables.iter() {
+        let mut data = vec![class_and_id];
+        data.extend_from_slice(lengths);
+        data.extend_from_slice(symbols);
+        segment(&mut jpeg, 0xc4, &data);
+    }
+    let dc_tables = [HuffmanTable::new(&LUMINANCE_DC_LENGTHS, &DC_SYMBOLS),
+                     HuffmanTable::new(&CHROMINANCE_DC_LENGTHS, &DC_SYMBOLS)];
+    let ac_tables = [HuffmanTable::new(&LUMINANCE_AC_LENGTHS, &LUMINANCE_AC_SYMBOLS),
+                     HuffmanTable::new(&CHROMINANCE_AC_LENGTHS, &CHROMINANCE_AC_SYMBOLS)];
+
+    segment(&mut jpeg, 0xda, &[3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0]);
+
+    let cosines = dct_cosines();
+    let mut writer = BitWriter { bytes: jpeg, bits: 0, bit_count: 0 };
+    let mut previous_dc = [0i32; 3];
+    for block_y in 0..height.div_ceil(8) {
+        for block_x in 0..width.div_ceil(8) {
+            let mut samples = [[0f32; 64]; 3];
+            for i in 0..64 {
+                // Edge pixels fill the blocks past the image. JPEG rows are top to bottom.
+                let x = (block_x * 8 + i % 8).min(width - 1);
+                let y = (block_y * 8 + i / 8).min(height - 1);
+                let p = 3 * ((height - 1 - y) * width + x);
+                let (r, g, b) = (pixels[p] as f32, pixels[p + 1] as f32, pixels[p + 2] as f32);
+                let ycbcr = [
+                    0.299 * r + 0.587 * g + 0.114 * b - 128.0,
+                    -0.168_736 * r - 0.331_264 * g + 0.5 * b,
+                    0.5 * r - 0.418_688 * g - 0.081_312 * b,
+                ];
+                for (component, &value) in samples.iter_mut().zip(ycbcr.iter()) {
+                    component[i] = value;
+                }
+            }
+
+            for component in 0..3 {
+                let table = if component == 0 { 0 } else { 1 };
+                let coefficients = dct(&samples[component], &cosines);
+                let mut quantized = [0i32; 64];
+                for (k, &i) in zigzag.iter().enumerate() {
+                    quantized[k] = (coefficients[i] / quantization[table][i] as f32).round() as i32;
+                }
+
+                encode_block(&mut writer, &quantized, &mut previous_dc[component],
+                             &dc_tables[table], &ac_tables[table]);
+            }
+        }
+    }
+    writer.flush();
+
+    let mut jpeg = writer.bytes;
+    jpeg.extend_from_slice(&[0xff, 0xd9]);
+    jpeg
+}
+
+fn segment(jpeg: &mut Vec<u8>, marker: u8, data: &[u8]) {
+    let length = data.len() + 2;
+    jpeg.extend_from_slice(&[0xff, marker, (length >> 8) as u8, length as u8]);
+    jpeg.extend_from_slice(data);
+}
+
+// Indices into a row by row block, in the zigzag order coefficients are sent in.
+fn zigzag_order() -> [usize; 64] {
+    let mut order = [0; 64];
+    let mut k = 0;
+    for diagonal in 0..15 {
+        let (start, end) = (diagonal.max(7) - 7, diagonal.min(7));
+        for j in start..=end {
+            // Odd diagonals run down to the left, even ones up to the right.
+            let row = if diagonal % 2 == 1 { j } else { diagonal - j };
+            order[k] = row * 8 + diagonal - row;
+            k += 1;
+        }
+    }
+    order
+}
+
+// cos((2x + 1)uπ/16) with the DCT's scale for u folded in, by u then x.
+fn dct_cosines() -> [[f32; 8]; 8] {
+    let mut cosines = [[0.0; 8]; 8];
+    for (u, row) in cosines.iter_mut().enumerate() {
+        let scale = if u == 0 { 0.5 / 2f32.sqrt() } else { 0.5 };
+        for (x, cosine) in row.iter_mut().enumerate() {
+            *cosine = scale * ((2 * x + 1) as f32 * u as f32 * PI / 16.0).cos();
+        }
+    }
+    cosines
+}
+
+// The 2D DCT of a row by row block, as rows then columns.
+fn dct(samples: &[f32; 64], cosines: &[[f32; 8]; 8]) -> [f32; 64] {
+    let mut rows = [0.0; 64];
+    for y in 0..8 {
+        for u in 0..8 {
+            rows[y * 8 + u] = (0..8).map(|x| cosines[u][x] * samples[y * 8 + x]).sum();
+        }
+    }
+
+    let mut coefficients = [0.0; 64];
+    for u in 0..8 {
+        for v in 0..8 {
+            coefficients[v * 8 + u] = (0..8).map(|y| cosines[v][y] * rows[y * 8 + u]).sum();
+        }
+    }
+    coefficients
+}
+
+// Write a block's zigzag ordered coefficients, the DC one as the difference from the last block's.
+fn encode_block(writer: &mut BitWriter, coefficients: &[i32; 64], previous_dc: &mut i32,
+                dc_table: &HuffmanTable, ac_table: &HuffmanTable) {
+    let write_value = |writer: &mut BitWriter, table: &HuffmanTable, run: u8, value: i32| {
+        let size = 32 - value.abs().leading_zeros() as u8;
+        let (code, length) = table.codes[(run << 4 | size) as usize];
+        writer.write(code, length);
+        // Negative values are sent one less, in as many bits.
+        let bits = if value < 0 { value - 1 } else { value };
+        writer.write(bits as u16, size);
+    };
+
+    write_value(writer, dc_table, 0, coefficients[0] - *previous_dc);
+    *previous_dc = coefficients[0];
+
+    let mut zeros = 0;
+    for &coefficient in coefficients[1..].iter() {
+        if coefficient == 0 {
+            zeros += 1;
+            continue;
+        }
+        while zeros >= 16 {
+            let (code, length) = ac_table.codes[0xf0];
+            writer.write(code, length);
+            zeros -= 16;
+        }
+        write_value(writer, ac_table, zeros, coefficient);
+        zeros = 0;
+    }
+    if zeros > 0 {
+        let (code, length) = ac_table.codes[0x00];
+        writer.write(code, length);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The standard's zigzag order, written out rather than worked out like zigzag_order.
+    const ZIGZAG: [usize; 64] = [
+        0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5,
+        12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28,
+        35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51,
+        58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+    ];
+
+    // The marker and data of each segment up to the scan, and the entropy coded data after it.
+    fn segments(jpeg: &[u8]) -> (Vec<(u8, &[u8])>, &[u8]) {
+        assert_eq!(&jpeg[..2], &[0xff, 0xd8]);
+        assert_eq!(&jpeg[jpeg.len() - 2..], &[0xff, 0xd9]);
+        let mut segments = Vec::new();
+        let mut i = 2;
+        loop {
+            assert_eq!(jpeg[i], 0xff);
+            let marker = jpeg[i + 1];
+            let length = (jpeg[i + 2] as usize) << 8 | jpeg[i + 3] as usize;
+            segments.push((marker, &jpeg[i + 4..i + 2 + length]));
+            i += 2 + length;
+            if marker == 0xda {
+                return (segments, &jpeg[i..jpeg.len() - 2]);
+            }
+        }
+    }
+
+    struct BitReader<'a> {
+        data: &'a [u8],
+        position: usize,
+        bit: u32,
+    }
+
+    impl<'a> BitReader<'a> {
+        fn read(&mut self, length: u8) -> u16 {
+            let mut value = 0;
+            for _ in 0..length {
+                let byte = self.data[self.position];
+                value = value << 1 | (byte >> (7 - self.bit) & 1) as u16;
+                self.bit += 1;
+                if self.bit == 8 {
+                    self.bit = 0;
+                    // Skip the zero stuffed after a 0xff.
+                    self.position += if byte == 0xff { 2 } else { 1 };
+                }
+            }
+            value
+        }
+
+        fn read_symbol(&mut self, table: &[(u16, u8, u8)]) -> u8 {
+            let (mut code, mut length) = (0, 0);
+            loop {
+                code = code << 1 | self.read(1);
+                length += 1;
+                if let Some(&(_, _, symbol)) = table.iter().find(|x| (x.0, x.1) == (code, length)) {
+                    return symbol;
+                }
+                assert!(length < 16, "no Huffman code {:b}", code);
+            }
+        }
+
+        fn read_value(&mut self, size: u8) -> i32 {
+            let bits = self.read(size) as i32;
+            if size > 0 && bits < 1 << (size - 1) { bits - (1 << size) + 1 } else { bits }
+        }
+    }
+
+    // A baseline decoder for what encode writes, with the tables the JPEG carries, to RGB rows
+    // top to bottom.
+    fn decode(jpeg: &[u8]) -> (Vec<u8>, usize, usize) {
+        let (segments, data) = segments(jpeg);
+        let mut quantization = vec![[0u16; 64]; 4];
+        let mut huffman = vec![Vec::new(); 0x20];
+        let (mut width, mut height, mut tables) = (0, 0, [0; 3]);
+        for &(marker, segment) in &segments {
+            match marker {
+                0xdb => {
+                    for (k, &x) in segment[1..].iter().enumerate() {
+                        quantization[segment[0] as usize][k] = x as u16;
+                    }
+                },
+                0xc0 => {
+                    height = (segment[1] as usize) << 8 | segment[2] as usize;
+                    width = (segment[3] as usize) << 8 | segment[4] as usize;
+                    for (component, table) in tables.iter_mut().enumerate() {
+                        *table = segment[6 + 3 * component + 2] as usize;
+                    }
+                },
+                0xc4 => {
+                    let mut symbols = segment[17..].iter();
+                    let mut code = 0;
+                    for length in 1..=16 {
+                        for _ in 0..segment[length] {
+                            huffman[segment[0] as usize].push((code, length as u8, *symbols.next().unwrap()));
+                            code += 1;
+                        }
+                        code <<= 1;
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        let mut reader = BitReader { data, position: 0, bit: 0 };
+        let mut previous_dc = [0i32; 3];
+        let mut pixels = vec![0u8; 3 * width * height];
+        for block_y in 0..height.div_ceil(8) {
+            for block_x in 0..width.div_ceil(8) {
+                let mut blocks = [[0f32; 64]; 3];
+                for component in 0..3 {
+                    let table = if component == 0 { 0 } else { 1 };
+                    let q = &quantization[tables[component]];
+                    let mut coefficients = [0f32; 64];
+
+                    let size = reader.read_symbol(&huffman[table]);
+                    previous_dc[component] += reader.read_value(size);
+                    coefficients[0] = (previous_dc[component] * q[0] as i32) as f32;
+                    let mut k = 1;
+                    while k < 64 {
+                        let symbol = reader.read_symbol(&huffman[0x10 | table]);
+                        match symbol {
+                            0x00 => break,
+                            0xf0 => k += 16,
+                            _ => {
+                                k += (symbol >> 4) as usize;
+                                let value = reader.read_value(symbol & 0xf);
+                                coefficients[ZIGZAG[k]] = (value * q[k] as i32) as f32;
+                                k += 1;
+                            },
+                        }
+                    }
+
+                    for y in 0..8 {
+                        for x in 0..8 {
+                            let mut sum = 0.0;
+                            for v in 0..8 {
+                                for u in 0..8 {
+                                    let cu = if u == 0 { 0.5f32.sqrt() } else { 1.0 };
+                                    let cv = if v == 0 { 0.5f32.sqrt() } else { 1.0 };
+                                    sum += cu * cv * coefficients[v * 8 + u]
+                                        * ((2 * x + 1) as f32 * u as f32 * PI / 16.0).cos()
+                                        * ((2 * y + 1) as f32 * v as f32 * PI / 16.0).cos();
+                                }
+                            }
+                            blocks[component][y * 8 + x] = sum / 4.0;
+                        }
+                    }
+                }
+
+                for i in 0..64 {
+                    let (x, y) = (block_x * 8 + i % 8, block_y * 8 + i / 8);
+                    if x >= width || y >= height {
+                        continue;
+                    }
+                    let (luma, cb, cr) = (blocks[0][i] + 128.0, blocks[1][i], blocks[2][i]);
+                    let rgb = [luma + 1.402 * cr, luma - 0.344_136 * cb - 0.714_136 * cr, luma + 1.772 * cb];
+                    for (c, &value) in rgb.iter().enumerate() {
+                        pixels[3 * (y * width + x) + c] = value.round().clamp(0.0, 255.0) as u8;
+                    }
+                }
+            }
+        }
+        (pixels, width, height)
+    }
+
+    // Rows bottom to top with a gradient each way and a bright square in the middle.
+    fn image(width: usize, height: usize) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity(3 * width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let square = x > width / 4 && x < 3 * width / 4 && y > height / 4 && y < 3 * height / 4;
+                pixels.extend_from_slice(&[
+                    (255 * x / width) as u8,
+                    (255 * y / height) as u8,
+                    if square { 220 } else { 40 },
+                ]);
+            }
+        }
+        pixels
+    }
+
+    // The biggest difference of any channel, flipping the decoded rows to bottom to top.
+    fn largest_error(pixels: &[u8], width: usize, height: usize, jpeg: &[u8]) -> i32 {
+        let (decoded, decoded_width, decoded_height) = decode(jpeg);
+        assert_eq!((decoded_width, decoded_height), (width, height));
+        let mut largest = 0;
+        for y in 0..height {
+            let row = &pixels[3 * width * y..3 * width * (y + 1)];
+            let decoded_row = &decoded[3 * width * (height - 1 - y)..3 * width * (height - y)];
+            for (&x, &z) in row.iter().zip(decoded_row.iter()) {
+                largest = largest.max((x as i32 - z as i32).abs());
+            }
+        }
+        largest
+    }
+
+    #[test]
+    fn zigzag_order_is_the_standards() {
+        assert_eq!(zigzag_order(), ZIGZAG);
+    }
+
+    #[test]
+    fn writes_the_baseline_segments() {
+        let jpeg = encode(&image(9, 7), 9, 7, 75);
+        let (segments, data) = segments(&jpeg);
+        let layout: Vec<(u8, usize)> = segments.iter().map(|&(marker, data)| (marker, data.len() + 2)).collect();
+        assert_eq!(layout, vec![
+            (0xdb, 67), (0xdb, 67),
+            (0xc0, 17),
+            (0xc4, 31), (0xc4, 181), (0xc4, 31), (0xc4, 181),
+            (0xda, 12),
+        ]);
+        assert_eq!(segments[2].1, &[8, 0, 7, 0, 9, 3, 1, 0x11, 0, 2, 0x11, 1, 3, 0x11, 1][..]);
+        // The scan has no markers in it, only stuffed 0xffs.
+        assert!(data.windows(2).all(|x| x[0] != 0xff || x[1] == 0));
+    }
+
+    #[test]
+    fn scales_the_quantization_tables() {
+        let table = |quality| {
+            let jpeg = encode(&image(8, 8), 8, 8, quality);
+            segments(&jpeg).0[0].1[1..].to_vec()
+        };
+        assert_eq!(table(50)[..4], [16, 11, 12, 14]);
+        assert!(table(100).iter().all(|&x| x == 1));
+        assert!(table(1).iter().all(|&x| x == 255));
+        assert_eq!(table(0), table(1));
+    }
+
+    #[test]
+    fn decodes_to_the_image() {
+        for &(width, height) in &[(8, 8), (9, 7)] {
+            let pixels = image(width, height);
+            assert!(largest_error(&pixels, width, height, &encode(&pixels, width, height, 100)) <= 4);
+        }
+        let flat = vec![90u8; 3 * 9 * 7];
+        assert!(largest_error(&flat, 9, 7, &encode(&flat, 9, 7, 50)) <= 2);
+    }
+}
@@ -0,0 +1,189 @@
+use gfx;
+use led_mapper;
+use mqtt;
+use screen;
+use screen::reconnect::Reconnecting;
+use screen::serial;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// Smart lights on a mesh network like Zigbee fall behind when sent a color every frame.
+const MQTT_FPS: f32 = 5.0;
+// The PWM channels' period, 1 kHz, too fast to see flicker.
+const PWM_PERIOD_NS: u32 = 1_000_000;
+const CLIENT_ID: &str = "rpi-music-visualizer";
+
+// Shows the output's average color on one RGB lamp, for a desk lamp following the music. It's
+// reached at `address`, one of:
+// - "pwm:pwmchip0/0,pwmchip0/1,pwmchip1/0", the sysfs PWM channels of its red, green and blue
+//   LEDs, like the Pi's own with the pwm-2chan overlay or a PCA9685's.
+// - A serial device like "/dev/ttyUSB0", at `baud`, for a microcontroller taking Adalight frames
+//   of one LED.
+// - "mqtt://[user:password@]broker[:port]/topic", a light taking Home Assistant's JSON schema on
+//   `topic`, like Zigbee2MQTT's "zigbee2mqtt/desk/set".
+// The color is only sent when it changes.
+pub struct LampScreen {
+    is_linear: bool,
+    last_color: Option<(u8, u8, u8)>,
+    colors_tx: mpsc::SyncSender<(u8, u8, u8)>,
+}
+
+impl LampScreen {
+    pub fn new(address: &str, baud: u32) -> Result<LampScreen, String> {
+        if address.is_empty() {
+            return Err("the lamp screen needs --lamp_address".to_string());
+        }
+        let lamp = Lamp::open(address, baud)?;
+        // LEDs on PWM or a microcontroller are linear, smart lights gamma correct themselves.
+        let is_linear = !matches!(lamp, Lamp::Mqtt(..));
+
+        let (colors_tx, colors_rx) = mpsc::sync_channel::<(u8, u8, u8)>(1);
+        let address = address.to_string();
+        thread::spawn(move || {
+            let name = format!("the lamp at {}", address);
+            let mut connection = Reconnecting::new(name, Some(lamp), || Lamp::open(&address, baud));
+            for color in colors_rx {
+                connection.send(|lamp| lamp.send(color).map_err(|e| e.to_string()));
+            }
+        });
+
+        Ok(LampScreen {
+            is_linear,
+            last_color: None,
+            colors_tx,
+        })
+    }
+}
+
+// How many frames a second the lamp at `address` takes, unless --lamp_fps says, 0 for every one.
+pub fn default_fps(address: &str) -> f32 {
+    if address.starts_with("mqtt://") { MQTT_FPS } else { 0.0 }
+}
+
+impl screen::Screen for LampScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn is_linear(&self) -> bool {
+        self.is_linear
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let color = led_mapper::led_matrix_mapper::average_columns(pixels, width as usize, height as usize,
+                                                                   0, width as usize);
+        if self.last_color == Some(color) {
+            return;
+        }
+
+        // Colors are dropped while the last one is still being sent, or while reconnecting.
+        if self.colors_tx.try_send(color).is_ok() {
+            self.last_color = Some(color);
+        }
+    }
+}
+
+enum Lamp {
+    // Each channel's directory in sysfs.
+    Pwm(Vec<String>),
+    Serial(File),
+    Mqtt(TcpStream, String),
+}
+
+impl Lamp {
+    fn open(address: &str, baud: u32) -> Result<Lamp, String> {
+        if let Some(channels) = address.strip_prefix("pwm:") {
+            return open_pwm(channels);
+        }
+        if let Some(broker) = address.strip_prefix("mqtt://") {
+            return open_mqtt(broker);
+        }
+        if address.starts_with('/') {
+            return serial::open(address, Some(baud)).map(Lamp::Serial);
+        }
+        Err(format!("unknown lamp address {}, expected pwm:<channels>, a serial device or mqtt://<broker>/<topic>",
+                    address))
+    }
+
+    fn send(&mut self, (r, g, b): (u8, u8, u8)) -> io::Result<()> {
+        match *self {
+            Lamp::Pwm(ref channels) => {
+                for (channel, &value) in channels.iter().zip([r, g, b].iter()) {
+                    let duty_cycle = PWM_PERIOD_NS as u64 * value as u64 / 255;
+                    fs::write(format!("{}/duty_cycle", channel), duty_cycle.to_string())?;
+                }
+                Ok(())
+            },
+            // Adalight's header for one LED: its magic word, the LED count less one, big endian,
+            // and their checksum.
+            Lamp::Serial(ref mut file) => file.write_all(&[b'A', b'd', b'a', 0, 0, 0x55, r, g, b]),
+            Lamp::Mqtt(ref mut stream, ref topic) => {
+                // The lights take the color apart from its brightness.
+                let brightness = u8::max(r, u8::max(g, b));
+                let payload = if brightness == 0 {
+                    "{\"state\":\"OFF\"}".to_string()
+                } else {
+                    let full = |x: u8| (x as u32 * 255 / brightness as u32) as u8;
+                    format!("{{\"state\":\"ON\",\"brightness\":{},\"color\":{{\"r\":{},\"g\":{},\"b\":{}}}}}",
+                            brightness, full(r), full(g), full(b))
+                };
+                stream.write_all(&mqtt::publish_packet(topic, payload.as_bytes(), false))
+            },
+        }
+    }
+}
+
+// Export and enable each of a list of sysfs PWM channels like "pwmchip0/0,pwmchip0/1,pwmchip1/0",
+// red, green and blue.
+fn open_pwm(channels: &str) -> Result<Lamp, String> {
+    let channels: Vec<&str> = channels.split(',').map(|x| x.trim()).collect();
+    if channels.len() != 3 {
+        return Err(format!("expected the red, green and blue PWM channels, got {}", channels.join(",")));
+    }
+
+    let mut paths = Vec::with_capacity(3);
+    for channel in channels {
+        let expected = || format!("expected a PWM channel like pwmchip0/0, got '{}'", channel);
+        let mut parts = channel.splitn(2, '/');
+        let chip = format!("/sys/class/pwm/{}", parts.next().ok_or_else(&expected)?);
+        let number = parts.next().ok_or_else(&expected)?.parse::<u32>().map_err(|_| expected())?;
+        let path = format!("{}/pwm{}", chip, number);
+        if fs::metadata(&path).is_err() {
+            fs::write(format!("{}/export", chip), number.to_string())
+                .map_err(|e| format!("could not export {} (is PWM enabled?): {}", channel, e))?;
+        }
+        // Set in this order, so the duty cycle never goes over the period.
+        for &(file, value) in [("duty_cycle", 0), ("period", PWM_PERIOD_NS), ("enable", 1)].iter() {
+            fs::write(format!("{}/{}", path, file), value.to_string())
+                .map_err(|e| format!("could not set {}'s {}: {}", channel, file, e))?;
+        }
+        paths.push(path);
+    }
+
+    Ok(Lamp::Pwm(paths))
+}
+
+// Connect to the MQTT broker at "[user:password@]broker[:port]/topic", to publish on `topic`.
+fn open_mqtt(address: &str) -> Result<Lamp, String> {
+    let mut parts = address.splitn(2, '/');
+    let authority = parts.next().unwrap_or("");
+    let topic = match parts.next() {
+        Some(x) if !x.is_empty() => x.to_string(),
+        _ => return Err(format!("expected mqtt://<broker>/<topic>, got mqtt://{}", address)),
+    };
+    // No keep alive, as the broker's told nothing while the color holds.
+    let stream = mqtt::connect(authority, CLIENT_ID, Duration::from_secs(0), None)?;
+    Ok(Lamp::Mqtt(stream, topic))
+}
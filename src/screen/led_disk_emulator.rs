@@ -5,22 +5,31 @@ use led_mapper::led_disk_mapper::NUM_PIXELS;
 use led_mapper::led_disk_mapper::PIXEL_RADIUS;
 use screen;
 use std::f32;
-use std::mem;
-use std::ptr;
 
 pub struct LedDiskEmulatorScreen {
-    program_id: u32,
+    program: Option<gfx::Program>,
+    mesh: Option<gfx::Mesh>,
     mapper: led_mapper::LedDiskMapper,
+    // Reads the output back for the mapper. Made on the first frame, and asynchronous, so the
+    // colors are from the frame before.
+    pixel_reader: Option<gfx::PixelReader>,
+    // Dims like the disk would, to preview the power budget.
+    power_limiter: led_mapper::PowerLimiter,
 }
 
 const FLOATS_PER_VERTEX: usize = 8;
 const FLOATS_PER_PIXEL: usize = 6 * FLOATS_PER_VERTEX;
 
 impl LedDiskEmulatorScreen {
-    pub fn new() -> LedDiskEmulatorScreen {
+    pub fn new(power_budget_milliamps: u32) -> LedDiskEmulatorScreen {
         LedDiskEmulatorScreen {
-            program_id: 0,
+            program: None,
+            mesh: None,
             mapper: led_mapper::LedDiskMapper::new(),
+            pixel_reader: None,
+            power_limiter: led_mapper::PowerLimiter::new("the emulated LEDs",
+                                                         power_budget_milliamps,
+                                                         led_mapper::led_disk_mapper::BRIGHTNESS),
         }
     }
 
@@ -109,105 +118,37 @@ impl LedDiskEmulatorScreen {
 }
 
 impl screen::Screen for LedDiskEmulatorScreen {
-    fn setup(&mut self, gl: &gfx::gl::Gl) {
-        unsafe {
-            let vs = gl_try!(gl; gl.CreateShader(gl::VERTEX_SHADER));
-            gl_try!(gl; gl.ShaderSource(vs, 1, [VS_SRC.as_ptr() as *const _].as_ptr(), ptr::null()));
-            gl_try!(gl; gl.CompileShader(vs));
-
-            let fs = gl_try!(gl; gl.CreateShader(gl::FRAGMENT_SHADER));
-            gl_try!(gl; gl.ShaderSource(fs, 1, [FS_SRC.as_ptr() as *const _].as_ptr(), ptr::null()));
-            gl_try!(gl; gl.CompileShader(fs));
-
-            let program = gl_try!(gl; gl.CreateProgram());
-            gl_try!(gl; gl.AttachShader(program, vs));
-            gl_try!(gl; gl.AttachShader(program, fs));
-            gl_try!(gl; gl.LinkProgram(program));
-
-            self.program_id = program;
+    fn setup(&mut self, gl: &gfx::gl::Gl) -> Result<(), String> {
+        let program = gfx::Program::new(gl, VS_SRC, FS_SRC).map_err(|e| format!("its {}", e))?;
 
-            let mut is_linked = mem::uninitialized();
-            gl_try!(gl; gl.GetProgramiv(program, gl::LINK_STATUS, &mut is_linked));
-            if is_linked == gl::FALSE as i32 {
-                let mut max_length = mem::uninitialized();
-                gl_try!(gl; gl.GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut max_length));
-
-                let mut info_log = vec![0; max_length as usize];
-                gl_try!(gl; gl.GetProgramInfoLog(program, max_length, &mut max_length, info_log.as_mut_ptr()));
-
-                for info_char in info_log.iter() {
-                    print!("{}", *info_char as u8 as char);
-                }
-                panic!();
-            }
-        }
+        self.mesh = Some(gfx::Mesh::new(gl, program.id(),
+                                        &[("center", 2), ("position", 2), ("radius", 1), ("color", 3)])?);
+        self.program = Some(program);
+        Ok(())
     }
 
-    fn render_from_texture(&mut self, gl: &gfx::gl::Gl, texture: u32, size: i32) {
+    fn render_from_texture(&mut self, gl: &gfx::gl::Gl, _texture: u32, width: i32, height: i32) {
         unsafe {
-            let pixel_colors = self.mapper.map_from_texture(gl, texture, size);
+            let pixel_reader = self.pixel_reader.get_or_insert_with(|| gfx::PixelReader::new(gl, true));
+            let pixels = pixel_reader.read(width as usize, height as usize);
+            let mut pixel_colors = self.mapper.map_from_pixels(pixels, width, height);
+            self.power_limiter.limit(&mut pixel_colors);
 
-            gl_try!(gl; gl.UseProgram(self.program_id));
+            if let Some(ref program) = self.program {
+                program.bind();
+            }
 
             let vertex_data = self.generate_vertex_data(pixel_colors);
 
-            let mut vb = mem::uninitialized();
-            gl_try!(gl; gl.GenBuffers(1, &mut vb));
-            gl_try!(gl; gl.BindBuffer(gl::ARRAY_BUFFER, vb));
-            gl_try!(gl; gl.BufferData(
-                gl::ARRAY_BUFFER,
-                (vertex_data.len() * mem::size_of::<f32>()) as gl::types::GLsizeiptr,
-                vertex_data.as_ptr() as *const _,
-                gl::STATIC_DRAW,
-            ));
-
-            let mut vao = mem::uninitialized();
-            gl_try!(gl; gl.GenVertexArrays(1, &mut vao));
-            gl_try!(gl; gl.BindVertexArray(vao));
-
-            let center_attrib = gl_try!(gl; gl.GetAttribLocation(
-                self.program_id, b"center\0".as_ptr() as *const _));
-            let pos_attrib = gl_try!(gl; gl.GetAttribLocation(
-                self.program_id, b"position\0".as_ptr() as *const _));
-            let radius_attrib = gl_try!(gl; gl.GetAttribLocation(
-                self.program_id, b"radius\0".as_ptr() as *const _));
-            let color_attrib = gl_try!(gl; gl.GetAttribLocation(
-                self.program_id, b"color\0".as_ptr() as *const _));
-            gl_try!(gl; gl.VertexAttribPointer(
-                center_attrib as gl::types::GLuint, 2, gl::FLOAT,
-                0, 8 * mem::size_of::<f32>() as gl::types::GLsizei,
-                ptr::null(),
-            ));
-            gl_try!(gl; gl.VertexAttribPointer(
-                pos_attrib as gl::types::GLuint, 2, gl::FLOAT,
-                0, 8 * mem::size_of::<f32>() as gl::types::GLsizei,
-                (2 * mem::size_of::<f32>()) as *const () as *const _,
-            ));
-            gl_try!(gl; gl.VertexAttribPointer(
-                radius_attrib as gl::types::GLuint, 1, gl::FLOAT,
-                0, 8 * mem::size_of::<f32>() as gl::types::GLsizei,
-                (4 * mem::size_of::<f32>()) as *const () as *const _,
-            ));
-            gl_try!(gl; gl.VertexAttribPointer(
-                color_attrib as gl::types::GLuint, 3, gl::FLOAT,
-                0, 8 * mem::size_of::<f32>() as gl::types::GLsizei,
-                (5 * mem::size_of::<f32>()) as *const () as *const _,
-            ));
-            gl_try!(gl; gl.EnableVertexAttribArray(center_attrib as gl::types::GLuint));
-            gl_try!(gl; gl.EnableVertexAttribArray(pos_attrib as gl::types::GLuint));
-            gl_try!(gl; gl.EnableVertexAttribArray(radius_attrib as gl::types::GLuint));
-            gl_try!(gl; gl.EnableVertexAttribArray(color_attrib as gl::types::GLuint));
-
             gl_try!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, 0));
 
             gl_try!(gl; gl.ClearColor(0.0, 0.0, 0.0, 1.0));
             gl_try!(gl; gl.Clear(gl::COLOR_BUFFER_BIT));
 
-            gl_try!(gl; gl.DrawArrays(gl::TRIANGLES, 0,
-                FLOATS_PER_PIXEL as i32 * NUM_PIXELS as i32));
-
-            gl_try!(gl; gl.DeleteBuffers(1, &vb));
-            gl_try!(gl; gl.DeleteVertexArrays(1, &vao));
+            if let Some(ref mesh) = self.mesh {
+                mesh.upload(&vertex_data);
+                mesh.draw();
+            }
         }
     }
 
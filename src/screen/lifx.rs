@@ -0,0 +1,338 @@
+use gfx;
+use led_mapper;
+use screen;
+use screen::reconnect::Reconnecting;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const PORT: u16 = 56700;
+// How long lamps are listened for when they're discovered.
+const DISCOVERY_TIME: Duration = Duration::from_secs(1);
+// How long a lamp has to say how many zones it has, before it's taken for a bulb.
+const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+// LIFX asks for no more than 20 messages a second to each lamp. Each color fades in over the
+// interval, so it doesn't step.
+const SEND_INTERVAL: Duration = Duration::from_millis(50);
+// The white point colors are mixed from, neither warm nor cool.
+const KELVIN: u16 = 3500;
+const HEADER_SIZE: usize = 36;
+const PROTOCOL: u16 = 1024;
+const ADDRESSABLE: u16 = 0x1000;
+// Sent to all lamps, thus to whichever lamp is at the address sent to.
+const TAGGED: u16 = 0x2000;
+const RES_REQUIRED: u8 = 0x01;
+const GET_SERVICE: u16 = 2;
+const STATE_SERVICE: u16 = 3;
+const SET_COLOR: u16 = 102;
+const SET_EXTENDED_COLOR_ZONES: u16 = 510;
+const GET_EXTENDED_COLOR_ZONES: u16 = 511;
+const STATE_EXTENDED_COLOR_ZONES: u16 = 512;
+const ZONES_PER_MESSAGE: usize = 82;
+const NO_APPLY: u8 = 0;
+const APPLY: u8 = 1;
+
+struct Lamp {
+    address: SocketAddr,
+    // For LIFX Z strips and Beams, how many zones they have along them, or 0 for a bulb.
+    zones: usize,
+}
+
+// Sets the colors of LIFX bulbs, Z strips and Beams over the LIFX LAN protocol. The lamps split
+// the output's columns between them, left to right in the order they're given, or of their
+// addresses when they're discovered, and each shows its columns averaged. Strips and Beams
+// divide theirs between their zones, with the extended multizone messages of firmware 2.77 and
+// later.
+pub struct LifxScreen {
+    lamps: Vec<Lamp>,
+    source: u32,
+    last_sent: Option<Instant>,
+    messages_tx: mpsc::SyncSender<Vec<(SocketAddr, Vec<u8>)>>,
+}
+
+impl LifxScreen {
+    // `lamps` are addresses separated by commas, or empty to discover the lamps on the network.
+    pub fn new(lamps: &str) -> Result<LifxScreen, String> {
+        let socket = open_socket()?;
+        // Identifies our messages to the lamps, and their replies to us.
+        let source = process::id() | 1;
+
+        let addresses = if lamps.trim().is_empty() {
+            discover(&socket, source)?
+        } else {
+            lamps.split(',').map(|x| resolve(x.trim())).collect::<Result<Vec<SocketAddr>, String>>()?
+        };
+        if addresses.is_empty() {
+            return Err("no LIFX lamps found on the network".to_string());
+        }
+
+        let mut lamps = Vec::new();
+        for address in addresses {
+            let zones = zone_count(&socket, source, address)?;
+            lamps.push(Lamp { address, zones });
+        }
+        let strips = lamps.iter().filter(|x| x.zones > 0).count();
+        println!("Sending to {} LIFX lamps, {} of them with zones", lamps.len(), strips);
+
+        let (messages_tx, messages_rx) = mpsc::sync_channel::<Vec<(SocketAddr, Vec<u8>)>>(1);
+        thread::spawn(move || {
+            let mut connection = Reconnecting::new("the LIFX lamps".to_string(), Some(socket), open_socket);
+            for messages in messages_rx {
+                connection.send(|socket| {
+                    for &(address, ref message) in messages.iter() {
+                        socket.send_to(message, address).map_err(|e| e.to_string())?;
+                    }
+                    Ok(())
+                });
+            }
+        });
+
+        Ok(LifxScreen {
+            lamps,
+            source,
+            last_sent: None,
+            messages_tx,
+        })
+    }
+}
+
+impl screen::Screen for LifxScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        if self.last_sent.is_some_and(|x| x.elapsed() < SEND_INTERVAL) {
+            return;
+        }
+        self.last_sent = Some(Instant::now());
+
+        let (width, height) = (width as usize, height as usize);
+        let duration = SEND_INTERVAL.as_millis() as u32;
+        let mut messages = Vec::new();
+        for (i, lamp) in self.lamps.iter().enumerate() {
+            let start = i * width / self.lamps.len();
+            let end = (i + 1) * width / self.lamps.len();
+            if lamp.zones == 0 {
+                let color = led_mapper::led_matrix_mapper::average_columns(pixels, width, height, start, end);
+                let mut payload = vec![0];
+                payload.extend_from_slice(&hsbk(color));
+                payload.extend_from_slice(&duration.to_le_bytes());
+                messages.push((lamp.address, message(SET_COLOR, self.source, 0, &payload)));
+                continue;
+            }
+
+            let colors: Vec<[u8; 8]> = (0..lamp.zones).map(|zone| {
+                let zone_start = start + zone * (end - start) / lamp.zones;
+                let zone_end = start + (zone + 1) * (end - start) / lamp.zones;
+                hsbk(led_mapper::led_matrix_mapper::average_columns(pixels, width, height, zone_start, zone_end))
+            }).collect();
+            let chunks = colors.len().div_ceil(ZONES_PER_MESSAGE);
+            for (chunk, zone_colors) in colors.chunks(ZONES_PER_MESSAGE).enumerate() {
+                // The strip shows the new colors once the last of them has arrived.
+                let apply = if chunk + 1 == chunks { APPLY } else { NO_APPLY };
+                let index = (chunk * ZONES_PER_MESSAGE) as u16;
+                let mut payload = duration.to_le_bytes().to_vec();
+                payload.push(apply);
+                payload.extend_from_slice(&index.to_le_bytes());
+                payload.push(zone_colors.len() as u8);
+                for color in zone_colors.iter() {
+                    payload.extend_from_slice(color);
+                }
+                // The message always has room for all its zones' colors.
+                payload.resize(8 + 8 * ZONES_PER_MESSAGE, 0);
+                messages.push((lamp.address, message(SET_EXTENDED_COLOR_ZONES, self.source, 0, &payload)));
+            }
+        }
+
+        // Frames are dropped while the last one is still being sent, or while reconnecting.
+        let _ = self.messages_tx.try_send(messages);
+    }
+}
+
+// A color as LIFX takes it: hue, saturation, brightness and kelvin, each 16 bits little endian.
+fn hsbk((r, g, b): (u8, u8, u8)) -> [u8; 8] {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = f32::max(r, f32::max(g, b));
+    let min = f32::min(r, f32::min(g, b));
+    let delta = max - min;
+    // Which sixth of the color wheel it's in, red, yellow, green, cyan, blue or magenta.
+    let sector = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+    let hue = sector / 6.0;
+    let saturation = if max > 0.0 { delta / max } else { 0.0 };
+
+    let mut color = [0u8; 8];
+    for (i, &value) in [(hue * 65535.0) as u16, (saturation * 65535.0) as u16, (max * 65535.0) as u16, KELVIN]
+        .iter()
+        .enumerate() {
+        color[2 * i..2 * i + 2].copy_from_slice(&value.to_le_bytes());
+    }
+    color
+}
+
+// A message of `kind` with its header, sent to whichever lamp it's sent to.
+fn message(kind: u16, source: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(HEADER_SIZE + payload.len());
+    message.extend_from_slice(&((HEADER_SIZE + payload.len()) as u16).to_le_bytes());
+    message.extend_from_slice(&(PROTOCOL | ADDRESSABLE | TAGGED).to_le_bytes());
+    message.extend_from_slice(&source.to_le_bytes());
+    // No target, 6 reserved bytes, the flags and an unused sequence number.
+    message.extend_from_slice(&[0; 14]);
+    message.extend_from_slice(&[flags, 0]);
+    // 8 reserved bytes, the message's type and 2 more reserved.
+    message.extend_from_slice(&[0; 8]);
+    message.extend_from_slice(&kind.to_le_bytes());
+    message.extend_from_slice(&[0; 2]);
+    message.extend_from_slice(payload);
+    message
+}
+
+// The type of a reply to one of our messages, and its payload, from the lamp at `address`.
+fn receive(socket: &UdpSocket, source: u32, address: Option<IpAddr>) -> Option<(u16, Vec<u8>, SocketAddr)> {
+    let mut buffer = [0u8; 1024];
+    loop {
+        let (length, from) = socket.recv_from(&mut buffer).ok()?;
+        let is_ours = length >= HEADER_SIZE && buffer[4..8] == source.to_le_bytes();
+        if is_ours && address.is_none_or(|x| x == from.ip()) {
+            let kind = u16::from_le_bytes([buffer[32], buffer[33]]);
+            return Some((kind, buffer[HEADER_SIZE..length].to_vec(), from));
+        }
+    }
+}
+
+// The addresses of the lamps answering a broadcast, in order.
+fn discover(socket: &UdpSocket, source: u32) -> Result<Vec<SocketAddr>, String> {
+    socket.set_broadcast(true).map_err(|e| format!("could not broadcast: {}", e))?;
+    socket.send_to(&message(GET_SERVICE, source, RES_REQUIRED, &[]), ("255.255.255.255", PORT))
+        .map_err(|e| format!("could not look for LIFX lamps: {}", e))?;
+
+    let end = Instant::now() + DISCOVERY_TIME;
+    let mut addresses = Vec::new();
+    while let Some(left) = end.checked_duration_since(Instant::now()) {
+        let _ = socket.set_read_timeout(Some(left + Duration::from_millis(1)));
+        match receive(socket, source, None) {
+            Some((STATE_SERVICE, _, from)) => {
+                let address = SocketAddr::new(from.ip(), PORT);
+                if !addresses.contains(&address) {
+                    addresses.push(address);
+                }
+            },
+            Some(_) => (),
+            None => break,
+        }
+    }
+    addresses.sort();
+    Ok(addresses)
+}
+
+// How many zones the lamp at `address` has, or 0 if it doesn't say, for a bulb.
+fn zone_count(socket: &UdpSocket, source: u32, address: SocketAddr) -> Result<usize, String> {
+    socket.send_to(&message(GET_EXTENDED_COLOR_ZONES, source, RES_REQUIRED, &[]), address)
+        .map_err(|e| format!("could not reach the LIFX lamp at {}: {}", address, e))?;
+
+    let _ = socket.set_read_timeout(Some(REPLY_TIMEOUT));
+    let end = Instant::now() + REPLY_TIMEOUT;
+    while Instant::now() < end {
+        match receive(socket, source, Some(address.ip())) {
+            Some((STATE_EXTENDED_COLOR_ZONES, payload, _)) if payload.len() >= 2 =>
+                return Ok(u16::from_le_bytes([payload[0], payload[1]]) as usize),
+            // Like StateUnhandled, from bulbs.
+            Some(_) => continue,
+            None => break,
+        }
+    }
+    Ok(0)
+}
+
+// `address`, with the LIFX port unless it has one.
+fn resolve(address: &str) -> Result<SocketAddr, String> {
+    let with_port = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, PORT) };
+    with_port.to_socket_addrs()
+        .map_err(|e| format!("invalid LIFX address {}: {}", address, e))?
+        .next()
+        .ok_or_else(|| format!("could not resolve {}", address))
+}
+
+fn open_socket() -> Result<UdpSocket, String> {
+    UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("could not open a UDP socket: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use screen::Screen;
+
+    #[test]
+    fn builds_headers() {
+        let message = message(SET_COLOR, 0x1234_5678, RES_REQUIRED, &[9, 9]);
+        assert_eq!(message.len(), 38);
+        assert_eq!(message[0..2], [38, 0]);
+        // Protocol 1024, addressable and tagged.
+        assert_eq!(message[2..4], [0x00, 0x34]);
+        assert_eq!(message[4..8], [0x78, 0x56, 0x34, 0x12]);
+        assert!(message[8..22].iter().all(|&x| x == 0));
+        assert_eq!(message[22..24], [RES_REQUIRED, 0]);
+        assert!(message[24..32].iter().all(|&x| x == 0));
+        assert_eq!(message[32..36], [102, 0, 0, 0]);
+        assert_eq!(message[36..], [9, 9]);
+    }
+
+    #[test]
+    fn converts_colors() {
+        // 3500 kelvin is 0x0dac.
+        assert_eq!(hsbk((255, 0, 0)), [0, 0, 0xff, 0xff, 0xff, 0xff, 0xac, 0x0d]);
+        assert_eq!(hsbk((0, 0, 255)), [0xaa, 0xaa, 0xff, 0xff, 0xff, 0xff, 0xac, 0x0d]);
+        assert_eq!(hsbk((0, 0, 0)), [0, 0, 0, 0, 0, 0, 0xac, 0x0d]);
+        assert_eq!(hsbk((255, 255, 255)), [0, 0, 0, 0, 0xff, 0xff, 0xac, 0x0d]);
+    }
+
+    #[test]
+    fn splits_columns_between_lamps() {
+        let (messages_tx, messages_rx) = mpsc::sync_channel(1);
+        let bulb: SocketAddr = "10.0.0.1:56700".parse().unwrap();
+        let strip: SocketAddr = "10.0.0.2:56700".parse().unwrap();
+        let mut screen = LifxScreen {
+            lamps: vec![Lamp { address: bulb, zones: 0 }, Lamp { address: strip, zones: 2 }],
+            source: 1,
+            last_sent: None,
+            messages_tx,
+        };
+        // A row of red, then black and white for the strip's zones.
+        screen.render_from_pixels(&[255, 0, 0, 255, 0, 0, 0, 0, 0, 255, 255, 255], 4, 1);
+        let messages = messages_rx.try_recv().unwrap();
+        assert_eq!(messages.len(), 2);
+
+        let (address, ref set_color) = messages[0];
+        assert_eq!(address, bulb);
+        assert_eq!(set_color[32..34], [102, 0]);
+        assert_eq!(set_color[37..45], hsbk((255, 0, 0)));
+        assert_eq!(set_color[45..49], [50, 0, 0, 0]);
+
+        let (address, ref set_zones) = messages[1];
+        assert_eq!(address, strip);
+        assert_eq!(set_zones.len(), HEADER_SIZE + 8 + 8 * ZONES_PER_MESSAGE);
+        assert_eq!(set_zones[32..34], [0xfe, 0x01]);
+        // The duration, applied, from zone 0, for 2 zones.
+        assert_eq!(set_zones[36..44], [50, 0, 0, 0, APPLY, 0, 0, 2]);
+        assert_eq!(set_zones[44..52], hsbk((0, 0, 0)));
+        assert_eq!(set_zones[52..60], hsbk((255, 255, 255)));
+    }
+}
@@ -0,0 +1,116 @@
+use gfx;
+use screen;
+use screen::jpeg;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+const BOUNDARY: &str = "frame";
+// Viewers that stop reading are dropped after this long.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+// The request is only read so it isn't answered before it's sent; anything past this is ignored.
+const MAX_REQUEST: usize = 8192;
+
+// Serves the output over HTTP as an MJPEG stream, at any path of `address`, for browsers, OBS
+// media sources, VLC and the like to show live. Frames are only encoded while someone's watching,
+// and each viewer gets the latest one it has time for.
+pub struct MjpegScreen {
+    frames_tx: mpsc::SyncSender<(Vec<u8>, i32, i32)>,
+}
+
+impl MjpegScreen {
+    // `address` is where to listen, like 0.0.0.0:8080, and `quality` is the JPEG's from 1 to 100.
+    pub fn new(address: &str, quality: u8) -> Result<MjpegScreen, String> {
+        let listener = TcpListener::bind(address).map_err(|e| format!("could not listen on {}: {}", address, e))?;
+        println!("Streaming MJPEG on http://{}/", address);
+
+        let (viewers_tx, viewers_rx) = mpsc::channel::<mpsc::SyncSender<Arc<Vec<u8>>>>();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(x) => x,
+                    Err(_) => continue,
+                };
+                let (jpegs_tx, jpegs_rx) = mpsc::sync_channel(1);
+                if viewers_tx.send(jpegs_tx).is_err() {
+                    break;
+                }
+                thread::spawn(move || {
+                    // The viewer went away.
+                    let _ = serve(stream, jpegs_rx);
+                });
+            }
+        });
+
+        let (frames_tx, frames_rx) = mpsc::sync_channel::<(Vec<u8>, i32, i32)>(1);
+        thread::spawn(move || {
+            let mut viewers = Vec::new();
+            for (pixels, width, height) in frames_rx {
+                viewers.extend(viewers_rx.try_iter());
+                if viewers.is_empty() {
+                    continue;
+                }
+
+                let jpeg = Arc::new(jpeg::encode(&pixels, width as usize, height as usize, quality));
+                // Viewers still sending the last frame skip this one, and those gone are forgotten.
+                viewers.retain(|viewer| {
+                    !matches!(viewer.try_send(jpeg.clone()), Err(mpsc::TrySendError::Disconnected(_)))
+                });
+            }
+        });
+
+        Ok(MjpegScreen { frames_tx })
+    }
+}
+
+impl screen::Screen for MjpegScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        // Frames are dropped while the last one is still being encoded.
+        let _ = self.frames_tx.try_send((pixels.to_vec(), width, height));
+    }
+}
+
+// Answer a viewer's request with the stream, until it stops reading.
+fn serve(mut stream: TcpStream, jpegs_rx: mpsc::Receiver<Arc<Vec<u8>>>) -> Result<(), String> {
+    stream.set_write_timeout(Some(WRITE_TIMEOUT)).map_err(|e| e.to_string())?;
+
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|x| x == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        let length = stream.read(&mut buffer).map_err(|e| e.to_string())?;
+        if length == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buffer[..length]);
+    }
+
+    let header = format!("HTTP/1.0 200 OK\r\n\
+                          Content-Type: multipart/x-mixed-replace; boundary={}\r\n\
+                          Cache-Control: no-cache\r\n\
+                          Connection: close\r\n\r\n", BOUNDARY);
+    stream.write_all(header.as_bytes()).map_err(|e| e.to_string())?;
+
+    for jpeg in jpegs_rx {
+        let part = format!("--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n", BOUNDARY, jpeg.len());
+        stream.write_all(part.as_bytes())
+            .and_then(|_| stream.write_all(&jpeg))
+            .and_then(|_| stream.write_all(b"\r\n"))
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
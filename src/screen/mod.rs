@@ -1,23 +1,659 @@
+use audio;
 use gfx;
+use led_mapper;
+use std::fmt;
+use std::panic;
+use std::str::FromStr;
+use std::time::Duration;
 
+#[cfg(feature="apa102")]
+mod apa102;
+mod artnet;
+mod correction;
+mod crop;
+mod delay;
+mod dmx;
+#[cfg(feature="hue")]
+mod dtls;
+#[cfg(feature="enttec")]
+mod enttec;
+#[cfg(feature="flipdot")]
+mod flipdot;
+#[cfg(feature="fbdev")]
+mod framebuffer;
+mod govee;
 #[cfg(feature="hardware")]
 mod hardware;
+#[cfg(feature="hub75")]
+mod hub75;
+#[cfg(feature="hue")]
+mod hue;
+mod jpeg;
+#[cfg(feature="lamp")]
+mod lamp;
 mod led_disk_emulator;
+mod lifx;
+mod mjpeg;
+#[cfg(any(feature="ssd1306", feature="flipdot"))]
+mod mono;
+mod nanoleaf;
+mod opc;
+mod openrgb;
+mod palette;
+mod peer;
 mod raw;
+mod reconnect;
+mod recorder;
+mod sacn;
+#[cfg(any(feature="unicorn", feature="enttec", feature="flipdot", feature="lamp"))]
+mod serial;
+mod sixel;
+mod slow;
+#[cfg(feature="ssd1306")]
+mod ssd1306;
+#[cfg(any(feature="ws2812", feature="apa102", feature="unicorn_hd", feature="tft"))]
+mod spi;
+mod strip;
+mod terminal;
+mod threaded;
+mod tpm2;
+mod transform;
+#[cfg(feature="tft")]
+mod tft;
+mod websocket;
+mod wled;
+#[cfg(feature="unicorn")]
+mod unicorn;
+#[cfg(feature="unicorn_hd")]
+mod unicorn_hd;
+#[cfg(feature="ws2812")]
+mod ws2812;
 
-pub fn create_screen(selected_screen: String) -> Box<dyn Screen> {
-    match selected_screen.as_ref() {
+pub use screen::correction::CorrectedScreen;
+pub use screen::crop::{CroppedScreen, Region};
+pub use screen::delay::{DelayedScreen, MAX_LATENCY};
+pub use screen::palette::{Palette, PaletteScreen, PALETTES};
+pub use screen::peer::listen as listen_for_peer;
+pub use screen::slow::SlowScreen;
+pub use screen::strip::{StripMode, StripScreen};
+pub use screen::transform::TransformedScreen;
+pub use screen::websocket::{accept_websocket, read_websocket_frame, websocket_frame};
+pub use screen::websocket::{OPCODE_CLOSE, OPCODE_PING, OPCODE_PONG, OPCODE_TEXT};
+
+// Every screen by name, with the feature it needs, if any, and whether it's built in.
+const SCREENS: &[(&str, Option<&str>, bool)] = &[
+    ("emulator", None, true),
+    ("raw", None, true),
+    ("terminal", None, true),
+    ("sixel", None, true),
+    ("mjpeg", None, true),
+    ("websocket", None, true),
+    ("recorder", None, true),
+    ("artnet", None, true),
+    ("sacn", None, true),
+    ("opc", None, true),
+    ("wled", None, true),
+    ("nanoleaf", None, true),
+    ("openrgb", None, true),
+    ("lifx", None, true),
+    ("govee", None, true),
+    ("tpm2", None, true),
+    ("peer", None, true),
+    ("ssd1306", Some("ssd1306"), cfg!(feature="ssd1306")),
+    ("flipdot", Some("flipdot"), cfg!(feature="flipdot")),
+    ("lamp", Some("lamp"), cfg!(feature="lamp")),
+    ("tft", Some("tft"), cfg!(feature="tft")),
+    ("fbdev", Some("fbdev"), cfg!(feature="fbdev")),
+    ("hue", Some("hue"), cfg!(feature="hue")),
+    ("enttec", Some("enttec"), cfg!(feature="enttec")),
+    ("hardware", Some("hardware"), cfg!(feature="hardware")),
+    ("ws2812", Some("ws2812"), cfg!(feature="ws2812")),
+    ("apa102", Some("apa102"), cfg!(feature="apa102")),
+    ("hub75", Some("hub75"), cfg!(feature="hub75")),
+    ("unicorn_hd", Some("unicorn_hd"), cfg!(feature="unicorn_hd")),
+    ("unicorn", Some("unicorn"), cfg!(feature="unicorn")),
+];
+
+// The screens built in, by name.
+pub fn screen_names() -> Vec<&'static str> {
+    SCREENS.iter().filter(|x| x.2).map(|x| x.0).collect()
+}
+
+// They're set for all the screens from the command line, and can be changed for one with `set`.
+// Those only screens behind features read are left out without them.
+#[derive(Clone)]
+pub struct Options {
+    // LED screens dim to keep under this current, unless it's 0.
+    pub power_budget_milliamps: u32,
+    // The SPI device the ws2812, apa102, unicorn_hd and tft screens write to.
+    #[cfg(any(feature="ws2812", feature="apa102", feature="unicorn_hd", feature="tft"))]
+    pub spi_device: String,
+    // Clock for screens on SPI that take any speed, like the apa102 screen.
+    #[cfg(any(feature="apa102", feature="tft"))]
+    pub spi_hz: u32,
+    // Global brightness of APA102 LEDs, from 1 to 31.
+    #[cfg(feature="apa102")]
+    pub apa102_brightness: u8,
+    // The ws2812 and apa102 screens' grid of chained panels, by led_mapper::PanelLayout, with
+    // the wirings written out and the rotations separated by commas.
+    pub panel_columns: i32,
+    pub panel_rows: i32,
+    pub panels_across: i32,
+    pub panels_down: i32,
+    pub panel_wiring: String,
+    pub panel_order: String,
+    pub panel_rotation: String,
+    // A file of where each LED is, for the LED and lighting screens to sample the output at
+    // instead of a grid, or empty.
+    pub pixel_map: String,
+    // The order the LED and lighting screens send colors in, like "grb", or empty for each's usual.
+    pub color_order: String,
+    // Size of each HUB75 panel in LEDs, and how many are chained one after the other and driven
+    // in parallel, which the hub75 screen stacks side by side and on top of each other.
+    #[cfg(feature="hub75")]
+    pub hub75_rows: u32,
+    #[cfg(feature="hub75")]
+    pub hub75_cols: u32,
+    #[cfg(feature="hub75")]
+    pub hub75_chain: u32,
+    #[cfg(feature="hub75")]
+    pub hub75_parallel: u32,
+    // How the panels are wired to the GPIO, like "regular" or "adafruit-hat".
+    #[cfg(feature="hub75")]
+    pub hub75_mapping: String,
+    // More flags for the rpi-rgb-led-matrix library, like "--led-slowdown-gpio=4".
+    #[cfg(feature="hub75")]
+    pub hub75_flags: String,
+    // Degrees to turn the picture clockwise on matrices that can be mounted any way round.
+    #[cfg(any(feature="unicorn_hd", feature="unicorn"))]
+    pub unicorn_rotation: u32,
+    // The Unicorn HAT HDs sharing the SPI bus as one display, each's device and top left LED,
+    // like "/dev/spidev0.0@0+0,/dev/spidev0.1@16+0", or empty for one on `spi_device`.
+    #[cfg(feature="unicorn_hd")]
+    pub unicorn_hd_panels: String,
+    // Which Pimoroni Unicorn board the unicorn screen streams to, and where.
+    #[cfg(feature="unicorn")]
+    pub unicorn_model: String,
+    #[cfg(feature="unicorn")]
+    pub unicorn_address: String,
+    // Where the artnet and sacn screens send to, empty for sACN to multicast.
+    pub artnet_address: String,
+    pub sacn_address: String,
+    // How they lay out pixels over DMX universes.
+    pub dmx_universe: u16,
+    pub dmx_channel: usize,
+    pub dmx_leds_per_universe: usize,
+    pub dmx_serpentine: bool,
+    // The enttec screen's USB serial device, how many fixtures it averages the frame into, and
+    // their channels, like "rgb" or "drgbw".
+    #[cfg(feature="enttec")]
+    pub enttec_device: String,
+    #[cfg(feature="enttec")]
+    pub dmx_fixtures: usize,
+    #[cfg(feature="enttec")]
+    pub dmx_fixture_channels: String,
+    // The Open Pixel Control server the opc screen sends to, the channel it sends on, and how
+    // many pixels, 0 for one per output pixel.
+    pub opc_address: String,
+    pub opc_channel: u8,
+    pub opc_pixels: usize,
+    // The WLED controller the wled screen sends to, with which realtime protocol, and to how
+    // many LEDs, 0 to ask it.
+    pub wled_address: String,
+    pub wled_protocol: String,
+    pub wled_leds: usize,
+    // The Hue bridge the hue screen streams to, the application's username and client key it
+    // registered there, and the entertainment area whose lights it sets.
+    #[cfg(feature="hue")]
+    pub hue_address: String,
+    #[cfg(feature="hue")]
+    pub hue_username: String,
+    #[cfg(feature="hue")]
+    pub hue_client_key: String,
+    #[cfg(feature="hue")]
+    pub hue_group: u32,
+    // The Nanoleaf panels the nanoleaf screen streams to, and the auth token their API gave.
+    pub nanoleaf_address: String,
+    pub nanoleaf_token: String,
+    // The OpenRGB server whose devices the openrgb screen drives.
+    pub openrgb_address: String,
+    // The LIFX lamps the lifx screen sets, left to right, or none to discover them.
+    pub lifx_lamps: String,
+    // The Govee devices the govee screen drives, left to right, or none to discover them, and
+    // how many segments their strips are split into, 0 for one color each.
+    pub govee_devices: String,
+    pub govee_segments: usize,
+    // The TPM2.net controller the tpm2 screen sends to, and the most bytes of LEDs in each of
+    // its packets, 0 for whole frames.
+    pub tpm2_address: String,
+    pub tpm2_packet_size: usize,
+    // The instance running with --peer_source the peer screen sends to.
+    pub peer_address: String,
+    // How many times bigger the sixel screen draws each pixel.
+    pub sixel_scale: u32,
+    // The framebuffer device the fbdev screen draws on.
+    #[cfg(feature="fbdev")]
+    pub fb_device: String,
+    // The ssd1306 screen's I2C bus and address, and how it turns colors into lit pixels:
+    // threshold, bayer or floyd, the first and last lighting them above `oled_threshold`.
+    #[cfg(feature="ssd1306")]
+    pub oled_device: String,
+    #[cfg(feature="ssd1306")]
+    pub oled_address: u16,
+    #[cfg(feature="ssd1306")]
+    pub oled_conversion: String,
+    #[cfg(feature="ssd1306")]
+    pub oled_threshold: u8,
+    // Where the flipdot screen sends to, its panels' addresses top to bottom, how many dots wide
+    // each is, the serial line's speed, its conversion and threshold like the ssd1306 screen's,
+    // and the most times a second it flips.
+    #[cfg(feature="flipdot")]
+    pub flipdot_address: String,
+    #[cfg(feature="flipdot")]
+    pub flipdot_panels: String,
+    #[cfg(feature="flipdot")]
+    pub flipdot_columns: i32,
+    #[cfg(feature="flipdot")]
+    pub flipdot_baud: u32,
+    #[cfg(feature="flipdot")]
+    pub flipdot_conversion: String,
+    #[cfg(feature="flipdot")]
+    pub flipdot_threshold: u8,
+    #[cfg(feature="flipdot")]
+    pub flipdot_fps: f32,
+    // Where the lamp screen sends the output's average color, the serial line's speed, and the
+    // most colors a second it sends, 0 for the lamp's usual.
+    #[cfg(feature="lamp")]
+    pub lamp_address: String,
+    #[cfg(feature="lamp")]
+    pub lamp_baud: u32,
+    #[cfg(feature="lamp")]
+    pub lamp_fps: f32,
+    // The tft screen's controller, its data/command and reset GPIO pins, the latter negative
+    // when not wired, its clockwise turn, and whether its red and blue are the other way round.
+    #[cfg(feature="tft")]
+    pub tft_model: String,
+    #[cfg(feature="tft")]
+    pub tft_dc: u8,
+    #[cfg(feature="tft")]
+    pub tft_reset: i32,
+    #[cfg(feature="tft")]
+    pub tft_rotation: u32,
+    #[cfg(feature="tft")]
+    pub tft_swap_red_blue: bool,
+    // Where the mjpeg screen serves its stream, and the JPEG quality from 1 to 100.
+    pub mjpeg_address: String,
+    pub mjpeg_quality: u8,
+    // Where the websocket screen serves its viewer, and whether it sends audio features too.
+    pub websocket_address: String,
+    pub websocket_audio: bool,
+    // Where the recorder screen writes its frames, and as png or raw.
+    pub recorder_dir: String,
+    pub recorder_format: String,
+}
+
+impl Options {
+    // Set an option by its flag's name without the dashes, like "wled_address".
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        macro_rules! set_by_flag {
+            ($($(#[$attribute:meta])* $flag:literal => $field:ident),*) => {
+                match name {
+                    $($(#[$attribute])* $flag => self.$field = parse_value(value)?,)*
+                    _ => return Err(format!("unknown screen option '{}'", name)),
+                }
+            };
+        }
+        set_by_flag!(
+            "power_budget" => power_budget_milliamps,
+            #[cfg(any(feature="ws2812", feature="apa102", feature="unicorn_hd", feature="tft"))]
+            "spi_device" => spi_device,
+            #[cfg(any(feature="apa102", feature="tft"))]
+            "spi_hz" => spi_hz,
+            #[cfg(feature="apa102")]
+            "apa102_brightness" => apa102_brightness,
+            "panel_columns" => panel_columns,
+            "panel_rows" => panel_rows,
+            "panels_across" => panels_across,
+            "panels_down" => panels_down,
+            "panel_wiring" => panel_wiring,
+            "panel_order" => panel_order,
+            "panel_rotation" => panel_rotation,
+            "pixel_map" => pixel_map,
+            "color_order" => color_order,
+            #[cfg(feature="hub75")]
+            "hub75_rows" => hub75_rows,
+            #[cfg(feature="hub75")]
+            "hub75_cols" => hub75_cols,
+            #[cfg(feature="hub75")]
+            "hub75_chain" => hub75_chain,
+            #[cfg(feature="hub75")]
+            "hub75_parallel" => hub75_parallel,
+            #[cfg(feature="hub75")]
+            "hub75_mapping" => hub75_mapping,
+            #[cfg(feature="hub75")]
+            "hub75_flags" => hub75_flags,
+            #[cfg(any(feature="unicorn_hd", feature="unicorn"))]
+            "unicorn_rotation" => unicorn_rotation,
+            #[cfg(feature="unicorn_hd")]
+            "unicorn_hd_panels" => unicorn_hd_panels,
+            #[cfg(feature="unicorn")]
+            "unicorn_model" => unicorn_model,
+            #[cfg(feature="unicorn")]
+            "unicorn_address" => unicorn_address,
+            "artnet_address" => artnet_address,
+            "sacn_address" => sacn_address,
+            "dmx_universe" => dmx_universe,
+            "dmx_channel" => dmx_channel,
+            "dmx_leds_per_universe" => dmx_leds_per_universe,
+            "dmx_serpentine" => dmx_serpentine,
+            #[cfg(feature="enttec")]
+            "enttec_device" => enttec_device,
+            #[cfg(feature="enttec")]
+            "dmx_fixtures" => dmx_fixtures,
+            #[cfg(feature="enttec")]
+            "dmx_fixture_channels" => dmx_fixture_channels,
+            "opc_address" => opc_address,
+            "opc_channel" => opc_channel,
+            "opc_pixels" => opc_pixels,
+            "wled_address" => wled_address,
+            "wled_protocol" => wled_protocol,
+            "wled_leds" => wled_leds,
+            #[cfg(feature="hue")]
+            "hue_address" => hue_address,
+            #[cfg(feature="hue")]
+            "hue_username" => hue_username,
+            #[cfg(feature="hue")]
+            "hue_client_key" => hue_client_key,
+            #[cfg(feature="hue")]
+            "hue_group" => hue_group,
+            "nanoleaf_address" => nanoleaf_address,
+            "nanoleaf_token" => nanoleaf_token,
+            "openrgb_address" => openrgb_address,
+            "lifx_lamps" => lifx_lamps,
+            "govee_devices" => govee_devices,
+            "govee_segments" => govee_segments,
+            "tpm2_address" => tpm2_address,
+            "tpm2_packet_size" => tpm2_packet_size,
+            "peer_address" => peer_address,
+            "sixel_scale" => sixel_scale,
+            #[cfg(feature="fbdev")]
+            "fb_device" => fb_device,
+            #[cfg(feature="ssd1306")]
+            "oled_device" => oled_device,
+            #[cfg(feature="ssd1306")]
+            "oled_address" => oled_address,
+            #[cfg(feature="ssd1306")]
+            "oled_conversion" => oled_conversion,
+            #[cfg(feature="ssd1306")]
+            "oled_threshold" => oled_threshold,
+            #[cfg(feature="flipdot")]
+            "flipdot_address" => flipdot_address,
+            #[cfg(feature="flipdot")]
+            "flipdot_panels" => flipdot_panels,
+            #[cfg(feature="flipdot")]
+            "flipdot_columns" => flipdot_columns,
+            #[cfg(feature="flipdot")]
+            "flipdot_baud" => flipdot_baud,
+            #[cfg(feature="flipdot")]
+            "flipdot_conversion" => flipdot_conversion,
+            #[cfg(feature="flipdot")]
+            "flipdot_threshold" => flipdot_threshold,
+            #[cfg(feature="flipdot")]
+            "flipdot_fps" => flipdot_fps,
+            #[cfg(feature="lamp")]
+            "lamp_address" => lamp_address,
+            #[cfg(feature="lamp")]
+            "lamp_baud" => lamp_baud,
+            #[cfg(feature="lamp")]
+            "lamp_fps" => lamp_fps,
+            #[cfg(feature="tft")]
+            "tft_model" => tft_model,
+            #[cfg(feature="tft")]
+            "tft_dc" => tft_dc,
+            #[cfg(feature="tft")]
+            "tft_reset" => tft_reset,
+            #[cfg(feature="tft")]
+            "tft_rotation" => tft_rotation,
+            #[cfg(feature="tft")]
+            "tft_swap_red_blue" => tft_swap_red_blue,
+            "mjpeg_address" => mjpeg_address,
+            "mjpeg_quality" => mjpeg_quality,
+            "websocket_address" => websocket_address,
+            "websocket_audio" => websocket_audio,
+            "recorder_dir" => recorder_dir,
+            "recorder_format" => recorder_format
+        );
+        Ok(())
+    }
+}
+
+fn parse_value<T>(value: &str) -> Result<T, String> where T: FromStr, T::Err: fmt::Display {
+    value.trim().parse::<T>().map_err(|e| format!("invalid value '{}': {}", value, e))
+}
+
+// Call `show` on each screen, leaving out from then on any that panics, so one failing screen
+// doesn't stop the others.
+pub fn show_on_each<F>(screens: &mut Vec<Box<dyn Screen>>, mut show: F) where F: FnMut(&mut dyn Screen) {
+    screens.retain_mut(|screen| {
+        let shown = panic::catch_unwind(panic::AssertUnwindSafe(|| show(screen.as_mut())));
+        if shown.is_err() {
+            println!("Stopped showing on a screen that failed");
+        }
+        shown.is_ok()
+    });
+}
+
+// Set up each screen, leaving out any that fails or panics. Fails when there were screens and
+// none of them could be set up.
+pub fn setup_each(screens: &mut Vec<Box<dyn Screen>>, gl: &gfx::gl::Gl) -> Result<(), String> {
+    let screen_count = screens.len();
+    let mut last_error = "they failed".to_string();
+    screens.retain_mut(|screen| {
+        match panic::catch_unwind(panic::AssertUnwindSafe(|| screen.setup(gl))) {
+            Ok(Ok(())) => true,
+            Ok(Err(e)) => {
+                println!("Leaving out a screen that could not be set up, {}", e);
+                last_error = e;
+                false
+            },
+            Err(_) => {
+                println!("Leaving out a screen that failed to set up");
+                false
+            },
+        }
+    });
+
+    if screen_count > 0 && screens.is_empty() {
+        return Err(format!("Could not set up any of the screens, {}", last_error));
+    }
+    Ok(())
+}
+
+// Screens that can't be set up with these options are an error, as are names that aren't
+// screens or ones that aren't built in.
+pub fn create_screen(selected_screen: &str, options: &Options) -> Result<Box<dyn Screen>, String> {
+    let power_budget_milliamps = options.power_budget_milliamps;
+    Ok(match selected_screen {
         "raw" => Box::new(raw::RawScreen::new()),
-        "emulator" => Box::new(led_disk_emulator::LedDiskEmulatorScreen::new()),
+        "emulator" => Box::new(led_disk_emulator::LedDiskEmulatorScreen::new(power_budget_milliamps)),
+        // Writing to a terminal over SSH can take longer than a frame.
+        "terminal" => Box::new(threaded::ThreadedScreen::new(terminal::TerminalScreen::new())),
+        "sixel" => Box::new(threaded::ThreadedScreen::new(sixel::SixelScreen::new(options.sixel_scale))),
+        "mjpeg" => Box::new(mjpeg::MjpegScreen::new(&options.mjpeg_address, options.mjpeg_quality)?),
+        "websocket" => Box::new(websocket::WebSocketScreen::new(&options.websocket_address, options.websocket_audio)?),
+        "recorder" => Box::new(recorder::RecorderScreen::new(&options.recorder_dir, &options.recorder_format)?),
+        #[cfg(feature="ssd1306")]
+        "ssd1306" => Box::new(ssd1306::Ssd1306Screen::new(&options.oled_device,
+                                                          options.oled_address,
+                                                          &options.oled_conversion,
+                                                          options.oled_threshold)?),
+        // Flipping is slower than rendering, and mid-animation frames flip dots for nothing.
+        #[cfg(feature="flipdot")]
+        "flipdot" => {
+            let flipdot = flipdot::FlipdotScreen::new(&options.flipdot_address,
+                                                      &options.flipdot_panels,
+                                                      options.flipdot_columns,
+                                                      options.flipdot_baud,
+                                                      &options.flipdot_conversion,
+                                                      options.flipdot_threshold)?;
+            Box::new(slow::SlowScreen::new(Box::new(flipdot), options.flipdot_fps))
+        },
+        #[cfg(feature="lamp")]
+        "lamp" => {
+            let lamp = lamp::LampScreen::new(&options.lamp_address, options.lamp_baud)?;
+            let fps = if options.lamp_fps > 0.0 { options.lamp_fps } else { lamp::default_fps(&options.lamp_address) };
+            Box::new(slow::SlowScreen::new(Box::new(lamp), fps))
+        },
+        #[cfg(feature="tft")]
+        "tft" => Box::new(tft::TftScreen::new(&options.tft_model,
+                                              &options.spi_device,
+                                              options.spi_hz,
+                                              options.tft_dc,
+                                              if options.tft_reset >= 0 { Some(options.tft_reset as u8) } else { None },
+                                              options.tft_rotation,
+                                              options.tft_swap_red_blue)?),
+        #[cfg(feature="fbdev")]
+        "fbdev" => Box::new(framebuffer::FramebufferScreen::new(&options.fb_device)?),
+        "artnet" => Box::new(artnet::ArtnetScreen::new(&options.artnet_address, universe_layout(options)?)?),
+        "sacn" => Box::new(sacn::SacnScreen::new(&options.sacn_address, universe_layout(options)?)?),
+        "opc" => Box::new(opc::OpcScreen::new(&options.opc_address,
+                                              options.opc_channel,
+                                              options.opc_pixels,
+                                              pixel_map(options)?)),
+        "wled" => Box::new(wled::WledScreen::new(&options.wled_address, &options.wled_protocol, options.wled_leds)?),
+        #[cfg(feature="hue")]
+        "hue" => Box::new(hue::HueScreen::new(&options.hue_address,
+                                              &options.hue_username,
+                                              &options.hue_client_key,
+                                              options.hue_group)?),
+        "nanoleaf" => Box::new(nanoleaf::NanoleafScreen::new(&options.nanoleaf_address, &options.nanoleaf_token)?),
+        "openrgb" => Box::new(openrgb::OpenRgbScreen::new(&options.openrgb_address)?),
+        "lifx" => Box::new(lifx::LifxScreen::new(&options.lifx_lamps)?),
+        "govee" => Box::new(govee::GoveeScreen::new(&options.govee_devices, options.govee_segments)?),
+        "tpm2" => Box::new(tpm2::Tpm2Screen::new(&options.tpm2_address,
+                                                 options.tpm2_packet_size,
+                                                 panel_layout(options)?,
+                                                 pixel_map(options)?,
+                                                 color_order(options, "rgb")?)?),
+        "peer" => Box::new(peer::PeerScreen::new(&options.peer_address)?),
+        #[cfg(feature="enttec")]
+        "enttec" => Box::new(enttec::EnttecScreen::new(&options.enttec_device,
+                                                       options.dmx_fixtures,
+                                                       options.dmx_channel,
+                                                       &options.dmx_fixture_channels)?),
         #[cfg(feature="hardware")]
-        "hardware" => Box::new(hardware::HardwareScreen::new()),
+        "hardware" => Box::new(hardware::HardwareScreen::new(power_budget_milliamps)),
+        #[cfg(feature="ws2812")]
+        "ws2812" => Box::new(ws2812::Ws2812Screen::new(power_budget_milliamps,
+                                                       &options.spi_device,
+                                                       panel_layout(options)?,
+                                                       pixel_map(options)?,
+                                                       color_order(options, "grb")?)),
+        #[cfg(feature="apa102")]
+        "apa102" => Box::new(apa102::Apa102Screen::new(power_budget_milliamps,
+                                                       &options.spi_device,
+                                                       options.spi_hz,
+                                                       options.apa102_brightness,
+                                                       panel_layout(options)?,
+                                                       pixel_map(options)?,
+                                                       color_order(options, "bgr")?)),
+        #[cfg(feature="hub75")]
+        "hub75" => Box::new(hub75::Hub75Screen::new(options)),
+        #[cfg(feature="unicorn_hd")]
+        "unicorn_hd" => Box::new(unicorn_hd::UnicornHdScreen::new(power_budget_milliamps,
+                                                                  &options.spi_device,
+                                                                  options.unicorn_rotation,
+                                                                  &options.unicorn_hd_panels)?),
+        #[cfg(feature="unicorn")]
+        "unicorn" => Box::new(unicorn::UnicornScreen::new(&options.unicorn_model,
+                                                          &options.unicorn_address,
+                                                          options.unicorn_rotation)?),
 
-        _ => Box::new(led_disk_emulator::LedDiskEmulatorScreen::new()),
+        _ => return Err(unknown_screen(selected_screen)),
+    })
+}
+
+fn unknown_screen(name: &str) -> String {
+    match SCREENS.iter().find(|x| x.0 == name) {
+        Some(&(_, Some(feature), false)) => {
+            format!("it isn't built in, build with cargo build --release --features {}", feature)
+        },
+        _ => format!("unknown screen '{}', expected one of: {}", name, screen_names().join(", ")),
     }
 }
 
+fn universe_layout(options: &Options) -> Result<dmx::UniverseLayout, String> {
+    dmx::UniverseLayout::new(options.dmx_universe,
+                             options.dmx_channel,
+                             options.dmx_leds_per_universe,
+                             options.dmx_serpentine,
+                             pixel_map(options)?,
+                             color_order(options, "rgb")?)
+}
+
+// The color order asked for, or the screen's usual one.
+fn color_order(options: &Options, usual: &str) -> Result<led_mapper::ColorOrder, String> {
+    led_mapper::ColorOrder::parse(if options.color_order.is_empty() { usual } else { &options.color_order })
+}
+
+fn pixel_map(options: &Options) -> Result<Option<led_mapper::PixelMap>, String> {
+    if options.pixel_map.is_empty() {
+        return Ok(None);
+    }
+
+    let map = led_mapper::PixelMap::load(&options.pixel_map)?;
+    println!("Mapped {} LEDs from {}", map.len(), options.pixel_map);
+    Ok(Some(map))
+}
+
+fn panel_layout(options: &Options) -> Result<led_mapper::PanelLayout, String> {
+    let rotations = options.panel_rotation.split(',')
+        .map(|x| x.trim().parse::<u32>().map_err(|e| format!("invalid panel rotation '{}': {}", x, e)))
+        .collect::<Result<Vec<u32>, String>>()?;
+    led_mapper::PanelLayout::new(options.panel_columns,
+                                 options.panel_rows,
+                                 options.panels_across,
+                                 options.panels_down,
+                                 led_mapper::Wiring::parse(&options.panel_wiring)?,
+                                 led_mapper::Wiring::parse(&options.panel_order)?,
+                                 rotations)
+}
+
 pub trait Screen {
-    fn setup(&mut self, gl: &gfx::gl::Gl);
-    fn render_from_texture(&mut self, gl: &gfx::gl::Gl, texture: u32, size: i32);
+    // Set up what's drawn with `gl`, if anything. Screens that fail aren't shown on.
+    fn setup(&mut self, gl: &gfx::gl::Gl) -> Result<(), String>;
     fn uses_window(&self) -> bool;
+
+    // Show the output texture, which is bound. Screens taking pixels are given them through
+    // `render_from_pixels` instead, by the pipeline reading them once for all of them, so have
+    // nothing to do here. Screens reading the texture back themselves keep their own reader.
+    fn render_from_texture(&mut self, _gl: &gfx::gl::Gl, _texture: u32, _width: i32, _height: i32) {}
+
+    // Screens whose brightness is linear in the values sent to them, like LEDs driven by PWM,
+    // rather than gamma encoded like monitors.
+    fn is_linear(&self) -> bool {
+        false
+    }
+
+    // Screens that can show frames drawn without GL, for when no GL context can be made.
+    fn renders_pixels(&self) -> bool {
+        false
+    }
+
+    // Show a frame of tightly packed RGB rows, bottom to top, drawn without GL. It's called from
+    // the render loop, so screens writing to slow sinks, like SPI or the network, hand the frame
+    // to a thread rather than waiting for it to be sent, or are run in a ThreadedScreen.
+    fn render_from_pixels(&mut self, _pixels: &[u8], _width: i32, _height: i32) {}
+
+    // Take each audio frame the visualizer is updated on, for screens that send more than pixels.
+    fn update_audio(&mut self, _audio_frame: &audio::AudioFrame) {}
+
+    // The gamma of the frames it'll be given, 1 for linear light, before it's set up.
+    fn set_output_gamma(&mut self, _gamma: f32) {}
+
+    // How long to hold back frames by, for screens wrapped to add latency.
+    fn set_latency(&mut self, _latency: Duration) {}
+
+    // Colors to recolor frames with, for screens wrapped to take a palette, or none to stop.
+    fn set_palette(&mut self, _palette: Option<&Palette>) {}
 }
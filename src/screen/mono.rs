@@ -0,0 +1,71 @@
+use led_mapper;
+
+const BAYER4: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+// How shades of gray become lit or dark pixels on displays that only have the two.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Conversion {
+    // Lit above the threshold.
+    Threshold,
+    // Ordered dithering, steady in animations.
+    Bayer,
+    // Floyd-Steinberg error diffusion, finer but shimmering in animations.
+    Floyd,
+}
+
+impl Conversion {
+    pub fn parse(name: &str) -> Result<Conversion, String> {
+        match name {
+            "threshold" => Ok(Conversion::Threshold),
+            "bayer" => Ok(Conversion::Bayer),
+            "floyd" => Ok(Conversion::Floyd),
+            _ => Err(format!("unknown conversion {}, expected threshold, bayer or floyd", name)),
+        }
+    }
+}
+
+// Whether each of a `columns` by `rows` display's pixels is lit, top to bottom, for the output
+// scaled to it. Threshold and floyd light pixels brighter than `threshold`.
+pub fn lit_pixels(pixels: &[u8], width: i32, height: i32, columns: i32, rows: i32,
+                  conversion: Conversion, threshold: u8) -> Vec<bool> {
+    let colors = led_mapper::led_matrix_mapper::sample_pixels(pixels, width, height, columns, rows);
+    // Rec. 601 luma, of the gamma encoded colors.
+    let mut gray: Vec<f32> = colors.iter()
+        .map(|&(r, g, b)| 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32)
+        .collect();
+
+    let (width, height) = (columns as usize, rows as usize);
+    let threshold = threshold as f32;
+    let mut lit = vec![false; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            lit[i] = match conversion {
+                Conversion::Threshold => gray[i] > threshold,
+                Conversion::Bayer => gray[i] > (BAYER4[y % 4][x % 4] as f32 + 0.5) * 16.0,
+                Conversion::Floyd => {
+                    let on = gray[i] > threshold;
+                    let error = gray[i] - if on { 255.0 } else { 0.0 };
+                    let mut spread = |dx: isize, dy: usize, weight: f32| {
+                        let (nx, ny) = (x as isize + dx, y + dy);
+                        if nx >= 0 && (nx as usize) < width && ny < height {
+                            gray[ny * width + nx as usize] += error * weight;
+                        }
+                    };
+                    spread(1, 0, 7.0 / 16.0);
+                    spread(-1, 1, 3.0 / 16.0);
+                    spread(0, 1, 5.0 / 16.0);
+                    spread(1, 1, 1.0 / 16.0);
+                    on
+                },
+            };
+        }
+    }
+
+    lit
+}
@@ -0,0 +1,206 @@
+use gfx;
+use screen;
+use screen::reconnect::Reconnecting;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const API_PORT: u16 = 16021;
+const API_TIMEOUT: Duration = Duration::from_secs(2);
+const STREAM_PORT: u16 = 60222;
+// In tenths of a second, how long each panel fades to its new color. The shortest fade there is
+// smooths over frames lost on the network without lagging behind the beat.
+const TRANSITION_TIME: u16 = 1;
+// Shapes in the layout without a light of their own: Rhythm modules, power supplies, the Shapes
+// controller, Lines connectors, controller caps and power connectors.
+const UNLIT_SHAPES: [u32; 6] = [1, 5, 12, 16, 19, 20];
+
+// A panel of the layout, and the pixel at its center.
+struct Panel {
+    id: u16,
+    column: usize,
+    row: usize,
+}
+
+// Streams the output to Nanoleaf panels, Canvas, Shapes, Elements or Lines, with version 2 of their
+// external control protocol over UDP. The panels' layout, as arranged in the Nanoleaf app, is
+// asked of their API and stretched over the output, each panel showing the pixel at its center.
+// `token` is an auth token from the API's /api/v1/new, while the controller's power button is held.
+pub struct NanoleafScreen {
+    // Each panel's id and position in the layout.
+    positions: Vec<(u16, i32, i32)>,
+    panels: Vec<Panel>,
+    // The output size `panels` were placed on.
+    size: (i32, i32),
+    packets_tx: mpsc::SyncSender<Vec<u8>>,
+}
+
+impl NanoleafScreen {
+    pub fn new(address: &str, token: &str) -> Result<NanoleafScreen, String> {
+        let api = resolve(address, API_PORT)?;
+        let positions = panel_positions(api, token)?;
+        if positions.is_empty() {
+            return Err("the Nanoleaf layout has no panels".to_string());
+        }
+        println!("Streaming to {} Nanoleaf panels", positions.len());
+
+        let destination = SocketAddr::new(api.ip(), STREAM_PORT);
+        let token = token.to_string();
+        let open = move || {
+            start_external_control(api, &token)?;
+            UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("could not open a UDP socket: {}", e))
+        };
+        let socket = open()?;
+
+        let (packets_tx, packets_rx) = mpsc::sync_channel::<Vec<u8>>(1);
+        thread::spawn(move || {
+            let mut connection = Reconnecting::new(format!("the Nanoleaf panels at {}", api.ip()), Some(socket), open);
+            for packet in packets_rx {
+                connection.send(|socket| socket.send_to(&packet, destination).map(|_| ()).map_err(|e| e.to_string()));
+            }
+        });
+
+        Ok(NanoleafScreen {
+            positions,
+            panels: Vec::new(),
+            size: (0, 0),
+            packets_tx,
+        })
+    }
+
+    // Place the layout's panels on an output of `width` by `height`, its lowest panel on the
+    // bottom row and its leftmost on the first column.
+    fn place_panels(&mut self, width: i32, height: i32) {
+        let positions = &self.positions;
+        let min_x = positions.iter().map(|x| x.1).min().unwrap_or(0);
+        let max_x = positions.iter().map(|x| x.1).max().unwrap_or(0);
+        let min_y = positions.iter().map(|x| x.2).min().unwrap_or(0);
+        let max_y = positions.iter().map(|x| x.2).max().unwrap_or(0);
+        let place = |position: i32, min: i32, max: i32, size: i32| {
+            if max == min {
+                (size / 2) as usize
+            } else {
+                ((position - min) as i64 * (size - 1) as i64 / (max - min) as i64) as usize
+            }
+        };
+
+        self.panels = positions.iter().map(|&(id, x, y)| Panel {
+            id,
+            column: place(x, min_x, max_x, width),
+            // The layout's y goes up, like GL's rows.
+            row: place(y, min_y, max_y, height),
+        }).collect();
+        self.size = (width, height);
+    }
+}
+
+impl screen::Screen for NanoleafScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        if self.size != (width, height) {
+            self.place_panels(width, height);
+        }
+
+        // The number of panels, then each panel with its color, an unused white channel and how
+        // long it fades, all big endian.
+        let count = self.panels.len();
+        let mut packet = vec![(count >> 8) as u8, count as u8];
+        for panel in self.panels.iter() {
+            let i = 3 * (panel.row * width as usize + panel.column);
+            packet.extend_from_slice(&[
+                (panel.id >> 8) as u8, panel.id as u8,
+                pixels[i], pixels[i + 1], pixels[i + 2], 0,
+                (TRANSITION_TIME >> 8) as u8, TRANSITION_TIME as u8,
+            ]);
+        }
+
+        // Frames are dropped while the last one is still being sent, or while reconnecting.
+        let _ = self.packets_tx.try_send(packet);
+    }
+}
+
+// `address` with `port`, unless it has one.
+fn resolve(address: &str, port: u16) -> Result<SocketAddr, String> {
+    let with_port = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, port) };
+    with_port.to_socket_addrs()
+        .map_err(|e| format!("invalid Nanoleaf address {}: {}", address, e))?
+        .next()
+        .ok_or_else(|| format!("could not resolve {}", address))
+}
+
+// Switch the panels over to taking colors on STREAM_PORT.
+fn start_external_control(api: SocketAddr, token: &str) -> Result<(), String> {
+    let body = "{\"write\":{\"command\":\"display\",\"animType\":\"extControl\",\"extControlVersion\":\"v2\"}}";
+    api_request(api, "PUT", &format!("/api/v1/{}/effects", token), body)
+        .map(|_| ())
+        .map_err(|e| format!("could not start external control, {}", e))
+}
+
+// The id and position of each lit panel, from the layout's "positionData":
+// [{"panelId":107,"x":0,"y":0,"o":0,"shapeType":2}, ...].
+fn panel_positions(api: SocketAddr, token: &str) -> Result<Vec<(u16, i32, i32)>, String> {
+    let response = api_request(api, "GET", &format!("/api/v1/{}/panelLayout/layout", token), "")?;
+    let data = response.find("\"positionData\"")
+        .map(|i| &response[i..])
+        .ok_or("no positionData in the layout")?;
+    let end = data.find(']').ok_or("the layout's positionData ends early")?;
+
+    let field = |panel: &str, name: &str| -> Option<i64> {
+        let key = format!("\"{}\":", name);
+        let value = &panel[panel.find(&key)? + key.len()..];
+        let value = value.trim_start();
+        let length = value.char_indices()
+            .take_while(|&(i, x)| x.is_ascii_digit() || (i == 0 && x == '-'))
+            .count();
+        value[..length].parse().ok()
+    };
+
+    let mut positions = Vec::new();
+    for panel in data[..end].split('}').filter(|x| x.contains("panelId")) {
+        match (field(panel, "panelId"), field(panel, "x"), field(panel, "y"), field(panel, "shapeType")) {
+            (Some(id), Some(x), Some(y), shape) => {
+                if !UNLIT_SHAPES.contains(&(shape.unwrap_or(0) as u32)) {
+                    positions.push((id as u16, x as i32, y as i32));
+                }
+            },
+            _ => return Err(format!("a panel of the layout has no id or position: {}}}", panel.trim_start_matches(','))),
+        }
+    }
+
+    Ok(positions)
+}
+
+// The body of the reply to an HTTP request of the panels' API.
+fn api_request(api: SocketAddr, method: &str, path: &str, body: &str) -> Result<String, String> {
+    let mut stream = TcpStream::connect_timeout(&api, API_TIMEOUT)
+        .map_err(|e| format!("could not reach the Nanoleaf panels at {}: {}", api, e))?;
+    let _ = stream.set_read_timeout(Some(API_TIMEOUT));
+    let request = format!("{} {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                          method, path, api, body.len(), body);
+    stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(|e| e.to_string())?;
+
+    // Like HTTP/1.1 401 Unauthorized, for a wrong token.
+    let status = response.lines().next().unwrap_or("");
+    if !status.contains(" 200") && !status.contains(" 204") {
+        return Err(format!("the panels replied {}", status.split_once(' ').map_or(status, |x| x.1)));
+    }
+    Ok(match response.find("\r\n\r\n") {
+        Some(i) => response[i + 4..].to_string(),
+        None => String::new(),
+    })
+}
@@ -0,0 +1,90 @@
+use gfx;
+use led_mapper;
+use screen;
+use screen::reconnect::Reconnecting;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::thread;
+
+const DEFAULT_PORT: u16 = 7890;
+const COMMAND_SET_PIXEL_COLORS: u8 = 0;
+// As many as a message's 16-bit length has room for.
+const MAX_PIXELS: usize = 0xffff / 3;
+
+// Sends the output to an Open Pixel Control server, like a FadeCandy's fcserver, over TCP. Pixels
+// go in rows from the top left to `channel`, 0 for all of them, and there are `pixel_count` of
+// them, cut short or padded with black, unless that's 0 for one per output pixel or LED of
+// `pixel_map`, which they're sampled from instead when there is one. Servers like
+// fcserver apply their own gamma curve, so the colors are gamma encoded. Reconnects whenever
+// the server goes away.
+pub struct OpcScreen {
+    channel: u8,
+    pixel_count: usize,
+    pixel_map: Option<led_mapper::PixelMap>,
+    messages_tx: mpsc::SyncSender<Vec<u8>>,
+}
+
+impl OpcScreen {
+    // `address` is a host, with the standard port unless it has one.
+    pub fn new(address: &str, channel: u8, pixel_count: usize, pixel_map: Option<led_mapper::PixelMap>) -> OpcScreen {
+        let address = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, DEFAULT_PORT) };
+        let (messages_tx, messages_rx) = mpsc::sync_channel::<Vec<u8>>(1);
+
+        thread::spawn(move || {
+            let name = format!("the OPC server at {}", address);
+            let mut connection = Reconnecting::new(name, None, || {
+                let stream = TcpStream::connect(&address).map_err(|e| e.to_string())?;
+                // Messages are small and sent one at a time.
+                let _ = stream.set_nodelay(true);
+                Ok(stream)
+            });
+            for message in messages_rx {
+                connection.send(|stream| stream.write_all(&message).map_err(|e| e.to_string()));
+            }
+        });
+
+        OpcScreen {
+            channel,
+            pixel_count,
+            pixel_map,
+            messages_tx,
+        }
+    }
+}
+
+impl screen::Screen for OpcScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let mut pixel_colors = match self.pixel_map {
+            Some(ref map) => map.sample(pixels, width, height),
+            None => led_mapper::led_matrix_mapper::sample_pixels(pixels, width, height, width, height),
+        };
+        if self.pixel_count > 0 {
+            pixel_colors.resize(self.pixel_count, (0, 0, 0));
+        }
+        pixel_colors.truncate(MAX_PIXELS);
+
+        let length = 3 * pixel_colors.len();
+        let mut message = Vec::with_capacity(4 + length);
+        message.extend_from_slice(&[self.channel, COMMAND_SET_PIXEL_COLORS, (length >> 8) as u8, length as u8]);
+        for &(r, g, b) in pixel_colors.iter() {
+            message.extend_from_slice(&[r, g, b]);
+        }
+
+        // Frames are dropped while the last one is still being sent, or while there's no
+        // connection.
+        let _ = self.messages_tx.try_send(message);
+    }
+}
@@ -0,0 +1,286 @@
+use gfx;
+use led_mapper;
+use screen;
+use screen::reconnect::Reconnecting;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const PORT: u16 = 6742;
+const TIMEOUT: Duration = Duration::from_secs(2);
+const CLIENT_NAME: &str = "rpi-music-visualizer";
+const REQUEST_CONTROLLER_COUNT: u32 = 0;
+const REQUEST_CONTROLLER_DATA: u32 = 1;
+const SET_CLIENT_NAME: u32 = 50;
+const UPDATE_LEDS: u32 = 1050;
+const SET_CUSTOM_MODE: u32 = 1100;
+// Where a matrix zone has no LED.
+const NO_LED: u32 = 0xffff_ffff;
+
+// A zone of a device's LEDs, like a keyboard's keys or a fan's ring, which follow on from the
+// previous zone's.
+struct Zone {
+    leds: usize,
+    // The zone's grid of LEDs, columns and rows, and which of the zone's LEDs is in each cell
+    // from the top left, or NO_LED.
+    matrix: Option<(usize, usize, Vec<u32>)>,
+}
+
+struct Device {
+    index: u32,
+    name: String,
+    zones: Vec<Zone>,
+}
+
+// Drives the PC peripherals an OpenRGB server controls, keyboards, RAM, fans and the like, over
+// its SDK protocol. Zones laid out as a matrix, like keyboards, show the frame scaled to their
+// grid, others the frame's columns averaged along their LEDs, left to right.
+pub struct OpenRgbScreen {
+    devices: Vec<Device>,
+    packets_tx: mpsc::SyncSender<Vec<Vec<u8>>>,
+}
+
+impl OpenRgbScreen {
+    pub fn new(address: &str) -> Result<OpenRgbScreen, String> {
+        let destination = resolve(address)?;
+        let mut stream = connect(destination)?;
+        let count = request(&mut stream, 0, REQUEST_CONTROLLER_COUNT, &[])?;
+        let count = Reader::new(&count).u32()?;
+
+        let mut devices = Vec::new();
+        for index in 0..count {
+            let data = request(&mut stream, index, REQUEST_CONTROLLER_DATA, &[])?;
+            let device = parse_device(index, &data).map_err(|e| format!("could not read OpenRGB device {}, {}", index, e))?;
+            if device.zones.iter().any(|x| x.leds > 0) {
+                devices.push(device);
+            }
+        }
+        if devices.is_empty() {
+            return Err("OpenRGB has no devices with LEDs".to_string());
+        }
+        let names: Vec<&str> = devices.iter().map(|x| x.name.as_str()).collect();
+        println!("Driving {} OpenRGB devices: {}", devices.len(), names.join(", "));
+
+        let indices: Vec<u32> = devices.iter().map(|x| x.index).collect();
+        let open = move || {
+            let mut stream = connect(destination)?;
+            // Stops the devices' own effects, so they take colors.
+            for &index in indices.iter() {
+                send(&mut stream, index, SET_CUSTOM_MODE, &[])?;
+            }
+            Ok(stream)
+        };
+        let stream = open()?;
+
+        let (packets_tx, packets_rx) = mpsc::sync_channel::<Vec<Vec<u8>>>(1);
+        thread::spawn(move || {
+            let mut connection = Reconnecting::new(format!("OpenRGB at {}", destination), Some(stream), open);
+            for packets in packets_rx {
+                connection.send(|stream| {
+                    stream.write_all(&packets.concat()).map_err(|e| e.to_string())
+                });
+            }
+        });
+
+        Ok(OpenRgbScreen {
+            devices,
+            packets_tx,
+        })
+    }
+}
+
+impl screen::Screen for OpenRgbScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let packets = self.devices.iter().map(|device| {
+            let mut colors = Vec::new();
+            for zone in device.zones.iter() {
+                match zone.matrix {
+                    Some((columns, rows, ref map)) => {
+                        let cells = led_mapper::led_matrix_mapper::sample_pixels(pixels, width, height,
+                                                                                  columns as i32, rows as i32);
+                        let mut zone_colors = vec![(0, 0, 0); zone.leds];
+                        for (&led, &color) in map.iter().zip(cells.iter()) {
+                            if led != NO_LED && (led as usize) < zone.leds {
+                                zone_colors[led as usize] = color;
+                            }
+                        }
+                        colors.extend(zone_colors);
+                    },
+                    None => colors.extend(strip_colors(pixels, width as usize, height as usize, zone.leds)),
+                }
+            }
+
+            // Its size, then the number of colors, then each color with a byte of padding.
+            let mut data = Vec::with_capacity(6 + 4 * colors.len());
+            data.extend_from_slice(&((6 + 4 * colors.len()) as u32).to_le_bytes());
+            data.extend_from_slice(&(colors.len() as u16).to_le_bytes());
+            for &(r, g, b) in colors.iter() {
+                data.extend_from_slice(&[r, g, b, 0]);
+            }
+            packet(device.index, UPDATE_LEDS, &data)
+        }).collect();
+
+        // Frames are dropped while the last one is still being sent, or while reconnecting.
+        let _ = self.packets_tx.try_send(packets);
+    }
+}
+
+// Colors for `leds` LEDs in a line, each the average of the columns that fall on it, or the
+// nearest when there are more LEDs than columns.
+fn strip_colors(pixels: &[u8], width: usize, height: usize, leds: usize) -> Vec<(u8, u8, u8)> {
+    (0..leds).map(|led| {
+        led_mapper::led_matrix_mapper::average_columns(pixels, width, height, led * width / leds, (led + 1) * width / leds)
+    }).collect()
+}
+
+// `address`, with the SDK's port unless it has one.
+fn resolve(address: &str) -> Result<SocketAddr, String> {
+    let with_port = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, PORT) };
+    with_port.to_socket_addrs()
+        .map_err(|e| format!("invalid OpenRGB address {}: {}", address, e))?
+        .next()
+        .ok_or_else(|| format!("could not resolve {}", address))
+}
+
+// Connect to the server and give it our name, for its list of clients.
+fn connect(destination: SocketAddr) -> Result<TcpStream, String> {
+    let mut stream = TcpStream::connect_timeout(&destination, TIMEOUT)
+        .map_err(|e| format!("could not connect to OpenRGB at {}: {}", destination, e))?;
+    let _ = stream.set_read_timeout(Some(TIMEOUT));
+    let _ = stream.set_write_timeout(Some(TIMEOUT));
+    let _ = stream.set_nodelay(true);
+
+    let mut name = CLIENT_NAME.as_bytes().to_vec();
+    name.push(0);
+    send(&mut stream, 0, SET_CLIENT_NAME, &name)?;
+    Ok(stream)
+}
+
+// A packet of the SDK protocol: "ORGB", then the device, the packet's id and its data's size,
+// little endian.
+fn packet(device: u32, id: u32, data: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(16 + data.len());
+    packet.extend_from_slice(b"ORGB");
+    packet.extend_from_slice(&device.to_le_bytes());
+    packet.extend_from_slice(&id.to_le_bytes());
+    packet.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    packet.extend_from_slice(data);
+    packet
+}
+
+fn send(stream: &mut TcpStream, device: u32, id: u32, data: &[u8]) -> Result<(), String> {
+    stream.write_all(&packet(device, id, data)).map_err(|e| e.to_string())
+}
+
+// Send a request and return the data of its reply.
+fn request(stream: &mut TcpStream, device: u32, id: u32, data: &[u8]) -> Result<Vec<u8>, String> {
+    send(stream, device, id, data)?;
+    let mut header = [0u8; 16];
+    stream.read_exact(&mut header).map_err(|e| format!("no reply from OpenRGB: {}", e))?;
+    let mut reader = Reader::new(&header);
+    if reader.bytes(4)? != b"ORGB" {
+        return Err("OpenRGB's reply isn't an SDK packet".to_string());
+    }
+    let (_, reply_id, size) = (reader.u32()?, reader.u32()?, reader.u32()?);
+    if reply_id != id {
+        return Err(format!("OpenRGB replied to {} with {}", id, reply_id));
+    }
+    let mut reply = vec![0u8; size as usize];
+    stream.read_exact(&mut reply).map_err(|e| format!("OpenRGB's reply ends early: {}", e))?;
+    Ok(reply)
+}
+
+// A device's name and zones from its description, as sent for protocol version 0.
+fn parse_device(index: u32, data: &[u8]) -> Result<Device, String> {
+    let mut reader = Reader::new(data);
+    // Its size and type.
+    reader.bytes(8)?;
+    let name = reader.string()?;
+    // Its description, version, serial and location.
+    for _ in 0..4 {
+        reader.string()?;
+    }
+
+    let modes = reader.u16()?;
+    // The active mode.
+    reader.bytes(4)?;
+    for _ in 0..modes {
+        reader.string()?;
+        // Its value, flags, speed and colors limits, speed, direction and color mode.
+        reader.bytes(36)?;
+        let colors = reader.u16()? as usize;
+        reader.bytes(4 * colors)?;
+    }
+
+    let mut zones = Vec::new();
+    for _ in 0..reader.u16()? {
+        reader.string()?;
+        // Its type and LED count limits.
+        reader.bytes(12)?;
+        let leds = reader.u32()? as usize;
+        let matrix = match reader.u16()? {
+            0 => None,
+            _ => {
+                let rows = reader.u32()? as usize;
+                let columns = reader.u32()? as usize;
+                let map = (0..rows * columns).map(|_| reader.u32()).collect::<Result<Vec<u32>, String>>()?;
+                Some((columns, rows, map))
+            },
+        };
+        zones.push(Zone { leds, matrix });
+    }
+
+    Ok(Device { index, name, zones })
+}
+
+// Reads the SDK protocol's little endian values and strings, which are a length then their
+// bytes, ending with a NUL.
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, offset: 0 }
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.offset + length > self.data.len() {
+            return Err("it ends early".to_string());
+        }
+        let bytes = &self.data[self.offset..self.offset + length];
+        self.offset += length;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.u16()? as usize;
+        let bytes = self.bytes(length)?;
+        Ok(String::from_utf8_lossy(bytes.split(|&x| x == 0).next().unwrap_or(&[])).into_owned())
+    }
+}
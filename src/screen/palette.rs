@@ -0,0 +1,135 @@
+use audio;
+use gfx;
+use screen;
+use std::time::Duration;
+
+// Palettes to pick by name, besides those a config file names.
+pub const PALETTES: [(&str, &str); 4] = [
+    ("fire", "#000000,#800000,#ff4000,#ffc000,#ffffc0"),
+    ("ocean", "#000010,#002060,#0080c0,#40e0ff,#e0ffff"),
+    ("forest", "#000000,#003010,#208020,#a0e040,#ffffa0"),
+    ("sunset", "#100020,#600060,#e02040,#ff9020,#ffe080"),
+];
+
+// Colors the output is shaded with from dark to bright, like "#000000,#ff0000,#ffff00" for fire.
+#[derive(Clone, Debug)]
+pub struct Palette {
+    colors: Vec<(u8, u8, u8)>,
+}
+
+impl Palette {
+    // Parse colors written as "#rrggbb", separated by commas, at least two.
+    pub fn parse(value: &str) -> Result<Palette, String> {
+        let colors = value.split(',')
+            .map(|x| parse_color(x.trim()))
+            .collect::<Result<Vec<(u8, u8, u8)>, String>>()?;
+        if colors.len() < 2 {
+            return Err("expected two colors or more, from dark to bright".to_string());
+        }
+
+        Ok(Palette { colors })
+    }
+
+    // The color at `level`, from 0 to 255, between the two colors either side of it.
+    fn color(&self, level: u8) -> (u8, u8, u8) {
+        let position = level as f32 / 255.0 * (self.colors.len() - 1) as f32;
+        let i = usize::min(position as usize, self.colors.len() - 2);
+        let t = position - i as f32;
+        let ((r0, g0, b0), (r1, g1, b1)) = (self.colors[i], self.colors[i + 1]);
+        let mix = |x: u8, y: u8| (x as f32 + (y as f32 - x as f32) * t).round() as u8;
+        (mix(r0, r1), mix(g0, g1), mix(b0, b1))
+    }
+}
+
+fn parse_color(value: &str) -> Result<(u8, u8, u8), String> {
+    let expected = || format!("expected a color like #ff8000, got '{}'", value);
+    if !value.starts_with('#') || value.len() != 7 {
+        return Err(expected());
+    }
+    let channel = |i: usize| u8::from_str_radix(&value[i..i + 2], 16).map_err(|_| expected());
+    Ok((channel(1)?, channel(3)?, channel(5)?))
+}
+
+// Recolors what a screen taking pixels is sent with a palette, by each pixel's brightness, so
+// every visualizer takes on one set of colors, like a room's. Without a palette, or for screens
+// drawing with GL, the output is shown as it is. The palette can be changed while running.
+pub struct PaletteScreen {
+    screen: Box<dyn screen::Screen>,
+    // The color of each level of brightness, unless there's no palette.
+    colors: Option<Vec<(u8, u8, u8)>>,
+    pixels: Vec<u8>,
+}
+
+impl PaletteScreen {
+    pub fn new(screen: Box<dyn screen::Screen>, palette: Option<&Palette>) -> PaletteScreen {
+        let mut screen = PaletteScreen {
+            screen,
+            colors: None,
+            pixels: Vec::new(),
+        };
+        screen::Screen::set_palette(&mut screen, palette);
+        screen
+    }
+
+    // Whether the palette can't be applied, for screens drawing with GL.
+    pub fn is_ignored(&self) -> bool {
+        self.colors.is_some() && !self.screen.renders_pixels()
+    }
+}
+
+impl screen::Screen for PaletteScreen {
+    fn setup(&mut self, gl: &gfx::gl::Gl) -> Result<(), String> {
+        self.screen.setup(gl)
+    }
+
+    fn render_from_texture(&mut self, gl: &gfx::gl::Gl, texture: u32, width: i32, height: i32) {
+        self.screen.render_from_texture(gl, texture, width, height);
+    }
+
+    fn uses_window(&self) -> bool {
+        self.screen.uses_window()
+    }
+
+    fn is_linear(&self) -> bool {
+        self.screen.is_linear()
+    }
+
+    fn renders_pixels(&self) -> bool {
+        self.screen.renders_pixels()
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let colors = match self.colors {
+            Some(ref x) => x,
+            None => return self.screen.render_from_pixels(pixels, width, height),
+        };
+
+        self.pixels.clear();
+        for pixel in pixels.chunks(3) {
+            // Rec. 601 luma, of the gamma encoded colors, as the palette's are.
+            let luma = match *pixel {
+                [r, g, b] => 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32,
+                _ => 0.0,
+            };
+            let (r, g, b) = colors[f32::min(255.0, luma.round()) as usize];
+            self.pixels.extend_from_slice(&[r, g, b]);
+        }
+        self.screen.render_from_pixels(&self.pixels, width, height);
+    }
+
+    fn update_audio(&mut self, audio_frame: &audio::AudioFrame) {
+        self.screen.update_audio(audio_frame);
+    }
+
+    fn set_output_gamma(&mut self, gamma: f32) {
+        self.screen.set_output_gamma(gamma);
+    }
+
+    fn set_latency(&mut self, latency: Duration) {
+        self.screen.set_latency(latency);
+    }
+
+    fn set_palette(&mut self, palette: Option<&Palette>) {
+        self.colors = palette.map(|palette| (0..256).map(|x| palette.color(x as u8)).collect());
+    }
+}
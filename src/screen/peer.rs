@@ -0,0 +1,314 @@
+use gfx;
+use screen;
+use screen::reconnect::Reconnecting;
+use std::io::{self, Read, Write};
+use std::iter;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+pub const PORT: u16 = 7681;
+const TIMEOUT: Duration = Duration::from_secs(2);
+// Each frame starts with this, then whether it's whole or changes from the last, its width and
+// height and its data's length, big endian.
+const MAGIC: &[u8; 3] = b"RMV";
+const KEY_FRAME: u8 = b'K';
+const DELTA_FRAME: u8 = b'D';
+const HEADER_LENGTH: usize = 12;
+// Bigger frames are taken for a confused peer, rather than allocated.
+const MAX_PIXELS: usize = 4096 * 4096;
+
+// Sends the output to another instance running with --peer_source at `address`, so a machine
+// with a fast GPU can render for a Pi Zero that only pushes the pixels to its LEDs. Frames are
+// lossless, as LEDs show compression artifacts, but small: each is XORed with the last one sent
+// so what stayed the same is zeros, then run length encoded. Reconnects whenever the peer goes
+// away, starting again from a whole frame.
+pub struct PeerScreen {
+    frames_tx: mpsc::SyncSender<(Vec<u8>, i32, i32)>,
+}
+
+impl PeerScreen {
+    pub fn new(address: &str) -> Result<PeerScreen, String> {
+        let destination = resolve(address)?;
+        let (frames_tx, frames_rx) = mpsc::sync_channel::<(Vec<u8>, i32, i32)>(1);
+        thread::spawn(move || {
+            let mut connection = Reconnecting::new(format!("the peer at {}", destination), None, || {
+                let stream = TcpStream::connect_timeout(&destination, TIMEOUT).map_err(|e| e.to_string())?;
+                let _ = stream.set_write_timeout(Some(TIMEOUT));
+                let _ = stream.set_nodelay(true);
+                Ok(stream)
+            });
+            let mut sent: (Vec<u8>, i32, i32) = (Vec::new(), 0, 0);
+            for (pixels, width, height) in frames_rx {
+                let previous = if (sent.1, sent.2) == (width, height) { Some(&sent.0[..]) } else { None };
+                let message = encode(&pixels, width, height, previous);
+                if connection.send(|stream| stream.write_all(&message).map_err(|e| e.to_string())) {
+                    sent = (pixels, width, height);
+                } else {
+                    // The peer starts again from a whole frame after reconnecting.
+                    sent = (Vec::new(), 0, 0);
+                }
+            }
+        });
+
+        Ok(PeerScreen { frames_tx })
+    }
+}
+
+impl screen::Screen for PeerScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        // Frames are dropped while the last one is still being sent, or while reconnecting.
+        let _ = self.frames_tx.try_send((pixels.to_vec(), width, height));
+    }
+}
+
+// Listens on `address` for a PeerScreen, one at a time, and hands on each of its frames as
+// tightly packed RGB rows, bottom to top, with their width and height. Frames are still decoded
+// but not handed on while the last one hasn't been taken.
+pub fn listen(address: &str) -> Result<mpsc::Receiver<(Vec<u8>, i32, i32)>, String> {
+    let address = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, PORT) };
+    let listener = TcpListener::bind(&address).map_err(|e| format!("could not listen on {}: {}", address, e))?;
+    println!("Waiting for a peer's frames on {}", address);
+
+    let (frames_tx, frames_rx) = mpsc::sync_channel::<(Vec<u8>, i32, i32)>(1);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            let peer = stream.peer_addr().map(|x| x.to_string()).unwrap_or_else(|_| "a peer".to_string());
+            println!("Showing frames from {}", peer);
+
+            let mut frame: (Vec<u8>, i32, i32) = (Vec::new(), 0, 0);
+            let result = loop {
+                if let Err(e) = read_frame(&mut stream, &mut frame) {
+                    break e;
+                }
+                if let Err(mpsc::TrySendError::Disconnected(_)) = frames_tx.try_send(frame.clone()) {
+                    return;
+                }
+            };
+            println!("Stopped showing frames from {}, {}", peer, result);
+        }
+    });
+
+    Ok(frames_rx)
+}
+
+// `address`, with the peer port unless it has one.
+fn resolve(address: &str) -> Result<SocketAddr, String> {
+    let with_port = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, PORT) };
+    with_port.to_socket_addrs()
+        .map_err(|e| format!("invalid peer address {}: {}", address, e))?
+        .next()
+        .ok_or_else(|| format!("could not resolve {}", address))
+}
+
+// A frame's message, changes from `previous` when there is one the same size.
+fn encode(pixels: &[u8], width: i32, height: i32, previous: Option<&[u8]>) -> Vec<u8> {
+    let mut data = Vec::with_capacity(pixels.len() / 4);
+    let kind = match previous {
+        Some(previous) => {
+            let changes: Vec<u8> = pixels.iter().zip(previous.iter()).map(|(&x, &y)| x ^ y).collect();
+            run_length_encode(&changes, &mut data);
+            DELTA_FRAME
+        },
+        None => {
+            run_length_encode(pixels, &mut data);
+            KEY_FRAME
+        },
+    };
+
+    let mut message = Vec::with_capacity(HEADER_LENGTH + data.len());
+    message.extend_from_slice(MAGIC);
+    message.push(kind);
+    message.extend_from_slice(&(width as u16).to_be_bytes());
+    message.extend_from_slice(&(height as u16).to_be_bytes());
+    message.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    message.extend_from_slice(&data);
+    message
+}
+
+// Read the next frame's message from `stream` over the last `frame`.
+fn read_frame(stream: &mut TcpStream, frame: &mut (Vec<u8>, i32, i32)) -> Result<(), String> {
+    let mut header = [0u8; HEADER_LENGTH];
+    stream.read_exact(&mut header).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => "it went away".to_string(),
+        _ => e.to_string(),
+    })?;
+    if &header[..3] != MAGIC {
+        return Err("it isn't sending frames".to_string());
+    }
+    let width = u16::from_be_bytes([header[4], header[5]]) as i32;
+    let height = u16::from_be_bytes([header[6], header[7]]) as i32;
+    let length = u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as usize;
+    let size = 3 * width as usize * height as usize;
+    // Encoded, a frame is never near twice its size.
+    if size > 3 * MAX_PIXELS || length > 2 * size {
+        return Err(format!("its {}x{} frame of {} bytes is too big", width, height, length));
+    }
+
+    let mut data = vec![0u8; length];
+    stream.read_exact(&mut data).map_err(|e| e.to_string())?;
+    let decoded = run_length_decode(&data, size)?;
+    match header[3] {
+        KEY_FRAME => *frame = (decoded, width, height),
+        DELTA_FRAME if (frame.1, frame.2) == (width, height) => {
+            for (pixel, change) in frame.0.iter_mut().zip(decoded) {
+                *pixel ^= change;
+            }
+        },
+        DELTA_FRAME => return Err("it sent changes to a frame it hadn't".to_string()),
+        kind => return Err(format!("it sent an unknown kind of frame, {}", kind)),
+    }
+    Ok(())
+}
+
+// PackBits: a byte under 128 is followed by that many bytes and one more as they are, and one
+// from 128 by a byte repeated 126 fewer times than it, from 2 to 129.
+fn run_length_encode(data: &[u8], encoded: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < data.len() {
+        let mut run = 1;
+        while i + run < data.len() && run < 129 && data[i + run] == data[i] {
+            run += 1;
+        }
+        if run > 1 {
+            encoded.extend_from_slice(&[(run + 126) as u8, data[i]]);
+            i += run;
+            continue;
+        }
+
+        // As they are, until a run starts.
+        let start = i;
+        while i < data.len() && i - start < 128 && (i + 1 == data.len() || data[i + 1] != data[i]) {
+            i += 1;
+        }
+        encoded.push((i - start - 1) as u8);
+        encoded.extend_from_slice(&data[start..i]);
+    }
+}
+
+fn run_length_decode(data: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    let mut decoded = Vec::with_capacity(size);
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i] as usize;
+        if control < 128 {
+            let end = i + 1 + control + 1;
+            if end > data.len() {
+                return Err("its frame ends early".to_string());
+            }
+            decoded.extend_from_slice(&data[i + 1..end]);
+            i = end;
+        } else {
+            if i + 1 >= data.len() {
+                return Err("its frame ends early".to_string());
+            }
+            decoded.extend(iter::repeat_n(data[i + 1], control - 126));
+            i += 2;
+        }
+        if decoded.len() > size {
+            break;
+        }
+    }
+
+    if decoded.len() != size {
+        return Err(format!("its frame is {} bytes rather than {}", decoded.len(), size));
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Send `messages` over a loopback connection and read them as frames, from none.
+    fn read_frames(messages: &[Vec<u8>]) -> Vec<Result<(Vec<u8>, i32, i32), String>> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut receiver, _) = listener.accept().unwrap();
+        for message in messages {
+            sender.write_all(message).unwrap();
+        }
+        drop(sender);
+
+        let mut frame = (Vec::new(), 0, 0);
+        messages.iter().map(|_| read_frame(&mut receiver, &mut frame).map(|_| frame.clone())).collect()
+    }
+
+    // A 5x4 frame with runs, bytes as they are and a run longer than one control byte covers.
+    fn pixels(seed: u8) -> Vec<u8> {
+        let mut pixels = vec![seed; 3 * 5 * 4];
+        for (i, pixel) in pixels.iter_mut().enumerate().skip(40) {
+            *pixel = (i as u8).wrapping_mul(seed);
+        }
+        pixels
+    }
+
+    #[test]
+    fn run_length_round_trips() {
+        let long_run = vec![7u8; 300];
+        let as_they_are: Vec<u8> = (0..=255).collect();
+        for data in &[Vec::new(), vec![1], vec![1, 1], long_run, as_they_are, pixels(3)] {
+            let mut encoded = Vec::new();
+            run_length_encode(data, &mut encoded);
+            assert_eq!(&run_length_decode(&encoded, data.len()).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let (first, second) = (pixels(3), pixels(5));
+        let key = encode(&first, 5, 4, None);
+        let delta = encode(&second, 5, 4, Some(&first));
+        assert_eq!(&key[..4], b"RMVK");
+        assert_eq!(&delta[..4], b"RMVD");
+        assert_eq!(&key[4..8], &[0, 5, 0, 4]);
+
+        let frames = read_frames(&[key, delta]);
+        assert_eq!(frames[0], Ok((first, 5, 4)));
+        assert_eq!(frames[1], Ok((second, 5, 4)));
+    }
+
+    #[test]
+    fn rejects_changes_without_a_frame() {
+        let delta = encode(&pixels(5), 5, 4, Some(&pixels(3)));
+        assert_eq!(read_frames(&[delta]), vec![Err("it sent changes to a frame it hadn't".to_string())]);
+    }
+
+    #[test]
+    fn rejects_truncated_frames() {
+        let mut encoded = Vec::new();
+        run_length_encode(&pixels(3), &mut encoded);
+        encoded.pop();
+        assert!(run_length_decode(&encoded, 60).is_err());
+        assert_eq!(run_length_decode(&[130], 4), Err("its frame ends early".to_string()));
+        assert_eq!(run_length_decode(&[3, 1, 2], 4), Err("its frame ends early".to_string()));
+
+        let key = encode(&pixels(3), 5, 4, None);
+        assert_eq!(read_frames(&[key[..8].to_vec()]), vec![Err("it went away".to_string())]);
+        assert!(read_frames(&[key[..key.len() - 1].to_vec()])[0].is_err());
+    }
+
+    #[test]
+    fn rejects_other_streams() {
+        let mut key = encode(&pixels(3), 5, 4, None);
+        key[0] = b'X';
+        assert_eq!(read_frames(&[key]), vec![Err("it isn't sending frames".to_string())]);
+    }
+}
@@ -1,95 +1,52 @@
 use gfx;
 use gfx::gl;
 use screen;
-use std::mem;
-use std::ptr;
 
 pub struct RawScreen {
-    program_id: u32,
+    program: Option<gfx::Program>,
+    quad: Option<gfx::Mesh>,
 }
 
 impl RawScreen {
     pub fn new() -> RawScreen {
-        RawScreen { program_id: 0 }
+        RawScreen { program: None, quad: None }
     }
 }
 
 impl screen::Screen for RawScreen {
-    fn setup(&mut self, gl: &gfx::gl::Gl) {
-        unsafe {
-            let vs = gl_try!(gl; gl.CreateShader(gl::VERTEX_SHADER));
-            gl_try!(gl; gl.ShaderSource(vs, 1, [VS_SRC.as_ptr() as *const _].as_ptr(), ptr::null()));
-            gl_try!(gl; gl.CompileShader(vs));
-
-            let fs = gl_try!(gl; gl.CreateShader(gl::FRAGMENT_SHADER));
-            gl_try!(gl; gl.ShaderSource(fs, 1, [FS_SRC.as_ptr() as *const _].as_ptr(), ptr::null()));
-            gl_try!(gl; gl.CompileShader(fs));
-
-            let program = gl_try!(gl; gl.CreateProgram());
-            gl_try!(gl; gl.AttachShader(program, vs));
-            gl_try!(gl; gl.AttachShader(program, fs));
-            gl_try!(gl; gl.LinkProgram(program));
-
-            self.program_id = program;
-
-            let mut is_linked = mem::uninitialized();
-            gl_try!(gl; gl.GetProgramiv(program, gl::LINK_STATUS, &mut is_linked));
-            if is_linked == gl::FALSE as i32 {
-                let mut max_length = mem::uninitialized();
-                gl_try!(gl; gl.GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut max_length));
-
-                let mut info_log = vec![0; max_length as usize];
-                gl_try!(gl; gl.GetProgramInfoLog(program, max_length, &mut max_length, info_log.as_mut_ptr()));
-
-                for info_char in info_log.iter() {
-                    print!("{}", *info_char as u8 as char);
-                }
-                panic!();
-            }
-        }
+    fn setup(&mut self, gl: &gfx::gl::Gl) -> Result<(), String> {
+        let program = gfx::Program::new(gl, VS_SRC, FS_SRC).map_err(|e| format!("its {}", e))?;
+
+        let vertex_data: [f32; 2 * 6] = [
+            -1.0, -1.0,
+            1.0, -1.0,
+            -1.0, 1.0,
+            -1.0, 1.0,
+            1.0, -1.0,
+            1.0, 1.0
+        ];
+
+        let quad = gfx::Mesh::new(gl, program.id(), &[("position", 2)])?;
+        quad.upload(&vertex_data);
+        self.quad = Some(quad);
+        self.program = Some(program);
+        Ok(())
     }
 
-    fn render_from_texture(&mut self, gl: &gfx::gl::Gl, _texture: u32, _size: i32) {
+    fn render_from_texture(&mut self, gl: &gfx::gl::Gl, texture: u32, _width: i32, _height: i32) {
+        if let Some(ref program) = self.program {
+            program.bind();
+            program.set_i32("texture_sampler", 0);
+        }
+
         unsafe {
-            gl_try!(gl; gl.UseProgram(self.program_id));
-
-            let vertex_data: [f32; 2 * 6] = [
-                -1.0, -1.0,
-                1.0, -1.0,
-                -1.0, 1.0,
-                -1.0, 1.0,
-                1.0, -1.0,
-                1.0, 1.0
-            ];
-
-            let mut vb = mem::uninitialized();
-            gl_try!(gl; gl.GenBuffers(1, &mut vb));
-            gl_try!(gl; gl.BindBuffer(gl::ARRAY_BUFFER, vb));
-            gl_try!(gl; gl.BufferData(
-                gl::ARRAY_BUFFER,
-                (vertex_data.len() * mem::size_of::<f32>()) as gl::types::GLsizeiptr,
-                vertex_data.as_ptr() as *const _,
-                gl::STATIC_DRAW,
-            ));
-
-            let mut vao = mem::uninitialized();
-            gl_try!(gl; gl.GenVertexArrays(1, &mut vao));
-            gl_try!(gl; gl.BindVertexArray(vao));
-
-            let pos_attrib = gl_try!(gl; gl.GetAttribLocation(
-                self.program_id, b"position\0".as_ptr() as *const _));
-            gl_try!(gl; gl.VertexAttribPointer(
-                pos_attrib as gl::types::GLuint, 2, gl::FLOAT, 0,
-                2 * mem::size_of::<f32>() as gl::types::GLsizei,
-                ptr::null(),
-            ));
-            gl_try!(gl; gl.EnableVertexAttribArray(pos_attrib as gl::types::GLuint));
+            gl_try!(gl; gl.ActiveTexture(gl::TEXTURE0));
+            gl_try!(gl; gl.BindTexture(gl::TEXTURE_2D, texture));
 
             gl_try!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, 0));
-            gl_try!(gl; gl.DrawArrays(gl::TRIANGLES, 0, 2 * 6));
-
-            gl_try!(gl; gl.DeleteBuffers(1, &vb));
-            gl_try!(gl; gl.DeleteVertexArrays(1, &vao));
+            if let Some(ref quad) = self.quad {
+                quad.draw();
+            }
         }
     }
 
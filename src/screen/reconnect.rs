@@ -0,0 +1,155 @@
+use std::cmp;
+use std::time::{Duration, Instant};
+
+// Retries start quick, for a blip, and back off to this for devices that stay away, so they
+// aren't hammered.
+const FIRST_RETRY: Duration = Duration::from_millis(250);
+const MAX_RETRY: Duration = Duration::from_secs(10);
+
+// A screen's connection to its device, LEDs on SPI, a serial adapter or a network controller,
+// that's opened again with `open` whenever writing to it fails, e.g. when a cable is loose.
+// Frames are dropped while it's down, and between attempts to reopen it, which back off up to
+// MAX_RETRY. Each outage is printed when it starts and when it ends, not every failure.
+pub struct Reconnecting<D, O> where O: FnMut() -> Result<D, String> {
+    // What the device is, like "the WS2812 LEDs".
+    name: String,
+    open: O,
+    device: Option<D>,
+    retry: Duration,
+    next_attempt: Instant,
+    // When the device was lost or first failed to open.
+    outage_start: Option<Instant>,
+    connected_before: bool,
+}
+
+impl<D, O> Reconnecting<D, O> where O: FnMut() -> Result<D, String> {
+    // Starts with `device` when the screen opened it already, otherwise opens it on the first
+    // `send`.
+    pub fn new(name: String, device: Option<D>, open: O) -> Reconnecting<D, O> {
+        Reconnecting {
+            name,
+            open,
+            connected_before: device.is_some(),
+            device,
+            retry: FIRST_RETRY,
+            next_attempt: Instant::now(),
+            outage_start: None,
+        }
+    }
+
+    // Write a frame with `write`, reopening the device first if it's due. Returns whether it
+    // was written, if not it was dropped.
+    pub fn send<W>(&mut self, write: W) -> bool where W: FnOnce(&mut D) -> Result<(), String> {
+        if self.device.is_none() {
+            if Instant::now() < self.next_attempt {
+                return false;
+            }
+            match (self.open)() {
+                Ok(x) => self.device = Some(x),
+                Err(e) => {
+                    if self.outage_start.is_none() {
+                        println!("Could not connect to {}, {}, retrying", self.name, e);
+                        self.outage_start = Some(Instant::now());
+                    }
+                    self.back_off();
+                    return false;
+                }
+            }
+        }
+
+        let result = match self.device {
+            Some(ref mut device) => write(device),
+            None => return false,
+        };
+        match result {
+            Ok(_) => {
+                if let Some(start) = self.outage_start.take() {
+                    let verb = if self.connected_before { "Reconnected" } else { "Connected" };
+                    println!("{} to {} after {} seconds", verb, self.name, start.elapsed().as_secs());
+                }
+                self.connected_before = true;
+                self.retry = FIRST_RETRY;
+                true
+            },
+            Err(e) => {
+                if self.outage_start.is_none() {
+                    println!("Lost {}, {}, reconnecting", self.name, e);
+                    self.outage_start = Some(Instant::now());
+                }
+                self.device = None;
+                self.back_off();
+                false
+            },
+        }
+    }
+
+    fn back_off(&mut self) {
+        self.next_attempt = Instant::now() + self.retry;
+        self.retry = cmp::min(self.retry * 2, MAX_RETRY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    // Send a frame as soon as the next attempt may be made, rather than waiting for it.
+    fn send_now<D, O>(connection: &mut Reconnecting<D, O>, written: &Cell<u32>) -> bool
+            where O: FnMut() -> Result<D, String> {
+        connection.next_attempt = Instant::now();
+        connection.send(|_| {
+            written.set(written.get() + 1);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn backs_off_until_the_device_opens() {
+        let attempts = Cell::new(0);
+        let written = Cell::new(0);
+        let mut connection = Reconnecting::new("the fake device".to_string(), None, || {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() <= 8 { Err("it's unplugged".to_string()) } else { Ok(()) }
+        });
+
+        let mut waits = Vec::new();
+        for _ in 0..8 {
+            assert!(!send_now(&mut connection, &written));
+            waits.push(connection.next_attempt.duration_since(Instant::now()));
+        }
+        // Waits are a little shorter than the retry for the time between sending and checking.
+        let retries: Vec<u64> = waits.iter().map(|x| (x.as_millis() as u64 + 50) / 250 * 250).collect();
+        assert_eq!(retries, vec![250, 500, 1000, 2000, 4000, 8000, 10000, 10000]);
+
+        // Until the next attempt is due, frames are dropped without trying it.
+        assert!(!connection.send(|_| Ok(())));
+        assert_eq!(attempts.get(), 8);
+
+        assert!(send_now(&mut connection, &written));
+        assert_eq!((attempts.get(), written.get()), (9, 1));
+        assert_eq!(connection.retry, FIRST_RETRY);
+    }
+
+    #[test]
+    fn starts_backing_off_again_when_a_write_fails() {
+        let attempts = Cell::new(0);
+        let written = Cell::new(0);
+        let mut connection = Reconnecting::new("the fake device".to_string(), Some(()), || {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() <= 3 { Err("it's unplugged".to_string()) } else { Ok(()) }
+        });
+        assert!(send_now(&mut connection, &written));
+
+        assert!(!connection.send(|_| Err("the cable came out".to_string())));
+        assert_eq!(connection.retry, FIRST_RETRY * 2);
+        for _ in 0..3 {
+            assert!(!send_now(&mut connection, &written));
+        }
+        assert_eq!(connection.retry, FIRST_RETRY * 16);
+
+        assert!(send_now(&mut connection, &written));
+        assert_eq!((attempts.get(), written.get()), (4, 2));
+        assert_eq!(connection.retry, FIRST_RETRY);
+    }
+}
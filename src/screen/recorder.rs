@@ -0,0 +1,97 @@
+use gfx;
+use screen;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+// Frames waiting to be written before rendering waits for the disk, rather than frames going
+// missing from the recording.
+const QUEUED_FRAMES: usize = 30;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Png,
+    // Just the RGB rows, top to bottom, 3 bytes a pixel.
+    Raw,
+}
+
+impl Format {
+    fn parse(name: &str) -> Result<Format, String> {
+        match name {
+            "png" => Ok(Format::Png),
+            "raw" => Ok(Format::Raw),
+            _ => Err(format!("unknown recording format {}, expected png or raw", name)),
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match *self {
+            Format::Png => "png",
+            Format::Raw => "rgb",
+        }
+    }
+}
+
+// Writes every frame it's given into `dir`, numbered from frame-000001, as a PNG or raw RGB.
+// Next to another screen it records what that screen is sent, given the same --screen_gamma,
+// --screen_brightness, --screen_rotation and --screen_flip, e.g. 1 for the gamma of LEDs.
+pub struct RecorderScreen {
+    frames_tx: mpsc::SyncSender<(Vec<u8>, usize, usize)>,
+}
+
+impl RecorderScreen {
+    pub fn new(dir: &str, format: &str) -> Result<RecorderScreen, String> {
+        let format = Format::parse(format)?;
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir).map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+        println!("Recording frames to {}", dir.display());
+
+        let (frames_tx, frames_rx) = mpsc::sync_channel::<(Vec<u8>, usize, usize)>(QUEUED_FRAMES);
+        thread::spawn(move || {
+            for (i, (pixels, width, height)) in frames_rx.iter().enumerate() {
+                let path = dir.join(format!("frame-{:06}.{}", i + 1, format.extension()));
+                let written = match format {
+                    Format::Png => gfx::save_png(&path, width, height, &pixels),
+                    Format::Raw => save_raw(&path, width, &pixels),
+                };
+                if let Err(e) = written {
+                    println!("Stopped recording frames, {}", e);
+                    break;
+                }
+            }
+        });
+
+        Ok(RecorderScreen { frames_tx })
+    }
+}
+
+impl screen::Screen for RecorderScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        // Fails once writing has, when there's nothing more to record.
+        let _ = self.frames_tx.send((pixels.to_vec(), width as usize, height as usize));
+    }
+}
+
+// Write tightly packed RGB rows, bottom to top as GL reads them, top to bottom.
+fn save_raw(path: &Path, width: usize, pixels: &[u8]) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("could not create {}: {}", path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    for row in pixels.chunks(3 * width).rev() {
+        writer.write_all(row).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    }
+    writer.flush().map_err(|e| format!("could not write {}: {}", path.display(), e))
+}
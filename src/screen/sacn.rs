@@ -0,0 +1,299 @@
+use gfx;
+use rand;
+use screen;
+use screen::dmx::UniverseLayout;
+use screen::reconnect::Reconnecting;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::thread;
+
+const PORT: u16 = 5568;
+const ACN_PACKET_IDENTIFIER: [u8; 12] = *b"ASC-E1.17\0\0\0";
+const VECTOR_ROOT_E131_DATA: u32 = 0x0000_0004;
+const VECTOR_ROOT_E131_EXTENDED: u32 = 0x0000_0008;
+const VECTOR_E131_DATA_PACKET: u32 = 0x0000_0002;
+const VECTOR_E131_EXTENDED_SYNCHRONIZATION: u32 = 0x0000_0001;
+const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+const SOURCE_NAME: &str = "rpi-music-visualizer";
+const PRIORITY: u8 = 100;
+// Universes go up to this, and are counted from 1.
+const MAX_UNIVERSE: u16 = 63999;
+
+// Sends the output to sACN (E1.31) receivers, like commercial pixel controllers, laid out over
+// universes by `layout`. Each universe goes to its multicast group, or all of them to one
+// receiver by unicast. With more than one universe, they're synchronized on the first one so
+// receivers that support it show all of them at once.
+pub struct SacnScreen {
+    layout: UniverseLayout,
+    // None to multicast.
+    unicast: Option<SocketAddr>,
+    // Identifies this source to receivers across packets.
+    cid: [u8; 16],
+    packets_tx: mpsc::SyncSender<Vec<(SocketAddr, Vec<u8>)>>,
+    sequence: u8,
+}
+
+impl SacnScreen {
+    // An empty `address` multicasts, otherwise it's a host to unicast to, with the standard port
+    // unless it has one.
+    pub fn new(address: &str, layout: UniverseLayout) -> Result<SacnScreen, String> {
+        let unicast = match address {
+            "" => None,
+            _ => {
+                let destination = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, PORT) };
+                Some(destination.to_socket_addrs()
+                    .map_err(|e| format!("invalid sACN address {}: {}", address, e))?
+                    .next()
+                    .ok_or_else(|| format!("could not resolve {}", address))?)
+            }
+        };
+        if layout.first_universe() < 1 || layout.first_universe() > MAX_UNIVERSE {
+            return Err(format!("sACN universes are from 1 to {}", MAX_UNIVERSE));
+        }
+        let socket = open_socket()?;
+
+        let (packets_tx, packets_rx) = mpsc::sync_channel::<Vec<(SocketAddr, Vec<u8>)>>(1);
+        let name = match unicast {
+            Some(x) => format!("the sACN receiver at {}", x),
+            None => "the sACN receivers".to_string(),
+        };
+        thread::spawn(move || {
+            let mut connection = Reconnecting::new(name, Some(socket), open_socket);
+            for packets in packets_rx {
+                connection.send(|socket| {
+                    for &(destination, ref packet) in packets.iter() {
+                        socket.send_to(packet, destination).map_err(|e| e.to_string())?;
+                    }
+                    Ok(())
+                });
+            }
+        });
+
+        Ok(SacnScreen {
+            layout,
+            unicast,
+            cid: rand::random(),
+            packets_tx,
+            sequence: 0,
+        })
+    }
+
+    // Where a universe's packets go.
+    fn destination(&self, universe: u16) -> SocketAddr {
+        match self.unicast {
+            Some(x) => x,
+            None => {
+                let group = Ipv4Addr::new(239, 255, (universe >> 8) as u8, universe as u8);
+                SocketAddr::V4(SocketAddrV4::new(group, PORT))
+            }
+        }
+    }
+
+    // The data packets for each universe, and a synchronization packet when there's more than
+    // one, with where they go. Universes past the last there is are left out.
+    fn packets(&mut self, mut universes: Vec<(u16, Vec<u8>)>) -> Vec<(SocketAddr, Vec<u8>)> {
+        universes.retain(|&(universe, _)| (1..=MAX_UNIVERSE).contains(&universe));
+        let sync_universe = match universes.len() {
+            0 | 1 => 0,
+            _ => universes[0].0,
+        };
+
+        let mut packets = Vec::new();
+        for (universe, channels) in universes {
+            let packet = self.data_packet(universe, sync_universe, &channels);
+            packets.push((self.destination(universe), packet));
+        }
+
+        if sync_universe != 0 {
+            let packet = self.sync_packet(sync_universe);
+            packets.push((self.destination(sync_universe), packet));
+        }
+
+        packets
+    }
+
+    fn data_packet(&mut self, universe: u16, sync_universe: u16, channels: &[u8]) -> Vec<u8> {
+        let length = 126 + channels.len();
+        let mut packet = self.root_layer(VECTOR_ROOT_E131_DATA, length);
+
+        // Framing layer.
+        push_u16(&mut packet, flags_and_length(length - 38));
+        push_u32(&mut packet, VECTOR_E131_DATA_PACKET);
+        let mut source_name = [0; 64];
+        source_name[..SOURCE_NAME.len()].copy_from_slice(SOURCE_NAME.as_bytes());
+        packet.extend_from_slice(&source_name);
+        packet.push(PRIORITY);
+        push_u16(&mut packet, sync_universe);
+        packet.push(self.next_sequence());
+        // No options.
+        packet.push(0);
+        push_u16(&mut packet, universe);
+
+        // DMP layer: the channels, after the null start code, as one range of properties.
+        push_u16(&mut packet, flags_and_length(length - 115));
+        packet.push(VECTOR_DMP_SET_PROPERTY);
+        packet.push(0xa1);
+        push_u16(&mut packet, 0);
+        push_u16(&mut packet, 1);
+        push_u16(&mut packet, channels.len() as u16 + 1);
+        packet.push(0);
+        packet.extend_from_slice(channels);
+
+        packet
+    }
+
+    fn sync_packet(&mut self, sync_universe: u16) -> Vec<u8> {
+        let length = 49;
+        let mut packet = self.root_layer(VECTOR_ROOT_E131_EXTENDED, length);
+
+        push_u16(&mut packet, flags_and_length(length - 38));
+        push_u32(&mut packet, VECTOR_E131_EXTENDED_SYNCHRONIZATION);
+        packet.push(self.next_sequence());
+        push_u16(&mut packet, sync_universe);
+        // Reserved.
+        push_u16(&mut packet, 0);
+
+        packet
+    }
+
+    // The start of a packet `length` bytes long.
+    fn root_layer(&self, vector: u32, length: usize) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(length);
+        // Preamble and postamble sizes.
+        push_u16(&mut packet, 0x0010);
+        push_u16(&mut packet, 0);
+        packet.extend_from_slice(&ACN_PACKET_IDENTIFIER);
+        push_u16(&mut packet, flags_and_length(length - 16));
+        push_u32(&mut packet, vector);
+        packet.extend_from_slice(&self.cid);
+        packet
+    }
+
+    fn next_sequence(&mut self) -> u8 {
+        self.sequence = self.sequence.wrapping_add(1);
+        self.sequence
+    }
+}
+
+impl screen::Screen for SacnScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn is_linear(&self) -> bool {
+        true
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let universes = self.layout.universes(pixels, width, height);
+        let packets = self.packets(universes);
+
+        // Frames are dropped while the last one is still being sent, or while reconnecting.
+        let _ = self.packets_tx.try_send(packets);
+    }
+}
+
+// The top 4 bits of a PDU's length are its flags, always 0x7.
+fn flags_and_length(length: usize) -> u16 {
+    0x7000 | length as u16
+}
+
+// sACN is big endian.
+fn push_u16(packet: &mut Vec<u8>, x: u16) {
+    packet.extend_from_slice(&[(x >> 8) as u8, x as u8]);
+}
+
+fn push_u32(packet: &mut Vec<u8>, x: u32) {
+    packet.extend_from_slice(&[(x >> 24) as u8, (x >> 16) as u8, (x >> 8) as u8, x as u8]);
+}
+
+fn open_socket() -> Result<UdpSocket, String> {
+    UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("could not open a UDP socket: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use led_mapper::ColorOrder;
+
+    fn screen(unicast: Option<SocketAddr>) -> SacnScreen {
+        let layout = UniverseLayout::new(1, 1, 170, false, None, ColorOrder::parse("rgb").unwrap()).unwrap();
+        SacnScreen {
+            layout,
+            unicast,
+            cid: [7; 16],
+            packets_tx: mpsc::sync_channel(1).0,
+            sequence: 0,
+        }
+    }
+
+    #[test]
+    fn builds_data_packets() {
+        let packet = screen(None).data_packet(3, 0, &[10, 20, 30]);
+        assert_eq!(packet.len(), 129);
+        // Root layer.
+        assert_eq!(packet[0..4], [0x00, 0x10, 0x00, 0x00]);
+        assert_eq!(packet[4..16], *b"ASC-E1.17\0\0\0");
+        assert_eq!(packet[16..18], [0x70, 113]);
+        assert_eq!(packet[18..22], [0, 0, 0, 4]);
+        assert_eq!(packet[22..38], [7; 16]);
+        // Framing layer.
+        assert_eq!(packet[38..40], [0x70, 91]);
+        assert_eq!(packet[40..44], [0, 0, 0, 2]);
+        assert_eq!(&packet[44..64], b"rpi-music-visualizer");
+        assert!(packet[64..108].iter().all(|&x| x == 0));
+        assert_eq!(packet[108], 100);
+        assert_eq!(packet[109..111], [0, 0]);
+        assert_eq!(packet[111], 1);
+        assert_eq!(packet[112], 0);
+        assert_eq!(packet[113..115], [0, 3]);
+        // DMP layer.
+        assert_eq!(packet[115..117], [0x70, 14]);
+        assert_eq!(packet[117..125], [0x02, 0xa1, 0, 0, 0, 1, 0, 4]);
+        assert_eq!(packet[125..], [0, 10, 20, 30]);
+    }
+
+    #[test]
+    fn builds_sync_packets() {
+        let packet = screen(None).sync_packet(0x0102);
+        assert_eq!(packet.len(), 49);
+        assert_eq!(packet[16..18], [0x70, 33]);
+        assert_eq!(packet[18..22], [0, 0, 0, 8]);
+        assert_eq!(packet[38..40], [0x70, 11]);
+        assert_eq!(packet[40..44], [0, 0, 0, 1]);
+        assert_eq!(packet[44], 1);
+        assert_eq!(packet[45..49], [1, 2, 0, 0]);
+    }
+
+    #[test]
+    fn synchronizes_universes_on_the_first() {
+        let mut screen = screen(None);
+        let packets = screen.packets(vec![(1, vec![0; 3]), (2, vec![0; 3]), (64000, vec![0; 3])]);
+        let destinations: Vec<SocketAddr> = packets.iter().map(|x| x.0).collect();
+        assert_eq!(destinations, vec!["239.255.0.1:5568".parse().unwrap(),
+                                      "239.255.0.2:5568".parse().unwrap(),
+                                      "239.255.0.1:5568".parse().unwrap()]);
+        // The data packets name the sync universe, and every packet takes the next sequence number.
+        assert_eq!(packets[0].1[109..112], [0, 1, 1]);
+        assert_eq!(packets[1].1[109..112], [0, 1, 2]);
+        assert_eq!(packets[2].1[44], 3);
+        assert_eq!(packets[2].1.len(), 49);
+    }
+
+    #[test]
+    fn sends_one_universe_unsynchronized() {
+        let receiver: SocketAddr = "192.168.1.20:5568".parse().unwrap();
+        let packets = screen(Some(receiver)).packets(vec![(5, vec![0; 3])]);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].0, receiver);
+        assert_eq!(packets[0].1[109..111], [0, 0]);
+    }
+}
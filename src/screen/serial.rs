@@ -0,0 +1,40 @@
+use libc;
+use std::fs::{File, OpenOptions};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+// Open a serial device for writing in raw mode, so data isn't changed on its way, like newlines
+// into carriage returns and newlines, at `baud` bits a second for devices on a real serial line
+// like RS-485. USB adapters ignore the speed, so they're left as they are with None.
+pub fn open(path: &str, baud: Option<u32>) -> Result<File, String> {
+    let speed = match baud {
+        None => None,
+        Some(9600) => Some(libc::B9600),
+        Some(19200) => Some(libc::B19200),
+        Some(38400) => Some(libc::B38400),
+        Some(57600) => Some(libc::B57600),
+        Some(115200) => Some(libc::B115200),
+        Some(230400) => Some(libc::B230400),
+        Some(x) => {
+            return Err(format!("unsupported speed {}, expected 9600, 19200, 38400, 57600, 115200 or 230400", x))
+        },
+    };
+    let file = OpenOptions::new().write(true).open(path)
+        .map_err(|e| format!("could not open {}: {}", path, e))?;
+
+    unsafe {
+        let mut termios: libc::termios = mem::zeroed();
+        if libc::tcgetattr(file.as_raw_fd(), &mut termios) < 0 {
+            return Err(format!("{} isn't a serial device", path));
+        }
+        libc::cfmakeraw(&mut termios);
+        if let Some(speed) = speed {
+            libc::cfsetspeed(&mut termios, speed);
+        }
+        if libc::tcsetattr(file.as_raw_fd(), libc::TCSANOW, &termios) < 0 {
+            return Err(format!("could not make {} raw", path));
+        }
+    }
+
+    Ok(file)
+}
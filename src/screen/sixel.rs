@@ -0,0 +1,134 @@
+use gfx;
+use screen;
+use std::io::{self, Write};
+
+// Colors are quantized to 6 levels of each channel.
+const LEVELS: usize = 6;
+
+// Draws the output in the terminal as a Sixel image, for terminals that support them like
+// xterm -ti vt340, mlterm, foot or WezTerm. It shows every pixel, unlike the terminal screen's
+// two per character, each `scale` times bigger in each direction.
+pub struct SixelScreen {
+    scale: usize,
+    cleared: bool,
+}
+
+impl SixelScreen {
+    pub fn new(scale: u32) -> SixelScreen {
+        SixelScreen {
+            scale: usize::max(1, scale as usize),
+            cleared: false,
+        }
+    }
+
+    fn draw(&mut self, pixels: &[u8], width: usize, height: usize) {
+        let mut frame = String::new();
+        if !self.cleared {
+            frame.push_str("\x1b[2J");
+            self.cleared = true;
+        }
+        // Redraw from the top left rather than scrolling.
+        frame.push_str("\x1b[H");
+
+        // Start the image, with square pixels, and define the palette in percentages.
+        frame.push_str(&format!("\x1bP0;1q\"1;1;{};{}", width * self.scale, height * self.scale));
+        for i in 0..LEVELS * LEVELS * LEVELS {
+            let percent = |level: usize| level * 100 / (LEVELS - 1);
+            let (r, g, b) = (i / (LEVELS * LEVELS), i / LEVELS % LEVELS, i % LEVELS);
+            frame.push_str(&format!("#{};2;{};{};{}", i, percent(r), percent(g), percent(b)));
+        }
+
+        // Palette entries of the scaled image, top to bottom.
+        let (scaled_width, scaled_height) = (width * self.scale, height * self.scale);
+        let quantize = |x: u8| (x as usize * (LEVELS - 1) + 127) / 255;
+        let color = |x: usize, y: usize| {
+            // Pixel rows are bottom to top.
+            let i = 3 * ((height - 1 - y / self.scale) * width + x / self.scale);
+            quantize(pixels[i]) * LEVELS * LEVELS + quantize(pixels[i + 1]) * LEVELS + quantize(pixels[i + 2])
+        };
+
+        // Each band of 6 rows is drawn a color at a time, each character setting the column's
+        // rows in that color.
+        let mut band = vec![0; scaled_width * 6];
+        for top in (0..scaled_height).step_by(6) {
+            let rows = usize::min(6, scaled_height - top);
+            let mut colors = Vec::new();
+            for row in 0..rows {
+                for x in 0..scaled_width {
+                    let c = color(x, top + row);
+                    band[row * scaled_width + x] = c;
+                    if !colors.contains(&c) {
+                        colors.push(c);
+                    }
+                }
+            }
+
+            for (i, &c) in colors.iter().enumerate() {
+                if i > 0 {
+                    // Back to the start of the band.
+                    frame.push('$');
+                }
+                frame.push_str(&format!("#{}", c));
+                let sixels = (0..scaled_width).map(|x| {
+                    let bits = (0..rows).filter(|row| band[row * scaled_width + x] == c)
+                        .fold(0, |bits, row| bits | 1 << row);
+                    (63 + bits) as u8 as char
+                });
+                push_run_length_encoded(&mut frame, sixels);
+            }
+            frame.push('-');
+        }
+        frame.push_str("\x1b\\");
+
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        // Nothing useful to do if the terminal went away.
+        let _ = stdout.write_all(frame.as_bytes()).and_then(|_| stdout.flush());
+    }
+}
+
+impl screen::Screen for SixelScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        self.draw(pixels, width as usize, height as usize);
+    }
+}
+
+// Runs of more than 3 of the same sixel are written as !count followed by it.
+fn push_run_length_encoded<I>(frame: &mut String, sixels: I) where I: Iterator<Item=char> {
+    let mut run: Option<(char, usize)> = None;
+    let push_run = |frame: &mut String, sixel: char, count: usize| {
+        if count > 3 {
+            frame.push_str(&format!("!{}{}", count, sixel));
+        } else {
+            for _ in 0..count {
+                frame.push(sixel);
+            }
+        }
+    };
+
+    for sixel in sixels {
+        run = match run {
+            Some((x, count)) if x == sixel => Some((x, count + 1)),
+            Some((x, count)) => {
+                push_run(frame, x, count);
+                Some((sixel, 1))
+            },
+            None => Some((sixel, 1)),
+        };
+    }
+    if let Some((x, count)) = run {
+        push_run(frame, x, count);
+    }
+}
@@ -0,0 +1,107 @@
+use audio;
+use gfx;
+use screen;
+use std::time::{Duration, Instant};
+
+// Sends a screen taking pixels at most `fps` frames a second, for displays that refresh slowly,
+// like e-paper or sign panels, which would otherwise catch whatever frame came last halfway
+// through an animation. Of the frames rendered since the last one was shown, it shows the one
+// that moved least from the frame before it, so each refresh is a settled snapshot rather than a
+// blur of motion. Screens drawing with GL are shown every frame.
+pub struct SlowScreen {
+    screen: Box<dyn screen::Screen>,
+    // Between frames shown, or 0 to show every frame.
+    interval: Duration,
+    last_shown: Option<Instant>,
+    // The frame before, to tell how much each one moved.
+    previous: (Vec<u8>, i32, i32),
+    // The stillest frame since the last one shown, and how much it moved.
+    stillest: Option<(Vec<u8>, i32, i32)>,
+    stillest_motion: u64,
+}
+
+impl SlowScreen {
+    pub fn new(screen: Box<dyn screen::Screen>, fps: f32) -> SlowScreen {
+        SlowScreen {
+            screen,
+            interval: if fps > 0.0 { Duration::from_secs_f32(1.0 / fps) } else { Duration::from_secs(0) },
+            last_shown: None,
+            previous: (Vec::new(), 0, 0),
+            stillest: None,
+            stillest_motion: 0,
+        }
+    }
+
+    // Whether the frame rate asked for can't be kept to, for screens drawing with GL.
+    pub fn is_ignored(&self) -> bool {
+        !self.screen.renders_pixels() && self.interval > Duration::from_secs(0)
+    }
+}
+
+impl screen::Screen for SlowScreen {
+    fn setup(&mut self, gl: &gfx::gl::Gl) -> Result<(), String> {
+        self.screen.setup(gl)
+    }
+
+    fn render_from_texture(&mut self, gl: &gfx::gl::Gl, texture: u32, width: i32, height: i32) {
+        self.screen.render_from_texture(gl, texture, width, height);
+    }
+
+    fn uses_window(&self) -> bool {
+        self.screen.uses_window()
+    }
+
+    fn is_linear(&self) -> bool {
+        self.screen.is_linear()
+    }
+
+    fn renders_pixels(&self) -> bool {
+        self.screen.renders_pixels()
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        if self.interval == Duration::from_secs(0) {
+            return self.screen.render_from_pixels(pixels, width, height);
+        }
+
+        // A frame of a new size can't be compared, so it's taken to move most.
+        let motion = if (self.previous.1, self.previous.2) == (width, height) {
+            pixels.iter().zip(self.previous.0.iter()).map(|(&x, &y)| (x as i32 - y as i32).unsigned_abs() as u64).sum()
+        } else {
+            u64::MAX
+        };
+        self.previous.0.clear();
+        self.previous.0.extend_from_slice(pixels);
+        self.previous.1 = width;
+        self.previous.2 = height;
+        if self.stillest.is_none() || motion <= self.stillest_motion {
+            self.stillest = Some((pixels.to_vec(), width, height));
+            self.stillest_motion = motion;
+        }
+
+        let now = Instant::now();
+        if self.last_shown.is_some_and(|x| now < x + self.interval) {
+            return;
+        }
+        if let Some((pixels, width, height)) = self.stillest.take() {
+            self.screen.render_from_pixels(&pixels, width, height);
+            self.last_shown = Some(now);
+        }
+    }
+
+    fn update_audio(&mut self, audio_frame: &audio::AudioFrame) {
+        self.screen.update_audio(audio_frame);
+    }
+
+    fn set_output_gamma(&mut self, gamma: f32) {
+        self.screen.set_output_gamma(gamma);
+    }
+
+    fn set_latency(&mut self, latency: Duration) {
+        self.screen.set_latency(latency);
+    }
+
+    fn set_palette(&mut self, palette: Option<&screen::Palette>) {
+        self.screen.set_palette(palette);
+    }
+}
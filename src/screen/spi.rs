@@ -0,0 +1,19 @@
+use libc;
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+
+// _IOW('k', 4, u32), from linux/spi/spidev.h.
+const SPI_IOC_WR_MAX_SPEED_HZ: u32 = 0x4004_6b04;
+
+// Open the SPI `device` for writing, clocked at `hz`. The Pi's SPI0, with MOSI on GPIO 10 and the
+// clock on GPIO 11, is /dev/spidev0.0.
+pub fn open(device: &str, hz: u32) -> Result<File, String> {
+    let spi = OpenOptions::new().write(true).open(device)
+        .map_err(|e| format!("could not open {} (is SPI enabled?): {}", device, e))?;
+
+    if unsafe { libc::ioctl(spi.as_raw_fd(), SPI_IOC_WR_MAX_SPEED_HZ as _, &hz) } < 0 {
+        return Err(format!("could not set the SPI speed to {} Hz", hz));
+    }
+
+    Ok(spi)
+}
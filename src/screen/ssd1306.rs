@@ -0,0 +1,133 @@
+use gfx;
+use libc;
+use screen;
+use screen::mono::{self, Conversion};
+use screen::reconnect::Reconnecting;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::sync::mpsc;
+use std::thread;
+
+const WIDTH: i32 = 128;
+const HEIGHT: i32 = 64;
+const PAGES: usize = HEIGHT as usize / 8;
+// From linux/i2c-dev.h.
+const I2C_SLAVE: u32 = 0x0703;
+// Prefixes every write, saying whether commands or display data follow.
+const COMMANDS: u8 = 0x00;
+const DATA: u8 = 0x40;
+const INIT_COMMANDS: [u8; 25] = [
+    0xae,       // Display off.
+    0xd5, 0x80, // Default clock.
+    0xa8, 0x3f, // 64 rows.
+    0xd3, 0x00, // No vertical offset.
+    0x40,       // Start at the first row.
+    0x8d, 0x14, // Charge pump on, for modules without an external supply.
+    0x20, 0x00, // Horizontal addressing, page after page.
+    0xa1,       // Columns left to right.
+    0xc8,       // Rows top to bottom.
+    0xda, 0x12, // Alternative row wiring, as 128x64 modules have.
+    0x81, 0xcf, // Contrast.
+    0xd9, 0xf1, // Precharge for the charge pump.
+    0xdb, 0x40, // Deselect voltage.
+    0xa4,       // Show the display memory.
+    0xa6,       // Not inverted.
+    0xaf,       // Display on.
+];
+// The whole display, columns 0 to 127 and pages 0 to 7.
+const WINDOW_COMMANDS: [u8; 6] = [0x21, 0x00, 0x7f, 0x22, 0x00, 0x07];
+// Drives an SSD1306 128x64 monochrome OLED over I2C, showing the output's brightness as lit and
+// dark pixels. The output is scaled to the display, so --width 128 --height 64 shows it exactly.
+pub struct Ssd1306Screen {
+    conversion: Conversion,
+    threshold: u8,
+    commands_tx: mpsc::SyncSender<[u8; PAGES * WIDTH as usize]>,
+}
+
+impl Ssd1306Screen {
+    // `address` is the display's 7-bit I2C address, usually 0x3c.
+    pub fn new(device: &str, address: u16, conversion: &str, threshold: u8) -> Result<Ssd1306Screen, String> {
+        let conversion = Conversion::parse(conversion)?;
+        let display = open_display(device, address)?;
+
+        let (commands_tx, commands_rx) = mpsc::sync_channel::<[u8; PAGES * WIDTH as usize]>(1);
+        let device = device.to_string();
+        thread::spawn(move || {
+            // Displays that lost power come back blank, and are set up again.
+            let mut connection = Reconnecting::new("the OLED".to_string(), Some(display),
+                                                   || open_display(&device, address));
+            for pages in commands_rx {
+                let mut data = Vec::with_capacity(1 + pages.len());
+                data.push(DATA);
+                data.extend_from_slice(&pages);
+                connection.send(|display| {
+                    write_commands(display, &WINDOW_COMMANDS).and_then(|_| display.write_all(&data))
+                        .map_err(|e| e.to_string())
+                });
+            }
+        });
+
+        Ok(Ssd1306Screen {
+            conversion,
+            threshold,
+            commands_tx,
+        })
+    }
+}
+
+impl screen::Screen for Ssd1306Screen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let lit = mono::lit_pixels(pixels, width, height, WIDTH, HEIGHT, self.conversion, self.threshold);
+
+        // Each byte is a column of 8 pixels of a page, the top one in the lowest bit.
+        let mut pages = [0; PAGES * WIDTH as usize];
+        for (i, byte) in pages.iter_mut().enumerate() {
+            let (page, x) = (i / WIDTH as usize, i % WIDTH as usize);
+            for bit in 0..8 {
+                if lit[(page * 8 + bit) * WIDTH as usize + x] {
+                    *byte |= 1 << bit;
+                }
+            }
+        }
+
+        // Frames are dropped while the last one is still being sent, or while reconnecting.
+        let _ = self.commands_tx.try_send(pages);
+    }
+}
+
+fn open_i2c(device: &str, address: u16) -> Result<File, String> {
+    let file = OpenOptions::new().read(true).write(true).open(device)
+        .map_err(|e| format!("could not open {} (is I2C enabled?): {}", device, e))?;
+    if unsafe { libc::ioctl(file.as_raw_fd(), I2C_SLAVE as _, address as libc::c_ulong) } < 0 {
+        return Err(format!("could not talk to I2C address {:#x}", address));
+    }
+
+    Ok(file)
+}
+
+// The display at `address` on the I2C bus `device`, set up and switched on.
+fn open_display(device: &str, address: u16) -> Result<File, String> {
+    let mut display = open_i2c(device, address)?;
+    write_commands(&mut display, &INIT_COMMANDS).map_err(|e| format!("could not set up the display, {}", e))?;
+    Ok(display)
+}
+
+fn write_commands(display: &mut File, commands: &[u8]) -> io::Result<()> {
+    let mut data = Vec::with_capacity(1 + commands.len());
+    data.push(COMMANDS);
+    data.extend_from_slice(commands);
+    display.write_all(&data)
+}
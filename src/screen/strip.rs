@@ -0,0 +1,161 @@
+use audio;
+use gfx;
+use led_mapper;
+use screen;
+use std::time::Duration;
+
+// How much of its loudest a band's peak keeps each audio update, so a quiet song still fills
+// the strip after a loud one.
+const PEAK_DECAY: f32 = 0.995;
+// The bands' edges grow evenly on a log scale from the lowest to this.
+const HIGHEST_HZ: f32 = 16000.0;
+const LOWEST_HZ: f32 = 100.0;
+
+// How a frame is collapsed to a strip.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StripMode {
+    // The LEDs show the pixels along a line through the frame, from (x0, y0) to (x1, y1), both
+    // from 0 to 1 across and down from the top left.
+    Line(f32, f32, f32, f32),
+    // The strip is split into this many lengths, bass at the start to treble at the end. Each
+    // LED shows the frame's columns above it averaged, dimmed to how loud its length's band is.
+    Bands(usize),
+}
+
+impl StripMode {
+    // `line` is used by the line mode and `bands` by the bands mode.
+    pub fn parse(name: &str, line: &[f32], bands: usize) -> Result<StripMode, String> {
+        match name {
+            "line" => match *line {
+                [x0, y0, x1, y1] => Ok(StripMode::Line(x0, y0, x1, y1)),
+                _ => Err("the line mode needs --strip_line to be x0,y0,x1,y1".to_string()),
+            },
+            "bands" if bands > 0 => Ok(StripMode::Bands(bands)),
+            "bands" => Err("the bands mode needs at least 1 of --strip_bands".to_string()),
+            _ => Err(format!("unknown strip mode '{}', expected one of: line, bands", name)),
+        }
+    }
+}
+
+// Collapses the frames a screen taking pixels is sent into one row of `leds` pixels, for a single
+// strip, like behind a TV, rather than a matrix. Screens drawing with GL are shown the output as
+// it is.
+pub struct StripScreen {
+    screen: Box<dyn screen::Screen>,
+    leds: usize,
+    mode: StripMode,
+    // For the bands mode, each band and how loud it is, from 0 to its recent peak at 1.
+    bands: Vec<audio::FrequencyBand>,
+    levels: Vec<f32>,
+    peaks: Vec<f32>,
+    pixels: Vec<u8>,
+}
+
+impl StripScreen {
+    pub fn new(screen: Box<dyn screen::Screen>, leds: usize, mode: StripMode) -> StripScreen {
+        let bands = match mode {
+            StripMode::Bands(count) => frequency_bands(usize::min(count, leds)),
+            StripMode::Line(..) => Vec::new(),
+        };
+
+        StripScreen {
+            screen,
+            leds,
+            mode,
+            levels: vec![0.0; bands.len()],
+            peaks: vec![0.0; bands.len()],
+            bands,
+            pixels: Vec::new(),
+        }
+    }
+
+    // Whether the strip asked for can't be made, for screens drawing with GL.
+    pub fn is_ignored(&self) -> bool {
+        !self.screen.renders_pixels()
+    }
+}
+
+impl screen::Screen for StripScreen {
+    fn setup(&mut self, gl: &gfx::gl::Gl) -> Result<(), String> {
+        self.screen.setup(gl)
+    }
+
+    fn render_from_texture(&mut self, gl: &gfx::gl::Gl, texture: u32, width: i32, height: i32) {
+        self.screen.render_from_texture(gl, texture, width, height);
+    }
+
+    fn uses_window(&self) -> bool {
+        self.screen.uses_window()
+    }
+
+    fn is_linear(&self) -> bool {
+        self.screen.is_linear()
+    }
+
+    fn renders_pixels(&self) -> bool {
+        self.screen.renders_pixels()
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let (width, height) = (width as usize, height as usize);
+        self.pixels.clear();
+        for led in 0..self.leds {
+            let (r, g, b) = match self.mode {
+                StripMode::Line(x0, y0, x1, y1) => {
+                    let t = if self.leds > 1 { led as f32 / (self.leds - 1) as f32 } else { 0.5 };
+                    let x = x0 + t * (x1 - x0);
+                    let y = y0 + t * (y1 - y0);
+                    let column = usize::min(width - 1, f32::max(0.0, x * width as f32) as usize);
+                    // Pixel rows are bottom to top.
+                    let row = height - 1 - usize::min(height - 1, f32::max(0.0, y * height as f32) as usize);
+                    let i = 3 * (row * width + column);
+                    (pixels[i], pixels[i + 1], pixels[i + 2])
+                },
+                StripMode::Bands(_) => {
+                    let (start, end) = (led * width / self.leds, (led + 1) * width / self.leds);
+                    let (r, g, b) = led_mapper::led_matrix_mapper::average_columns(pixels, width, height, start, end);
+                    let level = self.levels[led * self.levels.len() / self.leds];
+                    let dim = |x: u8| (x as f32 * level + 0.5) as u8;
+                    (dim(r), dim(g), dim(b))
+                },
+            };
+            self.pixels.extend_from_slice(&[r, g, b]);
+        }
+
+        self.screen.render_from_pixels(&self.pixels, self.leds as i32, 1);
+    }
+
+    fn update_audio(&mut self, audio_frame: &audio::AudioFrame) {
+        for (i, &band) in self.bands.iter().enumerate() {
+            let power = if audio_frame.is_silent { 0.0 } else { audio_frame.band_power(band) };
+            self.peaks[i] = f32::max(power, self.peaks[i] * PEAK_DECAY);
+            self.levels[i] = if self.peaks[i] > 0.0 { f32::min(1.0, power / self.peaks[i]) } else { 0.0 };
+        }
+        self.screen.update_audio(audio_frame);
+    }
+
+    fn set_output_gamma(&mut self, gamma: f32) {
+        self.screen.set_output_gamma(gamma);
+    }
+
+    fn set_latency(&mut self, latency: Duration) {
+        self.screen.set_latency(latency);
+    }
+
+    fn set_palette(&mut self, palette: Option<&screen::Palette>) {
+        self.screen.set_palette(palette);
+    }
+}
+
+// `count` bands from the bass up, each at least one of the spectrum's 100 Hz buckets wide.
+fn frequency_bands(count: usize) -> Vec<audio::FrequencyBand> {
+    let mut bands = Vec::with_capacity(count);
+    let mut low_hz = 0.0;
+    for i in 1..count + 1 {
+        let edge = LOWEST_HZ * (HIGHEST_HZ / LOWEST_HZ).powf(i as f32 / count as f32);
+        let high_hz = f32::max(low_hz + 100.0, (edge / 100.0).round() * 100.0);
+        bands.push(audio::FrequencyBand::new(low_hz, high_hz));
+        low_hz = high_hz;
+    }
+    bands
+}
@@ -0,0 +1,74 @@
+use gfx;
+use screen;
+use std::io::{self, Write};
+
+// Draws the output in the terminal that started the visualizer, two pixels per character with
+// 24-bit color escapes, e.g. to check a headless Pi over SSH. Best with a small --size.
+pub struct TerminalScreen {
+    cleared: bool,
+}
+
+impl TerminalScreen {
+    pub fn new() -> TerminalScreen {
+        TerminalScreen { cleared: false }
+    }
+
+    fn draw(&mut self, pixels: &[u8], width: usize, height: usize) {
+        let mut frame = String::new();
+        if !self.cleared {
+            frame.push_str("\x1b[2J");
+            self.cleared = true;
+        }
+        // Redraw from the top left rather than scrolling.
+        frame.push_str("\x1b[H");
+
+        let rgb = |column: usize, row: usize| {
+            let i = 3 * (row * width + column);
+            (pixels[i], pixels[i + 1], pixels[i + 2])
+        };
+
+        // Rows are bottom to top, and each character shows a pair of them: the upper half block
+        // in the top pixel's color over the bottom pixel's color.
+        let mut row = height;
+        while row > 0 {
+            let top = row - 1;
+            for column in 0..width {
+                let (r, g, b) = rgb(column, top);
+                frame.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b));
+                if top > 0 {
+                    let (r, g, b) = rgb(column, top - 1);
+                    frame.push_str(&format!("\x1b[48;2;{};{};{}m", r, g, b));
+                } else {
+                    // An odd row out, over the terminal's own background.
+                    frame.push_str("\x1b[49m");
+                }
+                frame.push('\u{2580}');
+            }
+            frame.push_str("\x1b[0m\n");
+            row = row.saturating_sub(2);
+        }
+
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        // Nothing useful to do if the terminal went away.
+        let _ = stdout.write_all(frame.as_bytes()).and_then(|_| stdout.flush());
+    }
+}
+
+impl screen::Screen for TerminalScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        self.draw(pixels, width as usize, height as usize);
+    }
+}
@@ -0,0 +1,241 @@
+use gfx;
+use led_mapper;
+use rppal::gpio::{Gpio, Level, Mode};
+use screen;
+use screen::reconnect::Reconnecting;
+use screen::spi;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// The most spidev takes in one write by default.
+const MAX_WRITE: usize = 4096;
+const SWRESET: u8 = 0x01;
+const SLPOUT: u8 = 0x11;
+const DISPON: u8 = 0x29;
+const CASET: u8 = 0x2a;
+const RASET: u8 = 0x2b;
+const RAMWR: u8 = 0x2c;
+const MADCTL: u8 = 0x36;
+const COLMOD: u8 = 0x3a;
+// 16 bits per pixel, as RGB565.
+const COLMOD_16_BIT: u8 = 0x55;
+// MADCTL's row and column order, row and column exchange, and blue-green-red bits.
+const MADCTL_MY: u8 = 0x80;
+const MADCTL_MX: u8 = 0x40;
+const MADCTL_MV: u8 = 0x20;
+const MADCTL_BGR: u8 = 0x08;
+
+#[derive(Clone, Copy)]
+enum Model {
+    // 128x160.
+    St7735,
+    // 240x320.
+    Ili9341,
+}
+
+impl Model {
+    // Width and height, upright.
+    fn size(&self) -> (i32, i32) {
+        match *self {
+            Model::St7735 => (128, 160),
+            Model::Ili9341 => (240, 320),
+        }
+    }
+
+    // The memory access control turning the picture clockwise by `quarter_turns`.
+    fn madctl(&self, quarter_turns: u32, swap_red_blue: bool) -> u8 {
+        let (order, bgr) = match *self {
+            Model::St7735 => ([MADCTL_MX | MADCTL_MY, MADCTL_MY | MADCTL_MV, 0, MADCTL_MX | MADCTL_MV], false),
+            Model::Ili9341 => ([MADCTL_MX, MADCTL_MV, MADCTL_MY, MADCTL_MX | MADCTL_MY | MADCTL_MV], true),
+        };
+        order[quarter_turns as usize % 4] | if bgr != swap_red_blue { MADCTL_BGR } else { 0 }
+    }
+}
+
+// Drives an ST7735 or ILI9341 TFT on SPI, usually the Pi's SPI0, with its data/command and reset lines on
+// GPIO pins, scaling the output to it. Frames are sent as RGB565, and only the rectangle that
+// changed since the last frame is sent, which keeps mostly still visualizers fast over SPI. It's
+// turnable by `rotation` degrees clockwise. Most take --spi_hz as high as 32000000.
+pub struct TftScreen {
+    width: i32,
+    height: i32,
+    frames_tx: mpsc::SyncSender<Vec<u16>>,
+}
+
+impl TftScreen {
+    pub fn new(model: &str,
+               spi_device: &str,
+               spi_hz: u32,
+               data_command_pin: u8,
+               reset_pin: Option<u8>,
+               rotation: u32,
+               swap_red_blue: bool) -> Result<TftScreen, String> {
+        let model = match model {
+            "st7735" => Model::St7735,
+            "ili9341" => Model::Ili9341,
+            _ => return Err(format!("unknown TFT {}, expected st7735 or ili9341", model)),
+        };
+        let quarter_turns = rotation / 90 % 4;
+        let (width, height) = match (model.size(), quarter_turns % 2) {
+            ((width, height), 0) => (width, height),
+            ((width, height), _) => (height, width),
+        };
+
+        let madctl = model.madctl(quarter_turns, swap_red_blue);
+        let spi_device = spi_device.to_string();
+        let open = move || {
+            let mut display = Display::open(&spi_device, spi_hz, data_command_pin, reset_pin)?;
+            display.init(madctl).map_err(|e| format!("could not set up the TFT, {}", e))?;
+            Ok(display)
+        };
+        let display = open()?;
+
+        let (frames_tx, frames_rx) = mpsc::sync_channel::<Vec<u16>>(1);
+        thread::spawn(move || {
+            let mut connection = Reconnecting::new("the TFT".to_string(), Some(display), open);
+            let mut shown: Vec<u16> = Vec::new();
+            for frame in frames_rx {
+                let changed = match changed_rectangle(&shown, &frame, width) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                if connection.send(|display| display.draw(&frame, width, changed).map_err(|e| e.to_string())) {
+                    shown = frame;
+                } else {
+                    // What it shows after reconnecting isn't known, so it's drawn whole.
+                    shown.clear();
+                }
+            }
+        });
+
+        Ok(TftScreen {
+            width,
+            height,
+            frames_tx,
+        })
+    }
+}
+
+impl screen::Screen for TftScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let frame = led_mapper::led_matrix_mapper::sample_pixels(pixels, width, height, self.width, self.height)
+            .iter()
+            .map(|&(r, g, b)| (r as u16 >> 3) << 11 | (g as u16 >> 2) << 5 | b as u16 >> 3)
+            .collect();
+
+        // Frames are dropped while the last one is still being sent, or while reconnecting.
+        let _ = self.frames_tx.try_send(frame);
+    }
+}
+
+struct Display {
+    spi: File,
+    gpio: Gpio,
+    data_command_pin: u8,
+    reset_pin: Option<u8>,
+}
+
+impl Display {
+    fn open(spi_device: &str, spi_hz: u32, data_command_pin: u8, reset_pin: Option<u8>) -> Result<Display, String> {
+        let spi = spi::open(spi_device, spi_hz)?;
+        let mut gpio = Gpio::new().map_err(|e| format!("could not open the GPIO: {}", e))?;
+        gpio.set_mode(data_command_pin, Mode::Output);
+        if let Some(pin) = reset_pin {
+            gpio.set_mode(pin, Mode::Output);
+        }
+
+        Ok(Display {
+            spi,
+            gpio,
+            data_command_pin,
+            reset_pin,
+        })
+    }
+
+    fn init(&mut self, madctl: u8) -> io::Result<()> {
+        if let Some(pin) = self.reset_pin {
+            self.gpio.write(pin, Level::Low);
+            thread::sleep(Duration::from_millis(10));
+            self.gpio.write(pin, Level::High);
+            thread::sleep(Duration::from_millis(120));
+        }
+
+        self.command(SWRESET, &[])?;
+        thread::sleep(Duration::from_millis(150));
+        self.command(SLPOUT, &[])?;
+        thread::sleep(Duration::from_millis(120));
+        self.command(COLMOD, &[COLMOD_16_BIT])?;
+        self.command(MADCTL, &[madctl])?;
+        self.command(DISPON, &[])?;
+        thread::sleep(Duration::from_millis(20));
+        Ok(())
+    }
+
+    // Send the (x, y, width, height) rectangle of a `frame_width` wide frame.
+    fn draw(&mut self, frame: &[u16], frame_width: i32, rectangle: (i32, i32, i32, i32)) -> io::Result<()> {
+        let (x, y, width, height) = rectangle;
+        let (right, bottom) = (x + width - 1, y + height - 1);
+        self.command(CASET, &[(x >> 8) as u8, x as u8, (right >> 8) as u8, right as u8])?;
+        self.command(RASET, &[(y >> 8) as u8, y as u8, (bottom >> 8) as u8, bottom as u8])?;
+
+        let mut data = Vec::with_capacity(2 * (width * height) as usize);
+        for row in y..=bottom {
+            for column in x..=right {
+                let color = frame[(row * frame_width + column) as usize];
+                data.extend_from_slice(&[(color >> 8) as u8, color as u8]);
+            }
+        }
+        self.command(RAMWR, &data)
+    }
+
+    fn command(&mut self, command: u8, data: &[u8]) -> io::Result<()> {
+        self.gpio.write(self.data_command_pin, Level::Low);
+        self.spi.write_all(&[command])?;
+        self.gpio.write(self.data_command_pin, Level::High);
+        for chunk in data.chunks(MAX_WRITE) {
+            self.spi.write_all(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+// The smallest (x, y, width, height) rectangle holding every pixel that changed between two
+// `width` wide frames, all of the new one if there wasn't an old one, or None if none changed.
+fn changed_rectangle(old: &[u16], new: &[u16], width: i32) -> Option<(i32, i32, i32, i32)> {
+    let height = new.len() as i32 / width;
+    if old.len() != new.len() {
+        return Some((0, 0, width, height));
+    }
+
+    let (mut left, mut top, mut right, mut bottom) = (width, height, -1, -1);
+    for (i, (a, b)) in old.iter().zip(new.iter()).enumerate() {
+        if a != b {
+            let (x, y) = (i as i32 % width, i as i32 / width);
+            left = i32::min(left, x);
+            right = i32::max(right, x);
+            top = i32::min(top, y);
+            bottom = i32::max(bottom, y);
+        }
+    }
+
+    if right < 0 {
+        None
+    } else {
+        Some((left, top, right - left + 1, bottom - top + 1))
+    }
+}
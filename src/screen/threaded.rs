@@ -0,0 +1,116 @@
+use audio;
+use gfx;
+use screen;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// What the render loop hands the screen's thread, in order.
+enum Message {
+    Frame(Vec<u8>, i32, i32),
+    // The latest audio frame is waiting.
+    Audio,
+    OutputGamma(f32),
+    Latency(Duration),
+}
+
+// Runs a screen taking pixels on its own thread, for screens that write to a slow sink in
+// `render_from_pixels` themselves, like a terminal over SSH. The render loop only copies each
+// frame and carries on, and frames are dropped while the last one is still being shown. Screens
+// that hand their frames to a thread of their own, as the LED and network screens do, don't
+// need it. Screens on a thread aren't set up with GL.
+pub struct ThreadedScreen {
+    is_linear: bool,
+    messages_tx: mpsc::Sender<Message>,
+    // Set while a frame is waiting for or being shown by the thread.
+    frame_pending: Arc<AtomicBool>,
+    // The audio frame the thread's still to take, replaced by newer ones so audio doesn't pile
+    // up while it's showing a frame.
+    pending_audio: Arc<Mutex<Option<audio::AudioFrame>>>,
+}
+
+impl ThreadedScreen {
+    pub fn new<S>(mut screen: S) -> ThreadedScreen where S: screen::Screen + Send + 'static {
+        let is_linear = screen.is_linear();
+        let frame_pending = Arc::new(AtomicBool::new(false));
+        let (messages_tx, messages_rx) = mpsc::channel::<Message>();
+        let pending = frame_pending.clone();
+        let pending_audio = Arc::new(Mutex::new(None));
+        let audio = pending_audio.clone();
+        thread::spawn(move || {
+            for message in messages_rx {
+                match message {
+                    Message::Frame(pixels, width, height) => {
+                        screen.render_from_pixels(&pixels, width, height);
+                        pending.store(false, Ordering::Release);
+                    },
+                    Message::Audio => {
+                        let audio_frame = audio.lock().unwrap().take();
+                        if let Some(audio_frame) = audio_frame {
+                            screen.update_audio(&audio_frame);
+                        }
+                    },
+                    Message::OutputGamma(gamma) => screen.set_output_gamma(gamma),
+                    Message::Latency(latency) => screen.set_latency(latency),
+                }
+            }
+        });
+
+        ThreadedScreen {
+            is_linear,
+            messages_tx,
+            frame_pending,
+            pending_audio,
+        }
+    }
+
+    // Panics if the screen failed on its thread, so it's left out like one failing in the render
+    // loop.
+    fn send(&self, message: Message) {
+        if self.messages_tx.send(message).is_err() {
+            panic!("the screen's thread stopped");
+        }
+    }
+}
+
+impl screen::Screen for ThreadedScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn is_linear(&self) -> bool {
+        self.is_linear
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        if self.frame_pending.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.send(Message::Frame(pixels.to_vec(), width, height));
+    }
+
+    fn update_audio(&mut self, audio_frame: &audio::AudioFrame) {
+        let was_pending = self.pending_audio.lock().unwrap().replace(audio_frame.clone()).is_some();
+        if !was_pending {
+            self.send(Message::Audio);
+        }
+    }
+
+    fn set_output_gamma(&mut self, gamma: f32) {
+        self.send(Message::OutputGamma(gamma));
+    }
+
+    fn set_latency(&mut self, latency: Duration) {
+        self.send(Message::Latency(latency));
+    }
+}
@@ -0,0 +1,120 @@
+use gfx;
+use led_mapper;
+use screen;
+use screen::reconnect::Reconnecting;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::thread;
+
+const PORT: u16 = 65506;
+const START: u8 = 0x9c;
+const DATA_FRAME: u8 = 0xda;
+const END: u8 = 0x36;
+// As many bytes as a packet's 16-bit size has room for, in whole LEDs.
+const MAX_PACKET_SIZE: usize = 0xffff / 3 * 3;
+// Packets are numbered with a byte, from 1.
+const MAX_PACKETS: usize = 255;
+
+// Sends the output to a TPM2.net controller over UDP, as the RGB of each LED of `panel_layout`,
+// or of `pixel_map` when there is one, in `color_order`. Frames are split into numbered packets of
+// up to `packet_size` bytes, rounded down to whole LEDs, for controllers that take a universe, a
+// strip or an MTU's worth at a time, or sent whole when it's 0.
+pub struct Tpm2Screen {
+    panel_layout: led_mapper::PanelLayout,
+    pixel_map: Option<led_mapper::PixelMap>,
+    color_order: led_mapper::ColorOrder,
+    packet_size: usize,
+    packets_tx: mpsc::SyncSender<Vec<Vec<u8>>>,
+}
+
+impl Tpm2Screen {
+    pub fn new(address: &str,
+               packet_size: usize,
+               panel_layout: led_mapper::PanelLayout,
+               pixel_map: Option<led_mapper::PixelMap>,
+               color_order: led_mapper::ColorOrder) -> Result<Tpm2Screen, String> {
+        let destination = resolve(address)?;
+        let packet_size = match packet_size {
+            0 => MAX_PACKET_SIZE,
+            x if x < 3 => return Err(format!("TPM2.net packets of {} bytes can't hold an LED", x)),
+            x => usize::min(x / 3 * 3, MAX_PACKET_SIZE),
+        };
+
+        let socket = open_socket()?;
+        let (packets_tx, packets_rx) = mpsc::sync_channel::<Vec<Vec<u8>>>(1);
+        thread::spawn(move || {
+            let mut connection = Reconnecting::new(format!("the TPM2.net controller at {}", destination),
+                                                   Some(socket),
+                                                   open_socket);
+            for packets in packets_rx {
+                connection.send(|socket| {
+                    for packet in packets.iter() {
+                        socket.send_to(packet, destination).map_err(|e| e.to_string())?;
+                    }
+                    Ok(())
+                });
+            }
+        });
+
+        Ok(Tpm2Screen {
+            panel_layout,
+            pixel_map,
+            color_order,
+            packet_size,
+            packets_tx,
+        })
+    }
+}
+
+impl screen::Screen for Tpm2Screen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let pixel_colors = match self.pixel_map {
+            Some(ref map) => map.sample(pixels, width, height),
+            None => self.panel_layout.map(pixels, width, height),
+        };
+        let mut data = Vec::with_capacity(3 * pixel_colors.len());
+        for &(r, g, b) in pixel_colors.iter() {
+            data.extend_from_slice(&self.color_order.channels(r, g, b));
+        }
+        // LEDs past the last packet aren't sent.
+        data.truncate(MAX_PACKETS * self.packet_size);
+
+        let total = data.len().div_ceil(self.packet_size);
+        let packets = data.chunks(self.packet_size).enumerate().map(|(i, chunk)| {
+            let mut packet = Vec::with_capacity(7 + chunk.len());
+            packet.extend_from_slice(&[START, DATA_FRAME, (chunk.len() >> 8) as u8, chunk.len() as u8]);
+            packet.extend_from_slice(&[(i + 1) as u8, total as u8]);
+            packet.extend_from_slice(chunk);
+            packet.push(END);
+            packet
+        }).collect();
+
+        // Frames are dropped while the last one is still being sent, or while reconnecting.
+        let _ = self.packets_tx.try_send(packets);
+    }
+}
+
+// `address`, with the TPM2.net port unless it has one.
+fn resolve(address: &str) -> Result<SocketAddr, String> {
+    let with_port = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, PORT) };
+    with_port.to_socket_addrs()
+        .map_err(|e| format!("invalid TPM2.net address {}: {}", address, e))?
+        .next()
+        .ok_or_else(|| format!("could not resolve {}", address))
+}
+
+fn open_socket() -> Result<UdpSocket, String> {
+    UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("could not open a UDP socket: {}", e))
+}
@@ -0,0 +1,111 @@
+use audio;
+use gfx;
+use screen;
+use std::time::Duration;
+
+// Turns and mirrors the frames a screen taking pixels is sent, for panels mounted on their side,
+// upside down or seen from behind. They're flipped first, `horizontal` swapping left and right
+// and `vertical` top and bottom, then turned clockwise by `degrees`, rounded down to a quarter
+// turn. Screens drawing with GL are shown the output as it is.
+pub struct TransformedScreen {
+    screen: Box<dyn screen::Screen>,
+    quarter_turns: u32,
+    horizontal: bool,
+    vertical: bool,
+    pixels: Vec<u8>,
+}
+
+impl TransformedScreen {
+    // `flip` is none, horizontal, vertical or both.
+    pub fn new(screen: Box<dyn screen::Screen>, degrees: u32, flip: &str) -> Result<TransformedScreen, String> {
+        let (horizontal, vertical) = match flip {
+            "" | "none" => (false, false),
+            "horizontal" => (true, false),
+            "vertical" => (false, true),
+            "both" => (true, true),
+            _ => return Err(format!("unknown flip '{}', expected one of: none, horizontal, vertical, both", flip)),
+        };
+
+        Ok(TransformedScreen {
+            screen,
+            quarter_turns: degrees / 90 % 4,
+            horizontal,
+            vertical,
+            pixels: Vec::new(),
+        })
+    }
+
+    fn is_identity(&self) -> bool {
+        self.quarter_turns == 0 && !self.horizontal && !self.vertical
+    }
+
+    // Whether the transform asked for can't be made, for screens drawing with GL.
+    pub fn is_ignored(&self) -> bool {
+        !self.screen.renders_pixels() && !self.is_identity()
+    }
+}
+
+impl screen::Screen for TransformedScreen {
+    fn setup(&mut self, gl: &gfx::gl::Gl) -> Result<(), String> {
+        self.screen.setup(gl)
+    }
+
+    fn render_from_texture(&mut self, gl: &gfx::gl::Gl, texture: u32, width: i32, height: i32) {
+        self.screen.render_from_texture(gl, texture, width, height);
+    }
+
+    fn uses_window(&self) -> bool {
+        self.screen.uses_window()
+    }
+
+    fn is_linear(&self) -> bool {
+        self.screen.is_linear()
+    }
+
+    fn renders_pixels(&self) -> bool {
+        self.screen.renders_pixels()
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        if self.is_identity() {
+            return self.screen.render_from_pixels(pixels, width, height);
+        }
+
+        let (turned_width, turned_height) = if self.quarter_turns.is_multiple_of(2) { (width, height) } else { (height, width) };
+        self.pixels.clear();
+        self.pixels.reserve(pixels.len());
+        // Rows are bottom to top, but turned from the top left like the LED mappers.
+        for turned_row in (0..turned_height).rev() {
+            for turned_column in 0..turned_width {
+                let (column, row) = match self.quarter_turns {
+                    1 => (turned_row, height - 1 - turned_column),
+                    2 => (width - 1 - turned_column, height - 1 - turned_row),
+                    3 => (width - 1 - turned_row, turned_column),
+                    _ => (turned_column, turned_row),
+                };
+                let column = if self.horizontal { width - 1 - column } else { column };
+                let row = if self.vertical { height - 1 - row } else { row };
+
+                let index = 3 * ((height - 1 - row) * width + column) as usize;
+                self.pixels.extend_from_slice(&pixels[index..index + 3]);
+            }
+        }
+        self.screen.render_from_pixels(&self.pixels, turned_width, turned_height);
+    }
+
+    fn update_audio(&mut self, audio_frame: &audio::AudioFrame) {
+        self.screen.update_audio(audio_frame);
+    }
+
+    fn set_output_gamma(&mut self, gamma: f32) {
+        self.screen.set_output_gamma(gamma);
+    }
+
+    fn set_latency(&mut self, latency: Duration) {
+        self.screen.set_latency(latency);
+    }
+
+    fn set_palette(&mut self, palette: Option<&screen::Palette>) {
+        self.screen.set_palette(palette);
+    }
+}
@@ -0,0 +1,119 @@
+use gfx;
+use led_mapper;
+use screen;
+use screen::reconnect::Reconnecting;
+use screen::serial;
+use std::fs::File;
+use std::io::{self, Write};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::thread;
+
+// Starts each frame, followed by its columns and rows and then the colors.
+const MAGIC: [u8; 2] = [b'U', b'N'];
+
+// Streams frames to a Pimoroni Galactic, Cosmic or Stellar Unicorn running unicorn/main.py,
+// which shows them. It's reached over UDP at an address like "192.168.1.20:6454", or over USB
+// serial at a device path like "/dev/ttyACM0". The output is scaled to the board's LEDs, so
+// e.g. --width 53 --height 11 for a Galactic Unicorn keeps its proportions.
+pub struct UnicornScreen {
+    columns: i32,
+    rows: i32,
+    rotation: u32,
+    frames_tx: mpsc::SyncSender<Vec<u8>>,
+}
+
+impl UnicornScreen {
+    pub fn new(model: &str, address: &str, rotation: u32) -> Result<UnicornScreen, String> {
+        let (columns, rows) = match model {
+            "galactic" => (53, 11),
+            "cosmic" => (32, 32),
+            "stellar" => (16, 16),
+            _ => return Err(format!("unknown Unicorn {}, expected galactic, cosmic or stellar", model)),
+        };
+        let connection = Connection::open(address)?;
+
+        let (frames_tx, frames_rx) = mpsc::sync_channel::<Vec<u8>>(1);
+        let address = address.to_string();
+        thread::spawn(move || {
+            let name = format!("the Unicorn at {}", address);
+            let mut connection = Reconnecting::new(name, Some(connection), || Connection::open(&address));
+            for frame in frames_rx {
+                connection.send(|connection| connection.send(&frame).map_err(|e| e.to_string()));
+            }
+        });
+
+        Ok(UnicornScreen {
+            columns,
+            rows,
+            rotation,
+            frames_tx,
+        })
+    }
+}
+
+impl screen::Screen for UnicornScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        // Sampled the other way round when turning a quarter, so they end up the board's shape.
+        let (columns, rows) = if (self.rotation / 90).is_multiple_of(2) {
+            (self.columns, self.rows)
+        } else {
+            (self.rows, self.columns)
+        };
+        let pixel_colors = led_mapper::led_matrix_mapper::sample_pixels(pixels, width, height, columns, rows);
+        let (pixel_colors, columns, rows) =
+            led_mapper::led_matrix_mapper::rotate(&pixel_colors, columns, rows, self.rotation);
+
+        let mut frame = Vec::with_capacity(MAGIC.len() + 2 + 3 * pixel_colors.len());
+        frame.extend_from_slice(&MAGIC);
+        frame.extend_from_slice(&[columns as u8, rows as u8]);
+        for &(r, g, b) in pixel_colors.iter() {
+            frame.extend_from_slice(&[r, g, b]);
+        }
+
+        // Frames are dropped while the last one is still being sent, or while reconnecting.
+        let _ = self.frames_tx.try_send(frame);
+    }
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Serial(File),
+}
+
+impl Connection {
+    // Device paths open serial connections, anything else is a UDP address.
+    fn open(address: &str) -> Result<Connection, String> {
+        if address.starts_with('/') {
+            return serial::open(address, None).map(Connection::Serial);
+        }
+
+        let destination = address.to_socket_addrs()
+            .map_err(|e| format!("invalid Unicorn address {}: {}", address, e))?
+            .next()
+            .ok_or_else(|| format!("could not resolve {}", address))?;
+        let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("could not open a UDP socket: {}", e))?;
+        socket.connect(destination).map_err(|e| format!("could not connect to {}: {}", address, e))?;
+
+        Ok(Connection::Udp(socket))
+    }
+
+    fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        match *self {
+            Connection::Udp(ref socket) => socket.send(frame).map(|_| ()),
+            Connection::Serial(ref mut file) => file.write_all(frame),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use gfx;
+use led_mapper;
+use screen;
+use screen::reconnect::Reconnecting;
+use screen::spi;
+use std::io::Write;
+use std::sync::mpsc;
+use std::thread;
+
+const SIZE: i32 = 16;
+const SPI_HZ: u32 = 9_000_000;
+// Starts each frame, before the LEDs' colors.
+const START_OF_FRAME: u8 = 0x72;
+
+// Drives Pimoroni Unicorn HAT HDs, whose microcontrollers take 16x16 frames over SPI and refresh
+// the LEDs themselves. Several can share the Pi's SPI bus, each on its own chip select, and act as
+// one display: `panels` lists each one's SPI device and where its top left LED is in the display,
+// like "/dev/spidev0.0@0+0,/dev/spidev0.1@16+0" for two side by side, or is empty for one HAT on
+// `spi_device`. The output is scaled to the display's LEDs, so a --size of 16 shows one HAT
+// exactly, and each HAT's part is turned by `unicorn_rotation` degrees clockwise for HATs mounted
+// on their side.
+pub struct UnicornHdScreen {
+    power_limiter: led_mapper::PowerLimiter,
+    rotation: u32,
+    panels: Vec<Panel>,
+    // The display's size in LEDs, to the right and bottom edges of the furthest HATs.
+    columns: i32,
+    rows: i32,
+    pixels_tx: mpsc::SyncSender<Vec<(u8, u8, u8)>>,
+}
+
+// Where a HAT is.
+struct Panel {
+    device: String,
+    x: i32,
+    y: i32,
+}
+
+impl UnicornHdScreen {
+    pub fn new(power_budget_milliamps: u32, spi_device: &str, rotation: u32, panels: &str)
+               -> Result<UnicornHdScreen, String> {
+        let panels = parse_panels(panels, spi_device)?;
+        let columns = panels.iter().map(|x| x.x + SIZE).max().unwrap_or(SIZE);
+        let rows = panels.iter().map(|x| x.y + SIZE).max().unwrap_or(SIZE);
+        let name = if panels.len() > 1 { "the Unicorn HAT HDs" } else { "the Unicorn HAT HD" };
+        let (pixels_tx, pixels_rx) = mpsc::sync_channel::<Vec<(u8, u8, u8)>>(1);
+
+        let devices: Vec<String> = panels.iter().map(|x| x.device.clone()).collect();
+        thread::spawn(move || spi_pipeline(pixels_rx, devices));
+
+        Ok(UnicornHdScreen {
+            // The HAT has no global brightness.
+            power_limiter: led_mapper::PowerLimiter::new(name, power_budget_milliamps, 1.0),
+            rotation,
+            panels,
+            columns,
+            rows,
+            pixels_tx,
+        })
+    }
+}
+
+impl screen::Screen for UnicornHdScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn is_linear(&self) -> bool {
+        true
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let display = led_mapper::led_matrix_mapper::sample_pixels(pixels, width, height, self.columns, self.rows);
+        // Each HAT's LEDs, one after the other in the order they were listed.
+        let mut pixel_colors = Vec::with_capacity(self.panels.len() * (SIZE * SIZE) as usize);
+        for panel in self.panels.iter() {
+            let mut panel_colors = Vec::with_capacity((SIZE * SIZE) as usize);
+            for y in panel.y..panel.y + SIZE {
+                let start = (y * self.columns + panel.x) as usize;
+                panel_colors.extend_from_slice(&display[start..start + SIZE as usize]);
+            }
+            let (panel_colors, _, _) = led_mapper::led_matrix_mapper::rotate(&panel_colors, SIZE, SIZE, self.rotation);
+            pixel_colors.extend(panel_colors);
+        }
+        self.power_limiter.limit(&mut pixel_colors);
+
+        // Frames are dropped while the HATs are still being sent the last one, or if sending them
+        // failed.
+        let _ = self.pixels_tx.try_send(pixel_colors);
+    }
+}
+
+fn spi_pipeline(pixels_rx: mpsc::Receiver<Vec<(u8, u8, u8)>>, devices: Vec<String>) {
+    let named = devices.len() > 1;
+    let mut connections: Vec<_> = devices.into_iter().map(|device| {
+        let name = if named { format!("the Unicorn HAT HD on {}", device) } else { "the Unicorn HAT HD".to_string() };
+        let open_device = device.clone();
+        (device, Reconnecting::new(name, None, move || spi::open(&open_device, SPI_HZ)))
+    }).collect();
+
+    let mut frame = Vec::with_capacity(1 + 3 * (SIZE * SIZE) as usize);
+    for pixel_colors in pixels_rx {
+        // A HAT down doesn't hold back the others.
+        let panels = pixel_colors.chunks((SIZE * SIZE) as usize).zip(connections.iter_mut());
+        for (panel_colors, &mut (ref device, ref mut spi)) in panels {
+            // The HAT takes its LEDs column by column, from the top left.
+            frame.clear();
+            frame.push(START_OF_FRAME);
+            for x in 0..SIZE {
+                for y in 0..SIZE {
+                    let (r, g, b) = panel_colors[(y * SIZE + x) as usize];
+                    frame.extend_from_slice(&[r, g, b]);
+                }
+            }
+
+            spi.send(|spi| spi.write_all(&frame).map_err(|e| format!("could not write to {}: {}", device, e)));
+        }
+    }
+}
+
+// Parse a list of HATs like "/dev/spidev0.0@0+0,/dev/spidev0.1@16+0", each's SPI device and the
+// column and row of its top left LED, or one HAT on `spi_device` if it's empty.
+fn parse_panels(value: &str, spi_device: &str) -> Result<Vec<Panel>, String> {
+    if value.trim().is_empty() {
+        return Ok(vec![Panel { device: spi_device.to_string(), x: 0, y: 0 }]);
+    }
+
+    value.split(',').map(|entry| {
+        let expected = || format!("expected <spi device>@<x>+<y>, got '{}'", entry.trim());
+        let mut parts = entry.trim().splitn(2, '@');
+        let device = parts.next().unwrap_or("");
+        let offset: Vec<&str> = parts.next().ok_or_else(&expected)?.split('+').collect();
+        if device.is_empty() || offset.len() != 2 {
+            return Err(expected());
+        }
+        let x = offset[0].trim().parse::<i32>().map_err(|_| expected())?;
+        let y = offset[1].trim().parse::<i32>().map_err(|_| expected())?;
+        if x < 0 || y < 0 {
+            return Err(expected());
+        }
+        Ok(Panel { device: device.to_string(), x, y })
+    }).collect()
+}
@@ -0,0 +1,406 @@
+use audio;
+use gfx;
+use screen;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+const VIEWER: &str = include_str!("websocket.html");
+// Appended to the client's key for the handshake, from RFC 6455.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+pub const OPCODE_TEXT: u8 = 1;
+const OPCODE_BINARY: u8 = 2;
+pub const OPCODE_CLOSE: u8 = 8;
+pub const OPCODE_PING: u8 = 9;
+pub const OPCODE_PONG: u8 = 10;
+// Viewers that stop reading are dropped after this long.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REQUEST: usize = 8192;
+// Messages queued for each viewer, so an audio message doesn't push out a frame.
+const QUEUED_MESSAGES: usize = 4;
+const AUDIO_BANDS: [(&str, audio::FrequencyBand); 3] = [
+    ("low", audio::FrequencyBand::new(0.0, 1000.0)),
+    ("mid", audio::FrequencyBand::new(1000.0, 4000.0)),
+    ("high", audio::FrequencyBand::new(4000.0, 20000.0)),
+];
+
+// Serves a viewer page over HTTP at `address`, which shows the output pushed to it over a
+// WebSocket at the same address, e.g. to mirror an LED matrix on a phone. Frames are binary
+// messages of the width and height as 16-bit big-endian numbers followed by RGB rows from the
+// top. With `sends_audio`, each audio update is also sent as a JSON text message with the bpm,
+// whether it's a beat or silent, and the low, mid and high band powers. What viewers send is
+// never read; they're dropped once writing to them fails.
+pub struct WebSocketScreen {
+    sends_audio: bool,
+    messages_tx: mpsc::SyncSender<Message>,
+}
+
+enum Message {
+    Frame(Vec<u8>),
+    Audio(String),
+}
+
+impl WebSocketScreen {
+    pub fn new(address: &str, sends_audio: bool) -> Result<WebSocketScreen, String> {
+        let listener = TcpListener::bind(address).map_err(|e| format!("could not listen on {}: {}", address, e))?;
+        println!("Serving the WebSocket viewer on http://{}/", address);
+
+        let (viewers_tx, viewers_rx) = mpsc::channel::<mpsc::SyncSender<Arc<Vec<u8>>>>();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(x) => x,
+                    Err(_) => continue,
+                };
+                let viewers_tx = viewers_tx.clone();
+                thread::spawn(move || {
+                    // The viewer went away, or only wanted the page.
+                    let _ = serve(stream, viewers_tx);
+                });
+            }
+        });
+
+        let (messages_tx, messages_rx) = mpsc::sync_channel::<Message>(QUEUED_MESSAGES);
+        thread::spawn(move || {
+            let mut viewers = Vec::new();
+            for message in messages_rx {
+                viewers.extend(viewers_rx.try_iter());
+                if viewers.is_empty() {
+                    continue;
+                }
+
+                let websocket_frame = Arc::new(match message {
+                    Message::Frame(payload) => websocket_frame(OPCODE_BINARY, &payload),
+                    Message::Audio(json) => websocket_frame(OPCODE_TEXT, json.as_bytes()),
+                });
+                // Viewers with a full queue skip this message, and those gone are forgotten.
+                viewers.retain(|viewer| {
+                    !matches!(viewer.try_send(websocket_frame.clone()), Err(mpsc::TrySendError::Disconnected(_)))
+                });
+            }
+        });
+
+        Ok(WebSocketScreen {
+            sends_audio,
+            messages_tx,
+        })
+    }
+}
+
+impl screen::Screen for WebSocketScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let mut payload = Vec::with_capacity(4 + pixels.len());
+        payload.extend_from_slice(&[(width >> 8) as u8, width as u8, (height >> 8) as u8, height as u8]);
+        for row in pixels.chunks(3 * width as usize).rev() {
+            payload.extend_from_slice(row);
+        }
+
+        // Frames are dropped while the viewers are still being sent the last ones.
+        let _ = self.messages_tx.try_send(Message::Frame(payload));
+    }
+
+    fn update_audio(&mut self, audio_frame: &audio::AudioFrame) {
+        if !self.sends_audio {
+            return;
+        }
+
+        let mut json = format!("{{\"bpm\":{},\"beat\":{},\"silent\":{}",
+                               audio_frame.bpm, audio_frame.is_beat, audio_frame.is_silent);
+        for &(name, band) in AUDIO_BANDS.iter() {
+            json.push_str(&format!(",\"{}\":{}", name, audio_frame.band_power(band)));
+        }
+        json.push('}');
+        let _ = self.messages_tx.try_send(Message::Audio(json));
+    }
+}
+
+// Answer a request with the viewer page, or upgrade it to a WebSocket and hand it to the
+// broadcaster, then write it what it's sent until it stops reading.
+fn serve(mut stream: TcpStream,
+         viewers_tx: mpsc::Sender<mpsc::SyncSender<Arc<Vec<u8>>>>) -> Result<(), String> {
+    stream.set_write_timeout(Some(WRITE_TIMEOUT)).map_err(|e| e.to_string())?;
+
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|x| x == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST {
+            return Err("request too long".to_string());
+        }
+        let length = stream.read(&mut buffer).map_err(|e| e.to_string())?;
+        if length == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buffer[..length]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let key = request.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if name.trim().eq_ignore_ascii_case("sec-websocket-key") =>
+                    Some(value.trim().to_string()),
+                _ => None,
+            }
+        })
+        .next();
+
+    let key = match key {
+        Some(x) => x,
+        None => {
+            let response = format!("HTTP/1.0 200 OK\r\n\
+                                    Content-Type: text/html; charset=utf-8\r\n\
+                                    Content-Length: {}\r\n\
+                                    Connection: close\r\n\r\n{}", VIEWER.len(), VIEWER);
+            return stream.write_all(response.as_bytes()).map_err(|e| e.to_string());
+        }
+    };
+
+    accept_websocket(&mut stream, &key)?;
+
+    let (messages_tx, messages_rx) = mpsc::sync_channel(QUEUED_MESSAGES);
+    viewers_tx.send(messages_tx).map_err(|e| e.to_string())?;
+    for message in messages_rx {
+        stream.write_all(&message).map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+// Answer the handshake of a request upgrading to a WebSocket, with its Sec-WebSocket-Key `key`.
+pub fn accept_websocket(stream: &mut TcpStream, key: &str) -> Result<(), String> {
+    let accept = base64(&sha1(format!("{}{}", key, ACCEPT_GUID).as_bytes()));
+    let response = format!("HTTP/1.1 101 Switching Protocols\r\n\
+                            Upgrade: websocket\r\n\
+                            Connection: Upgrade\r\n\
+                            Sec-WebSocket-Accept: {}\r\n\r\n", accept);
+    stream.write_all(response.as_bytes()).map_err(|e| e.to_string())?;
+    // Frames are small enough to go out as soon as they're written.
+    let _ = stream.set_nodelay(true);
+    Ok(())
+}
+
+// An unfragmented, unmasked WebSocket frame, as servers send them.
+pub fn websocket_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(10 + payload.len());
+    frame.push(0x80 | opcode);
+    let length = payload.len();
+    if length < 126 {
+        frame.push(length as u8);
+    } else if length <= 0xffff {
+        frame.extend_from_slice(&[126, (length >> 8) as u8, length as u8]);
+    } else {
+        frame.push(127);
+        for i in (0..8).rev() {
+            frame.push((length as u64 >> (8 * i)) as u8);
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+// The opcode and unmasked payload of the next frame a client sends, which may be at most
+// `max_length` bytes. Fragments come as they are, with an opcode of 0 after the first.
+pub fn read_websocket_frame(stream: &mut dyn Read, max_length: usize) -> Result<(u8, Vec<u8>), String> {
+    let mut header = [0; 2];
+    stream.read_exact(&mut header).map_err(|e| e.to_string())?;
+    let length = match header[1] & 0x7f {
+        126 => {
+            let mut bytes = [0; 2];
+            stream.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+            (bytes[0] as u64) << 8 | bytes[1] as u64
+        },
+        127 => {
+            let mut bytes = [0; 8];
+            stream.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+            bytes.iter().fold(0, |length, &x| length << 8 | x as u64)
+        },
+        x => x as u64,
+    };
+    if length > max_length as u64 {
+        return Err(format!("frame of {} bytes is too long", length));
+    }
+
+    let mut mask = [0; 4];
+    if header[1] & 0x80 != 0 {
+        stream.read_exact(&mut mask).map_err(|e| e.to_string())?;
+    }
+    let mut payload = vec![0; length as usize];
+    stream.read_exact(&mut payload).map_err(|e| e.to_string())?;
+    for (i, x) in payload.iter_mut().enumerate() {
+        *x ^= mask[i % 4];
+    }
+    Ok((header[0] & 0x0f, payload))
+}
+
+// Only the handshake needs it, so it's as simple as it gets rather than fast.
+fn sha1(message: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476, 0xc3d2_e1f0];
+
+    let mut padded = message.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    let bits = message.len() as u64 * 8;
+    for i in (0..8).rev() {
+        padded.push((bits >> (8 * i)) as u8);
+    }
+
+    for block in padded.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = (block[4 * i] as u32) << 24 | (block[4 * i + 1] as u32) << 16
+                | (block[4 * i + 2] as u32) << 8 | block[4 * i + 3] as u32;
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (x, y) in h.iter_mut().zip([a, b, c, d, e].iter()) {
+            *x = x.wrapping_add(*y);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (i, x) in h.iter().enumerate() {
+        digest[4 * i..4 * i + 4].copy_from_slice(&[(x >> 24) as u8, (x >> 16) as u8, (x >> 8) as u8, *x as u8]);
+    }
+    digest
+}
+
+pub fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|x| format!("{:02x}", x)).collect()
+    }
+
+    #[test]
+    fn hashes_sha1() {
+        assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(&sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // Two blocks, as the length no longer fits in the first.
+        assert_eq!(hex(&sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+                   "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+    }
+
+    #[test]
+    fn encodes_base64() {
+        let encoded: Vec<String> = ["", "f", "fo", "foo", "foob", "fooba", "foobar"].iter()
+            .map(|x| base64(x.as_bytes()))
+            .collect();
+        assert_eq!(encoded, vec!["", "Zg==", "Zm8=", "Zm9v", "Zm9vYg==", "Zm9vYmE=", "Zm9vYmFy"]);
+    }
+
+    #[test]
+    fn accepts_the_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        // The example key from RFC 6455.
+        accept_websocket(&mut server, "dGhlIHNhbXBsZSBub25jZQ==").unwrap();
+        drop(server);
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert_eq!(response, "HTTP/1.1 101 Switching Protocols\r\n\
+                              Upgrade: websocket\r\n\
+                              Connection: Upgrade\r\n\
+                              Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n");
+    }
+
+    #[test]
+    fn writes_frames_with_extended_lengths() {
+        assert_eq!(websocket_frame(OPCODE_TEXT, b"Hello"), b"\x81\x05Hello");
+        let frame = websocket_frame(OPCODE_BINARY, &[7; 126]);
+        assert_eq!(&frame[..4], &[0x82, 126, 0, 126]);
+        assert_eq!(frame.len(), 4 + 126);
+        let frame = websocket_frame(OPCODE_BINARY, &[7; 0x10000]);
+        assert_eq!(&frame[..10], &[0x82, 127, 0, 0, 0, 0, 0, 1, 0, 0]);
+        assert_eq!(frame.len(), 10 + 0x10000);
+    }
+
+    #[test]
+    fn reads_masked_frames() {
+        // A masked "Hello", from RFC 6455.
+        let mut frame: &[u8] = &[0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+        assert_eq!(read_websocket_frame(&mut frame, 125), Ok((OPCODE_TEXT, b"Hello".to_vec())));
+
+        let mut frame: &[u8] = &[0x89, 0x00];
+        assert_eq!(read_websocket_frame(&mut frame, 125), Ok((OPCODE_PING, Vec::new())));
+    }
+
+    #[test]
+    fn reads_frames_with_extended_lengths() {
+        for &length in &[126, 0xffff, 0x10000] {
+            let payload: Vec<u8> = (0..length).map(|x| x as u8).collect();
+            let frame = websocket_frame(OPCODE_PONG, &payload);
+            assert_eq!(read_websocket_frame(&mut &frame[..], length), Ok((OPCODE_PONG, payload)));
+        }
+    }
+
+    #[test]
+    fn rejects_long_and_truncated_frames() {
+        let frame = websocket_frame(OPCODE_CLOSE, &[0; 200]);
+        assert_eq!(read_websocket_frame(&mut &frame[..], 199), Err("frame of 200 bytes is too long".to_string()));
+
+        // Cut off in the header, the extended length, the mask and the payload.
+        let masked = [0x81, 0xfe, 0x00, 0x80, 1, 2, 3, 4, 5, 6];
+        for &end in &[1, 3, 6, masked.len()] {
+            assert!(read_websocket_frame(&mut &masked[..end], 200).is_err());
+        }
+        let long = websocket_frame(OPCODE_TEXT, &[0; 0x10000]);
+        assert!(read_websocket_frame(&mut &long[..9], 0x10000).is_err());
+        assert!(read_websocket_frame(&mut &long[..long.len() - 1], 0x10000).is_err());
+    }
+}
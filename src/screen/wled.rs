@@ -0,0 +1,210 @@
+use gfx;
+use led_mapper;
+use screen;
+use screen::reconnect::Reconnecting;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const REALTIME_PORT: u16 = 21324;
+const DDP_PORT: u16 = 4048;
+// Seconds WLED waits after the last frame before going back to its own effects.
+const REALTIME_TIMEOUT: u8 = 2;
+const DRGB: u8 = 2;
+const DNRGB: u8 = 4;
+const DRGB_MAX_LEDS: usize = 490;
+const DNRGB_LEDS_PER_PACKET: usize = 489;
+// DDP version 1, and the flag on a frame's last packet for it to be shown.
+const DDP_VERSION: u8 = 0x40;
+const DDP_PUSH: u8 = 0x01;
+// 8-bit RGB, to the default output.
+const DDP_DATA_TYPE: u8 = 0x0b;
+const DDP_ID: u8 = 1;
+const DDP_LEDS_PER_PACKET: usize = 480;
+const INFO_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, PartialEq)]
+enum WledProtocol {
+    Drgb,
+    Dnrgb,
+    Ddp,
+}
+
+impl WledProtocol {
+    fn parse(name: &str) -> Result<WledProtocol, String> {
+        match name {
+            "drgb" => Ok(WledProtocol::Drgb),
+            "dnrgb" => Ok(WledProtocol::Dnrgb),
+            "ddp" => Ok(WledProtocol::Ddp),
+            _ => Err(format!("unknown WLED protocol {}, expected drgb, dnrgb or ddp", name)),
+        }
+    }
+}
+
+// Sends the output to a WLED controller over UDP with one of its realtime protocols, DRGB for up
+// to 490 LEDs, DNRGB for more, or DDP. When `leds` is 0, the strip's length is asked of WLED's
+// JSON API. A frame with exactly as many pixels is sent in rows from the top left, for WLED's 2D
+// matrices, otherwise its columns are averaged and scaled down to the strip's length. Frames are
+// gamma encoded, as WLED applies its own gamma correction.
+pub struct WledScreen {
+    protocol: WledProtocol,
+    leds: usize,
+    packets_tx: mpsc::SyncSender<Vec<Vec<u8>>>,
+    sequence: u8,
+}
+
+impl WledScreen {
+    pub fn new(address: &str, protocol: &str, leds: usize) -> Result<WledScreen, String> {
+        let protocol = WledProtocol::parse(protocol)?;
+        let port = if protocol == WledProtocol::Ddp { DDP_PORT } else { REALTIME_PORT };
+        let destination = resolve(address, port)?;
+
+        let leds = match leds {
+            0 => match strip_length(address) {
+                Ok(x) => {
+                    println!("WLED has {} LEDs", x);
+                    x
+                },
+                Err(e) => return Err(format!("could not ask WLED how many LEDs it has, {}", e)),
+            },
+            x => x,
+        };
+        if protocol == WledProtocol::Drgb && leds > DRGB_MAX_LEDS {
+            return Err(format!("DRGB only takes up to {} LEDs, use dnrgb or ddp", DRGB_MAX_LEDS));
+        }
+
+        let socket = open_socket()?;
+
+        let (packets_tx, packets_rx) = mpsc::sync_channel::<Vec<Vec<u8>>>(1);
+        thread::spawn(move || {
+            // Controllers drop off the network and come back.
+            let mut connection = Reconnecting::new(format!("WLED at {}", destination), Some(socket), open_socket);
+            for packets in packets_rx {
+                connection.send(|socket| {
+                    for packet in packets.iter() {
+                        socket.send_to(packet, destination).map_err(|e| e.to_string())?;
+                    }
+                    Ok(())
+                });
+            }
+        });
+
+        Ok(WledScreen {
+            protocol,
+            leds,
+            packets_tx,
+            sequence: 0,
+        })
+    }
+
+    // The frame's colors for each of the strip's LEDs.
+    fn strip_colors(&self, pixels: &[u8], width: i32, height: i32) -> Vec<(u8, u8, u8)> {
+        if (width * height) as usize == self.leds {
+            return led_mapper::led_matrix_mapper::sample_pixels(pixels, width, height, width, height);
+        }
+
+        // Each LED averages the columns that fall on it, or takes the nearest when there are
+        // more LEDs than columns.
+        let (width, height) = (width as usize, height as usize);
+        (0..self.leds).map(|led| {
+            led_mapper::led_matrix_mapper::average_columns(pixels, width, height,
+                                                           led * width / self.leds, (led + 1) * width / self.leds)
+        }).collect()
+    }
+
+    fn packets(&mut self, strip_colors: &[(u8, u8, u8)]) -> Vec<Vec<u8>> {
+        let rgb = |colors: &[(u8, u8, u8)], packet: &mut Vec<u8>| {
+            for &(r, g, b) in colors.iter() {
+                packet.extend_from_slice(&[r, g, b]);
+            }
+        };
+
+        match self.protocol {
+            WledProtocol::Drgb => {
+                let mut packet = vec![DRGB, REALTIME_TIMEOUT];
+                rgb(strip_colors, &mut packet);
+                vec![packet]
+            },
+            WledProtocol::Dnrgb => strip_colors.chunks(DNRGB_LEDS_PER_PACKET).enumerate().map(|(i, colors)| {
+                let start = i * DNRGB_LEDS_PER_PACKET;
+                let mut packet = vec![DNRGB, REALTIME_TIMEOUT, (start >> 8) as u8, start as u8];
+                rgb(colors, &mut packet);
+                packet
+            }).collect(),
+            WledProtocol::Ddp => {
+                // 1 to 15, as 0 means the sequence isn't used.
+                self.sequence = self.sequence % 15 + 1;
+                let chunks = strip_colors.chunks(DDP_LEDS_PER_PACKET).count();
+                strip_colors.chunks(DDP_LEDS_PER_PACKET).enumerate().map(|(i, colors)| {
+                    let flags = if i + 1 == chunks { DDP_VERSION | DDP_PUSH } else { DDP_VERSION };
+                    let offset = 3 * i * DDP_LEDS_PER_PACKET;
+                    let length = 3 * colors.len();
+                    let mut packet = vec![
+                        flags, self.sequence, DDP_DATA_TYPE, DDP_ID,
+                        (offset >> 24) as u8, (offset >> 16) as u8, (offset >> 8) as u8, offset as u8,
+                        (length >> 8) as u8, length as u8,
+                    ];
+                    rgb(colors, &mut packet);
+                    packet
+                }).collect()
+            },
+        }
+    }
+}
+
+impl screen::Screen for WledScreen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let strip_colors = self.strip_colors(pixels, width, height);
+        let packets = self.packets(&strip_colors);
+
+        // Frames are dropped while the last one is still being sent, or while reconnecting.
+        let _ = self.packets_tx.try_send(packets);
+    }
+}
+
+// `address` with `port`, unless it has one.
+fn resolve(address: &str, port: u16) -> Result<SocketAddr, String> {
+    let with_port = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, port) };
+    with_port.to_socket_addrs()
+        .map_err(|e| format!("invalid WLED address {}: {}", address, e))?
+        .next()
+        .ok_or_else(|| format!("could not resolve {}", address))
+}
+
+// How many LEDs WLED at `address` has, from its JSON API's "leds": {"count": ...}.
+fn strip_length(address: &str) -> Result<usize, String> {
+    let host = address.split(':').next().unwrap_or(address);
+    let destination = resolve(host, 80)?;
+    let mut stream = TcpStream::connect_timeout(&destination, INFO_TIMEOUT).map_err(|e| e.to_string())?;
+    let _ = stream.set_read_timeout(Some(INFO_TIMEOUT));
+    let request = format!("GET /json/info HTTP/1.0\r\nHost: {}\r\n\r\n", host);
+    stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).map_err(|e| e.to_string())?;
+
+    let leds = response.find("\"leds\"").map(|i| &response[i..]).ok_or("no LEDs in its info")?;
+    let count = leds.find("\"count\":").map(|i| &leds[i + "\"count\":".len()..]).ok_or("no LED count in its info")?;
+    let digits: String = count.trim_start().chars().take_while(|x| x.is_ascii_digit()).collect();
+    match digits.parse::<usize>() {
+        Ok(x) if x > 0 => Ok(x),
+        _ => Err("no LED count in its info".to_string()),
+    }
+}
+
+fn open_socket() -> Result<UdpSocket, String> {
+    UdpSocket::bind("0.0.0.0:0").map_err(|e| format!("could not open a UDP socket: {}", e))
+}
@@ -0,0 +1,108 @@
+use gfx;
+use led_mapper;
+use screen;
+use screen::reconnect::Reconnecting;
+use screen::spi;
+use std::io::Write;
+use std::sync::mpsc;
+use std::thread;
+
+// Each bit of LED data is sent as 3 SPI bits, so at 3 times the LEDs' 800 kHz a 1 is high for
+// 0.83 us and a 0 for 0.42 us, both within the WS2812B's timing.
+const SPI_HZ: u32 = 2_400_000;
+const ONE: u8 = 0b110;
+const ZERO: u8 = 0b100;
+// The line is held low for at least 280 us after a frame for the LEDs to latch it.
+const RESET_BYTES: usize = 90;
+
+// Drives a WS2812B (NeoPixel) strip or matrix from the Pi's SPI MOSI pin (GPIO 10), one LED per
+// output pixel, so e.g. --width 16 --height 16 for a 16x16 matrix or --width 60 --height 1 for a
+// strip, or chained panels laid out by `panel_layout`, or the LEDs of `pixel_map` anywhere. SPI keeps the timing exact however busy the CPU is, but its clock follows the core
+// clock, so that has to be fixed: core_freq=250 on a Pi 3, or core_freq_min=500 on a Pi 4. Frames
+// bigger than the spidev buffer, 4096 bytes (about 450 LEDs) by default, need spidev.bufsiz
+// raised.
+pub struct Ws2812Screen {
+    panel_layout: led_mapper::PanelLayout,
+    pixel_map: Option<led_mapper::PixelMap>,
+    power_limiter: led_mapper::PowerLimiter,
+    pixels_tx: mpsc::SyncSender<Vec<(u8, u8, u8)>>,
+}
+
+impl Ws2812Screen {
+    pub fn new(power_budget_milliamps: u32,
+               spi_device: &str,
+               panel_layout: led_mapper::PanelLayout,
+               pixel_map: Option<led_mapper::PixelMap>,
+               color_order: led_mapper::ColorOrder) -> Ws2812Screen {
+        let (pixels_tx, pixels_rx) = mpsc::sync_channel::<Vec<(u8, u8, u8)>>(1);
+
+        let spi_device = spi_device.to_string();
+        thread::spawn(move || spi_pipeline(pixels_rx, spi_device, color_order));
+
+        Ws2812Screen {
+            panel_layout,
+            pixel_map,
+            // WS2812s have no global brightness.
+            power_limiter: led_mapper::PowerLimiter::new("the WS2812 LEDs", power_budget_milliamps, 1.0),
+            pixels_tx,
+        }
+    }
+}
+
+impl screen::Screen for Ws2812Screen {
+    fn setup(&mut self, _gl: &gfx::gl::Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn uses_window(&self) -> bool {
+        false
+    }
+
+    fn is_linear(&self) -> bool {
+        true
+    }
+
+    fn renders_pixels(&self) -> bool {
+        true
+    }
+
+    fn render_from_pixels(&mut self, pixels: &[u8], width: i32, height: i32) {
+        let mut pixel_colors = match self.pixel_map {
+            Some(ref map) => map.sample(pixels, width, height),
+            None => self.panel_layout.map(pixels, width, height),
+        };
+        self.power_limiter.limit(&mut pixel_colors);
+
+        // Frames are dropped while the LEDs are still being sent the last one, or if sending
+        // them failed.
+        let _ = self.pixels_tx.try_send(pixel_colors);
+    }
+}
+
+fn spi_pipeline(pixels_rx: mpsc::Receiver<Vec<(u8, u8, u8)>>, device: String, color_order: led_mapper::ColorOrder) {
+    let mut spi = Reconnecting::new("the WS2812 LEDs".to_string(), None, || spi::open(&device, SPI_HZ));
+
+    let mut frame = Vec::new();
+    for pixel_colors in pixels_rx {
+        encode_frame(&pixel_colors, color_order, &mut frame);
+        spi.send(|spi| spi.write_all(&frame).map_err(|e| format!("could not write to {}: {}", device, e)));
+    }
+}
+
+// The SPI bytes for the LEDs to show `pixel_colors`, in the order they take them, followed by the
+// reset.
+fn encode_frame(pixel_colors: &[(u8, u8, u8)], color_order: led_mapper::ColorOrder, frame: &mut Vec<u8>) {
+    frame.clear();
+    for &(r, g, b) in pixel_colors.iter() {
+        for &byte in color_order.channels(r, g, b).iter() {
+            // 8 bits become 24, most significant first.
+            let mut bits: u32 = 0;
+            for bit in (0..8).rev() {
+                let pattern = if byte & (1 << bit) != 0 { ONE } else { ZERO };
+                bits = (bits << 3) | pattern as u32;
+            }
+            frame.extend_from_slice(&[(bits >> 16) as u8, (bits >> 8) as u8, bits as u8]);
+        }
+    }
+    frame.extend_from_slice(&[0; RESET_BYTES]);
+}
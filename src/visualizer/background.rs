@@ -0,0 +1,117 @@
+use gfx;
+use gfx::gl;
+use png;
+use std::fs::File;
+use std::path::PathBuf;
+
+// A static base layer drawn behind the visualizers, written as either a path to a PNG image,
+// or "gradient:<top color>,<bottom color>" with colors as hex, e.g. "gradient:#000020,#200040".
+#[derive(Clone, Debug)]
+pub enum Background {
+    Image(PathBuf),
+    Gradient((u8, u8, u8), (u8, u8, u8)),
+}
+
+impl Background {
+    pub fn parse(value: &str) -> Result<Background, String> {
+        if let Some(gradient) = value.strip_prefix("gradient:") {
+            let colors: Vec<&str> = gradient.split(',').collect();
+            if colors.len() != 2 {
+                return Err(format!("expected gradient:<top color>,<bottom color>, got '{}'", value));
+            }
+
+            return Ok(Background::Gradient(parse_color(colors[0])?, parse_color(colors[1])?));
+        }
+
+        Ok(Background::Image(PathBuf::from(value)))
+    }
+
+    // Load the background into a new texture, stretched over the output. Gradients are as tall
+    // as the output.
+    pub fn create_texture(&self, gl: &gfx::gl::Gl, height: i32) -> Result<gfx::Texture, String> {
+        let (width, height, pixels) = match *self {
+            Background::Image(ref path) => load_png(path)?,
+            Background::Gradient(top, bottom) => gradient_pixels(top, bottom, height as usize),
+        };
+
+        let texture = gfx::Texture::new(gl, width as i32, height as i32, gl::RGBA, gl::LINEAR)?;
+        texture.upload(&pixels);
+
+        Ok(texture)
+    }
+}
+
+fn parse_color(value: &str) -> Result<(u8, u8, u8), String> {
+    let hex = value.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return Err(format!("expected a color like #1a2b3c, got '{}'", value));
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16)
+        .map_err(|_| format!("invalid color '{}'", value));
+
+    Ok((channel(0)?, channel(2)?, channel(4)?))
+}
+
+// A one pixel wide vertical gradient. Rows are bottom to top, matching GL's texture layout.
+fn gradient_pixels(top: (u8, u8, u8), bottom: (u8, u8, u8), height: usize) -> (usize, usize, Vec<u8>) {
+    let lerp = |from: u8, to: u8, t: f32| (from as f32 + (to as f32 - from as f32) * t).round() as u8;
+
+    let mut pixels = Vec::with_capacity(4 * height);
+    for y in 0..height {
+        let t = y as f32 / (height - 1).max(1) as f32;
+        pixels.extend_from_slice(&[
+            lerp(bottom.0, top.0, t),
+            lerp(bottom.1, top.1, t),
+            lerp(bottom.2, top.2, t),
+            255,
+        ]);
+    }
+
+    (1, height, pixels)
+}
+
+// Decode a PNG image background for a texture.
+pub fn load_image(path: &PathBuf) -> Result<gfx::Image, String> {
+    let (width, height, pixels) = load_png(path)?;
+    Ok(gfx::Image {
+        width: width as i32,
+        height: height as i32,
+        format: gl::RGBA,
+        pixels,
+    })
+}
+
+// Decode a PNG into tightly packed RGBA rows, bottom to top.
+fn load_png(path: &PathBuf) -> Result<(usize, usize, Vec<u8>), String> {
+    let file = File::open(path).map_err(|e| format!("could not open {}: {}", path.display(), e))?;
+
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)
+        .map_err(|e| format!("could not decode {}: {}", path.display(), e))?;
+
+    let width = info.width as usize;
+    let height = info.height as usize;
+    let channels = info.color_type.samples();
+
+    let mut pixels = Vec::with_capacity(4 * width * height);
+    for y in (0..height).rev() {
+        let row = &buffer[y * info.line_size..y * info.line_size + width * channels];
+        for pixel in row.chunks(channels) {
+            let rgba = match channels {
+                1 => [pixel[0], pixel[0], pixel[0], 255],
+                2 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+                3 => [pixel[0], pixel[1], pixel[2], 255],
+                _ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+            };
+            pixels.extend_from_slice(&rgba);
+        }
+    }
+
+    Ok((width, height, pixels))
+}
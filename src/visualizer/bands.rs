@@ -0,0 +1,78 @@
+use audio;
+use std::collections::HashMap;
+
+// Maps a visualizer's named inputs ("mouth", "speed", ...) onto frequency bands. Each
+// visualizer declares its inputs with default bands, and users may override them.
+#[derive(Clone)]
+pub struct BandMapping {
+    bands: HashMap<String, audio::FrequencyBand>,
+}
+
+impl BandMapping {
+    pub fn new(defaults: &[(&str, audio::FrequencyBand)]) -> BandMapping {
+        let mut bands = HashMap::new();
+        for &(name, band) in defaults.iter() {
+            bands.insert(name.to_string(), band);
+        }
+
+        BandMapping { bands }
+    }
+
+    pub fn band(&self, name: &str) -> audio::FrequencyBand {
+        match self.bands.get(name) {
+            Some(band) => *band,
+            None => panic!("visualizer input '{}' was never declared", name),
+        }
+    }
+
+    // Total power of the audio frame within the band feeding the named input.
+    pub fn power(&self, audio_frame: &audio::AudioFrame, name: &str) -> f32 {
+        audio_frame.band_power(self.band(name))
+    }
+
+    // Remap an already declared input. Returns false if the input doesn't exist.
+    pub fn set(&mut self, name: &str, band: audio::FrequencyBand) -> bool {
+        match self.bands.get_mut(name) {
+            Some(existing) => {
+                *existing = band;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.bands.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+// A user supplied remapping, written as "<visualizer>.<input>=<low_hz>-<high_hz>",
+// e.g. "smiley.mouth=1000-2000".
+#[derive(Clone, Debug)]
+pub struct BandOverride {
+    pub visualizer: String,
+    pub input: String,
+    pub band: audio::FrequencyBand,
+}
+
+impl BandOverride {
+    pub fn parse(value: &str) -> Result<BandOverride, String> {
+        let parts: Vec<&str> = value.splitn(2, '=').collect();
+        if parts.len() != 2 {
+            return Err(format!("expected <visualizer>.<input>=<low_hz>-<high_hz>, got '{}'", value));
+        }
+
+        let target: Vec<&str> = parts[0].trim().splitn(2, '.').collect();
+        if target.len() != 2 || target[0].is_empty() || target[1].is_empty() {
+            return Err(format!("expected <visualizer>.<input>, got '{}'", parts[0]));
+        }
+
+        Ok(BandOverride {
+            visualizer: target[0].to_string(),
+            input: target[1].to_string(),
+            band: audio::FrequencyBand::parse(parts[1])?,
+        })
+    }
+}
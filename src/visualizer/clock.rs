@@ -0,0 +1,54 @@
+use std::time;
+
+// Time as the visualizers animate by. It's the wall clock's, unless frames are being dumped,
+// when it moves one frame at the frame rate per update, so the frames only depend on the audio
+// and not on how long they took to render.
+pub struct Clock {
+    start: time::Instant,
+    // How long a frame lasts and the updates counted, for a frame clock.
+    frames: Option<(time::Duration, u32)>,
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock {
+            start: time::Instant::now(),
+            frames: None,
+        }
+    }
+
+    // Move by frames from now on, starting over from 0. Times read before then may be later.
+    pub fn count_frames(&mut self, fps: u32) {
+        self.frames = Some((time::Duration::from_secs(1) / u32::max(1, fps), 0));
+    }
+
+    // Count an update, which moves a frame clock by a frame.
+    pub fn tick(&mut self) {
+        if let Some((_, ref mut frames)) = self.frames {
+            *frames += 1;
+        }
+    }
+
+    // The time since the clock started.
+    pub fn now(&self) -> time::Duration {
+        match self.frames {
+            Some((frame, frames)) => frame * frames,
+            None => self.start.elapsed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_clock_moves_a_frame_per_tick() {
+        let mut clock = Clock::new();
+        clock.count_frames(25);
+        for _ in 0..50 {
+            clock.tick();
+        }
+        assert_eq!(clock.now(), time::Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,362 @@
+use gfx;
+use gfx::gl;
+use std::cell::Cell;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    // Blend by the layer's alpha. Visualizers that clear to opaque black hide everything below.
+    Normal,
+    // Brightens by summing colors, the usual choice for layering light effects.
+    Additive,
+    // Darkens by multiplying colors, useful for masking a layer by another.
+    Multiply,
+    // Brightens like additive, but saturates softly instead of clipping.
+    Screen,
+}
+
+impl BlendMode {
+    pub fn parse(value: &str) -> Result<BlendMode, String> {
+        match value {
+            "normal" => Ok(BlendMode::Normal),
+            "additive" => Ok(BlendMode::Additive),
+            "multiply" => Ok(BlendMode::Multiply),
+            "screen" => Ok(BlendMode::Screen),
+
+            _ => Err(format!("unknown blend mode '{}', expected one of: normal, additive, multiply, screen", value)),
+        }
+    }
+
+    fn blend_func(&self) -> (gl::types::GLenum, gl::types::GLenum) {
+        match *self {
+            BlendMode::Normal => (gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA),
+            BlendMode::Additive => (gl::ONE, gl::ONE),
+            BlendMode::Multiply => (gl::DST_COLOR, gl::ZERO),
+            BlendMode::Screen => (gl::ONE, gl::ONE_MINUS_SRC_COLOR),
+        }
+    }
+}
+
+// A visualizer drawn on top of the active one, written as "<visualizer>[:<blend mode>]",
+// e.g. "smiley:additive". The blend mode defaults to additive.
+#[derive(Clone, Debug)]
+pub struct Layer {
+    pub visualizer: String,
+    pub blend_mode: BlendMode,
+}
+
+impl Layer {
+    pub fn parse(value: &str) -> Result<Layer, String> {
+        let parts: Vec<&str> = value.splitn(2, ':').collect();
+        if parts[0].is_empty() {
+            return Err(format!("expected <visualizer>[:<blend mode>], got '{}'", value));
+        }
+
+        let blend_mode = match parts.get(1) {
+            Some(x) => BlendMode::parse(x)?,
+            None => BlendMode::Additive,
+        };
+
+        Ok(Layer {
+            visualizer: parts[0].to_string(),
+            blend_mode,
+        })
+    }
+}
+
+// How `draw` draws a texture over the framebuffer.
+struct Draw {
+    blend_mode: BlendMode,
+    // The fraction of the texture's width and height to show, around its center.
+    crop: (f32, f32),
+    // How many taps to average along each axis, and how far apart they are in texture
+    // coordinates.
+    taps: (u32, f32),
+    // Colors are raised to this before blending.
+    exponent: f32,
+    // The number of levels to dither to and its phase.
+    dither: Option<(f32, f32)>,
+}
+
+impl Draw {
+    // The whole texture in one sample, without dithering.
+    fn new(blend_mode: BlendMode, exponent: f32) -> Draw {
+        Draw {
+            blend_mode,
+            crop: (1.0, 1.0),
+            taps: (1, 0.0),
+            exponent,
+            dither: None,
+        }
+    }
+}
+
+// Stacks layer textures into a framebuffer, one full screen quad per layer. Layers are raised
+// to `gamma` as they're drawn, so that with the usual 2.2 they're blended in linear light
+// rather than muddying fades, and `encode` converts the result for the screen.
+pub struct Compositor {
+    program: Option<gfx::Program>,
+    quad: Option<gfx::Mesh>,
+    // Size of the framebuffer given to `begin`.
+    output_size: Cell<(i32, i32)>,
+    gamma: f32,
+}
+
+impl Compositor {
+    pub fn new() -> Compositor {
+        Compositor {
+            program: None,
+            quad: None,
+            output_size: Cell::new((0, 0)),
+            gamma: 1.0,
+        }
+    }
+
+    // The gamma layers are encoded with, 1 to blend them as they are.
+    pub fn set_gamma(&mut self, gamma: f32) {
+        self.gamma = gamma;
+    }
+
+    pub fn setup(&mut self, gl: &gfx::gl::Gl) -> Result<(), String> {
+        let program = gfx::Program::new(gl, VS_SRC, FS_SRC)
+            .map_err(|e| format!("Could not set up the compositor, its {}", e))?;
+
+        let vertex_data: [f32; 2 * 6] = [
+            -1.0, -1.0,
+            1.0, -1.0,
+            -1.0, 1.0,
+            -1.0, 1.0,
+            1.0, -1.0,
+            1.0, 1.0
+        ];
+
+        let quad = gfx::Mesh::new(gl, program.id(), &[("position", 2)])?;
+        quad.upload(&vertex_data);
+        self.quad = Some(quad);
+        self.program = Some(program);
+
+        Ok(())
+    }
+
+    // Bind and clear the framebuffer that layers will be drawn into.
+    pub fn begin(&self, gl: &gfx::gl::Gl, framebuffer: &gfx::Framebuffer) {
+        let (width, height) = (framebuffer.texture().width(), framebuffer.texture().height());
+        self.output_size.set((width, height));
+
+        framebuffer.bind();
+        unsafe {
+            gl_try!(gl; gl.Viewport(0, 0, width, height));
+            gl_try!(gl; gl.ClearColor(0.0, 0.0, 0.0, 1.0));
+            gl_try!(gl; gl.Clear(gl::COLOR_BUFFER_BIT));
+        }
+    }
+
+    // Stretch the texture over the whole output.
+    pub fn draw_layer(&self, gl: &gfx::gl::Gl, texture: &gfx::Texture, blend_mode: BlendMode) {
+        self.draw(gl, texture, &Draw::new(blend_mode, self.gamma));
+    }
+
+    // Like `draw_layer`, for what's drawn over the encoded output, e.g. the overlay.
+    pub fn draw_encoded_layer(&self, gl: &gfx::gl::Gl, texture: &gfx::Texture, blend_mode: BlendMode) {
+        self.draw(gl, texture, &Draw::new(blend_mode, 1.0));
+    }
+
+    // Draw layers composited in linear light into the output, raised to 1 / `output_gamma`,
+    // e.g. 2.2 for sRGB monitors. Dithering to `dither_bits` per channel, when not 0, hides
+    // banding in slow fades, by adding a pattern that shifts with `frame`.
+    pub fn encode(&self,
+                  gl: &gfx::gl::Gl,
+                  linear: &gfx::Texture,
+                  output_gamma: f32,
+                  dither_bits: u32,
+                  frame: u32) {
+        let dither = match dither_bits {
+            0 => None,
+            bits => Some(((1u32 << u32::min(bits, 16)) - 1, frame)),
+        };
+        self.draw(gl, linear, &Draw {
+            dither: dither.map(|(levels, frame)| (levels as f32, dither_phase(frame))),
+            ..Draw::new(BlendMode::Normal, 1.0 / output_gamma)
+        });
+    }
+
+    // Draw the middle of a square layer as big as the output's longest side, e.g. on a wide
+    // output. Layers rendered bigger than that are supersampled down, averaging the texels
+    // under each pixel, which smooths their edges.
+    pub fn draw_cropped_layer(&self, gl: &gfx::gl::Gl, texture: &gfx::Texture, blend_mode: BlendMode) {
+        let (width, height) = self.output_size.get();
+        let side = i32::max(width, height);
+        let crop = (width as f32 / side as f32, height as f32 / side as f32);
+
+        let (taps, spacing) = box_taps(texture.width() / side);
+        self.draw(gl, texture, &Draw {
+            crop,
+            taps: (taps, spacing / texture.width() as f32),
+            ..Draw::new(blend_mode, self.gamma)
+        });
+    }
+
+    fn draw(&self, gl: &gfx::gl::Gl, texture: &gfx::Texture, draw: &Draw) {
+        self.bind();
+        if let Some(ref program) = self.program {
+            let (dither_levels, dither_phase) = draw.dither.unwrap_or((0.0, 0.0));
+            program.set_vec2("crop", draw.crop.0, draw.crop.1);
+            program.set_f32("sample_taps", draw.taps.0 as f32);
+            program.set_f32("sample_spacing", draw.taps.1);
+            program.set_f32("exponent", draw.exponent);
+            program.set_f32("dither_levels", dither_levels);
+            program.set_f32("dither_phase", dither_phase);
+        }
+        texture.bind(0);
+
+        unsafe {
+            let (src_factor, dst_factor) = draw.blend_mode.blend_func();
+            gl_try!(gl; gl.BlendFunc(src_factor, dst_factor));
+
+            if let Some(ref quad) = self.quad {
+                quad.draw();
+            }
+        }
+    }
+
+    // Darken everything drawn so far, by multiplying each channel with a constant from 0 to 1.
+    pub fn scale_colors(&self, gl: &gfx::gl::Gl, scales: (f32, f32, f32)) {
+        self.bind();
+
+        unsafe {
+            gl_try!(gl; gl.BlendColor(scales.0, scales.1, scales.2, 1.0));
+            gl_try!(gl; gl.BlendFunc(gl::ZERO, gl::CONSTANT_COLOR));
+
+            if let Some(ref quad) = self.quad {
+                quad.draw();
+            }
+        }
+    }
+
+    fn bind(&self) {
+        if let Some(ref program) = self.program {
+            program.bind();
+            program.set_i32("layer_sampler", 0);
+        }
+    }
+
+    // Restore the blend function visualizers expect to draw with.
+    pub fn end(&self, gl: &gfx::gl::Gl) {
+        unsafe {
+            gl_try!(gl; gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA));
+        }
+    }
+}
+
+// The taps along each axis that average the `factor` by `factor` texels under a pixel of a
+// layer that many times bigger than the output, and how many texels apart they are. With an
+// even factor, each linearly filtered tap sits between four texels and averages them, so half
+// as many are needed. Factors past MAX_TAPS are only partly averaged.
+fn box_taps(factor: i32) -> (u32, f32) {
+    match factor {
+        x if x <= 1 => (1, 0.0),
+        x if x % 2 == 0 => (u32::min(x as u32 / 2, MAX_TAPS), 2.0),
+        x => (u32::min(x as u32, MAX_TAPS), 1.0),
+    }
+}
+
+// Offset added to the dither pattern's thresholds on a frame. Stepping by the golden ratio
+// spreads each pixel's thresholds evenly over time, so the dithering averages out.
+pub fn dither_phase(frame: u32) -> f32 {
+    (frame % 1000) as f32 * 0.618034 % 1.0
+}
+
+// The threshold, from 0 to 1, of a pixel in a 4x4 ordered dither pattern.
+pub fn bayer4(x: u32, y: u32) -> f32 {
+    let bayer2 = |x: u32, y: u32| ((x % 2) * 2 + ((x + y) % 2)) as f32;
+    (bayer2(x, y) * 4.0 + bayer2(x / 2, y / 2) + 0.5) / 16.0
+}
+
+// The most taps along each axis the shader averages.
+const MAX_TAPS: u32 = 8;
+
+const VS_SRC: &[u8] = b"
+#version 100
+precision mediump float;
+
+attribute vec2 position;
+
+varying vec2 v_position;
+
+void main(){
+    gl_Position = vec4(position, 0.0, 1.0);
+    v_position = position;
+}
+\0";
+
+const FS_SRC: &[u8] = b"
+#version 100
+precision mediump float;
+
+uniform sampler2D layer_sampler;
+// Fraction of the layer shown, around its center.
+uniform vec2 crop;
+// Supersampling taps averaged along each axis, at most 8, and their spacing, centered on the pixel.
+uniform float sample_taps;
+uniform float sample_spacing;
+// Power colors are raised to, to decode or encode their gamma.
+uniform float exponent;
+// Levels per channel to dither to, or 0 not to.
+uniform float dither_levels;
+uniform float dither_phase;
+
+// Interpolated from the Vertex Shader.
+varying vec2 v_position;
+
+// Matches bayer2 and bayer4 on the CPU.
+float bayer2(vec2 p) {
+    p = mod(p, 2.0);
+    return p.x * 2.0 + mod(p.x + p.y, 2.0);
+}
+
+float bayer4(vec2 p) {
+    p = floor(p);
+    return (bayer2(p) * 4.0 + bayer2(floor(p / 2.0)) + 0.5) / 16.0;
+}
+
+void main() {
+    vec2 position = v_position * crop;
+    vec2 uv = (position + 1.0) / 2.0;
+    vec4 color = vec4(0.0);
+    vec2 first = uv - (sample_taps - 1.0) / 2.0 * sample_spacing;
+    for (int y = 0; y < 8; y++) {
+        if (float(y) >= sample_taps) {
+            break;
+        }
+        for (int x = 0; x < 8; x++) {
+            if (float(x) >= sample_taps) {
+                break;
+            }
+            color += texture2D(layer_sampler, first + vec2(float(x), float(y)) * sample_spacing);
+        }
+    }
+    color /= sample_taps * sample_taps;
+    color.rgb = pow(color.rgb, vec3(exponent));
+    if (dither_levels > 0.0) {
+        float threshold = fract(bayer4(gl_FragCoord.xy) + dither_phase);
+        color.rgb += (threshold - 0.5) / dither_levels;
+    }
+    gl_FragColor = color;
+}
+\0";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_taps_cover_the_texels_under_a_pixel() {
+        for factor in 1..=8 {
+            let (taps, spacing) = box_taps(factor);
+            // Each tap averages spacing by spacing texels, side by side.
+            assert_eq!(taps as f32 * f32::max(spacing, 1.0), factor as f32, "factor {}", factor);
+        }
+        assert_eq!(box_taps(0), (1, 0.0));
+        assert_eq!(box_taps(4), (2, 2.0));
+        assert_eq!(box_taps(40), (MAX_TAPS, 2.0));
+    }
+}
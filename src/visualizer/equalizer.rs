@@ -1,110 +1,130 @@
 use audio;
-use gfx;
-use gfx::gl;
-use std::mem;
-use std::ptr;
-use visualizer::visualizer::SubVisualizer;
+use visualizer::bands::BandMapping;
+use visualizer::render_graph::{Pass, SCENE};
+use visualizer::visualizer::{self, Draw, SubVisualizer};
 
 const NUM_SQUARES: usize = 7;
 const NUM_VERTICIES_PER_SQUARE: usize = 6;
 const NUM_ATTRIBUTES_PER_VERTEX: usize = 7;
 const NUM_FLOATS: usize = NUM_SQUARES * NUM_VERTICIES_PER_SQUARE * NUM_ATTRIBUTES_PER_VERTEX;
+const DEFAULT_GLOW: f32 = 0.6;
 
 pub struct EqualizerVisualizer {
-    program_id: u32,
-    framebuffer_id: u32,
     vertex_data: Vec<f32>,
 
     phase: f32,
+    // Scales how fast the phase advances.
+    speed: f32,
+    // Shrinks the squares to fit outputs that aren't square.
+    scale: f32,
+    // How strongly the bright squares bloom, from 0 for no glow.
+    glow: f32,
 }
 
 impl SubVisualizer for EqualizerVisualizer {
     fn new() -> EqualizerVisualizer {
         EqualizerVisualizer {
-            program_id: 0,
-            framebuffer_id: 0,
             vertex_data: Vec::new(),
 
             phase: 0.0,
+            speed: 1.0,
+            scale: 1.0,
+            glow: DEFAULT_GLOW,
         }
     }
 
-    fn post_setup(&mut self, program_id: u32, framebuffer_id: u32) {
-        self.program_id = program_id;
-        self.framebuffer_id = framebuffer_id;
+    fn bands(&self) -> Vec<(&'static str, audio::FrequencyBand)> {
+        // Split evenly between the squares, with the outermost square showing the highest frequencies.
+        vec![("spectrum", audio::FrequencyBand::new(0.0, 700.0))]
     }
 
-    fn update(&mut self, audio_frame: audio::AudioFrame) {
-        self.vertex_data = generate_vertex_data(audio_frame);
-        self.phase += 0.1;
+    fn attributes(&self) -> Vec<(&'static str, usize)> {
+        vec![("position", 2), ("color", 3), ("radius", 1), ("power", 1)]
+    }
+
+    fn update(&mut self, audio_frame: audio::AudioFrame, bands: &BandMapping) {
+        self.vertex_data = generate_vertex_data(audio_frame, bands.band("spectrum"));
+        self.phase += 0.1 * self.speed;
         if self.phase >= 3.14 * 2.0 {
             self.phase -= 3.14 * 2.0;
         }
     }
 
-    fn render_to_texture(&self, gl: &gfx::gl::Gl) {
-        unsafe {
-            gl_try!(gl; gl.UseProgram(self.program_id));
-
-            let mut vb = mem::uninitialized();
-            gl_try!(gl; gl.GenBuffers(1, &mut vb));
-            gl_try!(gl; gl.BindBuffer(gl::ARRAY_BUFFER, vb));
-            gl_try!(gl; gl.BufferData(
-                gl::ARRAY_BUFFER,
-                (self.vertex_data.len() * mem::size_of::<f32>()) as gl::types::GLsizeiptr,
-                self.vertex_data.as_ptr() as *const _,
-                gl::STATIC_DRAW,
-            ));
-
-            let mut vao = mem::uninitialized();
-            gl_try!(gl; gl.GenVertexArrays(1, &mut vao));
-            gl_try!(gl; gl.BindVertexArray(vao));
-
-            let pos_attrib = gl_try!(gl; gl.GetAttribLocation(self.program_id, b"position\0".as_ptr() as *const _));
-            let color_attrib = gl_try!(gl; gl.GetAttribLocation(self.program_id, b"color\0".as_ptr() as *const _));
-            let radius_attrib = gl_try!(gl; gl.GetAttribLocation(self.program_id, b"radius\0".as_ptr() as *const _));
-            let power_attrib = gl_try!(gl; gl.GetAttribLocation(self.program_id, b"power\0".as_ptr() as *const _));
-            gl_try!(gl; gl.VertexAttribPointer(
-                pos_attrib as gl::types::GLuint, 2, gl::FLOAT, 0,
-                7 * mem::size_of::<f32>() as gl::types::GLsizei,
-                ptr::null(),
-            ));
-            gl_try!(gl; gl.VertexAttribPointer(
-                color_attrib as gl::types::GLuint, 3, gl::FLOAT, 0,
-                7 * mem::size_of::<f32>() as gl::types::GLsizei,
-                (2 * mem::size_of::<f32>()) as *const () as *const _,
-            ));
-            gl_try!(gl; gl.VertexAttribPointer(
-                radius_attrib as gl::types::GLuint, 1, gl::FLOAT, 0,
-                7 * mem::size_of::<f32>() as gl::types::GLsizei,
-                (5 * mem::size_of::<f32>()) as *const () as *const _,
-            ));
-            gl_try!(gl; gl.VertexAttribPointer(
-                power_attrib as gl::types::GLuint, 1, gl::FLOAT, 0,
-                7 * mem::size_of::<f32>() as gl::types::GLsizei,
-                (6 * mem::size_of::<f32>()) as *const () as *const _,
-            ));
-            gl_try!(gl; gl.EnableVertexAttribArray(pos_attrib as gl::types::GLuint));
-            gl_try!(gl; gl.EnableVertexAttribArray(color_attrib as gl::types::GLuint));
-            gl_try!(gl; gl.EnableVertexAttribArray(radius_attrib as gl::types::GLuint));
-            gl_try!(gl; gl.EnableVertexAttribArray(power_attrib as gl::types::GLuint));
-
-            let phase_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"phase\0".as_ptr() as *const _));
-            gl_try!(gl; gl.Uniform1f(phase_uniform, self.phase));
-
-            gl_try!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer_id));
-
-            gl_try!(gl; gl.ClearColor(0.0, 0.0, 0.0, 1.0));
-            gl_try!(gl; gl.Clear(gl::COLOR_BUFFER_BIT));
-
-            gl_try!(gl; gl.DrawBuffers(1, [gl::COLOR_ATTACHMENT0].as_ptr()));
-
-            gl_try!(gl; gl.DrawArrays(gl::TRIANGLES, 0,
-                (NUM_SQUARES * NUM_VERTICIES_PER_SQUARE) as i32));
-
-            gl_try!(gl; gl.DeleteBuffers(1, &vb));
-            gl_try!(gl; gl.DeleteVertexArrays(1, &vao));
+    fn draw(&self) -> Draw<'_> {
+        Draw {
+            uniforms: vec![
+                ("phase", self.phase),
+                ("scale", self.scale),
+            ],
+            vertices: &self.vertex_data,
+            clear_color: Some((0.0, 0.0, 0.0, 1.0)),
+        }
+    }
+
+    fn renders_in_software(&self) -> bool {
+        true
+    }
+
+    fn shade(&self, x: f32, y: f32) -> Option<(f32, f32, f32)> {
+        let (x, y) = (x / self.scale, y / self.scale);
+        // Smaller squares are drawn over bigger ones, so the last one that covers the point wins.
+        let floats_per_square = NUM_VERTICIES_PER_SQUARE * NUM_ATTRIBUTES_PER_VERTEX;
+        self.vertex_data.chunks(floats_per_square).rev()
+            .find(|square| x * x + y * y <= square[5] * square[5])
+            .map(|square| (square[2] * square[6], square[3] * square[6], square[4] * square[6]))
+    }
+
+    fn save_state(&self) -> Vec<f32> {
+        vec![self.phase]
+    }
+
+    fn restore_state(&mut self, state: &[f32]) {
+        if let Some(phase) = state.first() {
+            self.phase = *phase;
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f32) -> bool {
+        match name {
+            "speed" => self.speed = f32::max(0.0, value),
+            "glow" => self.glow = f32::max(0.0, value),
+
+            _ => return false,
         }
+
+        true
+    }
+
+    fn parameter(&self, name: &str) -> Option<f32> {
+        match name {
+            "speed" => Some(self.speed),
+            "glow" => Some(self.glow),
+
+            _ => None,
+        }
+    }
+
+    fn parameter_names(&self) -> Vec<&'static str> {
+        vec!["speed", "glow"]
+    }
+
+    // Bloom: the bright parts, at half size as they're blurred anyway, blurred one way then the
+    // other and added back onto the squares.
+    fn passes(&self) -> Vec<Pass> {
+        vec![
+            Pass { name: "bright", fs_src: BRIGHT_FS_SRC, inputs: vec![SCENE], scale: 0.5 },
+            Pass { name: "blur_x", fs_src: BLUR_X_FS_SRC, inputs: vec!["bright"], scale: 0.5 },
+            Pass { name: "blur_y", fs_src: BLUR_Y_FS_SRC, inputs: vec!["blur_x"], scale: 0.5 },
+            Pass { name: "glow", fs_src: GLOW_FS_SRC, inputs: vec![SCENE, "blur_y"], scale: 1.0 },
+        ]
+    }
+
+    fn pass_uniforms(&self) -> Vec<(&'static str, f32)> {
+        vec![("glow", self.glow)]
+    }
+
+    fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.scale = visualizer::fit_scale(aspect_ratio);
     }
 
     fn vs_src(&self) -> &[u8] {
@@ -115,6 +135,7 @@ precision mediump float;
 #define PI 3.1415926535897932384626433832795
 
 uniform float phase;
+uniform float scale;
 
 attribute vec2 position;
 attribute vec3 color;
@@ -131,7 +152,7 @@ void main() {
     v_position = position;
     // float x = sin((v_position.y + 2.0) * PI + phase) * radius / 1.0;
     // float y = sin((v_position.x + 2.0) * PI + phase) * radius / 1.0;
-    gl_Position = vec4(v_position, 0.0, 1.0);
+    gl_Position = vec4(v_position * scale, 0.0, 1.0);
     v_color = color;
     v_radius = radius;
     v_power = power;
@@ -166,7 +187,7 @@ void main() {
     }
 }
 
-fn generate_vertex_data(audio_frame: audio::AudioFrame) -> Vec<f32> {
+fn generate_vertex_data(audio_frame: audio::AudioFrame, spectrum: audio::FrequencyBand) -> Vec<f32> {
     let square_sizes = [
         1.0,
         1.0 - 1.0 / 7.0,
@@ -201,19 +222,9 @@ fn generate_vertex_data(audio_frame: audio::AudioFrame) -> Vec<f32> {
         ]);
     };
 
-    let buckets_per_square = (
-        6 as f32 / NUM_SQUARES as f32
-    ).round() as usize;
+    let square_bands = spectrum.split(NUM_SQUARES);
     for i in 0..NUM_SQUARES {
-        let mut amplitude = 0.0;
-        for j in 0..buckets_per_square {
-            let index = i * buckets_per_square + j;
-            if index >= audio_frame.hundred_hz_buckets.len() {
-                break;
-            }
-            amplitude += audio_frame.hundred_hz_buckets[NUM_SQUARES - index - 1];
-        }
-        amplitude = f32::min(1.0, amplitude);
+        let amplitude = f32::min(1.0, audio_frame.band_power(square_bands[NUM_SQUARES - i - 1]));
 
         let color = (square_colors[i].0 as f32 / 255.0,
                      square_colors[i].1 as f32 / 255.0,
@@ -223,3 +234,73 @@ fn generate_vertex_data(audio_frame: audio::AudioFrame) -> Vec<f32> {
 
     vertex_data
 }
+
+const BRIGHT_FS_SRC: &[u8] = b"
+#version 100
+precision mediump float;
+
+uniform sampler2D scene;
+
+varying vec2 v_uv;
+
+void main() {
+    vec4 color = texture2D(scene, v_uv);
+    // Only the brighter half of each square blooms.
+    gl_FragColor = vec4(max(color.rgb - 0.5, 0.0) * 2.0, color.a);
+}
+\0";
+
+const BLUR_X_FS_SRC: &[u8] = b"
+#version 100
+precision mediump float;
+
+uniform sampler2D bright;
+uniform float resolution;
+
+varying vec2 v_uv;
+
+void main() {
+    vec2 texel = vec2(1.5 / resolution, 0.0);
+    gl_FragColor = texture2D(bright, v_uv) * 0.227027 +
+        (texture2D(bright, v_uv + texel) + texture2D(bright, v_uv - texel)) * 0.1945946 +
+        (texture2D(bright, v_uv + 2.0 * texel) + texture2D(bright, v_uv - 2.0 * texel)) * 0.1216216 +
+        (texture2D(bright, v_uv + 3.0 * texel) + texture2D(bright, v_uv - 3.0 * texel)) * 0.054054 +
+        (texture2D(bright, v_uv + 4.0 * texel) + texture2D(bright, v_uv - 4.0 * texel)) * 0.016216;
+}
+\0";
+
+const BLUR_Y_FS_SRC: &[u8] = b"
+#version 100
+precision mediump float;
+
+uniform sampler2D blur_x;
+uniform float resolution;
+
+varying vec2 v_uv;
+
+void main() {
+    vec2 texel = vec2(0.0, 1.5 / resolution);
+    gl_FragColor = texture2D(blur_x, v_uv) * 0.227027 +
+        (texture2D(blur_x, v_uv + texel) + texture2D(blur_x, v_uv - texel)) * 0.1945946 +
+        (texture2D(blur_x, v_uv + 2.0 * texel) + texture2D(blur_x, v_uv - 2.0 * texel)) * 0.1216216 +
+        (texture2D(blur_x, v_uv + 3.0 * texel) + texture2D(blur_x, v_uv - 3.0 * texel)) * 0.054054 +
+        (texture2D(blur_x, v_uv + 4.0 * texel) + texture2D(blur_x, v_uv - 4.0 * texel)) * 0.016216;
+}
+\0";
+
+const GLOW_FS_SRC: &[u8] = b"
+#version 100
+precision mediump float;
+
+uniform sampler2D scene;
+uniform sampler2D blur_y;
+uniform float glow;
+
+varying vec2 v_uv;
+
+void main() {
+    vec4 color = texture2D(scene, v_uv);
+    vec4 bloom = texture2D(blur_y, v_uv) * glow;
+    gl_FragColor = vec4(min(color.rgb + bloom.rgb, 1.0), min(color.a + bloom.a, 1.0));
+}
+\0";
@@ -0,0 +1,168 @@
+use audio;
+use std::f32;
+use std::time;
+use visualizer::bands::BandMapping;
+use visualizer::visualizer::{Draw, SubVisualizer};
+
+// Seconds before the animation repeats. Both the breathing and gradient periods divide it evenly.
+const LOOP_SECONDS: f32 = 60.0;
+// Match BREATH_SECONDS and GRADIENT_SECONDS in the fragment shader.
+const BREATH_SECONDS: f32 = 4.0;
+const GRADIENT_SECONDS: f32 = 60.0;
+
+// A dim breathing glow over a slowly shifting gradient, shown while the room is quiet.
+pub struct IdleVisualizer {
+    vertex_data: Vec<f32>,
+
+    seconds: f32,
+    // The visualizer clock's time at the last update, and now.
+    last_update: time::Duration,
+    now: time::Duration,
+}
+
+impl IdleVisualizer {
+    // Set the visualizer clock's time, which the next update animates to.
+    pub fn set_time(&mut self, now: time::Duration) {
+        self.now = now;
+    }
+}
+
+impl SubVisualizer for IdleVisualizer {
+    fn new() -> IdleVisualizer {
+        IdleVisualizer {
+            vertex_data: generate_vertex_data(),
+
+            seconds: 0.0,
+            last_update: time::Duration::from_secs(0),
+            now: time::Duration::from_secs(0),
+        }
+    }
+
+    fn bands(&self) -> Vec<(&'static str, audio::FrequencyBand)> {
+        Vec::new()
+    }
+
+    fn attributes(&self) -> Vec<(&'static str, usize)> {
+        vec![("position", 2)]
+    }
+
+    fn update(&mut self, _audio_frame: audio::AudioFrame, _bands: &BandMapping) {
+        // Animate by the clock, as audio frames arrive at whatever rate the listener produces them.
+        let elapsed = self.now.saturating_sub(self.last_update);
+        self.last_update = self.now;
+
+        self.seconds += elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 / 1_000_000_000.0;
+        if self.seconds >= LOOP_SECONDS {
+            self.seconds -= LOOP_SECONDS;
+        }
+    }
+
+    fn draw(&self) -> Draw<'_> {
+        Draw {
+            uniforms: vec![("seconds", self.seconds)],
+            vertices: &self.vertex_data,
+            clear_color: Some((0.0, 0.0, 0.0, 1.0)),
+        }
+    }
+
+    fn static_vertices(&self) -> bool {
+        true
+    }
+
+    fn renders_in_software(&self) -> bool {
+        true
+    }
+
+    // The fragment shader, for drawing without GL.
+    fn shade(&self, x: f32, y: f32) -> Option<(f32, f32, f32)> {
+        let radius = (x * x + y * y).sqrt();
+        if radius > 1.0 {
+            return None;
+        }
+
+        let tau = 2.0 * f32::consts::PI;
+        let hue = (self.seconds / GRADIENT_SECONDS + radius * 0.25).fract();
+        let channel = |offset: f32| 0.5 + 0.5 * (tau * (hue + offset)).cos();
+
+        let breath = 0.5 + 0.5 * (tau * self.seconds / BREATH_SECONDS).sin();
+        let brightness = (0.05 + (0.25 - 0.05) * breath) * (1.0 - 0.5 * radius);
+
+        Some((channel(0.0) * brightness, channel(1.0 / 3.0) * brightness, channel(2.0 / 3.0) * brightness))
+    }
+
+    fn save_state(&self) -> Vec<f32> {
+        vec![self.seconds]
+    }
+
+    fn restore_state(&mut self, state: &[f32]) {
+        if let Some(seconds) = state.first() {
+            self.seconds = *seconds % LOOP_SECONDS;
+        }
+    }
+
+    fn vs_src(&self) -> &[u8] {
+        b"
+#version 100
+precision mediump float;
+
+attribute vec2 position;
+
+// Variables for the Fragment Shader.
+varying vec2 v_position;
+
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+    v_position = position;
+}
+\0"
+    }
+
+    fn fs_src(&self) -> &[u8] {
+        b"
+#version 100
+precision mediump float;
+
+#define PI 3.1415926535897932384626433832795
+#define TAU PI * 2.0
+
+#define BREATH_SECONDS 4.0
+#define GRADIENT_SECONDS 60.0
+
+uniform float seconds;
+
+// Interpolated from the Vertex Shader.
+varying vec2 v_position;
+
+void main() {
+    float radius = length(v_position);
+    if (radius > 1.0) {
+        // Out of bounds.
+        gl_FragColor = vec4(0.0);
+        return;
+    }
+
+    float hue = fract(seconds / GRADIENT_SECONDS + radius * 0.25);
+    vec3 color = 0.5 + 0.5 * cos(TAU * (hue + vec3(0.0, 1.0 / 3.0, 2.0 / 3.0)));
+
+    // Kept dim so an idle installation draws little power.
+    float breath = 0.5 + 0.5 * sin(TAU * seconds / BREATH_SECONDS);
+    float brightness = mix(0.05, 0.25, breath) * (1.0 - 0.5 * radius);
+
+    gl_FragColor = vec4(color * brightness, 1.0);
+}
+\0"
+    }
+}
+
+fn generate_vertex_data() -> Vec<f32> {
+    let size = 1.0;
+
+    vec![
+        -size, -size,
+        -size, size,
+        size, size,
+        -size, -size,
+        size, -size,
+        size, size,
+    ]
+}
@@ -1,7 +1,22 @@
-pub use self::visualizer::Visualizer;
+pub use self::background::Background;
+pub use self::bands::BandOverride;
+pub use self::compositor::{BlendMode, Layer};
+pub use self::overlay::TextOverlay;
+pub use self::visualizer::{Visualizer, VISUALIZER_NAMES};
+pub use self::white_balance::WhiteBalance;
 
+mod background;
+mod bands;
+mod clock;
+mod compositor;
 mod visualizer;
 mod power_circles;
 mod equalizer;
+mod font;
+mod idle;
+mod overlay;
+mod render_graph;
 mod smiley;
+mod state;
 mod symmetry;
+mod white_balance;
@@ -0,0 +1,159 @@
+use gfx;
+use gfx::gl;
+use std::fs;
+use std::path::PathBuf;
+use std::time;
+use visualizer::font;
+
+const BACKING_ALPHA: u8 = 160;
+
+// Small text (visualizer name, FPS, track info) drawn over the visualizer output. The text is
+// rasterized on the CPU with the built-in bitmap font, and only re-uploaded when it changes.
+pub struct TextOverlay {
+    texture: Option<gfx::Texture>,
+    width: usize,
+    height: usize,
+    enabled: bool,
+
+    frames: u32,
+    fps: u32,
+    // The visualizer clock's time when `fps` was last counted.
+    last_fps_update: time::Duration,
+
+    // Re-read every second, so whatever is playing music can write the current track into it.
+    track_path: Option<PathBuf>,
+    track_info: Vec<String>,
+
+    uploaded_lines: Vec<String>,
+}
+
+impl TextOverlay {
+    pub fn new(enabled: bool, track_path: Option<PathBuf>) -> TextOverlay {
+        TextOverlay {
+            texture: None,
+            width: 0,
+            height: 0,
+            enabled,
+
+            frames: 0,
+            fps: 0,
+            last_fps_update: time::Duration::from_secs(0),
+
+            track_path,
+            track_info: Vec::new(),
+
+            uploaded_lines: Vec::new(),
+        }
+    }
+
+    pub fn setup(&mut self, gl: &gfx::gl::Gl, width: i32, height: i32) -> Result<(), gfx::GlError> {
+        self.width = width as usize;
+        self.height = height as usize;
+        // Nearest filtering keeps the font's pixels crisp when scaled.
+        self.texture = Some(gfx::Texture::new(gl, width, height, gl::RGBA, gl::NEAREST)?);
+        Ok(())
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    // Count a rendered frame at the visualizer clock's time `now` and refresh the text. Returns the
+    // texture to draw, if the overlay is shown.
+    pub fn prepare(&mut self, visualizer_name: &str, now: time::Duration) -> Option<&gfx::Texture> {
+        self.frames += 1;
+        if now.saturating_sub(self.last_fps_update) >= time::Duration::from_secs(1) {
+            self.fps = self.frames;
+            self.frames = 0;
+            self.last_fps_update = now;
+            self.read_track_info();
+        }
+
+        if !self.enabled {
+            return None;
+        }
+
+        let mut lines = vec![visualizer_name.replace('_', " "), format!("{} FPS", self.fps)];
+        lines.extend(self.track_info.iter().cloned());
+
+        let texture = match self.texture {
+            Some(ref x) => x,
+            None => return None,
+        };
+        if lines != self.uploaded_lines {
+            texture.upload(&rasterize(&lines, self.width, self.height));
+            self.uploaded_lines = lines;
+        }
+
+        Some(texture)
+    }
+
+    fn read_track_info(&mut self) {
+        if let Some(ref path) = self.track_path {
+            self.track_info = match fs::read_to_string(path) {
+                Ok(contents) => contents.lines()
+                    .map(|line| line.trim().to_string())
+                    .filter(|line| !line.is_empty())
+                    .collect(),
+                Err(_) => Vec::new(),
+            };
+        }
+    }
+}
+
+// Draw the lines centered in a width x height RGBA image, on a dim backing so they stay readable
+// over bright visuals. Centered since round displays lose their corners. Rows are bottom to top.
+fn rasterize(lines: &[String], width: usize, height: usize) -> Vec<u8> {
+    let scale = usize::max(1, usize::min(width, height) / 64);
+    let advance = (font::GLYPH_WIDTH + 1) * scale;
+    let line_height = (font::GLYPH_HEIGHT + 1) * scale;
+    let max_chars = width / advance;
+
+    let lines: Vec<Vec<char>> = lines.iter()
+        .map(|line| line.chars().take(max_chars).collect())
+        .take(height / line_height)
+        .collect();
+
+    let mut pixels = vec![0u8; 4 * width * height];
+    let mut set_pixel = |x: usize, y: usize, rgba: [u8; 4]| {
+        if x < width && y < height {
+            let index = 4 * (x + (height - 1 - y) * width);
+            pixels[index..index + 4].copy_from_slice(&rgba);
+        }
+    };
+
+    let block_height = lines.len() * line_height;
+    let top = (height - block_height) / 2;
+    for (line_index, line) in lines.iter().enumerate() {
+        let line_width = line.len() * advance;
+        let left = (width - line_width) / 2;
+        let line_top = top + line_index * line_height;
+
+        for y in line_top..line_top + line_height {
+            for x in left..left + line_width + scale {
+                set_pixel(x, y, [0, 0, 0, BACKING_ALPHA]);
+            }
+        }
+
+        for (char_index, c) in line.iter().enumerate() {
+            let glyph = font::glyph(*c);
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..font::GLYPH_WIDTH {
+                    if bits & (1 << (font::GLYPH_WIDTH - 1 - column)) == 0 {
+                        continue;
+                    }
+
+                    let x = left + scale + char_index * advance + column * scale;
+                    let y = line_top + scale / 2 + row * scale;
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            set_pixel(x + dx, y + dy, [255, 255, 255, 255]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pixels
+}
@@ -1,97 +1,89 @@
 use audio;
-use gfx;
-use gfx::gl;
-use std::mem;
-use std::ptr;
-use visualizer::visualizer::SubVisualizer;
+use visualizer::bands::BandMapping;
+use visualizer::visualizer::{self, Draw, SubVisualizer};
 
 const NUM_SQUARES: usize = 4;
 const NUM_VERTICIES_PER_SQUARE: usize = 6;
 const NUM_ATTRIBUTES_PER_VERTEX: usize = 7;
 const NUM_FLOATS: usize = NUM_SQUARES * NUM_VERTICIES_PER_SQUARE * NUM_ATTRIBUTES_PER_VERTEX;
+const DEFAULT_TRAILS: f32 = 0.8;
 
 pub struct PowerCirclesVisualizer {
-    program_id: u32,
-    framebuffer_id: u32,
     vertex_data: [f32; NUM_FLOATS],
+    // Shrinks the circles to fit outputs that aren't square.
+    scale: f32,
+    // How much of the last frame is left behind the sectors each frame, from 0 for no trails.
+    trails: f32,
 }
 
 impl SubVisualizer for PowerCirclesVisualizer {
     fn new() -> PowerCirclesVisualizer {
         PowerCirclesVisualizer {
-            program_id: 0,
-            framebuffer_id: 0,
             vertex_data: [0.0; NUM_FLOATS],
+            scale: 1.0,
+            trails: DEFAULT_TRAILS,
         }
     }
 
-    fn post_setup(&mut self, program_id: u32, framebuffer_id: u32) {
-        self.program_id = program_id;
-        self.framebuffer_id = framebuffer_id;
+    fn bands(&self) -> Vec<(&'static str, audio::FrequencyBand)> {
+        vec![
+            ("low", audio::FrequencyBand::new(0.0, 1000.0)),
+            ("mid", audio::FrequencyBand::new(1000.0, 4000.0)),
+            ("high", audio::FrequencyBand::new(4000.0, 20000.0)),
+        ]
     }
 
-    fn update(&mut self, audio_frame: audio::AudioFrame) {
-        let low = audio_frame.low_power;
-        let mid = audio_frame.mid_power;
-        let high = audio_frame.high_power;
+    fn attributes(&self) -> Vec<(&'static str, usize)> {
+        vec![("position", 2), ("color", 3), ("radius", 1), ("power", 1)]
+    }
+
+    fn update(&mut self, audio_frame: audio::AudioFrame, bands: &BandMapping) {
+        let low = f32::min(1.0, bands.power(&audio_frame, "low"));
+        let mid = f32::min(1.0, bands.power(&audio_frame, "mid"));
+        let high = f32::min(1.0, bands.power(&audio_frame, "high"));
 
         self.vertex_data = generate_vertex_data(low, mid, high);
     }
 
-    fn render_to_texture(&self, gl: &gfx::gl::Gl) {
-        unsafe {
-            gl_try!(gl; gl.UseProgram(self.program_id));
-
-            let mut vb = mem::uninitialized();
-            gl_try!(gl; gl.GenBuffers(1, &mut vb));
-            gl_try!(gl; gl.BindBuffer(gl::ARRAY_BUFFER, vb));
-            gl_try!(gl; gl.BufferData(
-                gl::ARRAY_BUFFER,
-                (self.vertex_data.len() * mem::size_of::<f32>()) as gl::types::GLsizeiptr,
-                self.vertex_data.as_ptr() as *const _,
-                gl::STATIC_DRAW,
-            ));
-
-            let mut vao = mem::uninitialized();
-            gl_try!(gl; gl.GenVertexArrays(1, &mut vao));
-            gl_try!(gl; gl.BindVertexArray(vao));
-
-            let pos_attrib = gl_try!(gl; gl.GetAttribLocation(self.program_id, b"position\0".as_ptr() as *const _));
-            let color_attrib = gl_try!(gl; gl.GetAttribLocation(self.program_id, b"color\0".as_ptr() as *const _));
-            let radius_attrib = gl_try!(gl; gl.GetAttribLocation(self.program_id, b"radius\0".as_ptr() as *const _));
-            let power_attrib = gl_try!(gl; gl.GetAttribLocation(self.program_id, b"power\0".as_ptr() as *const _));
-            gl_try!(gl; gl.VertexAttribPointer(
-                pos_attrib as gl::types::GLuint, 2, gl::FLOAT, 0,
-                7 * mem::size_of::<f32>() as gl::types::GLsizei,
-                ptr::null(),
-            ));
-            gl_try!(gl; gl.VertexAttribPointer(
-                color_attrib as gl::types::GLuint, 3, gl::FLOAT, 0,
-                7 * mem::size_of::<f32>() as gl::types::GLsizei,
-                (2 * mem::size_of::<f32>()) as *const () as *const _,
-            ));
-            gl_try!(gl; gl.VertexAttribPointer(
-                radius_attrib as gl::types::GLuint, 1, gl::FLOAT, 0,
-                7 * mem::size_of::<f32>() as gl::types::GLsizei,
-                (5 * mem::size_of::<f32>()) as *const () as *const _,
-            ));
-            gl_try!(gl; gl.VertexAttribPointer(
-                power_attrib as gl::types::GLuint, 1, gl::FLOAT, 0,
-                7 * mem::size_of::<f32>() as gl::types::GLsizei,
-                (6 * mem::size_of::<f32>()) as *const () as *const _,
-            ));
-            gl_try!(gl; gl.EnableVertexAttribArray(pos_attrib as gl::types::GLuint));
-            gl_try!(gl; gl.EnableVertexAttribArray(color_attrib as gl::types::GLuint));
-            gl_try!(gl; gl.EnableVertexAttribArray(radius_attrib as gl::types::GLuint));
-            gl_try!(gl; gl.EnableVertexAttribArray(power_attrib as gl::types::GLuint));
-
-            gl_try!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer_id));
-            gl_try!(gl; gl.DrawBuffers(1, [gl::COLOR_ATTACHMENT0].as_ptr()));
-            gl_try!(gl; gl.DrawArrays(gl::TRIANGLES, 0, 6 * 4));
-
-            gl_try!(gl; gl.DeleteBuffers(1, &vb));
-            gl_try!(gl; gl.DeleteVertexArrays(1, &vao));
+    fn draw(&self) -> Draw<'_> {
+        Draw {
+            uniforms: vec![
+                ("scale", self.scale),
+                ("trails", self.trails),
+            ],
+            vertices: &self.vertex_data,
+            clear_color: None,
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f32) -> bool {
+        match name {
+            "trails" => self.trails = value.clamp(0.0, 0.99),
+
+            _ => return false,
         }
+
+        true
+    }
+
+    fn parameter(&self, name: &str) -> Option<f32> {
+        match name {
+            "trails" => Some(self.trails),
+
+            _ => None,
+        }
+    }
+
+    fn parameter_names(&self) -> Vec<&'static str> {
+        vec!["trails"]
+    }
+
+    fn uses_feedback(&self) -> bool {
+        true
+    }
+
+    fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.scale = visualizer::fit_scale(aspect_ratio);
     }
 
     fn vs_src(&self) -> &[u8] {
@@ -99,6 +91,8 @@ impl SubVisualizer for PowerCirclesVisualizer {
 #version 100
 precision mediump float;
 
+uniform float scale;
+
 attribute vec2 position;
 attribute vec3 color;
 attribute float radius;
@@ -109,9 +103,11 @@ varying vec2 v_position;
 varying vec3 v_color;
 varying float v_radius;
 varying float v_power;
+varying vec2 v_texcoord;
 
 void main() {
-    gl_Position = vec4(position, 0.0, 1.0);
+    gl_Position = vec4(position * scale, 0.0, 1.0);
+    v_texcoord = position * scale * 0.5 + 0.5;
     v_position = position;
     v_color = color;
     v_radius = radius;
@@ -127,11 +123,15 @@ precision mediump float;
 
 #define PI 3.1415926535897932384626433832795
 
+uniform sampler2D previous_frame;
+uniform float trails;
+
 // Interpolated from the Vertex Shader.
 varying vec2 v_position;
 varying vec3 v_color;
 varying float v_radius;
 varying float v_power;
+varying vec2 v_texcoord;
 
 void main() {
     if ((v_position.x * v_position.x) + (v_position.y * v_position.y) > v_radius * v_radius) {
@@ -162,7 +162,8 @@ void main() {
             float y_scaling = (-v_position.y + 3.0) / (1.0 + 3.0);
             gl_FragColor = vec4(v_color * y_scaling, 1.0);
         } else {
-            gl_FragColor = vec4(0.0, 0.0, 0.0, 1.0);
+            // Fading what the sectors drew before leaves trails behind them.
+            gl_FragColor = vec4(texture2D(previous_frame, v_texcoord).rgb * trails, 1.0);
         }
     }
 }
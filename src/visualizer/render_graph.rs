@@ -0,0 +1,226 @@
+use gfx;
+use gfx::gl;
+
+// The input name for what the visualizer itself rendered.
+pub const SCENE: &str = "scene";
+
+// A full screen pass run after a visualizer renders, e.g. to blur or warp it. Its fragment
+// shader samples each input through a sampler uniform of the same name, either SCENE or an
+// earlier pass, and gets the side length of its target as the `resolution` uniform.
+pub struct Pass {
+    pub name: &'static str,
+    pub fs_src: &'static [u8],
+    pub inputs: Vec<&'static str>,
+    // Size of the pass's target relative to the visualizer's, below 1 for cheap blurs.
+    pub scale: f32,
+}
+
+struct BuiltPass {
+    scale: f32,
+    program: gfx::Program,
+    // Attribute locations differ between programs, so every pass has its own quad.
+    quad: gfx::Mesh,
+    inputs: Vec<Input>,
+    target: gfx::Framebuffer,
+    size: i32,
+}
+
+// A visualizer's passes, each drawing into its own intermediate target. The last one's target
+// is what gets composited.
+pub struct RenderGraph {
+    passes: Vec<BuiltPass>,
+    size: i32,
+}
+
+// An input a pass samples, bound to texture unit `unit` of the sampler named `name`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Input {
+    name: &'static str,
+    unit: u32,
+    // The earlier pass whose target is sampled, or None for the scene.
+    pass: Option<usize>,
+}
+
+// Which inputs each pass samples and where from, in the order the passes run. Passes can only
+// read the scene and passes before them, so running them in order always has their inputs ready.
+fn resolve_inputs(passes: &[Pass]) -> Result<Vec<Vec<Input>>, String> {
+    if passes.is_empty() {
+        return Err("a render graph needs at least one pass".to_string());
+    }
+
+    let mut resolved = Vec::with_capacity(passes.len());
+    for (i, pass) in passes.iter().enumerate() {
+        if pass.name == SCENE || passes[..i].iter().any(|x| x.name == pass.name) {
+            return Err(format!("pass '{}' has the name of the scene or an earlier pass", pass.name));
+        }
+
+        let mut inputs = Vec::with_capacity(pass.inputs.len());
+        for (unit, &name) in pass.inputs.iter().enumerate() {
+            let source = if name == SCENE {
+                None
+            } else {
+                match passes[..i].iter().position(|x| x.name == name) {
+                    Some(x) => Some(x),
+                    None => return Err(format!("pass '{}' reads '{}', which isn't an earlier pass", pass.name, name)),
+                }
+            };
+            inputs.push(Input { name, unit: unit as u32, pass: source });
+        }
+        resolved.push(inputs);
+    }
+
+    Ok(resolved)
+}
+
+impl RenderGraph {
+    pub fn new(gl: &gfx::gl::Gl, size: i32, passes: Vec<Pass>) -> Result<RenderGraph, String> {
+        let inputs = resolve_inputs(&passes)?;
+        let mut built: Vec<BuiltPass> = Vec::new();
+        for (pass, inputs) in passes.into_iter().zip(inputs) {
+            let program = gfx::Program::new(gl, VS_SRC, pass.fs_src)
+                .map_err(|e| format!("pass '{}' failed to build, its {}", pass.name, e))?;
+            let pass_size = scaled_size(size, pass.scale);
+            let texture = gfx::Texture::new(gl, pass_size, pass_size, gl::RGBA, gl::LINEAR)?;
+            let quad = gfx::Mesh::new(gl, program.id(), &[("position", 2)])?;
+            quad.upload(&QUAD_VERTEX_DATA);
+
+            built.push(BuiltPass {
+                scale: pass.scale,
+                program,
+                quad,
+                inputs,
+                target: gfx::Framebuffer::new(gl, texture)?,
+                size: pass_size,
+            });
+        }
+
+        Ok(RenderGraph {
+            passes: built,
+            size,
+        })
+    }
+
+    // Run every pass on the scene, with `uniforms` set on each pass that uses them.
+    pub fn run(&self, gl: &gfx::gl::Gl, scene: &gfx::Texture, uniforms: &[(&str, f32)]) {
+        unsafe {
+            // Passes replace their target's contents rather than blend onto them.
+            gl_try!(gl; gl.BlendFunc(gl::ONE, gl::ZERO));
+        }
+
+        for pass in self.passes.iter() {
+            pass.target.bind();
+            pass.program.bind();
+            for input in pass.inputs.iter() {
+                let texture = match input.pass {
+                    Some(i) => self.passes[i].target.texture(),
+                    None => scene,
+                };
+                texture.bind(input.unit);
+                pass.program.set_i32(input.name, input.unit as i32);
+            }
+            pass.program.set_f32("resolution", pass.size as f32);
+            for &(name, value) in uniforms.iter() {
+                pass.program.set_f32(name, value);
+            }
+
+            unsafe {
+                gl_try!(gl; gl.Viewport(0, 0, pass.size, pass.size));
+            }
+            pass.quad.draw();
+        }
+
+        unsafe {
+            gl_try!(gl; gl.Viewport(0, 0, self.size, self.size));
+            gl_try!(gl; gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA));
+        }
+    }
+
+    // Recreate the targets for a new visualizer size.
+    pub fn resize(&mut self, gl: &gfx::gl::Gl, size: i32) -> Result<(), String> {
+        for pass in self.passes.iter_mut() {
+            pass.size = scaled_size(size, pass.scale);
+            let texture = gfx::Texture::new(gl, pass.size, pass.size, gl::RGBA, gl::LINEAR)?;
+            pass.target = gfx::Framebuffer::new(gl, texture)?;
+        }
+        self.size = size;
+
+        Ok(())
+    }
+
+    // What the last pass rendered.
+    pub fn output(&self) -> &gfx::Texture {
+        self.passes[self.passes.len() - 1].target.texture()
+    }
+}
+
+fn scaled_size(size: i32, scale: f32) -> i32 {
+    i32::max(1, (size as f32 * scale) as i32)
+}
+
+const QUAD_VERTEX_DATA: [f32; 2 * 6] = [
+    -1.0, -1.0,
+    1.0, -1.0,
+    -1.0, 1.0,
+    -1.0, 1.0,
+    1.0, -1.0,
+    1.0, 1.0
+];
+
+const VS_SRC: &[u8] = b"
+#version 100
+precision mediump float;
+
+attribute vec2 position;
+
+// Where to sample the inputs.
+varying vec2 v_uv;
+
+void main(){
+    gl_Position = vec4(position, 0.0, 1.0);
+    v_uv = (position + 1.0) / 2.0;
+}
+\0";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pass(name: &'static str, inputs: Vec<&'static str>) -> Pass {
+        Pass {
+            name,
+            fs_src: b"\0",
+            inputs,
+            scale: 1.0,
+        }
+    }
+
+    #[test]
+    fn binds_inputs_to_units_in_their_order() {
+        let passes = vec![
+            pass("bright", vec![SCENE]),
+            pass("blur", vec!["bright"]),
+            pass("combine", vec![SCENE, "blur"]),
+        ];
+        let inputs = resolve_inputs(&passes).unwrap();
+        assert_eq!(inputs, vec![
+            vec![Input { name: SCENE, unit: 0, pass: None }],
+            vec![Input { name: "bright", unit: 0, pass: Some(0) }],
+            vec![Input { name: SCENE, unit: 0, pass: None }, Input { name: "blur", unit: 1, pass: Some(1) }],
+        ]);
+    }
+
+    #[test]
+    fn rejects_reading_later_or_unknown_passes() {
+        let passes = vec![pass("blur", vec!["bright"]), pass("bright", vec![SCENE])];
+        assert!(resolve_inputs(&passes).is_err());
+        assert!(resolve_inputs(&[pass("blur", vec!["blur"])]).is_err());
+        assert!(resolve_inputs(&[pass("blur", vec!["missing"])]).is_err());
+    }
+
+    #[test]
+    fn rejects_empty_graphs_and_repeated_names() {
+        assert!(resolve_inputs(&[]).is_err());
+        assert!(resolve_inputs(&[pass("blur", vec![SCENE]), pass("blur", vec![SCENE])]).is_err());
+        assert!(resolve_inputs(&[pass(SCENE, vec![SCENE])]).is_err());
+    }
+}
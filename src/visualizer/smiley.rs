@@ -1,103 +1,182 @@
 use audio;
-use gfx;
-use gfx::gl;
-use std::mem;
-use std::ptr;
-use visualizer::visualizer::SubVisualizer;
+use visualizer::bands::BandMapping;
+use visualizer::visualizer::{self, Draw, SubVisualizer};
+
+// How much of each new frame's energy feeds into the long-term average, roughly a few
+// seconds of memory at typical audio frame rates.
+const ENERGY_SMOOTHING: f32 = 0.01;
+
+// Energy thresholds to enter and leave each mood. Leaving takes a bigger swing than entering
+// so the face doesn't flicker between moods when the energy hovers around a threshold.
+const SLEEPY_ENTER: f32 = 0.15;
+const SLEEPY_LEAVE: f32 = 0.25;
+const ECSTATIC_ENTER: f32 = 0.75;
+const ECSTATIC_LEAVE: f32 = 0.6;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mood {
+    Sleepy,
+    Happy,
+    Ecstatic,
+}
+
+impl Mood {
+    // Identifies the eye shape in the fragment shader.
+    fn eye_style(&self) -> f32 {
+        match *self {
+            Mood::Sleepy => 0.0,
+            Mood::Happy => 1.0,
+            Mood::Ecstatic => 2.0,
+        }
+    }
+
+    fn mouth_curvature(&self) -> f32 {
+        match *self {
+            Mood::Sleepy => 1.0,
+            Mood::Happy => 3.0,
+            Mood::Ecstatic => 5.0,
+        }
+    }
+
+    fn mouth_width(&self) -> f32 {
+        match *self {
+            Mood::Sleepy => 0.4,
+            Mood::Happy => 0.58,
+            Mood::Ecstatic => 0.7,
+        }
+    }
 
-const NUM_SQUARES: usize = 1;
-const NUM_VERTICIES_PER_SQUARE: usize = 6;
+    fn next(&self, energy: f32) -> Mood {
+        match *self {
+            Mood::Sleepy if energy > SLEEPY_LEAVE => Mood::Happy,
+            Mood::Happy if energy < SLEEPY_ENTER => Mood::Sleepy,
+            Mood::Happy if energy > ECSTATIC_ENTER => Mood::Ecstatic,
+            Mood::Ecstatic if energy < ECSTATIC_LEAVE => Mood::Happy,
+
+            _ => *self,
+        }
+    }
+}
 
 pub struct SmileyVisualizer {
-    program_id: u32,
-    framebuffer_id: u32,
     vertex_data: Vec<f32>,
 
     amplitude: f32,
     phase: f32,
+    // Scales how fast the phase advances.
+    speed: f32,
+
+    energy: f32,
+    mood: Mood,
+
+    // Shrinks the face to fit outputs that aren't square.
+    scale: f32,
 }
 
 impl SubVisualizer for SmileyVisualizer {
     fn new() -> SmileyVisualizer {
         SmileyVisualizer {
-            program_id: 0,
-            framebuffer_id: 0,
-            vertex_data: Vec::new(),
+            vertex_data: generate_vertex_data(),
 
             amplitude: 0.0,
             phase: 0.0,
+            speed: 1.0,
+
+            energy: (SLEEPY_LEAVE + ECSTATIC_LEAVE) / 2.0,
+            mood: Mood::Happy,
+
+            scale: 1.0,
         }
     }
 
-    fn post_setup(&mut self, program_id: u32, framebuffer_id: u32) {
-        self.program_id = program_id;
-        self.framebuffer_id = framebuffer_id;
+    fn bands(&self) -> Vec<(&'static str, audio::FrequencyBand)> {
+        vec![
+            ("mouth", audio::FrequencyBand::new(400.0, 2000.0)),
+            ("energy", audio::FrequencyBand::new(0.0, 4000.0)),
+        ]
     }
 
-    fn update(&mut self, audio_frame: audio::AudioFrame) {
-        self.vertex_data = generate_vertex_data();
+    fn attributes(&self) -> Vec<(&'static str, usize)> {
+        vec![("position", 2)]
+    }
 
-        // Sum the 1000-2000hz amplitudes.
-        self.amplitude = 0.0;
-        for i in 4..20 {
-            self.amplitude += audio_frame.hundred_hz_buckets[i];
-        }
-        self.amplitude /= 1.0;
+    fn update(&mut self, audio_frame: audio::AudioFrame, bands: &BandMapping) {
+        self.amplitude = bands.power(&audio_frame, "mouth");
         self.amplitude = f32::min(1.0, self.amplitude);
 
-        self.phase += 0.1;
+        let energy = f32::min(1.0, bands.power(&audio_frame, "energy") / 2.0);
+        self.energy += (energy - self.energy) * ENERGY_SMOOTHING;
+        self.mood = self.mood.next(self.energy);
+
+        self.phase += 0.1 * self.speed;
         if self.phase >= 3.14 * 2.0 {
             self.phase -= 3.14 * 2.0;
         }
     }
 
-    fn render_to_texture(&self, gl: &gfx::gl::Gl) {
-        unsafe {
-            gl_try!(gl; gl.UseProgram(self.program_id));
-
-            let mut vb = mem::uninitialized();
-            gl_try!(gl; gl.GenBuffers(1, &mut vb));
-            gl_try!(gl; gl.BindBuffer(gl::ARRAY_BUFFER, vb));
-            gl_try!(gl; gl.BufferData(
-                gl::ARRAY_BUFFER,
-                (self.vertex_data.len() * mem::size_of::<f32>()) as gl::types::GLsizeiptr,
-                self.vertex_data.as_ptr() as *const _,
-                gl::STATIC_DRAW,
-            ));
+    fn draw(&self) -> Draw<'_> {
+        Draw {
+            uniforms: vec![
+                ("amplitude", self.amplitude),
+                ("phase", self.phase),
+                ("eye_style", self.mood.eye_style()),
+                ("mouth_curvature", self.mood.mouth_curvature()),
+                ("mouth_width", self.mood.mouth_width()),
+                ("scale", self.scale),
+            ],
+            vertices: &self.vertex_data,
+            clear_color: Some((0.0, 0.0, 0.0, 1.0)),
+        }
+    }
 
-            let mut vao = mem::uninitialized();
-            gl_try!(gl; gl.GenVertexArrays(1, &mut vao));
-            gl_try!(gl; gl.BindVertexArray(vao));
+    fn static_vertices(&self) -> bool {
+        true
+    }
 
-            let pos_attrib = gl_try!(gl; gl.GetAttribLocation(self.program_id, b"position\0".as_ptr() as *const _));
-            gl_try!(gl; gl.VertexAttribPointer(
-                pos_attrib as gl::types::GLuint, 2, gl::FLOAT, 0,
-                2 * mem::size_of::<f32>() as gl::types::GLsizei,
-                ptr::null(),
-            ));
-            gl_try!(gl; gl.EnableVertexAttribArray(pos_attrib as gl::types::GLuint));
+    fn save_state(&self) -> Vec<f32> {
+        vec![self.phase, self.energy, self.mood.eye_style()]
+    }
 
-            let amplitude_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"amplitude\0".as_ptr() as *const _));
-            gl_try!(gl; gl.Uniform1f(amplitude_uniform, self.amplitude));
+    fn restore_state(&mut self, state: &[f32]) {
+        if state.len() < 3 {
+            return;
+        }
 
-            let phase_uniform = gl_try!(gl; gl.GetUniformLocation(self.program_id, b"phase\0".as_ptr() as *const _));
-            gl_try!(gl; gl.Uniform1f(phase_uniform, self.phase));
+        self.phase = state[0];
+        self.energy = state[1];
+        self.mood = match state[2] as i32 {
+            0 => Mood::Sleepy,
+            2 => Mood::Ecstatic,
+            _ => Mood::Happy,
+        };
+    }
 
-            gl_try!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer_id));
+    fn set_parameter(&mut self, name: &str, value: f32) -> bool {
+        match name {
+            "speed" => self.speed = f32::max(0.0, value),
 
-            gl_try!(gl; gl.ClearColor(0.0, 0.0, 0.0, 1.0));
-            gl_try!(gl; gl.Clear(gl::COLOR_BUFFER_BIT));
+            _ => return false,
+        }
 
-            gl_try!(gl; gl.DrawBuffers(1, [gl::COLOR_ATTACHMENT0].as_ptr()));
+        true
+    }
 
-            gl_try!(gl; gl.DrawArrays(gl::TRIANGLES, 0,
-                (NUM_SQUARES * NUM_VERTICIES_PER_SQUARE) as i32));
+    fn parameter(&self, name: &str) -> Option<f32> {
+        match name {
+            "speed" => Some(self.speed),
 
-            gl_try!(gl; gl.DeleteBuffers(1, &vb));
-            gl_try!(gl; gl.DeleteVertexArrays(1, &vao));
+            _ => None,
         }
     }
 
+    fn parameter_names(&self) -> Vec<&'static str> {
+        vec!["speed"]
+    }
+
+    fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.scale = visualizer::fit_scale(aspect_ratio);
+    }
+
     fn vs_src(&self) -> &[u8] {
         b"
 #version 100
@@ -107,6 +186,7 @@ precision mediump float;
 
 uniform float amplitude;
 uniform float phase;
+uniform float scale;
 
 attribute vec2 position;
 
@@ -114,7 +194,7 @@ attribute vec2 position;
 varying vec2 v_position;
 
 void main() {
-    gl_Position = vec4(position, 0.0, 1.0);
+    gl_Position = vec4(position * scale, 0.0, 1.0);
     v_position = position;
 }
 \0"
@@ -128,6 +208,10 @@ precision mediump float;
 
 uniform float amplitude;
 uniform float phase;
+// 0 is sleepy slits, 1 is round eyes, 2 is ecstatic ^ ^ arcs.
+uniform float eye_style;
+uniform float mouth_curvature;
+uniform float mouth_width;
 
 // Interpolated from the Vertex Shader.
 varying vec2 v_position;
@@ -138,8 +222,25 @@ bool in_circle(vec2 p, vec2 center, float radius) {
     return d_x * d_x + d_y * d_y < radius * radius;
 }
 
+bool in_ellipse(vec2 p, vec2 center, vec2 radii) {
+    vec2 d = (p - center) / radii;
+    return d.x * d.x + d.y * d.y < 1.0;
+}
+
+bool in_eye(vec2 p, vec2 center, float radius) {
+    if (eye_style < 0.5) {
+        return in_ellipse(p, center, vec2(radius * 1.5, radius * 0.3));
+    } else if (eye_style < 1.5) {
+        return in_circle(p, center, radius);
+    } else {
+        vec2 d = p - center;
+        float arc_y = radius * 0.5 - abs(d.x) * 0.8;
+        return abs(d.x) <= radius * 1.5 && abs(d.y - arc_y) <= 0.03;
+    }
+}
+
 float y_mouth_top() {
-    float a = -amplitude * 3.0;
+    float a = -amplitude * mouth_curvature;
     float b = 0.0;
     float x_offset = 0.0;
     float y_offset = -0.25 + amplitude / 4.0;
@@ -149,7 +250,7 @@ float y_mouth_top() {
 }
 
 float y_mouth_bottom() {
-    float a = amplitude * 3.0;
+    float a = amplitude * mouth_curvature;
     float b = 0.0;
     float x_offset = 0.0;
     float y_offset = -0.25 - amplitude / 4.0;
@@ -169,16 +270,15 @@ void main() {
 
     vec3 yellow = vec3(1.0, 1.0, 0.0);
 
-    float mouth_width = 0.58;
     float epsilon = 0.025;
 
     if (!in_circle(v_position, vec2(0), border_radius)) {
         // Border.
         gl_FragColor = vec4(yellow, amplitude);
-    } else if (in_circle(v_position, eye_position_1, eye_radius_1)) {
+    } else if (in_eye(v_position, eye_position_1, eye_radius_1)) {
         // Eye 1.
         gl_FragColor = vec4(yellow, 1.0);
-    } else if (in_circle(v_position, eye_position_2, eye_radius_2)) {
+    } else if (in_eye(v_position, eye_position_2, eye_radius_2)) {
         // Eye 2.
         gl_FragColor = vec4(yellow, 1.0);
     } else if (v_position.x >= -mouth_width / 2.0 && v_position.x <= mouth_width / 2.0) {
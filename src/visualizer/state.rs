@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Saved visualizer states are stored one visualizer per line, as its name followed by its
+// state values separated by spaces.
+pub fn load_states(path: &Path) -> io::Result<HashMap<String, Vec<f32>>> {
+    let contents = fs::read_to_string(path)?;
+
+    let mut states = HashMap::new();
+    for (line_index, line) in contents.lines().enumerate() {
+        let mut parts = line.split_whitespace();
+        let name = match parts.next() {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let mut state = Vec::new();
+        for part in parts {
+            match part.parse::<f32>() {
+                Ok(x) => state.push(x),
+                Err(_) => return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: invalid state value '{}'", line_index + 1, part))),
+            }
+        }
+
+        states.insert(name.to_string(), state);
+    }
+
+    Ok(states)
+}
+
+pub fn save_states(path: &Path, states: &HashMap<String, Vec<f32>>) -> io::Result<()> {
+    let mut names: Vec<&String> = states.keys().collect();
+    names.sort();
+
+    let mut contents = String::new();
+    for name in names {
+        contents.push_str(name);
+        for value in states[name].iter() {
+            contents.push_str(&format!(" {}", value));
+        }
+        contents.push('\n');
+    }
+
+    fs::write(path, contents)
+}
@@ -1,7 +1,7 @@
 use audio;
-use gfx;
+use visualizer::bands::BandMapping;
 use visualizer::symmetry::symmetry::SymmetryVisualizer;
-use visualizer::visualizer::SubVisualizer;
+use visualizer::visualizer::{Draw, SubVisualizer};
 
 pub struct QuadSymmetryVisualizer {
     symmetry_visualizer: SymmetryVisualizer,
@@ -14,16 +14,44 @@ impl SubVisualizer for QuadSymmetryVisualizer {
         }
     }
 
-    fn post_setup(&mut self, program_id: u32, framebuffer_id: u32) {
-        self.symmetry_visualizer.post_setup(program_id, framebuffer_id);
+    fn bands(&self) -> Vec<(&'static str, audio::FrequencyBand)> {
+        self.symmetry_visualizer.bands()
     }
 
-    fn update(&mut self, audio_frame: audio::AudioFrame) {
-        self.symmetry_visualizer.update(audio_frame);
+    fn attributes(&self) -> Vec<(&'static str, usize)> {
+        self.symmetry_visualizer.attributes()
     }
 
-    fn render_to_texture(&self, gl: &gfx::gl::Gl) {
-        self.symmetry_visualizer.render_to_texture(gl);
+    fn update(&mut self, audio_frame: audio::AudioFrame, bands: &BandMapping) {
+        self.symmetry_visualizer.update(audio_frame, bands);
+    }
+
+    fn draw(&self) -> Draw<'_> {
+        self.symmetry_visualizer.draw()
+    }
+
+    fn static_vertices(&self) -> bool {
+        true
+    }
+
+    fn save_state(&self) -> Vec<f32> {
+        self.symmetry_visualizer.save_state()
+    }
+
+    fn restore_state(&mut self, state: &[f32]) {
+        self.symmetry_visualizer.restore_state(state);
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f32) -> bool {
+        self.symmetry_visualizer.set_parameter(name, value)
+    }
+
+    fn parameter(&self, name: &str) -> Option<f32> {
+        self.symmetry_visualizer.parameter(name)
+    }
+
+    fn parameter_names(&self) -> Vec<&'static str> {
+        self.symmetry_visualizer.parameter_names()
     }
 
     fn vs_src(&self) -> &[u8] {
@@ -1,31 +1,167 @@
 use audio;
 use gfx;
 use gfx::gl;
+use std::collections::HashMap;
+use std::io;
 use std::mem;
-use std::ptr;
+use std::path::PathBuf;
 use std::time;
+use visualizer::background::{self, Background};
+use visualizer::bands::{BandMapping, BandOverride};
+use visualizer::clock::Clock;
+use visualizer::compositor::{self, BlendMode, Compositor, Layer};
 use visualizer::equalizer::EqualizerVisualizer;
+use visualizer::idle::IdleVisualizer;
+use visualizer::overlay::TextOverlay;
 use visualizer::power_circles::PowerCirclesVisualizer;
+use visualizer::render_graph::{Pass, RenderGraph};
 use visualizer::smiley::SmileyVisualizer;
+use visualizer::state;
 use visualizer::symmetry::BiSymmetryVisualizer;
 use visualizer::symmetry::TriSymmetryVisualizer;
 use visualizer::symmetry::QuadSymmetryVisualizer;
 use visualizer::symmetry::PentaSymmetryVisualizer;
+use visualizer::white_balance::WhiteBalance;
+
+// What a visualizer draws in a frame, without saying how, so whichever backend renders the
+// layers can draw it: triangles from `vertices`, laid out as its `attributes`, with its shaders'
+// `uniforms` set.
+pub struct Draw<'a> {
+    pub uniforms: Vec<(&'static str, f32)>,
+    pub vertices: &'a [f32],
+    // What the layer's cleared to first, or None to draw over a transparent layer.
+    pub clear_color: Option<(f32, f32, f32, f32)>,
+}
 
 pub trait SubVisualizer {
     fn new() -> Self where Self: Sized;
-    fn post_setup(&mut self, program_id: u32, framebuffer_id: u32);
-    fn update(&mut self, audio_frame: audio::AudioFrame);
-    fn render_to_texture(&self, gl: &gfx::gl::Gl);
+    // Named audio inputs read in `update`, along with the frequency bands feeding them by default.
+    fn bands(&self) -> Vec<(&'static str, audio::FrequencyBand)>;
+    // The vertex shader's attributes with their number of floats, in the order they're
+    // interleaved in the vertices drawn.
+    fn attributes(&self) -> Vec<(&'static str, usize)>;
+    fn update(&mut self, audio_frame: audio::AudioFrame, bands: &BandMapping);
+    fn draw(&self) -> Draw<'_>;
     fn vs_src(&self) -> &[u8];
     fn fs_src(&self) -> &[u8];
+
+    // Lightweight state (phases, smoothed levels, ...) worth keeping when the visualizer is
+    // switched away from, including across restarts. Stateless visualizers keep the defaults.
+    fn save_state(&self) -> Vec<f32> {
+        Vec::new()
+    }
+
+    // Restore state from `save_state`. Must tolerate state saved by an older version.
+    fn restore_state(&mut self, _state: &[f32]) {}
+
+    // Live tweakable parameters, set by name. Returns false for parameters the visualizer doesn't have.
+    fn set_parameter(&mut self, _name: &str, _value: f32) -> bool {
+        false
+    }
+
+    fn parameter(&self, _name: &str) -> Option<f32> {
+        None
+    }
+
+    // The names `set_parameter` takes.
+    fn parameter_names(&self) -> Vec<&'static str> {
+        Vec::new()
+    }
+
+    // Visualizers drawing on top of their last frame, for trails, ripples or warps. Their
+    // fragment shader samples it through the `previous_frame` sampler uniform.
+    fn uses_feedback(&self) -> bool {
+        false
+    }
+
+    // Full screen passes run in order on what the visualizer rendered, the last one being what
+    // gets shown. Built once along with the visualizer's program.
+    fn passes(&self) -> Vec<Pass> {
+        Vec::new()
+    }
+
+    // Uniforms set on every pass before each frame.
+    fn pass_uniforms(&self) -> Vec<(&'static str, f32)> {
+        Vec::new()
+    }
+
+    // Visualizers always drawing the same vertices, which get uploaded once when set up instead
+    // of every frame.
+    fn static_vertices(&self) -> bool {
+        false
+    }
+
+    // Visualizers simple enough to draw without GL, when no GL context can be made.
+    fn renders_in_software(&self) -> bool {
+        false
+    }
+
+    // The color at a point of the layer, both coordinates from -1 to 1, or None where nothing
+    // is drawn, like the fragment shader would.
+    fn shade(&self, _x: f32, _y: f32) -> Option<(f32, f32, f32)> {
+        None
+    }
+
+    // The output's width over its height. Layers are square and cropped to the output, so
+    // visualizers drawn around a shape in the middle can shrink it by `fit_scale` to keep all of
+    // it on e.g. a 32x8 strip.
+    fn set_aspect_ratio(&mut self, _aspect_ratio: f32) {}
 }
 
+// How much a shape filling a square layer has to shrink to fit in an output of `aspect_ratio`.
+pub fn fit_scale(aspect_ratio: f32) -> f32 {
+    f32::min(aspect_ratio, 1.0 / aspect_ratio)
+}
+
+// Shown one after the other, each for TIME_PER_VISUALIZER, unless a visualizer or a playlist is
+// picked.
+const DEFAULT_PLAYLIST: [&str; 4] = ["bisymmetry", "trisymmetry", "quadsymmetry", "pentasymmetry"];
 const TIME_PER_VISUALIZER: time::Duration = time::Duration::from_secs(30);
 
-pub struct Visualizer {
-    texture_id: u32,
+pub const VISUALIZER_NAMES: [&str; 8] = [
+    "idle",
+    "equalizer",
+    "power_circles",
+    "smiley",
+    "bisymmetry",
+    "trisymmetry",
+    "quadsymmetry",
+    "pentasymmetry",
+];
 
+pub struct Visualizer {
+    // Where the layers are composited into.
+    output: Option<gfx::Framebuffer>,
+    // Where they're composited first when the screen needs the output gamma encoded or
+    // dithered. It only has 8 bits per channel, so the darkest shades band slightly.
+    linear_output: Option<gfx::Framebuffer>,
+    // The gamma visualizer colors are authored in, and the gamma the screen expects.
+    gamma: f32,
+    output_gamma: f32,
+    // Bits per channel the screen shows to dither the output to, or 0 not to.
+    dither_bits: u32,
+    // Frames rendered, which the dither pattern moves with.
+    frame: u32,
+    // What each channel is multiplied by to correct the screen's white point.
+    channel_scales: (f32, f32, f32),
+    compositor: Compositor,
+    // Layers are square, as big as the output's longest side times `supersample`, so visualizers
+    // keep their proportions on any output. They're cropped to the output when composited.
+    layer_size: i32,
+    // How many times bigger than shown layers are rendered, to smooth their edges.
+    supersample: i32,
+    // Fraction of that size layers are actually rendered at, lowered when rendering can't keep up.
+    render_scale: f32,
+    // Framebuffer each visualizer renders into before being composited, keyed by name.
+    layer_targets: HashMap<String, gfx::Framebuffer>,
+    // Used instead for visualizers that draw from their previous frame.
+    feedback_targets: HashMap<String, gfx::PingPong>,
+    // Passes run after rendering, for visualizers that have them.
+    render_graphs: HashMap<String, RenderGraph>,
+    // The built program of each visualizer, and the mesh its vertices are drawn from.
+    programs: HashMap<String, (gfx::Program, gfx::Mesh)>,
+
+    idle_visualizer: IdleVisualizer,
     equalizer_visualizer: EqualizerVisualizer,
     power_circles_visualizer: PowerCirclesVisualizer,
     smiley_visualizer: SmileyVisualizer,
@@ -35,23 +171,85 @@ pub struct Visualizer {
     pentasymmetry_visualizer: PentaSymmetryVisualizer,
 
     selected_visualizer: String,
-
-    rotate_visualizers: bool,
-    last_rotation: time::Instant,
+    band_mappings: HashMap<String, BandMapping>,
+    // Drawn on top of the selected visualizer, bottom to top.
+    layers: Vec<Layer>,
+    // Drawn below everything, with the selected visualizer blended onto it.
+    background: Option<Background>,
+    background_blend_mode: BlendMode,
+    background_texture: Option<gfx::Texture>,
+    // Loads images off the render thread, and the id the background image is loading under.
+    uploader: Option<gfx::Uploader>,
+    background_upload: Option<u32>,
+    // Drawn above everything.
+    overlay: TextOverlay,
+    // Scales the composited output, below the overlay.
+    brightness: f32,
+    // What a blackout brings the brightness back to when it's toggled off.
+    unblacked_brightness: f32,
+    // Set while the audio is silent, replacing everything with the idle visualizer.
+    is_idle: bool,
+
+    // Where visualizer states are saved whenever the shown visualizers change.
+    state_path: Option<PathBuf>,
+    shown_visualizers: Vec<&'static str>,
+
+    // Visualizers whose shaders failed to build, which are replaced by a working one.
+    failed_visualizers: Vec<&'static str>,
+
+    // Visualizers shown in turn, each for `time_per_visualizer`, unless one was picked.
+    playlist: Vec<String>,
+    rotating: bool,
+    time_per_visualizer: time::Duration,
+    // When the shown visualizer last changed, by `clock`.
+    last_rotation: time::Duration,
+    clock: Clock,
 }
 
 impl Visualizer {
-    pub fn new(selected_visualizer: String) -> Visualizer {
-        let rotate_visualizers = selected_visualizer == "";
+    pub fn new(selected_visualizer: String,
+               band_overrides: &[BandOverride],
+               layers: Vec<Layer>,
+               background: Option<Background>,
+               background_blend_mode: BlendMode,
+               overlay: TextOverlay,
+               state_path: Option<PathBuf>) -> Result<Visualizer, String> {
+        if !selected_visualizer.is_empty() && !VISUALIZER_NAMES.contains(&selected_visualizer.as_ref()) {
+            return Err(format!("Unknown visualizer '{}', expected one of: {}.",
+                               selected_visualizer, VISUALIZER_NAMES.join(", ")));
+        }
+        for layer in layers.iter() {
+            if !VISUALIZER_NAMES.contains(&layer.visualizer.as_ref()) {
+                return Err(format!("Unknown layer visualizer '{}'.", layer.visualizer));
+            }
+        }
+
+        let playlist: Vec<String> = DEFAULT_PLAYLIST.iter().map(|x| x.to_string()).collect();
+        let rotating = selected_visualizer.is_empty();
 
         let mut selected_visualizer = selected_visualizer;
-        if rotate_visualizers {
-            selected_visualizer = "bisymmetry".to_string();
+        if rotating {
+            selected_visualizer = playlist[0].clone();
         }
 
-        Visualizer {
-            texture_id: 0,
-
+        let mut visualizer = Visualizer {
+            output: None,
+            linear_output: None,
+            gamma: 1.0,
+            output_gamma: 1.0,
+            dither_bits: 0,
+            frame: 0,
+            channel_scales: (1.0, 1.0, 1.0),
+            compositor: Compositor::new(),
+            layer_size: 0,
+            supersample: 1,
+            render_scale: 1.0,
+            layer_targets: HashMap::new(),
+            feedback_targets: HashMap::new(),
+            render_graphs: HashMap::new(),
+            programs: HashMap::new(),
+
+            idle_visualizer: IdleVisualizer::new(),
             power_circles_visualizer: PowerCirclesVisualizer::new(),
             smiley_visualizer: SmileyVisualizer::new(),
             equalizer_visualizer: EqualizerVisualizer::new(),
@@ -61,160 +259,665 @@ impl Visualizer {
             pentasymmetry_visualizer: PentaSymmetryVisualizer::new(),
 
             selected_visualizer,
+            band_mappings: HashMap::new(),
+            layers,
+            background,
+            background_blend_mode,
+            background_texture: None,
+            uploader: None,
+            background_upload: None,
+            overlay,
+            brightness: 1.0,
+            unblacked_brightness: 1.0,
+            is_idle: false,
+
+            state_path,
+            shown_visualizers: Vec::new(),
+
+            failed_visualizers: Vec::new(),
+
+            playlist,
+            rotating,
+            time_per_visualizer: TIME_PER_VISUALIZER,
+            last_rotation: time::Duration::from_secs(0),
+            clock: Clock::new(),
+        };
+
+        for name in VISUALIZER_NAMES.iter() {
+            let mapping = BandMapping::new(&visualizer.visualizer_by_name(name).bands());
+            visualizer.band_mappings.insert(name.to_string(), mapping);
+        }
 
-            rotate_visualizers,
-            last_rotation: time::Instant::now(),
+        for band_override in band_overrides.iter() {
+            let mapping = match visualizer.band_mappings.get_mut(&band_override.visualizer) {
+                Some(x) => x,
+                None => return Err(format!("Unknown visualizer '{}'.", band_override.visualizer)),
+            };
+            if !mapping.set(&band_override.input, band_override.band) {
+                return Err(format!("Visualizer '{}' has no input '{}', expected one of: {}.",
+                                   band_override.visualizer, band_override.input,
+                                   mapping.names().join(", ")));
+            }
         }
+
+        visualizer.restore_states();
+
+        Ok(visualizer)
     }
 
-    pub fn setup(&mut self, gl: &gfx::gl::Gl, size: i32) {
-        unsafe {
-            let mut texture = mem::uninitialized();
-            gl_try!(gl; gl.GenTextures(1, &mut texture));
-            gl_try!(gl; gl.BindTexture(gl::TEXTURE_2D, texture));
-            gl_try!(gl; gl.ActiveTexture(gl::TEXTURE0));
-
-            gl_try!(gl; gl.TexParameteri(
-                gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32
-            ));
-            gl_try!(gl; gl.TexParameteri(
-                gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32
-            ));
-
-            gl_try!(gl; gl.TexImage2D(
-                gl::TEXTURE_2D, 0, gl::RGB as i32,
-                size, size, 0, gl::RGB, gl::UNSIGNED_BYTE,
-                ptr::null(),
-            ));
-
-            self.texture_id = texture;
-
-            let mut framebuffer = mem::uninitialized();
-            gl_try!(gl; gl.GenFramebuffers(1, &mut framebuffer));
-            gl_try!(gl; gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer));
-            gl_try!(gl; gl.FramebufferTexture2D(
-                gl::FRAMEBUFFER,
-                gl::COLOR_ATTACHMENT0,
-                gl::TEXTURE_2D,
-                texture, 0,
-            ));
-
-            let mut all_visualizers: [&mut dyn SubVisualizer; 7] = [
-                &mut self.equalizer_visualizer,
-                &mut self.power_circles_visualizer,
-                &mut self.smiley_visualizer,
-                &mut self.bisymmetry_visualizer,
-                &mut self.trisymmetry_visualizer,
-                &mut self.quadsymmetry_visualizer,
-                &mut self.pentasymmetry_visualizer];
-            for visualizer in all_visualizers.iter_mut() {
-                let (program_id, framebuffer_id) = visualizer.setup(gl, framebuffer);
-                visualizer.post_setup(program_id, framebuffer_id);
+    // Layers are rendered `supersample` times bigger in each direction than shown.
+    pub fn setup(&mut self,
+                 gl: &gfx::gl::Gl,
+                 width: i32,
+                 height: i32,
+                 supersample: i32) -> Result<(), String> {
+        self.supersample = i32::max(1, supersample);
+        self.compositor.setup(gl)?;
+        self.create_targets(gl, width, height)?;
+
+        let size = self.layer_size;
+        for name in VISUALIZER_NAMES.iter() {
+            let visualizer = self.visualizer_by_name(name);
+            let passes = visualizer.passes();
+            let built = gfx::Program::new(gl, visualizer.vs_src(), visualizer.fs_src())
+                .and_then(|program| {
+                    let mesh = gfx::Mesh::new(gl, program.id(), &visualizer.attributes())?;
+                    if passes.is_empty() {
+                        return Ok((program, mesh, None));
+                    }
+                    RenderGraph::new(gl, size, passes).map(|graph| (program, mesh, Some(graph)))
+                });
+            match built {
+                Ok((program, mesh, graph)) => {
+                    if visualizer.static_vertices() {
+                        mesh.upload(visualizer.draw().vertices);
+                    }
+                    self.programs.insert(name.to_string(), (program, mesh));
+                    if let Some(graph) = graph {
+                        self.render_graphs.insert(name.to_string(), graph);
+                    }
+                },
+                Err(e) => {
+                    println!("Disabling the {} visualizer, its {}", name, e);
+                    self.failed_visualizers.push(name);
+                }
             }
         }
+
+        if self.failed_visualizers.len() == VISUALIZER_NAMES.len() {
+            return Err("No visualizer could be set up.".to_string());
+        }
+        if self.failed_visualizers.contains(&self.selected_visualizer.as_ref()) {
+            println!("Showing {} instead.", self.active_visualizer_name());
+        }
+
+        Ok(())
     }
 
-    pub fn update(&mut self, audio_frame: audio::AudioFrame) {
-        self.active_visualizer().update(audio_frame.clone());
+    // Blend in linear light by decoding layers from `gamma`, then encode the result with
+    // `output_gamma`, e.g. 2.2 for monitors or 1 for LEDs whose brightness is already linear.
+    // Both 1 blends colors as they are. Takes effect at `setup`.
+    pub fn set_gamma(&mut self, gamma: f32, output_gamma: f32) {
+        self.gamma = gamma;
+        self.output_gamma = output_gamma;
+        self.compositor.set_gamma(gamma);
     }
 
-    pub fn render_to_texture(&mut self, gl: &gfx::gl::Gl) -> u32 {
-        self.active_visualizer().render_to_texture(gl);
+    // Dither the output to `bits` per channel, or not with 0, so that slow fades on screens
+    // with fewer levels, like LEDs at low brightness, don't band. The pattern changes every
+    // frame so that it averages out rather than showing. Takes effect at `setup`.
+    pub fn set_dithering(&mut self, bits: u32) {
+        self.dither_bits = bits;
+    }
+
+    // Show `playlist`'s visualizers in turn, each for `time_per_visualizer`, unless a visualizer
+    // was picked. An empty one is the symmetries. The current one keeps showing if it's in the
+    // new playlist, as when only the time changes.
+    pub fn set_playlist(&mut self, playlist: Vec<String>, time_per_visualizer: time::Duration) -> Result<(), String> {
+        for name in playlist.iter() {
+            if !VISUALIZER_NAMES.contains(&name.as_ref()) {
+                return Err(format!("Unknown visualizer '{}', expected one of: {}.", name, VISUALIZER_NAMES.join(", ")));
+            }
+        }
 
-        self.texture_id
+        self.playlist = if playlist.is_empty() {
+            DEFAULT_PLAYLIST.iter().map(|x| x.to_string()).collect()
+        } else {
+            playlist
+        };
+        if self.rotating && !self.playlist.contains(&self.selected_visualizer) {
+            self.selected_visualizer = self.playlist[0].clone();
+        }
+        self.time_per_visualizer = time_per_visualizer;
+        self.last_rotation = self.clock.now();
+        Ok(())
     }
 
-    fn active_visualizer(&mut self) -> &mut dyn SubVisualizer {
-        if self.rotate_visualizers &&
-            self.last_rotation.elapsed() > TIME_PER_VISUALIZER {
-            match self.selected_visualizer.as_ref() {
-                "bisymmetry" => self.selected_visualizer = "trisymmetry".to_string(),
-                "trisymmetry" => self.selected_visualizer = "quadsymmetry".to_string(),
-                "quadsymmetry" => self.selected_visualizer = "pentasymmetry".to_string(),
-                "pentasymmetry" => self.selected_visualizer = "bisymmetry".to_string(),
+    // Show one visualizer from now on, or go back to the playlist if `name` is empty.
+    pub fn select(&mut self, name: &str) -> Result<(), String> {
+        if !name.is_empty() && !VISUALIZER_NAMES.contains(&name) {
+            return Err(format!("Unknown visualizer '{}', expected one of: {}.", name, VISUALIZER_NAMES.join(", ")));
+        }
 
-                _ => (),
+        self.rotating = name.is_empty();
+        self.selected_visualizer = if self.rotating { self.playlist[0].clone() } else { name.to_string() };
+        self.last_rotation = self.clock.now();
+        Ok(())
+    }
+
+    // Show the visualizer `step` after the one shown, or before for a negative step, in
+    // VISUALIZER_NAMES' order without idle, looping round.
+    pub fn cycle(&mut self, step: i32) {
+        let names: Vec<&str> = VISUALIZER_NAMES.iter().cloned().filter(|x| *x != "idle").collect();
+        let i = names.iter().position(|x| *x == self.selected_visualizer).unwrap_or(0) as i32;
+        let next = (i + step).rem_euclid(names.len() as i32) as usize;
+        self.rotating = false;
+        self.selected_visualizer = names[next].to_string();
+        self.last_rotation = self.clock.now();
+    }
+
+    // The visualizer shown unless the audio is silent, and whether it's rotating through the
+    // playlist.
+    pub fn selected(&self) -> (&str, bool) {
+        (&self.selected_visualizer, self.rotating)
+    }
+
+    // Correct the output's colors for a screen's white point, in linear light after blending.
+    pub fn set_white_balance(&mut self, white_balance: WhiteBalance) {
+        self.channel_scales = white_balance.channel_scales();
+    }
+
+    // Load background images with `uploader` rather than on the render thread. They're shown once
+    // loaded. Takes effect at `setup`.
+    pub fn set_uploader(&mut self, uploader: gfx::Uploader) {
+        self.uploader = Some(uploader);
+    }
+
+    // Set up to draw with `render_software` instead of GL. Visualizers that need GL are left
+    // out, like ones that failed to set up.
+    pub fn setup_software(&mut self) -> Result<(), String> {
+        for name in VISUALIZER_NAMES.iter() {
+            if !self.visualizer_by_name(name).renders_in_software() && !self.failed_visualizers.contains(name) {
+                self.failed_visualizers.push(name);
             }
+        }
 
-            self.last_rotation = time::Instant::now();
+        if self.failed_visualizers.len() == VISUALIZER_NAMES.len() {
+            return Err("No visualizer can be drawn in software.".to_string());
+        }
+        if self.failed_visualizers.contains(&self.selected_visualizer.as_ref()) {
+            println!("The {} visualizer needs GL, showing {} instead.",
+                     self.selected_visualizer, self.active_visualizer_name());
         }
 
-        match self.selected_visualizer.as_ref() {
-            "equalizer" => &mut self.equalizer_visualizer,
-            "power_circles" => &mut self.power_circles_visualizer,
-            "smiley" => &mut self.smiley_visualizer,
-            "bisymmetry" => &mut self.bisymmetry_visualizer,
-            "trisymmetry" => &mut self.trisymmetry_visualizer,
-            "quadsymmetry" => &mut self.quadsymmetry_visualizer,
-            "pentasymmetry" => &mut self.pentasymmetry_visualizer,
+        Ok(())
+    }
 
-            _ => &mut self.equalizer_visualizer,
+    // Draw on the CPU, after `setup_software`. Layers are drawn over each other without their
+    // blend modes, and without the background or overlay. Returns tightly packed RGB rows,
+    // bottom to top as GL would read them.
+    pub fn render_software(&mut self, width: i32, height: i32) -> Vec<u8> {
+        let (width, height) = (width as usize, height as usize);
+        // Layers are square and cropped to the output, like when composited.
+        let side = usize::max(width, height) as f32;
+        for name in VISUALIZER_NAMES.iter() {
+            self.visualizer_by_name(name).set_aspect_ratio(width as f32 / height as f32);
+        }
+        let mut colors = vec![(0.0, 0.0, 0.0); width * height];
+
+        for &(name, _) in self.visible_layers().iter() {
+            let visualizer = self.visualizer_by_name(name);
+            for row in 0..height {
+                let y = ((row as f32 + 0.5) * 2.0 - height as f32) / side;
+                for column in 0..width {
+                    let x = ((column as f32 + 0.5) * 2.0 - width as f32) / side;
+                    if let Some(color) = visualizer.shade(x, y) {
+                        colors[row * width + column] = color;
+                    }
+                }
+            }
+        }
+
+        let levels = match self.dither_bits {
+            0 => 0.0,
+            bits => ((1u32 << u32::min(bits, 16)) - 1) as f32,
+        };
+        let phase = compositor::dither_phase(self.frame);
+        self.frame = self.frame.wrapping_add(1);
+        let to_byte = |x: f32, scale: f32, threshold: f32| {
+            let linear = f32::max(0.0, x).powf(self.gamma) * scale * self.brightness;
+            let mut encoded = linear.powf(1.0 / self.output_gamma);
+            if levels > 0.0 {
+                encoded += (threshold - 0.5) / levels;
+            }
+            // Rounded like GL does when writing to the output.
+            (encoded.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+        };
+        let mut pixels = Vec::with_capacity(3 * colors.len());
+        for (i, &(r, g, b)) in colors.iter().enumerate() {
+            let (column, row) = ((i % width) as u32, (i / width) as u32);
+            let threshold = (compositor::bayer4(column, row) + phase) % 1.0;
+            let (r_scale, g_scale, b_scale) = self.channel_scales;
+            pixels.extend_from_slice(&[to_byte(r, r_scale, threshold),
+                                       to_byte(g, g_scale, threshold),
+                                       to_byte(b, b_scale, threshold)]);
         }
+
+        pixels
     }
-}
 
-impl dyn SubVisualizer {
-    fn setup(&self, gl: &gfx::gl::Gl, framebuffer_id: u32) -> (u32, u32) {
-        unsafe {
-            let vs = gl_try!(gl; gl.CreateShader(gl::VERTEX_SHADER));
-            gl_try!(gl; gl.ShaderSource(vs, 1, [self.vs_src().as_ptr() as *const _].as_ptr(), ptr::null()));
-            gl_try!(gl; gl.CompileShader(vs));
+    // Render at a new size from now on.
+    pub fn resize(&mut self, gl: &gfx::gl::Gl, width: i32, height: i32) -> Result<(), String> {
+        self.create_targets(gl, width, height)?;
+        self.resize_render_graphs(gl)
+    }
 
-            let mut is_compiled = mem::uninitialized();
-            gl_try!(gl; gl.GetShaderiv(vs, gl::COMPILE_STATUS, &mut is_compiled));
-            if is_compiled == gl::FALSE as i32 {
-                let mut max_length = mem::uninitialized();
-                gl_try!(gl; gl.GetShaderiv(vs, gl::INFO_LOG_LENGTH, &mut max_length));
+    // Render layers at a fraction of their full size, keeping the output size. Visualizers
+    // drawing from their previous frame start over.
+    pub fn set_render_scale(&mut self, gl: &gfx::gl::Gl, scale: f32) -> Result<(), String> {
+        let (width, height) = match self.output {
+            Some(ref x) => (x.texture().width(), x.texture().height()),
+            None => return Err("the visualizer isn't set up".to_string()),
+        };
+
+        self.render_scale = scale;
+        self.create_layer_targets(gl, width, height)?;
+        self.resize_render_graphs(gl)
+    }
 
-                let mut info_log = vec![0; max_length as usize];
-                gl_try!(gl; gl.GetShaderInfoLog(vs, max_length, &mut max_length, info_log.as_mut_ptr()));
+    fn resize_render_graphs(&mut self, gl: &gfx::gl::Gl) -> Result<(), String> {
+        for graph in self.render_graphs.values_mut() {
+            graph.resize(gl, self.layer_size)?;
+        }
+
+        Ok(())
+    }
 
-                for info_char in info_log.iter() {
-                    print!("{}", *info_char as u8 as char);
+    fn receive_uploads(&mut self, gl: &gfx::gl::Gl) {
+        let uploads = match self.uploader {
+            Some(ref x) => x.receive(gl),
+            None => return,
+        };
+
+        for (id, texture) in uploads {
+            if Some(id) != self.background_upload {
+                continue;
+            }
+            self.background_upload = None;
+            match texture {
+                Ok(x) => self.background_texture = Some(x),
+                Err(e) => println!("Not showing the background: {}", e),
+            }
+        }
+    }
+
+    // Everything that depends on the output size: the output itself, the layers, and what's
+    // drawn over and under them.
+    fn create_targets(&mut self, gl: &gfx::gl::Gl, width: i32, height: i32) -> Result<(), String> {
+        let output_texture = gfx::Texture::new(gl, width, height, gl::RGB, gl::LINEAR)?;
+        self.output = Some(gfx::Framebuffer::new(gl, output_texture)?);
+        self.linear_output = None;
+        if self.output_gamma != 1.0 || self.dither_bits > 0 {
+            self.linear_output = Some(gfx::Framebuffer::new_linear(gl, width, height)?);
+        }
+
+        self.overlay.setup(gl, width, height)?;
+        for name in VISUALIZER_NAMES.iter() {
+            self.visualizer_by_name(name).set_aspect_ratio(width as f32 / height as f32);
+        }
+
+        match (self.background.as_ref(), self.uploader.as_mut()) {
+            // Images don't depend on the output size, so are only loaded once.
+            (Some(Background::Image(path)), Some(uploader)) => {
+                if self.background_texture.is_none() && self.background_upload.is_none() {
+                    let path = path.clone();
+                    self.background_upload = Some(uploader.load(move || background::load_image(&path)));
                 }
-                panic!();
+            },
+            (Some(background), _) => match background.create_texture(gl, height) {
+                Ok(x) => self.background_texture = Some(x),
+                Err(e) => println!("Not showing the background: {}", e),
+            },
+            (None, _) => (),
+        }
+
+        self.create_layer_targets(gl, width, height)
+    }
+
+    fn create_layer_targets(&mut self, gl: &gfx::gl::Gl, width: i32, height: i32) -> Result<(), String> {
+        let full_size = i32::max(width, height) * self.supersample;
+        let size = i32::max(1, (full_size as f32 * self.render_scale) as i32);
+        self.layer_size = size;
+        for name in VISUALIZER_NAMES.iter() {
+            // Layers keep their alpha so normal blending can show the layers below.
+            if self.visualizer_by_name(name).uses_feedback() {
+                self.feedback_targets.insert(name.to_string(), gfx::PingPong::new(gl, size, size, gl::RGBA)?);
+            } else {
+                let layer_texture = gfx::Texture::new(gl, size, size, gl::RGBA, gl::LINEAR)?;
+                self.layer_targets.insert(name.to_string(), gfx::Framebuffer::new(gl, layer_texture)?);
             }
+        }
 
-            let fs = gl_try!(gl; gl.CreateShader(gl::FRAGMENT_SHADER));
-            gl_try!(gl; gl.ShaderSource(fs, 1, [self.fs_src().as_ptr() as *const _].as_ptr(), ptr::null()));
-            gl_try!(gl; gl.CompileShader(fs));
+        Ok(())
+    }
 
-            let mut is_compiled = mem::uninitialized();
-            gl_try!(gl; gl.GetShaderiv(fs, gl::COMPILE_STATUS, &mut is_compiled));
-            if is_compiled == gl::FALSE as i32 {
-                let mut max_length = mem::uninitialized();
-                gl_try!(gl; gl.GetShaderiv(fs, gl::INFO_LOG_LENGTH, &mut max_length));
+    pub fn update(&mut self, audio_frame: audio::AudioFrame) {
+        self.clock.tick();
+        self.rotate_if_due();
+
+        if audio_frame.is_silent != self.is_idle {
+            self.is_idle = audio_frame.is_silent;
+            if self.is_idle {
+                println!("Audio is silent, switching to the idle visualizer.");
+            } else {
+                println!("Audio resumed, switching back to {}.", self.active_visualizer_name());
+            }
+        }
 
-                let mut info_log = vec![0; max_length as usize];
-                gl_try!(gl; gl.GetShaderInfoLog(fs, max_length, &mut max_length, info_log.as_mut_ptr()));
+        let shown_visualizers: Vec<&'static str> = self.visible_layers().iter().map(|&(name, _)| name).collect();
+        if shown_visualizers != self.shown_visualizers {
+            self.save_states();
+            self.shown_visualizers = shown_visualizers;
+        }
 
-                for info_char in info_log.iter() {
-                    print!("{}", *info_char as u8 as char);
-                }
-                panic!();
+        // Taken out while the visualizers borrow self, which leaves an empty map that doesn't
+        // allocate.
+        let band_mappings = mem::take(&mut self.band_mappings);
+        self.idle_visualizer.set_time(self.clock.now());
+        for &(name, _) in self.visible_layers().iter() {
+            self.visualizer_by_name(name).update(audio_frame.clone(), &band_mappings[name]);
+        }
+        self.band_mappings = band_mappings;
+    }
+
+    fn restore_states(&mut self) {
+        let path = match self.state_path {
+            Some(ref x) => x.clone(),
+            None => return,
+        };
+
+        let states = match state::load_states(&path) {
+            Ok(x) => x,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => {
+                println!("Could not load visualizer states from {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        for (name, visualizer_state) in states.iter() {
+            if VISUALIZER_NAMES.contains(&name.as_ref()) {
+                self.visualizer_by_name(name).restore_state(visualizer_state);
+            }
+        }
+    }
+
+    fn save_states(&mut self) {
+        let path = match self.state_path {
+            Some(ref x) => x.clone(),
+            None => return,
+        };
+
+        let mut states = HashMap::new();
+        for name in VISUALIZER_NAMES.iter() {
+            let visualizer_state = self.visualizer_by_name(name).save_state();
+            if !visualizer_state.is_empty() {
+                states.insert(name.to_string(), visualizer_state);
+            }
+        }
+
+        if let Err(e) = state::save_states(&path, &states) {
+            println!("Could not save visualizer states to {}: {}", path.display(), e);
+        }
+    }
+
+    pub fn render_to_texture(&mut self, gl: &gfx::gl::Gl) -> u32 {
+        self.receive_uploads(gl);
+        let layers = self.visible_layers();
+        unsafe {
+            gl_try!(gl; gl.Viewport(0, 0, self.layer_size, self.layer_size));
+        }
+        // Taken out while the visualizers borrow self, like the band mappings.
+        let programs = mem::take(&mut self.programs);
+        for &(name, _) in layers.iter() {
+            let (program, mesh) = match programs.get(name) {
+                Some((program, mesh)) => (program, mesh),
+                None => continue,
+            };
+            let upload = !self.visualizer_by_name(name).static_vertices();
+            // Taken out while drawing, as the visualizer reads from it.
+            if let Some(mut target) = self.feedback_targets.remove(name) {
+                target.bind();
+                target.previous().bind(0);
+                program.bind();
+                program.set_i32("previous_frame", 0);
+                draw_layer(gl, program, mesh, self.visualizer_by_name(name).draw(), upload);
+                target.swap();
+                self.feedback_targets.insert(name.to_string(), target);
+            } else {
+                self.layer_targets[name].bind();
+                draw_layer(gl, program, mesh, self.visualizer_by_name(name).draw(), upload);
+            }
+
+            let uniforms = self.visualizer_by_name(name).pass_uniforms();
+            if let Some(graph) = self.render_graphs.get(name) {
+                graph.run(gl, self.scene_texture(name), &uniforms);
             }
+        }
+        self.programs = programs;
+
+        let output = match self.output {
+            Some(ref x) => x,
+            None => return 0,
+        };
+
+        self.compositor.begin(gl, self.linear_output.as_ref().unwrap_or(output));
+        if let Some(ref background_texture) = self.background_texture {
+            self.compositor.draw_layer(gl, background_texture, BlendMode::Normal);
+        }
+        for &(name, blend_mode) in layers.iter() {
+            self.compositor.draw_cropped_layer(gl, self.layer_texture(name), blend_mode);
+        }
+        let (r_scale, g_scale, b_scale) = self.channel_scales;
+        let scales = (r_scale * self.brightness, g_scale * self.brightness, b_scale * self.brightness);
+        if scales != (1.0, 1.0, 1.0) {
+            self.compositor.scale_colors(gl, scales);
+        }
+        if let Some(ref linear_output) = self.linear_output {
+            self.compositor.begin(gl, output);
+            self.compositor.encode(gl, linear_output.texture(), self.output_gamma,
+                                   self.dither_bits, self.frame);
+            self.frame = self.frame.wrapping_add(1);
+        }
+        // Text is drawn as is, over the encoded output.
+        if let Some(overlay_texture) = self.overlay.prepare(layers[0].0, self.clock.now()) {
+            self.compositor.draw_encoded_layer(gl, overlay_texture, BlendMode::Normal);
+        }
+        self.compositor.end(gl);
+
+        output.texture().id()
+    }
+
+    // What a visualizer last rendered, after its passes.
+    fn layer_texture(&self, name: &str) -> &gfx::Texture {
+        match self.render_graphs.get(name) {
+            Some(x) => x.output(),
+            None => self.scene_texture(name),
+        }
+    }
+
+    // What a visualizer last rendered itself, before its passes.
+    fn scene_texture(&self, name: &str) -> &gfx::Texture {
+        match self.feedback_targets.get(name) {
+            Some(x) => x.previous(),
+            None => self.layer_targets[name].texture(),
+        }
+    }
+
+    // Animate by frames at `fps` instead of by wall time, one frame per update.
+    pub fn count_frames(&mut self, fps: u32) {
+        self.clock.count_frames(fps);
+        self.last_rotation = self.clock.now();
+    }
+
+    pub fn toggle_overlay(&mut self) {
+        self.overlay.toggle();
+    }
+
+    // Turn the brightness down to 0, or back up to what it was if it's already 0.
+    pub fn toggle_blackout(&mut self) {
+        if self.brightness > 0.0 {
+            self.unblacked_brightness = self.brightness;
+            self.brightness = 0.0;
+        } else {
+            self.brightness = self.unblacked_brightness;
+        }
+    }
+
+    // Set a live parameter, either "brightness" or "<visualizer>.<parameter>", e.g. "smiley.speed".
+    pub fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), String> {
+        if name == "brightness" {
+            self.brightness = value.clamp(0.0, 1.0);
+            return Ok(());
+        }
 
-            let program_id = gl_try!(gl; gl.CreateProgram());
-            gl_try!(gl; gl.AttachShader(program_id, vs));
-            gl_try!(gl; gl.AttachShader(program_id, fs));
-            gl_try!(gl; gl.LinkProgram(program_id));
+        let (visualizer_name, parameter) = split_parameter(name)?;
+        if self.visualizer_by_name(visualizer_name).set_parameter(parameter, value) {
+            Ok(())
+        } else {
+            Err(format!("Visualizer '{}' has no parameter '{}'.", visualizer_name, parameter))
+        }
+    }
 
-            let mut is_linked = mem::uninitialized();
-            gl_try!(gl; gl.GetProgramiv(program_id, gl::LINK_STATUS, &mut is_linked));
-            if is_linked == gl::FALSE as i32 {
-                let mut max_length = mem::uninitialized();
-                gl_try!(gl; gl.GetProgramiv(program_id, gl::INFO_LOG_LENGTH, &mut max_length));
+    pub fn parameter(&mut self, name: &str) -> Option<f32> {
+        if name == "brightness" {
+            return Some(self.brightness);
+        }
 
-                let mut info_log = vec![0; max_length as usize];
-                gl_try!(gl; gl.GetProgramInfoLog(program_id, max_length, &mut max_length, info_log.as_mut_ptr()));
+        match split_parameter(name) {
+            Ok((visualizer_name, parameter)) => self.visualizer_by_name(visualizer_name).parameter(parameter),
+            Err(_) => None,
+        }
+    }
 
-                for info_char in info_log.iter() {
-                    print!("{}", *info_char as u8 as char);
+    // Every live parameter with its value, brightness first, then each visualizer's as
+    // "<visualizer>.<parameter>".
+    pub fn parameters(&mut self) -> Vec<(String, f32)> {
+        let mut parameters = vec![("brightness".to_string(), self.brightness)];
+        for name in VISUALIZER_NAMES.iter() {
+            let visualizer = self.visualizer_by_name(name);
+            for parameter in visualizer.parameter_names() {
+                if let Some(value) = visualizer.parameter(parameter) {
+                    parameters.push((format!("{}.{}", name, parameter), value));
                 }
-                panic!();
             }
+        }
+        parameters
+    }
+
+    // The name to give a parameter of the active visualizer, e.g. "speed" -> "smiley.speed".
+    pub fn active_parameter_name(&self, parameter: &str) -> String {
+        format!("{}.{}", self.active_visualizer_name(), parameter)
+    }
+
+    fn rotate_if_due(&mut self) {
+        if self.rotating &&
+            self.clock.now().saturating_sub(self.last_rotation) > self.time_per_visualizer {
+            if let Some(i) = self.playlist.iter().position(|x| *x == self.selected_visualizer) {
+                self.selected_visualizer = self.playlist[(i + 1) % self.playlist.len()].clone();
+            }
+
+            self.last_rotation = self.clock.now();
+        }
+    }
+
+    // The selected visualizer followed by each layer drawn on top of it. A visualizer is only
+    // drawn once, even if it's selected and layered at the same time.
+    fn visible_layers(&self) -> Vec<(&'static str, BlendMode)> {
+        let base_blend_mode = match self.background_texture {
+            Some(_) => self.background_blend_mode,
+            None => BlendMode::Normal,
+        };
+
+        if self.is_idle {
+            return vec![(self.visualizer_name("idle"), base_blend_mode)];
+        }
+
+        let mut layers = vec![(self.active_visualizer_name(), base_blend_mode)];
+        for layer in self.layers.iter() {
+            let name = self.visualizer_name(&layer.visualizer);
+            if name != layer.visualizer {
+                continue;
+            }
+            if !layers.iter().any(|&(x, _)| x == name) {
+                layers.push((name, layer.blend_mode));
+            }
+        }
+
+        layers
+    }
+
+    fn active_visualizer_name(&self) -> &'static str {
+        self.visualizer_name(&self.selected_visualizer)
+    }
+
+    // The visualizer to show for a name, falling back to the equalizer for unknown names or
+    // visualizers that failed to set up, or to any working visualizer if the equalizer failed too.
+    fn visualizer_name(&self, name: &str) -> &'static str {
+        let usable: Vec<&'static str> = VISUALIZER_NAMES.iter()
+            .cloned()
+            .filter(|x| !self.failed_visualizers.contains(x))
+            .collect();
+
+        for candidate in [name, "equalizer"].iter() {
+            if let Some(x) = usable.iter().find(|x| *x == candidate) {
+                return x;
+            }
+        }
 
-            (program_id, framebuffer_id)
+        usable.first().cloned().unwrap_or("equalizer")
+    }
+
+    fn visualizer_by_name(&mut self, name: &str) -> &mut (dyn SubVisualizer + 'static) {
+        match name {
+            "idle" => &mut self.idle_visualizer,
+            "equalizer" => &mut self.equalizer_visualizer,
+            "power_circles" => &mut self.power_circles_visualizer,
+            "smiley" => &mut self.smiley_visualizer,
+            "bisymmetry" => &mut self.bisymmetry_visualizer,
+            "trisymmetry" => &mut self.trisymmetry_visualizer,
+            "quadsymmetry" => &mut self.quadsymmetry_visualizer,
+            "pentasymmetry" => &mut self.pentasymmetry_visualizer,
+
+            _ => &mut self.equalizer_visualizer,
         }
     }
 }
+
+// Draw what a visualizer describes into whatever framebuffer is bound, uploading its vertices
+// first unless they're static and already uploaded.
+fn draw_layer(gl: &gfx::gl::Gl, program: &gfx::Program, mesh: &gfx::Mesh, draw: Draw, upload: bool) {
+    let (r, g, b, a) = draw.clear_color.unwrap_or((0.0, 0.0, 0.0, 0.0));
+    unsafe {
+        gl_try!(gl; gl.ClearColor(r, g, b, a));
+        gl_try!(gl; gl.Clear(gl::COLOR_BUFFER_BIT));
+    }
+
+    program.bind();
+    for &(name, value) in draw.uniforms.iter() {
+        program.set_f32(name, value);
+    }
+    if upload {
+        mesh.upload(draw.vertices);
+    }
+    mesh.draw();
+}
+
+fn split_parameter(name: &str) -> Result<(&str, &str), String> {
+    let parts: Vec<&str> = name.splitn(2, '.').collect();
+    if parts.len() != 2 || !VISUALIZER_NAMES.contains(&parts[0]) {
+        return Err(format!("Unknown parameter '{}', expected brightness or <visualizer>.<parameter>.", name));
+    }
+
+    Ok((parts[0], parts[1]))
+}
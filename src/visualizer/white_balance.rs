@@ -0,0 +1,79 @@
+// Color correction for screens whose white point is off, like cheap LED panels that show white
+// as blue. Written as per-channel gains "<red>,<green>,<blue>", e.g. "1,0.85,0.7", and warmed or
+// cooled by a color temperature in kelvin, where 6500 is neutral.
+#[derive(Clone, Copy, Debug)]
+pub struct WhiteBalance {
+    pub gains: (f32, f32, f32),
+    pub temperature: f32,
+}
+
+// The temperature that leaves colors as they are.
+const NEUTRAL_TEMPERATURE: f32 = 6500.0;
+
+impl WhiteBalance {
+    pub fn parse(gains: &str, temperature: f32) -> Result<WhiteBalance, String> {
+        let parts: Vec<&str> = gains.split(',').collect();
+        if parts.len() != 3 {
+            return Err(format!("expected <red>,<green>,<blue> gains, got '{}'", gains));
+        }
+
+        let mut values = [0.0; 3];
+        for (value, part) in values.iter_mut().zip(parts.iter()) {
+            *value = match part.trim().parse::<f32>() {
+                Ok(x) if x >= 0.0 => x,
+                _ => return Err(format!("expected a gain of at least 0, got '{}'", part)),
+            };
+        }
+        if !(1000.0..=40000.0).contains(&temperature) {
+            return Err(format!("expected a temperature from 1000 to 40000 kelvin, got {}", temperature));
+        }
+
+        Ok(WhiteBalance {
+            gains: (values[0], values[1], values[2]),
+            temperature,
+        })
+    }
+
+    // What to multiply each channel by in linear light. Scaled so the largest is at most 1, as
+    // the output can only get darker.
+    pub fn channel_scales(&self) -> (f32, f32, f32) {
+        let tint = temperature_color(self.temperature);
+        let neutral = temperature_color(NEUTRAL_TEMPERATURE);
+        let (r, g, b) = (self.gains.0 * tint.0 / neutral.0,
+                         self.gains.1 * tint.1 / neutral.1,
+                         self.gains.2 * tint.2 / neutral.2);
+
+        let largest = f32::max(r, f32::max(g, b));
+        if largest <= 1.0 {
+            return (r, g, b);
+        }
+        (r / largest, g / largest, b / largest)
+    }
+}
+
+// The linear color of a black body at `kelvin`, from Tanner Helland's fit to blackbody data,
+// which is close enough for tinting from 1000 to 40000 kelvin.
+fn temperature_color(kelvin: f32) -> (f32, f32, f32) {
+    let t = kelvin / 100.0;
+    let red = if t <= 66.0 {
+        255.0
+    } else {
+        329.69873 * (t - 60.0).powf(-0.13320476)
+    };
+    let green = if t <= 66.0 {
+        99.4708 * t.ln() - 161.11957
+    } else {
+        288.12216 * (t - 60.0).powf(-0.075514846)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.51773 * (t - 10.0).ln() - 305.0448
+    };
+
+    // The fit gives sRGB values, decoded here to blend as light.
+    let to_linear = |x: f32| (x.clamp(0.0, 255.0) / 255.0).powf(2.2);
+    (to_linear(red), to_linear(green), to_linear(blue))
+}
@@ -0,0 +1,81 @@
+# Shows frames streamed by rpi-music-visualizer's unicorn screen on a Pimoroni Galactic, Cosmic
+# or Stellar Unicorn. Copy it to the board as main.py. With WIFI_SSID set it takes frames over
+# UDP on PORT, otherwise over USB serial.
+#
+# Each frame is b"UN", its columns and rows as a byte each, then their RGB colors in rows from
+# the top left.
+
+import micropython
+import sys
+
+from picographics import PicoGraphics
+
+WIFI_SSID = ""
+WIFI_PASSWORD = ""
+PORT = 6454
+
+try:
+    from galactic import GalacticUnicorn as Unicorn
+    from picographics import DISPLAY_GALACTIC_UNICORN as DISPLAY
+except ImportError:
+    try:
+        from cosmic import CosmicUnicorn as Unicorn
+        from picographics import DISPLAY_COSMIC_UNICORN as DISPLAY
+    except ImportError:
+        from stellar import StellarUnicorn as Unicorn
+        from picographics import DISPLAY_STELLAR_UNICORN as DISPLAY
+
+unicorn = Unicorn()
+graphics = PicoGraphics(display=DISPLAY)
+width, height = graphics.get_bounds()
+
+
+def show(frame):
+    if len(frame) < 4 or frame[0:2] != b"UN":
+        return
+    columns, rows = frame[2], frame[3]
+    if (columns, rows) != (width, height) or len(frame) != 4 + 3 * columns * rows:
+        return
+
+    i = 4
+    for y in range(rows):
+        for x in range(columns):
+            graphics.set_pen(graphics.create_pen(frame[i], frame[i + 1], frame[i + 2]))
+            graphics.pixel(x, y)
+            i += 3
+    unicorn.update(graphics)
+
+
+def receive_udp():
+    import network
+    import socket
+
+    wlan = network.WLAN(network.STA_IF)
+    wlan.active(True)
+    wlan.connect(WIFI_SSID, WIFI_PASSWORD)
+    while not wlan.isconnected():
+        pass
+    print("Listening on {}:{}".format(wlan.ifconfig()[0], PORT))
+
+    s = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+    s.bind(("0.0.0.0", PORT))
+    while True:
+        show(s.recv(4 + 3 * width * height))
+
+
+def receive_serial():
+    # Frames can contain Ctrl-C, which would otherwise stop the script.
+    micropython.kbd_intr(-1)
+    stdin = sys.stdin.buffer
+    while True:
+        # Find the start of a frame, in case the stream started partway through one.
+        if stdin.read(1) != b"U" or stdin.read(1) != b"N":
+            continue
+        size = stdin.read(2)
+        show(b"UN" + size + stdin.read(3 * size[0] * size[1]))
+
+
+if WIFI_SSID:
+    receive_udp()
+else:
+    receive_serial()